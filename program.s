@@ -1,7 +1,8 @@
 .section __DATA,__data
 L0:
-    .asciz "Hello, World!
-"
+    .asciz "Hello, World!\n"
 .section __TEXT,__text
-.global _start
+.globl _start
+.extern _printf
+.extern _exit
 _start:
@@ -1,40 +1,201 @@
+use crate::builder::InstructionBuilder;
+use crate::context::{quote, Value};
 use crate::instruction::*;
+use crate::platform::{macos::MacOS, Platform};
 use std::fmt::{self, Display};
+use std::panic::Location;
+
+pub mod boot;
+pub mod emulator;
+pub mod encoder;
+pub mod imm;
+pub(crate) mod layout;
+pub mod validate;
+
+/// `ldr` (literal) reaches +/-1MB from the instruction.
+const LITERAL_RANGE: u64 = 1 << 20;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Arm64Register {
     X0, X1, X2, X3, X4, X5, X6, X7, X8, X9, X10, 
     X11, X12, X13, X14, X15, X16, X17, X18, X19, X20,
     X21, X22, X23, X24, X25, X26, X27, X28, X29, X30,
+    W0, W1, W2, W3, W4, W5, W6, W7, W8, W9, W10,
+    W11, W12, W13, W14, W15, W16, W17, W18, W19, W20,
+    W21, W22, W23, W24, W25, W26, W27, W28, W29, W30,
     V0, V1, V2, V3, V4, V5, V6, V7, V8, V9, V10,
     V11, V12, V13, V14, V15, V16, V17, V18, V19, V20,
     V21, V22, V23, V24, V25, V26, V27, V28, V29, V30,
     V31,
-    SP, LR, XZR,
+    SP, LR, XZR, WSP, WZR,
 }
 
 impl Register for Arm64Register {
+    /// X0-X30 and their W views, including LR as X30.
     fn is_general_purpose(&self) -> bool {
-        matches!(self, 
-            Self::X0 | Self::X1 | Self::X2 | Self::X3 |
-            Self::X4 | Self::X5 | Self::X6 | Self::X7 |
-            Self::X8 | Self::X9 | Self::X10 | Self::X11 |
-            Self::X12 | Self::X13 | Self::X14 | Self::X15
+        matches!(self,
+            Self::X0 | Self::X1 | Self::X2 | Self::X3 | Self::X4 | Self::X5 | Self::X6 | Self::X7 |
+            Self::X8 | Self::X9 | Self::X10 | Self::X11 | Self::X12 | Self::X13 | Self::X14 | Self::X15 |
+            Self::X16 | Self::X17 | Self::X18 | Self::X19 | Self::X20 | Self::X21 | Self::X22 | Self::X23 |
+            Self::X24 | Self::X25 | Self::X26 | Self::X27 | Self::X28 | Self::X29 | Self::X30 | Self::LR |
+            Self::W0 | Self::W1 | Self::W2 | Self::W3 | Self::W4 | Self::W5 | Self::W6 | Self::W7 |
+            Self::W8 | Self::W9 | Self::W10 | Self::W11 | Self::W12 | Self::W13 | Self::W14 | Self::W15 |
+            Self::W16 | Self::W17 | Self::W18 | Self::W19 | Self::W20 | Self::W21 | Self::W22 | Self::W23 |
+            Self::W24 | Self::W25 | Self::W26 | Self::W27 | Self::W28 | Self::W29 | Self::W30
         )
     }
 
+    /// V0-V31, at any scalar or vector view.
     fn is_floating_point(&self) -> bool {
+        self.is_vector()
+    }
+
+    fn is_special(&self) -> bool {
         matches!(self,
-            Self::V0 | Self::V1 | Self::V2 | Self::V3 |
-            Self::V4 | Self::V5 | Self::V6 | Self::V7
+            Self::SP | Self::LR | Self::XZR | Self::WSP | Self::WZR
         )
     }
+}
 
-    fn is_special(&self) -> bool {
+impl Arm64Register {
+    /// The 5-bit register number used in instruction encodings.
+    pub fn index(&self) -> u8 {
+        match self {
+            Self::X0 | Self::W0 | Self::V0 => 0,
+            Self::X1 | Self::W1 | Self::V1 => 1,
+            Self::X2 | Self::W2 | Self::V2 => 2,
+            Self::X3 | Self::W3 | Self::V3 => 3,
+            Self::X4 | Self::W4 | Self::V4 => 4,
+            Self::X5 | Self::W5 | Self::V5 => 5,
+            Self::X6 | Self::W6 | Self::V6 => 6,
+            Self::X7 | Self::W7 | Self::V7 => 7,
+            Self::X8 | Self::W8 | Self::V8 => 8,
+            Self::X9 | Self::W9 | Self::V9 => 9,
+            Self::X10 | Self::W10 | Self::V10 => 10,
+            Self::X11 | Self::W11 | Self::V11 => 11,
+            Self::X12 | Self::W12 | Self::V12 => 12,
+            Self::X13 | Self::W13 | Self::V13 => 13,
+            Self::X14 | Self::W14 | Self::V14 => 14,
+            Self::X15 | Self::W15 | Self::V15 => 15,
+            Self::X16 | Self::W16 | Self::V16 => 16,
+            Self::X17 | Self::W17 | Self::V17 => 17,
+            Self::X18 | Self::W18 | Self::V18 => 18,
+            Self::X19 | Self::W19 | Self::V19 => 19,
+            Self::X20 | Self::W20 | Self::V20 => 20,
+            Self::X21 | Self::W21 | Self::V21 => 21,
+            Self::X22 | Self::W22 | Self::V22 => 22,
+            Self::X23 | Self::W23 | Self::V23 => 23,
+            Self::X24 | Self::W24 | Self::V24 => 24,
+            Self::X25 | Self::W25 | Self::V25 => 25,
+            Self::X26 | Self::W26 | Self::V26 => 26,
+            Self::X27 | Self::W27 | Self::V27 => 27,
+            Self::X28 | Self::W28 | Self::V28 => 28,
+            Self::X29 | Self::W29 | Self::V29 => 29,
+            Self::X30 | Self::W30 | Self::V30 | Self::LR => 30,
+            Self::V31 | Self::SP | Self::XZR | Self::WSP | Self::WZR => 31,
+        }
+    }
+
+    /// Whether this is one of the SIMD&FP registers V0-V31.
+    pub(crate) fn is_vector(&self) -> bool {
+        matches!(self,
+            Self::V0 | Self::V1 | Self::V2 | Self::V3 | Self::V4 | Self::V5 | Self::V6 | Self::V7 |
+            Self::V8 | Self::V9 | Self::V10 | Self::V11 | Self::V12 | Self::V13 | Self::V14 | Self::V15 |
+            Self::V16 | Self::V17 | Self::V18 | Self::V19 | Self::V20 | Self::V21 | Self::V22 | Self::V23 |
+            Self::V24 | Self::V25 | Self::V26 | Self::V27 | Self::V28 | Self::V29 | Self::V30 | Self::V31
+        )
+    }
+
+    /// The scalar view of a SIMD&FP register at the given precision.
+    fn fp_name(&self, size: FpSize) -> String {
+        let prefix = match size {
+            FpSize::Half => 'h',
+            FpSize::Single => 's',
+            FpSize::Double => 'd',
+        };
+        format!("{}{}", prefix, self.index())
+    }
+
+    /// The register with number `index + offset`, wrapping at V31.
+    pub(crate) fn vector_offset(&self, offset: u8) -> Self {
+        const VECTORS: [Arm64Register; 32] = [
+            Arm64Register::V0, Arm64Register::V1, Arm64Register::V2, Arm64Register::V3,
+            Arm64Register::V4, Arm64Register::V5, Arm64Register::V6, Arm64Register::V7,
+            Arm64Register::V8, Arm64Register::V9, Arm64Register::V10, Arm64Register::V11,
+            Arm64Register::V12, Arm64Register::V13, Arm64Register::V14, Arm64Register::V15,
+            Arm64Register::V16, Arm64Register::V17, Arm64Register::V18, Arm64Register::V19,
+            Arm64Register::V20, Arm64Register::V21, Arm64Register::V22, Arm64Register::V23,
+            Arm64Register::V24, Arm64Register::V25, Arm64Register::V26, Arm64Register::V27,
+            Arm64Register::V28, Arm64Register::V29, Arm64Register::V30, Arm64Register::V31,
+        ];
+        VECTORS[((self.index() + offset) % 32) as usize]
+    }
+
+    /// Whether this is the 32-bit view of a general purpose register.
+    pub fn is_32bit(&self) -> bool {
         matches!(self,
-            Self::SP | Self::LR | Self::XZR
+            Self::W0 | Self::W1 | Self::W2 | Self::W3 | Self::W4 | Self::W5 | Self::W6 | Self::W7 |
+            Self::W8 | Self::W9 | Self::W10 | Self::W11 | Self::W12 | Self::W13 | Self::W14 | Self::W15 |
+            Self::W16 | Self::W17 | Self::W18 | Self::W19 | Self::W20 | Self::W21 | Self::W22 | Self::W23 |
+            Self::W24 | Self::W25 | Self::W26 | Self::W27 | Self::W28 | Self::W29 | Self::W30 |
+            Self::WSP | Self::WZR
         )
     }
+
+    /// Whether this is the stack pointer, at either width.
+    pub(crate) fn is_sp(&self) -> bool {
+        matches!(self, Self::SP | Self::WSP)
+    }
+
+    /// Whether this is the zero register, at either width.
+    pub(crate) fn is_zero(&self) -> bool {
+        matches!(self, Self::XZR | Self::WZR)
+    }
+
+    /// The 32-bit view of a general purpose register.
+    pub fn to_w(&self) -> Self {
+        const W: [Arm64Register; 31] = [
+            Arm64Register::W0, Arm64Register::W1, Arm64Register::W2, Arm64Register::W3,
+            Arm64Register::W4, Arm64Register::W5, Arm64Register::W6, Arm64Register::W7,
+            Arm64Register::W8, Arm64Register::W9, Arm64Register::W10, Arm64Register::W11,
+            Arm64Register::W12, Arm64Register::W13, Arm64Register::W14, Arm64Register::W15,
+            Arm64Register::W16, Arm64Register::W17, Arm64Register::W18, Arm64Register::W19,
+            Arm64Register::W20, Arm64Register::W21, Arm64Register::W22, Arm64Register::W23,
+            Arm64Register::W24, Arm64Register::W25, Arm64Register::W26, Arm64Register::W27,
+            Arm64Register::W28, Arm64Register::W29, Arm64Register::W30,
+        ];
+        match self {
+            Self::SP | Self::WSP => Self::WSP,
+            Self::XZR | Self::WZR => Self::WZR,
+            _ => W[self.index() as usize],
+        }
+    }
+
+    /// The 64-bit view of a general purpose register.
+    pub fn to_x(&self) -> Self {
+        const X: [Arm64Register; 31] = [
+            Arm64Register::X0, Arm64Register::X1, Arm64Register::X2, Arm64Register::X3,
+            Arm64Register::X4, Arm64Register::X5, Arm64Register::X6, Arm64Register::X7,
+            Arm64Register::X8, Arm64Register::X9, Arm64Register::X10, Arm64Register::X11,
+            Arm64Register::X12, Arm64Register::X13, Arm64Register::X14, Arm64Register::X15,
+            Arm64Register::X16, Arm64Register::X17, Arm64Register::X18, Arm64Register::X19,
+            Arm64Register::X20, Arm64Register::X21, Arm64Register::X22, Arm64Register::X23,
+            Arm64Register::X24, Arm64Register::X25, Arm64Register::X26, Arm64Register::X27,
+            Arm64Register::X28, Arm64Register::X29, Arm64Register::X30,
+        ];
+        match self {
+            Self::SP | Self::WSP => Self::SP,
+            Self::XZR | Self::WZR => Self::XZR,
+            Self::LR => Self::LR,
+            _ => X[self.index() as usize],
+        }
+    }
+
+    /// The 32-bit name of a general purpose register, used by the narrow
+    /// load/store forms (`strb w0, ..`) whatever width it was given at.
+    fn w_name(&self) -> String {
+        self.to_w().to_string()
+    }
 }
 
 impl Display for Arm64Register {
@@ -71,6 +232,37 @@ impl Display for Arm64Register {
             Self::X28 => write!(f, "x28"),
             Self::X29 => write!(f, "x29"),
             Self::X30 => write!(f, "x30"),
+            Self::W0 => write!(f, "w0"),
+            Self::W1 => write!(f, "w1"),
+            Self::W2 => write!(f, "w2"),
+            Self::W3 => write!(f, "w3"),
+            Self::W4 => write!(f, "w4"),
+            Self::W5 => write!(f, "w5"),
+            Self::W6 => write!(f, "w6"),
+            Self::W7 => write!(f, "w7"),
+            Self::W8 => write!(f, "w8"),
+            Self::W9 => write!(f, "w9"),
+            Self::W10 => write!(f, "w10"),
+            Self::W11 => write!(f, "w11"),
+            Self::W12 => write!(f, "w12"),
+            Self::W13 => write!(f, "w13"),
+            Self::W14 => write!(f, "w14"),
+            Self::W15 => write!(f, "w15"),
+            Self::W16 => write!(f, "w16"),
+            Self::W17 => write!(f, "w17"),
+            Self::W18 => write!(f, "w18"),
+            Self::W19 => write!(f, "w19"),
+            Self::W20 => write!(f, "w20"),
+            Self::W21 => write!(f, "w21"),
+            Self::W22 => write!(f, "w22"),
+            Self::W23 => write!(f, "w23"),
+            Self::W24 => write!(f, "w24"),
+            Self::W25 => write!(f, "w25"),
+            Self::W26 => write!(f, "w26"),
+            Self::W27 => write!(f, "w27"),
+            Self::W28 => write!(f, "w28"),
+            Self::W29 => write!(f, "w29"),
+            Self::W30 => write!(f, "w30"),
             Self::V0 => write!(f, "v0"),
             Self::V1 => write!(f, "v1"),
             Self::V2 => write!(f, "v2"),
@@ -106,6 +298,8 @@ impl Display for Arm64Register {
             Self::SP => write!(f, "sp"),
             Self::LR => write!(f, "lr"),
             Self::XZR => write!(f, "xzr"),
+            Self::WSP => write!(f, "wsp"),
+            Self::WZR => write!(f, "wzr"),
         }
     }
 }
@@ -144,6 +338,37 @@ impl From<GenericRegister> for Arm64Register {
             GenericRegister::X28 => Arm64Register::X28,
             GenericRegister::X29 => Arm64Register::X29,
             GenericRegister::X30 => Arm64Register::X30,
+            GenericRegister::W0 => Arm64Register::W0,
+            GenericRegister::W1 => Arm64Register::W1,
+            GenericRegister::W2 => Arm64Register::W2,
+            GenericRegister::W3 => Arm64Register::W3,
+            GenericRegister::W4 => Arm64Register::W4,
+            GenericRegister::W5 => Arm64Register::W5,
+            GenericRegister::W6 => Arm64Register::W6,
+            GenericRegister::W7 => Arm64Register::W7,
+            GenericRegister::W8 => Arm64Register::W8,
+            GenericRegister::W9 => Arm64Register::W9,
+            GenericRegister::W10 => Arm64Register::W10,
+            GenericRegister::W11 => Arm64Register::W11,
+            GenericRegister::W12 => Arm64Register::W12,
+            GenericRegister::W13 => Arm64Register::W13,
+            GenericRegister::W14 => Arm64Register::W14,
+            GenericRegister::W15 => Arm64Register::W15,
+            GenericRegister::W16 => Arm64Register::W16,
+            GenericRegister::W17 => Arm64Register::W17,
+            GenericRegister::W18 => Arm64Register::W18,
+            GenericRegister::W19 => Arm64Register::W19,
+            GenericRegister::W20 => Arm64Register::W20,
+            GenericRegister::W21 => Arm64Register::W21,
+            GenericRegister::W22 => Arm64Register::W22,
+            GenericRegister::W23 => Arm64Register::W23,
+            GenericRegister::W24 => Arm64Register::W24,
+            GenericRegister::W25 => Arm64Register::W25,
+            GenericRegister::W26 => Arm64Register::W26,
+            GenericRegister::W27 => Arm64Register::W27,
+            GenericRegister::W28 => Arm64Register::W28,
+            GenericRegister::W29 => Arm64Register::W29,
+            GenericRegister::W30 => Arm64Register::W30,
             GenericRegister::V0 => Arm64Register::V0,
             GenericRegister::V1 => Arm64Register::V1,
             GenericRegister::V2 => Arm64Register::V2,
@@ -179,7 +404,8 @@ impl From<GenericRegister> for Arm64Register {
             GenericRegister::SP => Arm64Register::SP,
             GenericRegister::LR => Arm64Register::LR,
             GenericRegister::XZR => Arm64Register::XZR,
-            _ => panic!("Invalid register {:?} for ARM64 architecture", reg),
+            GenericRegister::WSP => Arm64Register::WSP,
+            GenericRegister::WZR => Arm64Register::WZR,
         }
     }
 }
@@ -190,67 +416,647 @@ impl RegisterMapping<Arm64Register> for GenericRegister {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Instruction {
+    Label(String),
     Arithmetic(ArithmeticOp),
+    Float(FloatOp),
+    Vector(VectorOp),
+    Logical(LogicalOp),
+    Shift(ShiftOp),
+    Bitfield(BitfieldOp),
+    Bit(BitOp),
     Branch(BranchOp),
     LoadStore(LoadStoreOp),
+    Atomic(AtomicOp),
     System(SystemOp),
     Address(AddressOp),
+    Move(MoveOp),
+    Data(DataDirective),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ArithmeticOp {
     Add { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
     AddImm { dst: Arm64Register, src1: Arm64Register, imm: String },
-    Fadd { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    AddShifted { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, shift: ShiftKind, amount: u8 },
     Sub { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
     Mul { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Sdiv { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Udiv { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Madd { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, acc: Arm64Register },
+    Msub { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, acc: Arm64Register },
+    Mneg { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    /// Widening multiplies read the 32-bit views of `src1` and `src2`
+    Smull { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Umull { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Smulh { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Umulh { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Smaddl { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, acc: Arm64Register },
+    Umaddl { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, acc: Arm64Register },
+    Cmp { src1: Arm64Register, src2: Arm64Register },
+    CmpImm { src1: Arm64Register, imm: String },
+    CmpShifted { src1: Arm64Register, src2: Arm64Register, shift: ShiftKind, amount: u8 },
+    Cset { dst: Arm64Register, cond: Condition },
+    Csel { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, cond: Condition },
+}
+
+/// Scalar floating-point operations. Register operands are V registers
+/// rendered through the `h`/`s`/`d` view selected by `size`, except for the
+/// general purpose side of moves and conversions.
+#[derive(Debug, Clone)]
+pub enum FloatOp {
+    Fadd { size: FpSize, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Fsub { size: FpSize, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Fmul { size: FpSize, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Fdiv { size: FpSize, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Fsqrt { size: FpSize, dst: Arm64Register, src: Arm64Register },
+    Fneg { size: FpSize, dst: Arm64Register, src: Arm64Register },
+    Fabs { size: FpSize, dst: Arm64Register, src: Arm64Register },
+    Fmadd { size: FpSize, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, acc: Arm64Register },
+    Fcmp { size: FpSize, src1: Arm64Register, src2: Arm64Register },
+    Fcsel { size: FpSize, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, cond: Condition },
+    /// FP register to FP register
+    Fmov { size: FpSize, dst: Arm64Register, src: Arm64Register },
+    /// General purpose register to FP register
+    FmovToFp { size: FpSize, dst: Arm64Register, src: Arm64Register },
+    /// FP register to general purpose register
+    FmovFromFp { size: FpSize, dst: Arm64Register, src: Arm64Register },
+    /// An immediate accepted by [`imm::encode_fp_imm`]
+    FmovImm { size: FpSize, dst: Arm64Register, imm: f64 },
+    Scvtf { size: FpSize, dst: Arm64Register, src: Arm64Register },
+    Ucvtf { size: FpSize, dst: Arm64Register, src: Arm64Register },
+    Fcvtzs { size: FpSize, dst: Arm64Register, src: Arm64Register },
+    Fcvtzu { size: FpSize, dst: Arm64Register, src: Arm64Register },
+    Fcvt { dst_size: FpSize, dst: Arm64Register, src_size: FpSize, src: Arm64Register },
+}
+
+/// NEON operations. `first`/`count` name a list of consecutive registers,
+/// wrapping from V31 to V0.
+#[derive(Debug, Clone)]
+pub enum VectorOp {
+    Add { arrangement: VectorArrangement, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Sub { arrangement: VectorArrangement, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Mul { arrangement: VectorArrangement, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Fadd { arrangement: VectorArrangement, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Fsub { arrangement: VectorArrangement, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Fmul { arrangement: VectorArrangement, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Ld1 { arrangement: VectorArrangement, first: Arm64Register, count: u8, addr: MemOperand<Arm64Register> },
+    St1 { arrangement: VectorArrangement, first: Arm64Register, count: u8, addr: MemOperand<Arm64Register> },
+    Dup { arrangement: VectorArrangement, dst: Arm64Register, src: Arm64Register },
+    DupLane { arrangement: VectorArrangement, dst: Arm64Register, src: Arm64Register, index: u8 },
+    Ins { lane: MemSize, dst: Arm64Register, index: u8, src: Arm64Register },
+    Umov { lane: MemSize, dst: Arm64Register, src: Arm64Register, index: u8 },
+    Addv { arrangement: VectorArrangement, dst: Arm64Register, src: Arm64Register },
+}
+
+/// Register forms carry an optional constant shift of `src2`.
+#[derive(Debug, Clone)]
+pub enum LogicalOp {
+    And { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, shift: Option<(ShiftKind, u8)> },
+    Orr { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, shift: Option<(ShiftKind, u8)> },
+    Eor { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, shift: Option<(ShiftKind, u8)> },
+    Bic { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, shift: Option<(ShiftKind, u8)> },
+    Orn { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, shift: Option<(ShiftKind, u8)> },
+    Eon { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, shift: Option<(ShiftKind, u8)> },
+    AndImm { dst: Arm64Register, src: Arm64Register, imm: u64 },
+    OrrImm { dst: Arm64Register, src: Arm64Register, imm: u64 },
+    EorImm { dst: Arm64Register, src: Arm64Register, imm: u64 },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+pub enum ShiftOp {
+    LslImm { dst: Arm64Register, src: Arm64Register, amount: u8 },
+    LsrImm { dst: Arm64Register, src: Arm64Register, amount: u8 },
+    AsrImm { dst: Arm64Register, src: Arm64Register, amount: u8 },
+    RorImm { dst: Arm64Register, src: Arm64Register, amount: u8 },
+    Lsl { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Lsr { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Asr { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+    Ror { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register },
+}
+
+#[derive(Debug, Clone)]
+pub enum BitfieldOp {
+    Ubfx { dst: Arm64Register, src: Arm64Register, lsb: u8, width: u8 },
+    Sbfx { dst: Arm64Register, src: Arm64Register, lsb: u8, width: u8 },
+    Bfi { dst: Arm64Register, src: Arm64Register, lsb: u8, width: u8 },
+    Ubfiz { dst: Arm64Register, src: Arm64Register, lsb: u8, width: u8 },
+    Extr { dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, lsb: u8 },
+}
+
+#[derive(Debug, Clone)]
+pub enum BitOp {
+    Clz { dst: Arm64Register, src: Arm64Register },
+    Cls { dst: Arm64Register, src: Arm64Register },
+    Rbit { dst: Arm64Register, src: Arm64Register },
+    Rev { dst: Arm64Register, src: Arm64Register },
+    Rev16 { dst: Arm64Register, src: Arm64Register },
+    Rev32 { dst: Arm64Register, src: Arm64Register },
+}
+
+#[derive(Debug, Clone)]
 pub enum BranchOp {
     Bl { label: String },
     B { label: String },
     Ret,
     Cbz { reg: Arm64Register, label: String },
+    Cbnz { reg: Arm64Register, label: String },
+    BCond { cond: Condition, label: String },
+    Br { reg: Arm64Register },
+    Blr { reg: Arm64Register },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum LoadStoreOp {
     Ldr { dst: Arm64Register, src: String },
     Str { src: Arm64Register, dst: String },
+    Load { size: MemSize, signed: bool, dst: Arm64Register, addr: MemOperand<Arm64Register> },
+    Store { size: MemSize, src: Arm64Register, addr: MemOperand<Arm64Register> },
+    Ldp { dst1: Arm64Register, dst2: Arm64Register, addr: MemOperand<Arm64Register> },
+    Stp { src1: Arm64Register, src2: Arm64Register, addr: MemOperand<Arm64Register> },
+}
+
+/// Exclusive and LSE atomic accesses. The address is always a bare base
+/// register.
+#[derive(Debug, Clone)]
+pub enum AtomicOp {
+    Ldxr { size: MemSize, acquire: bool, dst: Arm64Register, addr: Arm64Register },
+    Stxr { size: MemSize, release: bool, status: Arm64Register, src: Arm64Register, addr: Arm64Register },
+    Ldadd { order: MemoryOrder, size: MemSize, src: Arm64Register, dst: Arm64Register, addr: Arm64Register },
+    Swp { order: MemoryOrder, size: MemSize, src: Arm64Register, dst: Arm64Register, addr: Arm64Register },
+    Cas { order: MemoryOrder, size: MemSize, expected: Arm64Register, new: Arm64Register, addr: Arm64Register },
 }
 
-#[derive(Debug)]
+/// System registers reachable with `mrs`/`msr`. [`Self::Raw`] names any
+/// other register by its encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SystemRegister {
+    // EL0 accessible
+    TpidrEl0,
+    CntvctEl0,
+    CntfrqEl0,
+    Nzcv,
+    Daif,
+    Fpcr,
+    Fpsr,
+    // EL1 control and exception state
+    CurrentEl,
+    SctlrEl1,
+    VbarEl1,
+    ElrEl1,
+    SpsrEl1,
+    EsrEl1,
+    FarEl1,
+    SpEl0,
+    // EL2 state used when dropping to EL1
+    HcrEl2,
+    ElrEl2,
+    SpsrEl2,
+    SpEl1,
+    Raw { op0: u8, op1: u8, crn: u8, crm: u8, op2: u8 },
+}
+
+impl SystemRegister {
+    /// The `(op0, op1, CRn, CRm, op2)` fields selecting the register.
+    pub fn encoding(&self) -> (u8, u8, u8, u8, u8) {
+        match *self {
+            Self::TpidrEl0 => (3, 3, 13, 0, 2),
+            Self::CntvctEl0 => (3, 3, 14, 0, 2),
+            Self::CntfrqEl0 => (3, 3, 14, 0, 0),
+            Self::Nzcv => (3, 3, 4, 2, 0),
+            Self::Daif => (3, 3, 4, 2, 1),
+            Self::Fpcr => (3, 3, 4, 4, 0),
+            Self::Fpsr => (3, 3, 4, 4, 1),
+            Self::CurrentEl => (3, 0, 4, 2, 2),
+            Self::SctlrEl1 => (3, 0, 1, 0, 0),
+            Self::VbarEl1 => (3, 0, 12, 0, 0),
+            Self::ElrEl1 => (3, 0, 4, 0, 1),
+            Self::SpsrEl1 => (3, 0, 4, 0, 0),
+            Self::EsrEl1 => (3, 0, 5, 2, 0),
+            Self::FarEl1 => (3, 0, 6, 0, 0),
+            Self::SpEl0 => (3, 0, 4, 1, 0),
+            Self::HcrEl2 => (3, 4, 1, 1, 0),
+            Self::ElrEl2 => (3, 4, 4, 0, 1),
+            Self::SpsrEl2 => (3, 4, 4, 0, 0),
+            Self::SpEl1 => (3, 4, 4, 1, 0),
+            Self::Raw { op0, op1, crn, crm, op2 } => (op0, op1, crn, crm, op2),
+        }
+    }
+
+    /// Whether `msr` may write the register. The counter and the current
+    /// exception level are read-only; raw encodings are taken on trust.
+    pub fn is_writable(&self) -> bool {
+        !matches!(self, Self::CntvctEl0 | Self::CurrentEl)
+    }
+}
+
+impl Display for SystemRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::TpidrEl0 => "tpidr_el0",
+            Self::CntvctEl0 => "cntvct_el0",
+            Self::CntfrqEl0 => "cntfrq_el0",
+            Self::Nzcv => "nzcv",
+            Self::Daif => "daif",
+            Self::Fpcr => "fpcr",
+            Self::Fpsr => "fpsr",
+            Self::CurrentEl => "currentel",
+            Self::SctlrEl1 => "sctlr_el1",
+            Self::VbarEl1 => "vbar_el1",
+            Self::ElrEl1 => "elr_el1",
+            Self::SpsrEl1 => "spsr_el1",
+            Self::EsrEl1 => "esr_el1",
+            Self::FarEl1 => "far_el1",
+            Self::SpEl0 => "sp_el0",
+            Self::HcrEl2 => "hcr_el2",
+            Self::ElrEl2 => "elr_el2",
+            Self::SpsrEl2 => "spsr_el2",
+            Self::SpEl1 => "sp_el1",
+            Self::Raw { op0, op1, crn, crm, op2 } => return write!(f, "s{}_{}_c{}_c{}_{}", op0, op1, crn, crm, op2),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum SystemOp {
     Svc { number: u32 },
-    Msr { dst: String, src: Arm64Register },
+    Msr { dst: SystemRegister, src: Arm64Register },
+    Mrs { dst: Arm64Register, src: SystemRegister },
+    /// System instruction with op0 = 1, such as the cache and TLB
+    /// maintenance operations
+    Sys { op1: u8, crn: u8, crm: u8, op2: u8, src: Arm64Register },
+    Sysl { dst: Arm64Register, op1: u8, crn: u8, crm: u8, op2: u8 },
+    /// Exception return to the address and state in ELR/SPSR of the
+    /// current exception level
+    Eret,
+    Dmb { domain: BarrierDomain },
+    Dsb { domain: BarrierDomain },
+    Isb,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AddressOp {
+    Adr { dst: Arm64Register, label: String },
     Adrp { dst: Arm64Register, label: String },
     AdrpAdd { dst: Arm64Register, base: Arm64Register, label: String },
+    /// Loads the address of `label` from its GOT entry, `label@GOT`
+    AdrpGot { dst: Arm64Register, label: String },
+}
+
+#[derive(Debug, Clone)]
+pub enum MoveOp {
+    /// Moves `imm << shift` into `dst`, zeroing the other bits
+    Movz { dst: Arm64Register, imm: u16, shift: u8 },
+    /// Moves the complement of `imm << shift` into `dst`
+    Movn { dst: Arm64Register, imm: u16, shift: u8 },
+    /// Replaces bits `shift..shift + 16` of `dst`, keeping the others
+    Movk { dst: Arm64Register, imm: u16, shift: u8 },
+}
+
+#[derive(Debug, Clone)]
+pub enum DataDirective {
+    /// `.p2align n`
+    Align(u32),
+    Byte(Vec<u8>),
+    Hword(u16),
+    Word(i32),
+    Quad(u64),
+    /// `.word target - base`, a 32-bit label difference
+    LabelDiff { target: String, base: String },
+    /// A NUL-terminated string
+    Asciz(String),
+    /// `.zero n`
+    Zero(u64),
+    /// `.quad symbol+offset`, an absolute address
+    Pointer { symbol: String, offset: i64 },
 }
 
+#[derive(Clone)]
 pub struct ARM64 {
-    instructions: Vec<Instruction>,
+    instructions: Vec<Emitted<Instruction>>,
+    rodata: Vec<Instruction>,
+    /// Size in bytes of `instructions`
+    text_size: u64,
+    literal_pool: LiteralPool,
+    /// Metadata for the next instruction pushed
+    pending_comment: Option<String>,
+    pending_tags: Vec<String>,
+    /// Operands rejected while building the next instruction
+    pending_errors: Vec<Rejected>,
+    errors: Vec<BuildError>,
+}
+
+/// The text instructions of an [`ARM64`] without their metadata, borrowed
+/// from its [`Emitted`] instructions.
+#[derive(Clone, Copy)]
+pub struct Instructions<'a>(&'a [Emitted<Instruction>]);
+
+impl<'a> Instructions<'a> {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a Instruction> + 'a {
+        self.0.iter().map(|emitted| &emitted.inst)
+    }
+}
+
+impl std::ops::Index<usize> for Instructions<'_> {
+    type Output = Instruction;
+
+    fn index(&self, index: usize) -> &Instruction {
+        &self.0[index].inst
+    }
+}
+
+/// An operand the builders could not use, reported as a [`BuildError`] once
+/// the instruction it belongs to is pushed and its site is known.
+#[derive(Clone)]
+enum Rejected {
+    Immediate(String),
+    Operand { operand: String, expected: &'static str },
+    ReadOnly(SystemRegister),
+}
+
+impl Rejected {
+    fn at(self, site: Site) -> BuildError {
+        match self {
+            Rejected::Immediate(value) => BuildError::ImmediateOutOfRange { site, value },
+            Rejected::Operand { operand, expected } => BuildError::InvalidRegisterClass { site, operand, expected },
+            Rejected::ReadOnly(register) => BuildError::ReadOnlyRegister { site, register: register.to_string() },
+        }
+    }
+}
+
+/// Constants loaded with `ldr` (literal) that have not been placed yet.
+#[derive(Default, Clone)]
+struct LiteralPool {
+    entries: Vec<(String, u64)>,
+    /// Offset of the earliest `ldr` that refers to a pending entry
+    first_use: Option<u64>,
+    label_counter: usize,
 }
 
 impl ARM64 {
     pub fn new() -> Self {
-        Self { instructions: Vec::new() }
+        Self {
+            instructions: Vec::new(),
+            rodata: Vec::new(),
+            text_size: 0,
+            literal_pool: LiteralPool::default(),
+            pending_comment: None,
+            pending_tags: Vec::new(),
+            pending_errors: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Places pending literals at the current position, branching around
+    /// them unless the previous instruction never falls through.
+    pub fn flush_literal_pool(&mut self) {
+        if self.literal_pool.entries.is_empty() {
+            return;
+        }
+        let falls_through = !matches!(
+            self.instructions.last().map(|emitted| &emitted.inst),
+            Some(Instruction::Branch(BranchOp::B { .. } | BranchOp::Br { .. } | BranchOp::Ret))
+        );
+        let skip_label = format!("Lpoolskip{}", self.literal_pool.label_counter);
+        self.literal_pool.label_counter += 1;
+        if falls_through {
+            self.push_raw(Instruction::Branch(BranchOp::B { label: skip_label.clone() }));
+        }
+        self.push_raw(Instruction::Data(DataDirective::Align(3)));
+        for (label, value) in std::mem::take(&mut self.literal_pool.entries) {
+            self.push_raw(Instruction::Label(label));
+            self.push_raw(Instruction::Data(DataDirective::Quad(value)));
+        }
+        self.literal_pool.first_use = None;
+        if falls_through {
+            self.push_raw(Instruction::Label(skip_label));
+        }
+    }
+
+    /// Literals that will be placed by the next [`Self::flush_literal_pool`].
+    pub fn pending_literals(&self) -> &[(String, u64)] {
+        &self.literal_pool.entries
+    }
+
+    /// The pending literals as they are placed after the last instruction.
+    pub(crate) fn trailing_pool(&self) -> Vec<Instruction> {
+        let mut pool = Vec::new();
+        if !self.literal_pool.entries.is_empty() {
+            pool.push(Instruction::Data(DataDirective::Align(3)));
+            for (label, value) in &self.literal_pool.entries {
+                pool.push(Instruction::Label(label.clone()));
+                pool.push(Instruction::Data(DataDirective::Quad(*value)));
+            }
+        }
+        pool
+    }
+
+    /// Appends `inst` with the pending comment and tags and the location of
+    /// the generator code that emitted it. Operands rejected while building
+    /// `inst`, or of the wrong register class, are recorded as errors at its
+    /// site.
+    #[track_caller]
+    fn push(&mut self, inst: Instruction) {
+        if let Some(first_use) = self.literal_pool.first_use {
+            // Worst case: this instruction, the branch around the pool,
+            // alignment padding and the entries themselves
+            let pool_end = self.text_size + 4 + 4 + 4 + 8 * self.literal_pool.entries.len() as u64;
+            if pool_end - first_use >= LITERAL_RANGE {
+                self.flush_literal_pool();
+            }
+        }
+        let emitted = Emitted {
+            inst,
+            comment: self.pending_comment.take(),
+            source_location: Some(Location::caller()),
+            tags: std::mem::take(&mut self.pending_tags),
+        };
+        let site = Site { index: self.instructions.len(), location: emitted.source_location };
+        let rejected = std::mem::take(&mut self.pending_errors);
+        // A placeholder for a rejected operand would be reported twice
+        if rejected.is_empty() {
+            if let Some((reg, kind)) = validate::misclassified(&emitted.inst) {
+                self.errors.push(BuildError::InvalidRegisterClass {
+                    site,
+                    operand: reg.to_string(),
+                    expected: kind.description(),
+                });
+            }
+        }
+        for rejected in rejected {
+            self.errors.push(rejected.at(site));
+        }
+        self.push_emitted(emitted);
+    }
+
+    /// Appends an instruction the backend generated itself, such as a
+    /// literal pool entry.
+    fn push_raw(&mut self, inst: Instruction) {
+        self.push_emitted(Emitted::new(inst));
+    }
+
+    fn push_emitted(&mut self, emitted: Emitted<Instruction>) {
+        self.text_size = layout::advance(self.text_size, &emitted.inst);
+        self.instructions.push(emitted);
+    }
+
+    /// Loads `imm` from the literal pool, sharing entries for equal values.
+    #[track_caller]
+    fn ldr_literal(&mut self, dst: Arm64Register, imm: u64) {
+        let label = match self.literal_pool.entries.iter().find(|(_, value)| *value == imm) {
+            Some((label, _)) => label.clone(),
+            None => {
+                let label = format!("Llit{}", self.literal_pool.label_counter);
+                self.literal_pool.label_counter += 1;
+                self.literal_pool.entries.push((label.clone(), imm));
+                label
+            }
+        };
+        self.push(Instruction::LoadStore(LoadStoreOp::Ldr { dst, src: label }));
+        if self.literal_pool.first_use.is_none() {
+            self.literal_pool.first_use = Some(self.text_size - 4);
+        }
+    }
+
+    pub fn get_instructions(&self) -> Instructions<'_> {
+        Instructions(&self.instructions)
     }
 
-    pub fn get_instructions(&self) -> &[Instruction] {
+    /// The text instructions with their comments, tags and call sites.
+    pub fn emitted(&self) -> &[Emitted<Instruction>] {
         &self.instructions
     }
+
+    /// Problems the builders found in their operands, in emission order.
+    /// The offending instructions are still emitted with a placeholder
+    /// operand so later indices stay meaningful.
+    pub fn build_errors(&self) -> &[BuildError] {
+        &self.errors
+    }
+
+    /// The listing with each instruction's comment and the generator call
+    /// site that emitted it, for tracing output back to its source.
+    pub fn annotated_listing(&self) -> String {
+        let mut out = String::new();
+        self.write_text(&mut out, &MacOS, true).unwrap();
+        out
+    }
+
+    /// Labels and data placed in the read-only data section.
+    pub fn get_rodata(&self) -> &[Instruction] {
+        &self.rodata
+    }
+
+    /// Targets of `bl` with no label in the program, in order of first use.
+    pub fn undefined_calls(&self) -> Vec<String> {
+        let defined = self.defined_labels();
+        let mut calls: Vec<String> = Vec::new();
+        for emitted in &self.instructions {
+            if let Instruction::Branch(BranchOp::Bl { label }) = &emitted.inst {
+                if !defined.contains(label) && !calls.contains(label) {
+                    calls.push(label.clone());
+                }
+            }
+        }
+        calls
+    }
+
+    /// Labels loaded through the GOT, in order of first use.
+    pub fn got_references(&self) -> Vec<String> {
+        let mut labels: Vec<String> = Vec::new();
+        for emitted in &self.instructions {
+            if let Instruction::Address(AddressOp::AdrpGot { label, .. }) = &emitted.inst {
+                if !labels.contains(label) {
+                    labels.push(label.clone());
+                }
+            }
+        }
+        labels
+    }
+
+    /// Every label the text and read-only data define, pending literals
+    /// included.
+    pub fn defined_labels(&self) -> Vec<String> {
+        let mut labels: Vec<String> = self
+            .instructions
+            .iter()
+            .map(|emitted| &emitted.inst)
+            .chain(&self.rodata)
+            .filter_map(|inst| match inst {
+                Instruction::Label(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+        labels.extend(self.literal_pool.entries.iter().map(|(label, _)| label.clone()));
+        labels
+    }
+
+    /// Renames labels everywhere they are defined or referred to, leaving
+    /// those `rename` returns `None` for.
+    pub(crate) fn relabel(&mut self, rename: &dyn Fn(&str) -> Option<String>) {
+        for emitted in &mut self.instructions {
+            emitted.inst.relabel(rename);
+        }
+        for inst in &mut self.rodata {
+            inst.relabel(rename);
+        }
+        for (label, _) in &mut self.literal_pool.entries {
+            if let Some(name) = rename(label) {
+                *label = name;
+            }
+        }
+    }
+
+    /// Renames the definition of `label` alone, so references still reach
+    /// whichever definition keeps the name.
+    pub(crate) fn rename_definition(&mut self, label: &str, name: &str) {
+        let defined = self.instructions.iter_mut().map(|emitted| &mut emitted.inst).chain(&mut self.rodata);
+        for inst in defined {
+            if matches!(inst, Instruction::Label(existing) if existing == label) {
+                *inst = Instruction::Label(name.to_string());
+            }
+        }
+    }
+
+    /// Appends the text and read-only data of `other`, placing the literals
+    /// either program still has pending first.
+    pub(crate) fn append(&mut self, mut other: ARM64) {
+        self.place_trailing_pool();
+        other.place_trailing_pool();
+        if !self.text_size.is_multiple_of(4) {
+            self.push_raw(Instruction::Data(DataDirective::Align(2)));
+        }
+        for emitted in other.instructions {
+            self.push_emitted(emitted);
+        }
+        self.rodata.extend(other.rodata);
+    }
+
+    /// Places pending literals where the listing would, after the last
+    /// instruction.
+    fn place_trailing_pool(&mut self) {
+        for inst in self.trailing_pool() {
+            self.push_raw(inst);
+        }
+        self.literal_pool.entries.clear();
+        self.literal_pool.first_use = None;
+    }
 }
 
 impl ARM64 {
+    #[track_caller]
     pub fn add(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) {
-        self.instructions.push(Instruction::Arithmetic(
+        self.push(Instruction::Arithmetic(
             ArithmeticOp::Add { 
                 dst: dst.to_arch_reg(), 
                 src1: src1.to_arch_reg(), 
@@ -261,55 +1067,266 @@ impl ARM64 {
 }
 
 impl BranchBuilder<Arm64Register> for ARM64 {
+    #[track_caller]
     fn bl(&mut self, label: &str) {
-        self.instructions.push(Instruction::Branch(
+        self.push(Instruction::Branch(
             BranchOp::Bl { label: label.to_string() }
         ));
     }
 
+    #[track_caller]
     fn b(&mut self, label: &str) {
-        self.instructions.push(Instruction::Branch(
+        self.push(Instruction::Branch(
             BranchOp::B { label: label.to_string() }
         ));
     }
 
+    #[track_caller]
     fn ret(&mut self) {
-        self.instructions.push(Instruction::Branch(BranchOp::Ret));
+        self.push(Instruction::Branch(BranchOp::Ret));
     }
 
+    #[track_caller]
     fn cbz(&mut self, reg: Arm64Register, label: &str) {
-        self.instructions.push(Instruction::Branch(
+        self.push(Instruction::Branch(
             BranchOp::Cbz { reg, label: label.to_string() }
         ));
     }
+
+    #[track_caller]
+    fn cbnz(&mut self, reg: Arm64Register, label: &str) {
+        self.push(Instruction::Branch(
+            BranchOp::Cbnz { reg, label: label.to_string() }
+        ));
+    }
+
+    #[track_caller]
+    fn b_cond(&mut self, cond: Condition, label: &str) {
+        self.push(Instruction::Branch(
+            BranchOp::BCond { cond, label: label.to_string() }
+        ));
+    }
+
+    #[track_caller]
+    fn br(&mut self, reg: Arm64Register) {
+        self.push(Instruction::Branch(BranchOp::Br { reg }));
+    }
+
+    #[track_caller]
+    fn blr(&mut self, reg: Arm64Register) {
+        self.push(Instruction::Branch(BranchOp::Blr { reg }));
+    }
+}
+
+impl JumpTableBuilder for ARM64 {
+    fn jump_table(&mut self, table: &str, targets: &[String]) {
+        self.rodata.push(Instruction::Data(DataDirective::Align(2)));
+        self.rodata.push(Instruction::Label(table.to_string()));
+        for target in targets {
+            self.rodata.push(Instruction::Data(
+                DataDirective::LabelDiff { target: target.clone(), base: table.to_string() }
+            ));
+        }
+    }
+}
+
+impl ARM64 {
+    /// Places a NUL-terminated string at `label` in read-only data.
+    pub fn asciz(&mut self, label: &str, value: &str) {
+        self.rodata.push(Instruction::Label(label.to_string()));
+        self.rodata.push(Instruction::Data(DataDirective::Asciz(value.to_string())));
+    }
+
+    /// Places `value` at `label` in read-only data, aligned to `align`
+    /// bytes.
+    pub fn global(&mut self, label: &str, value: &Value, align: u64) {
+        if align > 1 {
+            self.rodata.push(Instruction::Data(DataDirective::Align(align.trailing_zeros())));
+        }
+        self.rodata.push(Instruction::Label(label.to_string()));
+        let directives = match value {
+            Value::Asciz(value) => vec![DataDirective::Asciz(value.clone())],
+            Value::Byte(items) => vec![DataDirective::Byte(items.clone())],
+            Value::Hword(items) => items.iter().map(|item| DataDirective::Hword(*item)).collect(),
+            Value::Word(items) => items.iter().map(|item| DataDirective::Word(*item as i32)).collect(),
+            Value::Quad(items) => items.iter().map(|item| DataDirective::Quad(*item)).collect(),
+            Value::Float(items) => items.iter().map(|item| DataDirective::Word(item.to_bits() as i32)).collect(),
+            Value::Double(items) => items.iter().map(|item| DataDirective::Quad(item.to_bits())).collect(),
+            Value::Zero(size) => vec![DataDirective::Zero(*size)],
+            Value::Pointer { symbol, offset } => vec![DataDirective::Pointer { symbol: symbol.clone(), offset: *offset }],
+        };
+        self.rodata.extend(directives.into_iter().map(Instruction::Data));
+    }
+}
+
+impl Backend for ARM64 {
+    type Register = Arm64Register;
+}
+
+impl AnnotationBuilder for ARM64 {
+    fn comment(&mut self, comment: &str) {
+        self.pending_comment = Some(comment.to_string());
+    }
+
+    fn tag(&mut self, tag: &str) {
+        self.pending_tags.push(tag.to_string());
+    }
+}
+
+impl LabelBuilder for ARM64 {
+    #[track_caller]
+    fn label(&mut self, name: &str) {
+        self.push(Instruction::Label(name.to_string()));
+    }
+}
+
+impl CompareBuilder<Arm64Register> for ARM64 {
+    #[track_caller]
+    fn cmp(&mut self, src1: Arm64Register, src2: Operand<Arm64Register>) {
+        match src2 {
+            Operand::Register(src2) => {
+                self.push(Instruction::Arithmetic(
+                    ArithmeticOp::Cmp { src1, src2 }
+                ));
+            },
+            Operand::Immediate(imm) => {
+                self.push(Instruction::Arithmetic(
+                    ArithmeticOp::CmpImm { src1, imm }
+                ));
+            },
+            Operand::Shifted(src2, shift, amount) => {
+                self.push(Instruction::Arithmetic(
+                    ArithmeticOp::CmpShifted { src1, src2, shift, amount }
+                ));
+            }
+        }
+    }
+
+    #[track_caller]
+    fn cset(&mut self, dst: Arm64Register, cond: Condition) {
+        self.push(Instruction::Arithmetic(
+            ArithmeticOp::Cset { dst, cond }
+        ));
+    }
+
+    #[track_caller]
+    fn csel(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, cond: Condition) {
+        self.push(Instruction::Arithmetic(
+            ArithmeticOp::Csel { dst, src1, src2, cond }
+        ));
+    }
 }
 
 impl LoadStoreBuilder<Arm64Register> for ARM64 {
+    #[track_caller]
     fn str(&mut self, src: Arm64Register, addr: &str) {
-        self.instructions.push(Instruction::LoadStore(
+        self.push(Instruction::LoadStore(
             LoadStoreOp::Str { src, dst: addr.to_string() }
         ));
     }
 
+    #[track_caller]
     fn ldr(&mut self, dst: Arm64Register, addr: &str) {
-        self.instructions.push(Instruction::LoadStore(
+        self.push(Instruction::LoadStore(
             LoadStoreOp::Ldr { dst, src: addr.to_string() }
         ));
     }
+
+    #[track_caller]
+    fn load(&mut self, size: MemSize, signed: bool, dst: Arm64Register, addr: MemOperand<Arm64Register>) {
+        self.push(Instruction::LoadStore(
+            LoadStoreOp::Load { size, signed, dst, addr }
+        ));
+    }
+
+    #[track_caller]
+    fn store(&mut self, size: MemSize, src: Arm64Register, addr: MemOperand<Arm64Register>) {
+        self.push(Instruction::LoadStore(
+            LoadStoreOp::Store { size, src, addr }
+        ));
+    }
+
+    #[track_caller]
+    fn ldp(&mut self, dst1: Arm64Register, dst2: Arm64Register, addr: MemOperand<Arm64Register>) {
+        self.push(Instruction::LoadStore(
+            LoadStoreOp::Ldp { dst1, dst2, addr }
+        ));
+    }
+
+    #[track_caller]
+    fn stp(&mut self, src1: Arm64Register, src2: Arm64Register, addr: MemOperand<Arm64Register>) {
+        self.push(Instruction::LoadStore(
+            LoadStoreOp::Stp { src1, src2, addr }
+        ));
+    }
 }
 
 impl MovBuilder<Arm64Register> for ARM64 {
+    #[track_caller]
     fn mov(&mut self, dst: Arm64Register, src: Arm64Register) {
         // For ARM64, mov is actually an alias for orr with XZR
-        self.instructions.push(Instruction::Arithmetic(
-            ArithmeticOp::Add { dst, src1: src, src2: Arm64Register::XZR }
+        let zero = if dst.is_32bit() { Arm64Register::WZR } else { Arm64Register::XZR };
+        self.push(Instruction::Arithmetic(
+            ArithmeticOp::Add { dst, src1: src, src2: zero }
         ));
     }
+
+    /// Picks the shortest of a `movz`/`movn` + `movk` sequence, a single
+    /// `orr` with a logical immediate, or a literal-pool load. The pool costs
+    /// one instruction plus eight bytes of data, so it only wins over four
+    /// moves. A W destination takes the low 32 bits of `imm`.
+    #[track_caller]
+    fn mov_imm(&mut self, dst: Arm64Register, imm: u64) {
+        let (width, zero) = if dst.is_32bit() { (32, Arm64Register::WZR) } else { (64, Arm64Register::XZR) };
+        let imm = if width == 32 { imm & 0xffff_ffff } else { imm };
+        let ones = if width == 32 { 0xffff_ffff } else { u64::MAX };
+        let halfwords: Vec<u16> = (0..width / 16).map(|i| (imm >> (16 * i)) as u16).collect();
+        let movz_len = halfwords.iter().filter(|hw| **hw != 0).count().max(1);
+        let movn_len = halfwords.iter().filter(|hw| **hw != 0xffff).count().max(1);
+
+        if movz_len.min(movn_len) > 1 && imm::is_logical_imm(imm, width) {
+            self.push(Instruction::Logical(
+                LogicalOp::OrrImm { dst, src: zero, imm }
+            ));
+        } else if movz_len.min(movn_len) == 4 {
+            self.ldr_literal(dst, imm);
+        } else if movz_len <= movn_len {
+            let mut first = true;
+            for (i, hw) in halfwords.iter().enumerate() {
+                let shift = 16 * i as u8;
+                if first && (*hw != 0 || (imm == 0 && i == 0)) {
+                    self.push(Instruction::Move(MoveOp::Movz { dst, imm: *hw, shift }));
+                    first = false;
+                } else if !first && *hw != 0 {
+                    self.push(Instruction::Move(MoveOp::Movk { dst, imm: *hw, shift }));
+                }
+            }
+        } else {
+            let mut first = true;
+            for (i, hw) in halfwords.iter().enumerate() {
+                let shift = 16 * i as u8;
+                if first && (*hw != 0xffff || (imm == ones && i == 0)) {
+                    self.push(Instruction::Move(MoveOp::Movn { dst, imm: !*hw, shift }));
+                    first = false;
+                } else if !first && *hw != 0xffff {
+                    self.push(Instruction::Move(MoveOp::Movk { dst, imm: *hw, shift }));
+                }
+            }
+        }
+    }
 }
 
 impl AddressBuilder<Arm64Register> for ARM64 {
+    #[track_caller]
+    fn adr(&mut self, dst: Arm64Register, label: &str) {
+        self.push(Instruction::Address(
+            AddressOp::Adr { dst, label: label.to_string() }
+        ));
+    }
+
+    #[track_caller]
     fn adrp(&mut self, dst: Arm64Register, label: &str) {
-        self.instructions.push(Instruction::Address(
+        self.push(Instruction::Address(
             AddressOp::Adrp { 
                 dst, 
                 label: label.to_string() 
@@ -317,8 +1334,9 @@ impl AddressBuilder<Arm64Register> for ARM64 {
         ));
     }
 
+    #[track_caller]
     fn adrp_add(&mut self, dst: Arm64Register, base: Arm64Register, label: &str) {
-        self.instructions.push(Instruction::Address(
+        self.push(Instruction::Address(
             AddressOp::AdrpAdd { 
                 dst, 
                 base,
@@ -326,59 +1344,1313 @@ impl AddressBuilder<Arm64Register> for ARM64 {
             }
         ));
     }
+
+    #[track_caller]
+    fn adrp_got(&mut self, dst: Arm64Register, label: &str) {
+        self.push(Instruction::Address(
+            AddressOp::AdrpGot { dst, label: label.to_string() }
+        ));
+    }
 }
 
 impl ArithmeticBuilder<Arm64Register> for ARM64 {
+    #[track_caller]
     fn add(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Operand<Arm64Register>) {
         match src2 {
             Operand::Register(reg) => {
-                self.instructions.push(Instruction::Arithmetic(
+                self.push(Instruction::Arithmetic(
                     ArithmeticOp::Add { dst, src1, src2: reg }
                 ));
             },
             Operand::Immediate(imm) => {
-                self.instructions.push(Instruction::Arithmetic(
+                self.push(Instruction::Arithmetic(
                     ArithmeticOp::AddImm { dst, src1, imm }
                 ));
+            },
+            Operand::Shifted(src2, shift, amount) => {
+                self.push(Instruction::Arithmetic(
+                    ArithmeticOp::AddShifted { dst, src1, src2, shift, amount }
+                ));
             }
         }
     }
 
+    #[track_caller]
     fn sub(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
-        self.instructions.push(Instruction::Arithmetic(
+        self.push(Instruction::Arithmetic(
             ArithmeticOp::Sub { dst, src1, src2 }
         ));
     }
 
+    #[track_caller]
     fn mul(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
-        self.instructions.push(Instruction::Arithmetic(
+        self.push(Instruction::Arithmetic(
             ArithmeticOp::Mul { dst, src1, src2 }
         ));
     }
 
-    fn fadd(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
-        self.instructions.push(Instruction::Arithmetic(
-            ArithmeticOp::Fadd { dst, src1, src2 }
+    #[track_caller]
+    fn sdiv(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Arithmetic(
+            ArithmeticOp::Sdiv { dst, src1, src2 }
         ));
     }
-}
 
-// Add conversion from String to Operand
-impl From<String> for Operand<Arm64Register> {
-    fn from(s: String) -> Self {
-        Operand::Immediate(s)
+    #[track_caller]
+    fn udiv(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Arithmetic(
+            ArithmeticOp::Udiv { dst, src1, src2 }
+        ));
     }
-}
 
-impl<'a> From<&'a str> for Operand<Arm64Register> {
-    fn from(s: &'a str) -> Self {
-        Operand::Immediate(s.to_string())
+    #[track_caller]
+    fn madd(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, acc: Arm64Register) {
+        self.push(Instruction::Arithmetic(
+            ArithmeticOp::Madd { dst, src1, src2, acc }
+        ));
     }
-}
 
-// Add this implementation
-impl From<GenericRegister> for Operand<Arm64Register> {
-    fn from(reg: GenericRegister) -> Self {
-        Operand::Register(reg.to_arch_reg())
+    #[track_caller]
+    fn msub(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, acc: Arm64Register) {
+        self.push(Instruction::Arithmetic(
+            ArithmeticOp::Msub { dst, src1, src2, acc }
+        ));
     }
-} 
\ No newline at end of file
+
+    #[track_caller]
+    fn mneg(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Arithmetic(
+            ArithmeticOp::Mneg { dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn smull(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Arithmetic(
+            ArithmeticOp::Smull { dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn umull(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Arithmetic(
+            ArithmeticOp::Umull { dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn smulh(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Arithmetic(
+            ArithmeticOp::Smulh { dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn umulh(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Arithmetic(
+            ArithmeticOp::Umulh { dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn smaddl(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, acc: Arm64Register) {
+        self.push(Instruction::Arithmetic(
+            ArithmeticOp::Smaddl { dst, src1, src2, acc }
+        ));
+    }
+
+    #[track_caller]
+    fn umaddl(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, acc: Arm64Register) {
+        self.push(Instruction::Arithmetic(
+            ArithmeticOp::Umaddl { dst, src1, src2, acc }
+        ));
+    }
+}
+
+impl FloatBuilder<Arm64Register> for ARM64 {
+    #[track_caller]
+    fn fadd(&mut self, size: FpSize, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Float(
+            FloatOp::Fadd { size, dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn fsub(&mut self, size: FpSize, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Float(
+            FloatOp::Fsub { size, dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn fmul(&mut self, size: FpSize, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Float(
+            FloatOp::Fmul { size, dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn fdiv(&mut self, size: FpSize, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Float(
+            FloatOp::Fdiv { size, dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn fsqrt(&mut self, size: FpSize, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Float(
+            FloatOp::Fsqrt { size, dst, src }
+        ));
+    }
+
+    #[track_caller]
+    fn fneg(&mut self, size: FpSize, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Float(
+            FloatOp::Fneg { size, dst, src }
+        ));
+    }
+
+    #[track_caller]
+    fn fabs(&mut self, size: FpSize, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Float(
+            FloatOp::Fabs { size, dst, src }
+        ));
+    }
+
+    #[track_caller]
+    fn fmadd(&mut self, size: FpSize, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, acc: Arm64Register) {
+        self.push(Instruction::Float(
+            FloatOp::Fmadd { size, dst, src1, src2, acc }
+        ));
+    }
+
+    #[track_caller]
+    fn fcmp(&mut self, size: FpSize, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Float(
+            FloatOp::Fcmp { size, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn fcsel(&mut self, size: FpSize, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, cond: Condition) {
+        self.push(Instruction::Float(
+            FloatOp::Fcsel { size, dst, src1, src2, cond }
+        ));
+    }
+
+    #[track_caller]
+    fn fmov(&mut self, size: FpSize, dst: Arm64Register, src: Arm64Register) {
+        let op = match (dst.is_vector(), src.is_vector()) {
+            (true, true) => FloatOp::Fmov { size, dst, src },
+            (true, false) => FloatOp::FmovToFp { size, dst, src },
+            (false, true) => FloatOp::FmovFromFp { size, dst, src },
+            (false, false) => {
+                self.pending_errors.push(Rejected::Operand { operand: src.to_string(), expected: "floating-point register" });
+                FloatOp::Fmov { size, dst, src }
+            }
+        };
+        self.push(Instruction::Float(op));
+    }
+
+    /// Uses the 8-bit immediate form when the value allows it, a move from
+    /// the zero register for +0.0, and otherwise builds the bit pattern in
+    /// X16.
+    #[track_caller]
+    fn fmov_imm(&mut self, size: FpSize, dst: Arm64Register, imm: f64) {
+        if imm::is_fp_imm(imm) {
+            self.push(Instruction::Float(FloatOp::FmovImm { size, dst, imm }));
+            return;
+        }
+        let bits = match size {
+            FpSize::Half => imm::f64_to_f16(imm) as u64,
+            FpSize::Single => (imm as f32).to_bits() as u64,
+            FpSize::Double => imm.to_bits(),
+        };
+        let src = if bits == 0 {
+            Arm64Register::XZR
+        } else {
+            self.mov_imm(Arm64Register::X16, bits);
+            Arm64Register::X16
+        };
+        self.push(Instruction::Float(FloatOp::FmovToFp { size, dst, src }));
+    }
+
+    #[track_caller]
+    fn scvtf(&mut self, size: FpSize, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Float(
+            FloatOp::Scvtf { size, dst, src }
+        ));
+    }
+
+    #[track_caller]
+    fn ucvtf(&mut self, size: FpSize, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Float(
+            FloatOp::Ucvtf { size, dst, src }
+        ));
+    }
+
+    #[track_caller]
+    fn fcvtzs(&mut self, size: FpSize, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Float(
+            FloatOp::Fcvtzs { size, dst, src }
+        ));
+    }
+
+    #[track_caller]
+    fn fcvtzu(&mut self, size: FpSize, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Float(
+            FloatOp::Fcvtzu { size, dst, src }
+        ));
+    }
+
+    #[track_caller]
+    fn fcvt(&mut self, dst_size: FpSize, dst: Arm64Register, src_size: FpSize, src: Arm64Register) {
+        self.push(Instruction::Float(
+            FloatOp::Fcvt { dst_size, dst, src_size, src }
+        ));
+    }
+}
+
+impl VectorBuilder<Arm64Register> for ARM64 {
+    #[track_caller]
+    fn vadd(&mut self, arrangement: VectorArrangement, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Vector(
+            VectorOp::Add { arrangement, dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn vsub(&mut self, arrangement: VectorArrangement, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Vector(
+            VectorOp::Sub { arrangement, dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn vmul(&mut self, arrangement: VectorArrangement, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Vector(
+            VectorOp::Mul { arrangement, dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn vfadd(&mut self, arrangement: VectorArrangement, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Vector(
+            VectorOp::Fadd { arrangement, dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn vfsub(&mut self, arrangement: VectorArrangement, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Vector(
+            VectorOp::Fsub { arrangement, dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn vfmul(&mut self, arrangement: VectorArrangement, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) {
+        self.push(Instruction::Vector(
+            VectorOp::Fmul { arrangement, dst, src1, src2 }
+        ));
+    }
+
+    #[track_caller]
+    fn ld1(&mut self, arrangement: VectorArrangement, first: Arm64Register, count: u8, addr: MemOperand<Arm64Register>) {
+        self.push(Instruction::Vector(
+            VectorOp::Ld1 { arrangement, first, count, addr }
+        ));
+    }
+
+    #[track_caller]
+    fn st1(&mut self, arrangement: VectorArrangement, first: Arm64Register, count: u8, addr: MemOperand<Arm64Register>) {
+        self.push(Instruction::Vector(
+            VectorOp::St1 { arrangement, first, count, addr }
+        ));
+    }
+
+    #[track_caller]
+    fn dup(&mut self, arrangement: VectorArrangement, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Vector(
+            VectorOp::Dup { arrangement, dst, src }
+        ));
+    }
+
+    #[track_caller]
+    fn dup_lane(&mut self, arrangement: VectorArrangement, dst: Arm64Register, src: Arm64Register, index: u8) {
+        self.push(Instruction::Vector(
+            VectorOp::DupLane { arrangement, dst, src, index }
+        ));
+    }
+
+    #[track_caller]
+    fn ins(&mut self, lane: MemSize, dst: Arm64Register, index: u8, src: Arm64Register) {
+        self.push(Instruction::Vector(
+            VectorOp::Ins { lane, dst, index, src }
+        ));
+    }
+
+    #[track_caller]
+    fn umov(&mut self, lane: MemSize, dst: Arm64Register, src: Arm64Register, index: u8) {
+        self.push(Instruction::Vector(
+            VectorOp::Umov { lane, dst, src, index }
+        ));
+    }
+
+    #[track_caller]
+    fn addv(&mut self, arrangement: VectorArrangement, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Vector(
+            VectorOp::Addv { arrangement, dst, src }
+        ));
+    }
+}
+
+impl ARM64 {
+    /// Splits a logical-instruction operand into a register and optional shift.
+    fn shifted_register(&mut self, op: Operand<Arm64Register>) -> (Arm64Register, Option<(ShiftKind, u8)>) {
+        match op {
+            Operand::Register(reg) => (reg, None),
+            Operand::Shifted(reg, shift, amount) => (reg, Some((shift, amount))),
+            Operand::Immediate(imm) => {
+                self.pending_errors.push(Rejected::Operand { operand: fmt_imm(&imm), expected: "register" });
+                (Arm64Register::XZR, None)
+            }
+        }
+    }
+
+    fn logical_imm(&mut self, imm: &str) -> u64 {
+        match parse_imm(imm) {
+            Some(value) => value as u64,
+            None => {
+                self.pending_errors.push(Rejected::Immediate(imm.to_string()));
+                0
+            }
+        }
+    }
+
+    /// The complement of `imm` within the width of `dst`, so a W register
+    /// keeps a 32-bit pattern.
+    fn inverted_logical_imm(&mut self, dst: Arm64Register, imm: &str) -> u64 {
+        let inverted = !self.logical_imm(imm);
+        if dst.is_32bit() {
+            inverted & 0xffff_ffff
+        } else {
+            inverted
+        }
+    }
+
+    fn shift_amount(&mut self, op: Operand<Arm64Register>) -> u8 {
+        match op {
+            Operand::Immediate(imm) => match parse_imm(&imm) {
+                Some(amount) if (0..64).contains(&amount) => amount as u8,
+                _ => {
+                    self.pending_errors.push(Rejected::Immediate(imm));
+                    0
+                }
+            },
+            // Callers take the register form themselves
+            Operand::Register(reg) | Operand::Shifted(reg, ..) => {
+                self.pending_errors.push(Rejected::Operand { operand: reg.to_string(), expected: "shift amount" });
+                0
+            }
+        }
+    }
+}
+
+impl LogicalBuilder<Arm64Register> for ARM64 {
+    #[track_caller]
+    fn and(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Operand<Arm64Register>) {
+        let inst = match src2 {
+            Operand::Immediate(imm) => LogicalOp::AndImm { dst, src: src1, imm: self.logical_imm(&imm) },
+            op => {
+                let (src2, shift) = self.shifted_register(op);
+                LogicalOp::And { dst, src1, src2, shift }
+            }
+        };
+        self.push(Instruction::Logical(inst));
+    }
+
+    #[track_caller]
+    fn orr(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Operand<Arm64Register>) {
+        let inst = match src2 {
+            Operand::Immediate(imm) => LogicalOp::OrrImm { dst, src: src1, imm: self.logical_imm(&imm) },
+            op => {
+                let (src2, shift) = self.shifted_register(op);
+                LogicalOp::Orr { dst, src1, src2, shift }
+            }
+        };
+        self.push(Instruction::Logical(inst));
+    }
+
+    #[track_caller]
+    fn eor(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Operand<Arm64Register>) {
+        let inst = match src2 {
+            Operand::Immediate(imm) => LogicalOp::EorImm { dst, src: src1, imm: self.logical_imm(&imm) },
+            op => {
+                let (src2, shift) = self.shifted_register(op);
+                LogicalOp::Eor { dst, src1, src2, shift }
+            }
+        };
+        self.push(Instruction::Logical(inst));
+    }
+
+    // The inverted forms have no immediate encoding of their own; an
+    // immediate is folded into the plain form with its complement.
+    #[track_caller]
+    fn bic(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Operand<Arm64Register>) {
+        let inst = match src2 {
+            Operand::Immediate(imm) => LogicalOp::AndImm { dst, src: src1, imm: self.inverted_logical_imm(dst, &imm) },
+            op => {
+                let (src2, shift) = self.shifted_register(op);
+                LogicalOp::Bic { dst, src1, src2, shift }
+            }
+        };
+        self.push(Instruction::Logical(inst));
+    }
+
+    #[track_caller]
+    fn orn(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Operand<Arm64Register>) {
+        let inst = match src2 {
+            Operand::Immediate(imm) => LogicalOp::OrrImm { dst, src: src1, imm: self.inverted_logical_imm(dst, &imm) },
+            op => {
+                let (src2, shift) = self.shifted_register(op);
+                LogicalOp::Orn { dst, src1, src2, shift }
+            }
+        };
+        self.push(Instruction::Logical(inst));
+    }
+
+    #[track_caller]
+    fn eon(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Operand<Arm64Register>) {
+        let inst = match src2 {
+            Operand::Immediate(imm) => LogicalOp::EorImm { dst, src: src1, imm: self.inverted_logical_imm(dst, &imm) },
+            op => {
+                let (src2, shift) = self.shifted_register(op);
+                LogicalOp::Eon { dst, src1, src2, shift }
+            }
+        };
+        self.push(Instruction::Logical(inst));
+    }
+}
+
+impl ShiftBuilder<Arm64Register> for ARM64 {
+    #[track_caller]
+    fn lsl(&mut self, dst: Arm64Register, src: Arm64Register, amount: Operand<Arm64Register>) {
+        let inst = match amount {
+            Operand::Register(src2) => ShiftOp::Lsl { dst, src1: src, src2 },
+            op => ShiftOp::LslImm { dst, src, amount: self.shift_amount(op) },
+        };
+        self.push(Instruction::Shift(inst));
+    }
+
+    #[track_caller]
+    fn lsr(&mut self, dst: Arm64Register, src: Arm64Register, amount: Operand<Arm64Register>) {
+        let inst = match amount {
+            Operand::Register(src2) => ShiftOp::Lsr { dst, src1: src, src2 },
+            op => ShiftOp::LsrImm { dst, src, amount: self.shift_amount(op) },
+        };
+        self.push(Instruction::Shift(inst));
+    }
+
+    #[track_caller]
+    fn asr(&mut self, dst: Arm64Register, src: Arm64Register, amount: Operand<Arm64Register>) {
+        let inst = match amount {
+            Operand::Register(src2) => ShiftOp::Asr { dst, src1: src, src2 },
+            op => ShiftOp::AsrImm { dst, src, amount: self.shift_amount(op) },
+        };
+        self.push(Instruction::Shift(inst));
+    }
+
+    #[track_caller]
+    fn ror(&mut self, dst: Arm64Register, src: Arm64Register, amount: Operand<Arm64Register>) {
+        let inst = match amount {
+            Operand::Register(src2) => ShiftOp::Ror { dst, src1: src, src2 },
+            op => ShiftOp::RorImm { dst, src, amount: self.shift_amount(op) },
+        };
+        self.push(Instruction::Shift(inst));
+    }
+}
+
+impl BitfieldBuilder<Arm64Register> for ARM64 {
+    #[track_caller]
+    fn ubfx(&mut self, dst: Arm64Register, src: Arm64Register, lsb: u8, width: u8) {
+        self.push(Instruction::Bitfield(BitfieldOp::Ubfx { dst, src, lsb, width }));
+    }
+
+    #[track_caller]
+    fn sbfx(&mut self, dst: Arm64Register, src: Arm64Register, lsb: u8, width: u8) {
+        self.push(Instruction::Bitfield(BitfieldOp::Sbfx { dst, src, lsb, width }));
+    }
+
+    #[track_caller]
+    fn bfi(&mut self, dst: Arm64Register, src: Arm64Register, lsb: u8, width: u8) {
+        self.push(Instruction::Bitfield(BitfieldOp::Bfi { dst, src, lsb, width }));
+    }
+
+    #[track_caller]
+    fn ubfiz(&mut self, dst: Arm64Register, src: Arm64Register, lsb: u8, width: u8) {
+        self.push(Instruction::Bitfield(BitfieldOp::Ubfiz { dst, src, lsb, width }));
+    }
+
+    #[track_caller]
+    fn extr(&mut self, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, lsb: u8) {
+        self.push(Instruction::Bitfield(BitfieldOp::Extr { dst, src1, src2, lsb }));
+    }
+}
+
+impl BitBuilder<Arm64Register> for ARM64 {
+    #[track_caller]
+    fn clz(&mut self, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Bit(BitOp::Clz { dst, src }));
+    }
+
+    #[track_caller]
+    fn cls(&mut self, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Bit(BitOp::Cls { dst, src }));
+    }
+
+    #[track_caller]
+    fn rbit(&mut self, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Bit(BitOp::Rbit { dst, src }));
+    }
+
+    #[track_caller]
+    fn rev(&mut self, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Bit(BitOp::Rev { dst, src }));
+    }
+
+    #[track_caller]
+    fn rev16(&mut self, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Bit(BitOp::Rev16 { dst, src }));
+    }
+
+    #[track_caller]
+    fn rev32(&mut self, dst: Arm64Register, src: Arm64Register) {
+        self.push(Instruction::Bit(BitOp::Rev32 { dst, src }));
+    }
+}
+
+impl AtomicBuilder<Arm64Register> for ARM64 {
+    #[track_caller]
+    fn ldxr(&mut self, size: MemSize, dst: Arm64Register, addr: Arm64Register) {
+        self.push(Instruction::Atomic(AtomicOp::Ldxr { size, acquire: false, dst, addr }));
+    }
+
+    #[track_caller]
+    fn ldaxr(&mut self, size: MemSize, dst: Arm64Register, addr: Arm64Register) {
+        self.push(Instruction::Atomic(AtomicOp::Ldxr { size, acquire: true, dst, addr }));
+    }
+
+    #[track_caller]
+    fn stxr(&mut self, size: MemSize, status: Arm64Register, src: Arm64Register, addr: Arm64Register) {
+        self.push(Instruction::Atomic(AtomicOp::Stxr { size, release: false, status, src, addr }));
+    }
+
+    #[track_caller]
+    fn stlxr(&mut self, size: MemSize, status: Arm64Register, src: Arm64Register, addr: Arm64Register) {
+        self.push(Instruction::Atomic(AtomicOp::Stxr { size, release: true, status, src, addr }));
+    }
+
+    #[track_caller]
+    fn ldadd(&mut self, order: MemoryOrder, size: MemSize, src: Arm64Register, dst: Arm64Register, addr: Arm64Register) {
+        self.push(Instruction::Atomic(AtomicOp::Ldadd { order, size, src, dst, addr }));
+    }
+
+    #[track_caller]
+    fn swp(&mut self, order: MemoryOrder, size: MemSize, src: Arm64Register, dst: Arm64Register, addr: Arm64Register) {
+        self.push(Instruction::Atomic(AtomicOp::Swp { order, size, src, dst, addr }));
+    }
+
+    #[track_caller]
+    fn cas(&mut self, order: MemoryOrder, size: MemSize, expected: Arm64Register, new: Arm64Register, addr: Arm64Register) {
+        self.push(Instruction::Atomic(AtomicOp::Cas { order, size, expected, new, addr }));
+    }
+}
+
+impl ARM64 {
+    /// Sequentially consistent compare and swap, `cas` with both acquire and
+    /// release semantics.
+    #[track_caller]
+    pub fn casal(&mut self, size: MemSize, expected: Arm64Register, new: Arm64Register, addr: Arm64Register) {
+        self.cas(MemoryOrder::AcquireRelease, size, expected, new, addr);
+    }
+}
+
+impl ARM64 {
+    /// Writes `src` to a system register
+    #[track_caller]
+    pub fn msr(&mut self, dst: SystemRegister, src: Arm64Register) {
+        if !dst.is_writable() {
+            self.pending_errors.push(Rejected::ReadOnly(dst));
+        }
+        self.push(Instruction::System(SystemOp::Msr { dst, src }));
+    }
+
+    /// Reads a system register into `dst`
+    #[track_caller]
+    pub fn mrs(&mut self, dst: Arm64Register, src: SystemRegister) {
+        self.push(Instruction::System(SystemOp::Mrs { dst, src }));
+    }
+
+    /// `sys #op1, Cn, Cm, #op2, src` for system operations without a
+    /// dedicated builder
+    #[track_caller]
+    pub fn sys(&mut self, op1: u8, crn: u8, crm: u8, op2: u8, src: Arm64Register) {
+        self.push(Instruction::System(SystemOp::Sys { op1, crn, crm, op2, src }));
+    }
+
+    /// `sysl dst, #op1, Cn, Cm, #op2`, the result-returning form of [`Self::sys`]
+    #[track_caller]
+    pub fn sysl(&mut self, dst: Arm64Register, op1: u8, crn: u8, crm: u8, op2: u8) {
+        self.push(Instruction::System(SystemOp::Sysl { dst, op1, crn, crm, op2 }));
+    }
+
+    #[track_caller]
+    pub fn eret(&mut self) {
+        self.push(Instruction::System(SystemOp::Eret));
+    }
+}
+
+impl SystemBuilder for ARM64 {
+    #[track_caller]
+    fn svc(&mut self, number: u32) {
+        self.push(Instruction::System(SystemOp::Svc { number }));
+    }
+}
+
+/// Forwarders for the operations that only exist on ARM64.
+impl InstructionBuilder<ARM64, Arm64Register> {
+    #[track_caller]
+    pub fn msr(&mut self, dst: SystemRegister, src: GenericRegister) -> &mut Self {
+        self.arch.msr(dst, src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn mrs(&mut self, dst: GenericRegister, src: SystemRegister) -> &mut Self {
+        self.arch.mrs(dst.to_arch_reg(), src);
+        self
+    }
+
+    #[track_caller]
+    pub fn sys(&mut self, op1: u8, crn: u8, crm: u8, op2: u8, src: GenericRegister) -> &mut Self {
+        self.arch.sys(op1, crn, crm, op2, src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn sysl(&mut self, dst: GenericRegister, op1: u8, crn: u8, crm: u8, op2: u8) -> &mut Self {
+        self.arch.sysl(dst.to_arch_reg(), op1, crn, crm, op2);
+        self
+    }
+
+    #[track_caller]
+    pub fn eret(&mut self) -> &mut Self {
+        self.arch.eret();
+        self
+    }
+
+    #[track_caller]
+    pub fn casal(&mut self, size: MemSize, expected: GenericRegister, new: GenericRegister, addr: GenericRegister) -> &mut Self {
+        self.arch.casal(size, expected.to_arch_reg(), new.to_arch_reg(), addr.to_arch_reg());
+        self
+    }
+}
+
+impl BarrierBuilder for ARM64 {
+    #[track_caller]
+    fn dmb(&mut self, domain: BarrierDomain) {
+        self.push(Instruction::System(SystemOp::Dmb { domain }));
+    }
+
+    #[track_caller]
+    fn dsb(&mut self, domain: BarrierDomain) {
+        self.push(Instruction::System(SystemOp::Dsb { domain }));
+    }
+
+    #[track_caller]
+    fn isb(&mut self) {
+        self.push(Instruction::System(SystemOp::Isb));
+    }
+}
+
+// Add conversion from String to Operand
+impl From<String> for Operand<Arm64Register> {
+    fn from(s: String) -> Self {
+        Operand::Immediate(s)
+    }
+}
+
+impl<'a> From<&'a str> for Operand<Arm64Register> {
+    fn from(s: &'a str) -> Self {
+        Operand::Immediate(s.to_string())
+    }
+}
+
+// Add this implementation
+impl From<GenericRegister> for Operand<Arm64Register> {
+    fn from(reg: GenericRegister) -> Self {
+        Operand::Register(reg.to_arch_reg())
+    }
+} 
+/// Renders an immediate operand, adding `#` to plain numbers and leaving
+/// symbolic operands such as `L0@PAGEOFF` untouched.
+fn fmt_imm(imm: &str) -> String {
+    if parse_imm(imm).is_some() {
+        format!("#{}", imm)
+    } else {
+        imm.to_string()
+    }
+}
+
+/// Parses a numeric immediate in decimal or `0x` hexadecimal form.
+pub(crate) fn parse_imm(imm: &str) -> Option<i64> {
+    let (negative, digits) = match imm.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, imm),
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()? as i64,
+        None => digits.parse::<u64>().ok()? as i64,
+    };
+    Some(if negative { value.wrapping_neg() } else { value })
+}
+
+/// Renames the label in a symbolic immediate such as `L0@PAGEOFF`.
+fn relabel_imm(imm: &mut String, rename: &dyn Fn(&str) -> Option<String>) {
+    if let Some(name) = imm.strip_suffix("@PAGEOFF").and_then(rename) {
+        *imm = format!("{}@PAGEOFF", name);
+    } else if let Some(name) = imm.strip_prefix(":lo12:").and_then(rename) {
+        *imm = format!(":lo12:{}", name);
+    }
+}
+
+impl Display for MemOperand<Arm64Register> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemOperand::Offset(base, 0) => write!(f, "[{}]", base),
+            MemOperand::Offset(base, offset) => write!(f, "[{}, #{}]", base, offset),
+            MemOperand::PreIndex(base, offset) => write!(f, "[{}, #{}]!", base, offset),
+            MemOperand::PostIndex(base, offset) => write!(f, "[{}], #{}", base, offset),
+            MemOperand::Indexed(base, index, 0) => write!(f, "[{}, {}]", base, index),
+            MemOperand::Indexed(base, index, shift) => write!(f, "[{}, {}, lsl #{}]", base, index, shift),
+        }
+    }
+}
+
+impl Instruction {
+    /// Renames the labels the instruction defines or refers to, including
+    /// those in `label@PAGEOFF` and `:lo12:label` immediates.
+    pub(crate) fn relabel(&mut self, rename: &dyn Fn(&str) -> Option<String>) {
+        let apply = |label: &mut String| {
+            if let Some(name) = rename(label) {
+                *label = name;
+            }
+        };
+        match self {
+            Instruction::Label(label) => apply(label),
+            Instruction::Arithmetic(ArithmeticOp::AddImm { imm, .. }) => relabel_imm(imm, rename),
+            Instruction::Arithmetic(ArithmeticOp::CmpImm { imm, .. }) => relabel_imm(imm, rename),
+            Instruction::Branch(BranchOp::Bl { label }) => apply(label),
+            Instruction::Branch(BranchOp::B { label }) => apply(label),
+            Instruction::Branch(BranchOp::Cbz { label, .. }) => apply(label),
+            Instruction::Branch(BranchOp::Cbnz { label, .. }) => apply(label),
+            Instruction::Branch(BranchOp::BCond { label, .. }) => apply(label),
+            Instruction::LoadStore(LoadStoreOp::Ldr { src, .. }) => apply(src),
+            Instruction::LoadStore(LoadStoreOp::Str { dst, .. }) => apply(dst),
+            Instruction::Address(AddressOp::Adr { label, .. }) => apply(label),
+            Instruction::Address(AddressOp::Adrp { label, .. }) => apply(label),
+            Instruction::Address(AddressOp::AdrpAdd { label, .. }) => apply(label),
+            Instruction::Address(AddressOp::AdrpGot { label, .. }) => apply(label),
+            Instruction::Data(DataDirective::LabelDiff { target, base }) => {
+                apply(target);
+                apply(base);
+            }
+            Instruction::Data(DataDirective::Pointer { symbol, .. }) => apply(symbol),
+            _ => {}
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Label(name) => write!(f, "{}:", name),
+            Self::Arithmetic(op) => write!(f, "{}", op),
+            Self::Float(op) => write!(f, "{}", op),
+            Self::Vector(op) => write!(f, "{}", op),
+            Self::Logical(op) => write!(f, "{}", op),
+            Self::Shift(op) => write!(f, "{}", op),
+            Self::Bitfield(op) => write!(f, "{}", op),
+            Self::Bit(op) => write!(f, "{}", op),
+            Self::Branch(op) => write!(f, "{}", op),
+            Self::LoadStore(op) => write!(f, "{}", op),
+            Self::Atomic(op) => write!(f, "{}", op),
+            Self::System(op) => write!(f, "{}", op),
+            Self::Address(op) => write!(f, "{}", op),
+            Self::Move(op) => write!(f, "{}", op),
+            Self::Data(directive) => write!(f, "{}", directive),
+        }
+    }
+}
+
+impl Display for ArithmeticOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // `mov` is recorded as an add of the zero register
+            Self::Add { dst, src1, src2 } if src2.is_zero() => write!(f, "mov {}, {}", dst, src1),
+            Self::Add { dst, src1, src2 } => write!(f, "add {}, {}, {}", dst, src1, src2),
+            Self::AddImm { dst, src1, imm } => match imm.strip_prefix('-') {
+                Some(abs) if parse_imm(abs).is_some() => write!(f, "sub {}, {}, #{}", dst, src1, abs),
+                _ => write!(f, "add {}, {}, {}", dst, src1, fmt_imm(imm)),
+            },
+            Self::AddShifted { dst, src1, src2, shift, amount } => {
+                write!(f, "add {}, {}, {}, {} #{}", dst, src1, src2, shift.as_str(), amount)
+            }
+            Self::Sub { dst, src1, src2 } => write!(f, "sub {}, {}, {}", dst, src1, src2),
+            Self::Mul { dst, src1, src2 } => write!(f, "mul {}, {}, {}", dst, src1, src2),
+            Self::Sdiv { dst, src1, src2 } => write!(f, "sdiv {}, {}, {}", dst, src1, src2),
+            Self::Udiv { dst, src1, src2 } => write!(f, "udiv {}, {}, {}", dst, src1, src2),
+            Self::Madd { dst, src1, src2, acc } => write!(f, "madd {}, {}, {}, {}", dst, src1, src2, acc),
+            Self::Msub { dst, src1, src2, acc } => write!(f, "msub {}, {}, {}, {}", dst, src1, src2, acc),
+            Self::Mneg { dst, src1, src2 } => write!(f, "mneg {}, {}, {}", dst, src1, src2),
+            Self::Smull { dst, src1, src2 } => write!(f, "smull {}, {}, {}", dst, src1.w_name(), src2.w_name()),
+            Self::Umull { dst, src1, src2 } => write!(f, "umull {}, {}, {}", dst, src1.w_name(), src2.w_name()),
+            Self::Smulh { dst, src1, src2 } => write!(f, "smulh {}, {}, {}", dst, src1, src2),
+            Self::Umulh { dst, src1, src2 } => write!(f, "umulh {}, {}, {}", dst, src1, src2),
+            Self::Smaddl { dst, src1, src2, acc } => {
+                write!(f, "smaddl {}, {}, {}, {}", dst, src1.w_name(), src2.w_name(), acc)
+            }
+            Self::Umaddl { dst, src1, src2, acc } => {
+                write!(f, "umaddl {}, {}, {}, {}", dst, src1.w_name(), src2.w_name(), acc)
+            }
+            Self::Cmp { src1, src2 } => write!(f, "cmp {}, {}", src1, src2),
+            Self::CmpImm { src1, imm } => write!(f, "cmp {}, {}", src1, fmt_imm(imm)),
+            Self::CmpShifted { src1, src2, shift, amount } => {
+                write!(f, "cmp {}, {}, {} #{}", src1, src2, shift.as_str(), amount)
+            }
+            Self::Cset { dst, cond } => write!(f, "cset {}, {}", dst, cond.as_str()),
+            Self::Csel { dst, src1, src2, cond } => {
+                write!(f, "csel {}, {}, {}, {}", dst, src1, src2, cond.as_str())
+            }
+        }
+    }
+}
+
+impl Display for FloatOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fadd { size, dst, src1, src2 } => {
+                write!(f, "fadd {}, {}, {}", dst.fp_name(*size), src1.fp_name(*size), src2.fp_name(*size))
+            }
+            Self::Fsub { size, dst, src1, src2 } => {
+                write!(f, "fsub {}, {}, {}", dst.fp_name(*size), src1.fp_name(*size), src2.fp_name(*size))
+            }
+            Self::Fmul { size, dst, src1, src2 } => {
+                write!(f, "fmul {}, {}, {}", dst.fp_name(*size), src1.fp_name(*size), src2.fp_name(*size))
+            }
+            Self::Fdiv { size, dst, src1, src2 } => {
+                write!(f, "fdiv {}, {}, {}", dst.fp_name(*size), src1.fp_name(*size), src2.fp_name(*size))
+            }
+            Self::Fsqrt { size, dst, src } => write!(f, "fsqrt {}, {}", dst.fp_name(*size), src.fp_name(*size)),
+            Self::Fneg { size, dst, src } => write!(f, "fneg {}, {}", dst.fp_name(*size), src.fp_name(*size)),
+            Self::Fabs { size, dst, src } => write!(f, "fabs {}, {}", dst.fp_name(*size), src.fp_name(*size)),
+            Self::Fmadd { size, dst, src1, src2, acc } => write!(
+                f,
+                "fmadd {}, {}, {}, {}",
+                dst.fp_name(*size),
+                src1.fp_name(*size),
+                src2.fp_name(*size),
+                acc.fp_name(*size)
+            ),
+            Self::Fcmp { size, src1, src2 } => write!(f, "fcmp {}, {}", src1.fp_name(*size), src2.fp_name(*size)),
+            Self::Fcsel { size, dst, src1, src2, cond } => write!(
+                f,
+                "fcsel {}, {}, {}, {}",
+                dst.fp_name(*size),
+                src1.fp_name(*size),
+                src2.fp_name(*size),
+                cond.as_str()
+            ),
+            Self::Fmov { size, dst, src } => write!(f, "fmov {}, {}", dst.fp_name(*size), src.fp_name(*size)),
+            Self::FmovToFp { size, dst, src } => write!(f, "fmov {}, {}", dst.fp_name(*size), gpr_view(*src, *size)),
+            Self::FmovFromFp { size, dst, src } => write!(f, "fmov {}, {}", gpr_view(*dst, *size), src.fp_name(*size)),
+            Self::FmovImm { size, dst, imm } => write!(f, "fmov {}, #{:?}", dst.fp_name(*size), imm),
+            Self::Scvtf { size, dst, src } => write!(f, "scvtf {}, {}", dst.fp_name(*size), src),
+            Self::Ucvtf { size, dst, src } => write!(f, "ucvtf {}, {}", dst.fp_name(*size), src),
+            Self::Fcvtzs { size, dst, src } => write!(f, "fcvtzs {}, {}", dst, src.fp_name(*size)),
+            Self::Fcvtzu { size, dst, src } => write!(f, "fcvtzu {}, {}", dst, src.fp_name(*size)),
+            Self::Fcvt { dst_size, dst, src_size, src } => {
+                write!(f, "fcvt {}, {}", dst.fp_name(*dst_size), src.fp_name(*src_size))
+            }
+        }
+    }
+}
+
+impl Display for VectorOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (mnemonic, arrangement, dst, src1, src2) = match self {
+            Self::Add { arrangement, dst, src1, src2 } => ("add", arrangement, dst, src1, src2),
+            Self::Sub { arrangement, dst, src1, src2 } => ("sub", arrangement, dst, src1, src2),
+            Self::Mul { arrangement, dst, src1, src2 } => ("mul", arrangement, dst, src1, src2),
+            Self::Fadd { arrangement, dst, src1, src2 } => ("fadd", arrangement, dst, src1, src2),
+            Self::Fsub { arrangement, dst, src1, src2 } => ("fsub", arrangement, dst, src1, src2),
+            Self::Fmul { arrangement, dst, src1, src2 } => ("fmul", arrangement, dst, src1, src2),
+            Self::Ld1 { arrangement, first, count, addr } => {
+                return write!(f, "ld1 {}, {}", register_list(*first, *count, *arrangement), addr);
+            }
+            Self::St1 { arrangement, first, count, addr } => {
+                return write!(f, "st1 {}, {}", register_list(*first, *count, *arrangement), addr);
+            }
+            Self::Dup { arrangement, dst, src } => {
+                return write!(f, "dup {}, {}", vector(*dst, *arrangement), sized_gpr(*src, arrangement.element()));
+            }
+            Self::DupLane { arrangement, dst, src, index } => {
+                return write!(f, "dup {}, {}", vector(*dst, *arrangement), lane(*src, arrangement.element(), *index));
+            }
+            Self::Ins { lane: size, dst, index, src } => {
+                return write!(f, "ins {}, {}", lane(*dst, *size, *index), sized_gpr(*src, *size));
+            }
+            Self::Umov { lane: size, dst, src, index } => {
+                return write!(f, "umov {}, {}", sized_gpr(*dst, *size), lane(*src, *size, *index));
+            }
+            Self::Addv { arrangement, dst, src } => {
+                let scalar = format!("{}{}", lane_suffix(arrangement.element()), dst.index());
+                return write!(f, "addv {}, {}", scalar, vector(*src, *arrangement));
+            }
+        };
+        write!(
+            f,
+            "{} {}, {}, {}",
+            mnemonic,
+            vector(*dst, *arrangement),
+            vector(*src1, *arrangement),
+            vector(*src2, *arrangement)
+        )
+    }
+}
+
+fn vector(reg: Arm64Register, arrangement: VectorArrangement) -> String {
+    format!("v{}.{}", reg.index(), arrangement.as_str())
+}
+
+fn lane_suffix(size: MemSize) -> char {
+    match size {
+        MemSize::Byte => 'b',
+        MemSize::Half => 'h',
+        MemSize::Word => 's',
+        MemSize::Double => 'd',
+    }
+}
+
+fn lane(reg: Arm64Register, size: MemSize, index: u8) -> String {
+    format!("v{}.{}[{}]", reg.index(), lane_suffix(size), index)
+}
+
+/// The general purpose register holding a lane or memory value of `size`:
+/// `x` for doublewords and `w` otherwise.
+fn sized_gpr(reg: Arm64Register, size: MemSize) -> String {
+    match size {
+        MemSize::Double => reg.to_string(),
+        _ => reg.w_name(),
+    }
+}
+
+fn register_list(first: Arm64Register, count: u8, arrangement: VectorArrangement) -> String {
+    let regs: Vec<String> = (0..count).map(|offset| vector(first.vector_offset(offset), arrangement)).collect();
+    format!("{{ {} }}", regs.join(", "))
+}
+
+/// The general purpose register matching an `fmov` of the given precision:
+/// `x` for doubles and `w` otherwise.
+fn gpr_view(reg: Arm64Register, size: FpSize) -> String {
+    match size {
+        FpSize::Double => reg.to_string(),
+        FpSize::Single | FpSize::Half => reg.w_name(),
+    }
+}
+
+impl Display for LogicalOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (mnemonic, dst, src1, src2, shift) = match self {
+            Self::And { dst, src1, src2, shift } => ("and", dst, src1, src2, shift),
+            Self::Orr { dst, src1, src2, shift } => ("orr", dst, src1, src2, shift),
+            Self::Eor { dst, src1, src2, shift } => ("eor", dst, src1, src2, shift),
+            Self::Bic { dst, src1, src2, shift } => ("bic", dst, src1, src2, shift),
+            Self::Orn { dst, src1, src2, shift } => ("orn", dst, src1, src2, shift),
+            Self::Eon { dst, src1, src2, shift } => ("eon", dst, src1, src2, shift),
+            Self::AndImm { dst, src, imm } => return write!(f, "and {}, {}, #{:#x}", dst, src, imm),
+            Self::OrrImm { dst, src, imm } => return write!(f, "orr {}, {}, #{:#x}", dst, src, imm),
+            Self::EorImm { dst, src, imm } => return write!(f, "eor {}, {}, #{:#x}", dst, src, imm),
+        };
+        write!(f, "{} {}, {}, {}", mnemonic, dst, src1, src2)?;
+        if let Some((kind, amount)) = shift {
+            write!(f, ", {} #{}", kind.as_str(), amount)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ShiftOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LslImm { dst, src, amount } => write!(f, "lsl {}, {}, #{}", dst, src, amount),
+            Self::LsrImm { dst, src, amount } => write!(f, "lsr {}, {}, #{}", dst, src, amount),
+            Self::AsrImm { dst, src, amount } => write!(f, "asr {}, {}, #{}", dst, src, amount),
+            Self::RorImm { dst, src, amount } => write!(f, "ror {}, {}, #{}", dst, src, amount),
+            Self::Lsl { dst, src1, src2 } => write!(f, "lsl {}, {}, {}", dst, src1, src2),
+            Self::Lsr { dst, src1, src2 } => write!(f, "lsr {}, {}, {}", dst, src1, src2),
+            Self::Asr { dst, src1, src2 } => write!(f, "asr {}, {}, {}", dst, src1, src2),
+            Self::Ror { dst, src1, src2 } => write!(f, "ror {}, {}, {}", dst, src1, src2),
+        }
+    }
+}
+
+impl Display for BitfieldOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ubfx { dst, src, lsb, width } => write!(f, "ubfx {}, {}, #{}, #{}", dst, src, lsb, width),
+            Self::Sbfx { dst, src, lsb, width } => write!(f, "sbfx {}, {}, #{}, #{}", dst, src, lsb, width),
+            Self::Bfi { dst, src, lsb, width } => write!(f, "bfi {}, {}, #{}, #{}", dst, src, lsb, width),
+            Self::Ubfiz { dst, src, lsb, width } => write!(f, "ubfiz {}, {}, #{}, #{}", dst, src, lsb, width),
+            Self::Extr { dst, src1, src2, lsb } => write!(f, "extr {}, {}, {}, #{}", dst, src1, src2, lsb),
+        }
+    }
+}
+
+impl Display for BitOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Clz { dst, src } => write!(f, "clz {}, {}", dst, src),
+            Self::Cls { dst, src } => write!(f, "cls {}, {}", dst, src),
+            Self::Rbit { dst, src } => write!(f, "rbit {}, {}", dst, src),
+            Self::Rev { dst, src } => write!(f, "rev {}, {}", dst, src),
+            Self::Rev16 { dst, src } => write!(f, "rev16 {}, {}", dst, src),
+            Self::Rev32 { dst, src } => write!(f, "rev32 {}, {}", dst, src),
+        }
+    }
+}
+
+impl Display for BranchOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bl { label } => write!(f, "bl {}", label),
+            Self::B { label } => write!(f, "b {}", label),
+            Self::Ret => write!(f, "ret"),
+            Self::Cbz { reg, label } => write!(f, "cbz {}, {}", reg, label),
+            Self::Cbnz { reg, label } => write!(f, "cbnz {}, {}", reg, label),
+            Self::BCond { cond, label } => write!(f, "b.{} {}", cond.as_str(), label),
+            Self::Br { reg } => write!(f, "br {}", reg),
+            Self::Blr { reg } => write!(f, "blr {}", reg),
+        }
+    }
+}
+
+impl Display for LoadStoreOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ldr { dst, src } => write!(f, "ldr {}, {}", dst, src),
+            Self::Str { src, dst } => write!(f, "str {}, {}", src, dst),
+            Self::Load { size, signed, dst, addr } => match (size, signed) {
+                (MemSize::Byte, false) => write!(f, "ldrb {}, {}", dst.w_name(), addr),
+                (MemSize::Byte, true) => write!(f, "ldrsb {}, {}", dst, addr),
+                (MemSize::Half, false) => write!(f, "ldrh {}, {}", dst.w_name(), addr),
+                (MemSize::Half, true) => write!(f, "ldrsh {}, {}", dst, addr),
+                (MemSize::Word, false) => write!(f, "ldr {}, {}", dst.w_name(), addr),
+                (MemSize::Word, true) => write!(f, "ldrsw {}, {}", dst, addr),
+                (MemSize::Double, _) => write!(f, "ldr {}, {}", dst, addr),
+            },
+            Self::Store { size, src, addr } => match size {
+                MemSize::Byte => write!(f, "strb {}, {}", src.w_name(), addr),
+                MemSize::Half => write!(f, "strh {}, {}", src.w_name(), addr),
+                MemSize::Word => write!(f, "str {}, {}", src.w_name(), addr),
+                MemSize::Double => write!(f, "str {}, {}", src, addr),
+            },
+            Self::Ldp { dst1, dst2, addr } => write!(f, "ldp {}, {}, {}", dst1, dst2, addr),
+            Self::Stp { src1, src2, addr } => write!(f, "stp {}, {}, {}", src1, src2, addr),
+        }
+    }
+}
+
+/// The `b`/`h` mnemonic suffix of a sub-word access.
+fn size_suffix(size: MemSize) -> &'static str {
+    match size {
+        MemSize::Byte => "b",
+        MemSize::Half => "h",
+        MemSize::Word | MemSize::Double => "",
+    }
+}
+
+impl Display for AtomicOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ldxr { size, acquire, dst, addr } => {
+                let a = if *acquire { "a" } else { "" };
+                write!(f, "ld{}xr{} {}, [{}]", a, size_suffix(*size), sized_gpr(*dst, *size), addr)
+            }
+            Self::Stxr { size, release, status, src, addr } => {
+                let l = if *release { "l" } else { "" };
+                let src = sized_gpr(*src, *size);
+                write!(f, "st{}xr{} {}, {}, [{}]", l, size_suffix(*size), status.w_name(), src, addr)
+            }
+            Self::Ldadd { order, size, src, dst, addr } => {
+                let (src, dst) = (sized_gpr(*src, *size), sized_gpr(*dst, *size));
+                write!(f, "ldadd{}{} {}, {}, [{}]", order.suffix(), size_suffix(*size), src, dst, addr)
+            }
+            Self::Swp { order, size, src, dst, addr } => {
+                let (src, dst) = (sized_gpr(*src, *size), sized_gpr(*dst, *size));
+                write!(f, "swp{}{} {}, {}, [{}]", order.suffix(), size_suffix(*size), src, dst, addr)
+            }
+            Self::Cas { order, size, expected, new, addr } => {
+                let (expected, new) = (sized_gpr(*expected, *size), sized_gpr(*new, *size));
+                write!(f, "cas{}{} {}, {}, [{}]", order.suffix(), size_suffix(*size), expected, new, addr)
+            }
+        }
+    }
+}
+
+impl Display for SystemOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Svc { number } => write!(f, "svc #{}", number),
+            Self::Msr { dst, src } => write!(f, "msr {}, {}", dst, src),
+            Self::Mrs { dst, src } => write!(f, "mrs {}, {}", dst, src),
+            Self::Sys { op1, crn, crm, op2, src } => write!(f, "sys #{}, c{}, c{}, #{}, {}", op1, crn, crm, op2, src),
+            Self::Sysl { dst, op1, crn, crm, op2 } => write!(f, "sysl {}, #{}, c{}, c{}, #{}", dst, op1, crn, crm, op2),
+            Self::Dmb { domain } => write!(f, "dmb {}", domain.as_str()),
+            Self::Dsb { domain } => write!(f, "dsb {}", domain.as_str()),
+            Self::Isb => write!(f, "isb"),
+            Self::Eret => write!(f, "eret"),
+        }
+    }
+}
+
+impl Display for AddressOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Adr { dst, label } => write!(f, "adr {}, {}", dst, label),
+            Self::Adrp { dst, label } => write!(f, "adrp {}, {}", dst, label),
+            Self::AdrpAdd { dst, base, label } => {
+                writeln!(f, "adrp {}, {}@PAGE", base, label)?;
+                write!(f, "    add {}, {}, {}@PAGEOFF", dst, base, label)
+            }
+            Self::AdrpGot { dst, label } => {
+                writeln!(f, "adrp {}, {}@GOTPAGE", dst, label)?;
+                write!(f, "    ldr {}, [{}, {}@GOTPAGEOFF]", dst, dst, label)
+            }
+        }
+    }
+}
+
+impl Display for MoveOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (mnemonic, dst, imm, shift) = match self {
+            Self::Movz { dst, imm, shift } => ("movz", dst, imm, shift),
+            Self::Movn { dst, imm, shift } => ("movn", dst, imm, shift),
+            Self::Movk { dst, imm, shift } => ("movk", dst, imm, shift),
+        };
+        match shift {
+            0 => write!(f, "{} {}, #{:#x}", mnemonic, dst, imm),
+            _ => write!(f, "{} {}, #{:#x}, lsl #{}", mnemonic, dst, imm, shift),
+        }
+    }
+}
+
+impl Display for DataDirective {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Align(power) => write!(f, ".p2align {}", power),
+            Self::Byte(bytes) => {
+                let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:#x}", byte)).collect();
+                write!(f, ".byte {}", bytes.join(", "))
+            }
+            Self::Hword(value) => write!(f, ".hword {:#x}", value),
+            Self::Word(value) => write!(f, ".word {}", value),
+            Self::Quad(value) => write!(f, ".quad {:#x}", value),
+            Self::LabelDiff { target, base } => write!(f, ".word {} - {}", target, base),
+            Self::Asciz(value) => write!(f, ".asciz {}", quote(value)),
+            Self::Zero(size) => write!(f, ".zero {}", size),
+            Self::Pointer { symbol, offset } => match offset {
+                0 => write!(f, ".quad {}", symbol),
+                offset if *offset < 0 => write!(f, ".quad {}-{}", symbol, offset.unsigned_abs()),
+                offset => write!(f, ".quad {}+{}", symbol, offset),
+            },
+        }
+    }
+}
+
+fn write_listing(f: &mut impl fmt::Write, instructions: &[Instruction]) -> fmt::Result {
+    for inst in instructions {
+        match inst {
+            Instruction::Label(_) => writeln!(f, "{}", inst)?,
+            _ => writeln!(f, "    {}", inst)?,
+        }
+    }
+    Ok(())
+}
+
+/// `inst` with its page and GOT operands in `platform`'s syntax. The
+/// [`Display`] of an instruction uses the Mach-O syntax.
+fn render(inst: &Instruction, platform: &impl Platform) -> String {
+    match inst {
+        Instruction::Address(AddressOp::AdrpAdd { dst, base, label }) => {
+            let (page, offset) = platform.page_operands(label);
+            format!("adrp {}, {}\n    add {}, {}, {}", base, page, dst, base, offset)
+        }
+        Instruction::Address(AddressOp::AdrpGot { dst, label }) => {
+            let (page, offset) = platform.got_page_operands(label);
+            format!("adrp {}, {}\n    ldr {}, [{}, {}]", dst, page, dst, dst, offset)
+        }
+        inst => inst.to_string(),
+    }
+}
+
+impl ARM64 {
+    /// The listing for `platform`'s assembler. [`Display`] writes the one
+    /// for [`MacOS`].
+    pub fn listing(&self, platform: &impl Platform) -> String {
+        let mut out = String::new();
+        self.write_text(&mut out, platform, false).unwrap();
+        out
+    }
+
+    /// Writes the text, then the read-only data. Each instruction is
+    /// followed by its comment, and with `call_sites` by the generator call
+    /// site that emitted it in an aligned column.
+    pub(crate) fn write_text(&self, f: &mut impl fmt::Write, platform: &impl Platform, call_sites: bool) -> fmt::Result {
+        for emitted in &self.instructions {
+            let line = match &emitted.inst {
+                Instruction::Label(_) => render(&emitted.inst, platform),
+                inst => format!("    {}", render(inst, platform)),
+            };
+            let mut notes = Vec::new();
+            if let (true, Some(location)) = (call_sites, emitted.source_location) {
+                notes.push(location.to_string());
+            }
+            notes.extend(emitted.comment.clone());
+            match (notes.is_empty(), call_sites) {
+                (true, _) => writeln!(f, "{}", line)?,
+                (false, false) => writeln!(f, "{} // {}", line, notes.join(": "))?,
+                (false, true) => writeln!(f, "{:<40} // {}", line, notes.join(": "))?,
+            }
+        }
+        // Literals still pending are placed after the last instruction
+        write_listing(f, &self.trailing_pool())?;
+        if !self.rodata.is_empty() {
+            writeln!(f, "{}", platform.rodata_section())?;
+            write_listing(f, &self.rodata)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ARM64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_text(f, &MacOS, false)
+    }
+}
+
+impl From<(GenericRegister, ShiftKind, u8)> for Operand<Arm64Register> {
+    fn from((reg, shift, amount): (GenericRegister, ShiftKind, u8)) -> Self {
+        Operand::Shifted(reg.to_arch_reg(), shift, amount)
+    }
+}
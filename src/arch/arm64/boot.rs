@@ -0,0 +1,176 @@
+//! Bare-metal support: the EL1 exception vector table and exception level
+//! transitions.
+//!
+//! [`VectorTable::emit`] lays out the 16 architectural entries in a 2KB
+//! aligned block. Every entry branches to a stub that saves the interrupted
+//! context in an [`FRAME_SIZE`] byte frame on the stack, calls the handler
+//! with the frame address in X0 and restores the context before `eret`.
+//! Handlers may rewrite the frame, for example to return a value in X0.
+//! [`LinkerScript`](crate::program::image::LinkerScript) places the table
+//! for `ld` links.
+
+use super::*;
+
+/// Size of the frame built by the handler stubs: X0-X30 at `8 * n`, then
+/// ELR_EL1 and SPSR_EL1, padded to keep SP 16-byte aligned.
+pub const FRAME_SIZE: i64 = 272;
+pub const FRAME_ELR: i64 = 248;
+pub const FRAME_SPSR: i64 = 256;
+
+/// HCR_EL2.RW: EL1 executes in AArch64
+const HCR_EL2_RW: u64 = 1 << 31;
+/// SPSR value entering EL1 on SP_EL1 with all interrupts masked
+const SPSR_EL1H_MASKED: u64 = 0x3c5;
+/// SPSR value entering EL0 with interrupts unmasked
+const SPSR_EL0T: u64 = 0;
+
+const PAIRS: [(Arm64Register, Arm64Register); 15] = [
+    (Arm64Register::X0, Arm64Register::X1),
+    (Arm64Register::X2, Arm64Register::X3),
+    (Arm64Register::X4, Arm64Register::X5),
+    (Arm64Register::X6, Arm64Register::X7),
+    (Arm64Register::X8, Arm64Register::X9),
+    (Arm64Register::X10, Arm64Register::X11),
+    (Arm64Register::X12, Arm64Register::X13),
+    (Arm64Register::X14, Arm64Register::X15),
+    (Arm64Register::X16, Arm64Register::X17),
+    (Arm64Register::X18, Arm64Register::X19),
+    (Arm64Register::X20, Arm64Register::X21),
+    (Arm64Register::X22, Arm64Register::X23),
+    (Arm64Register::X24, Arm64Register::X25),
+    (Arm64Register::X26, Arm64Register::X27),
+    (Arm64Register::X28, Arm64Register::X29),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExceptionKind {
+    Synchronous,
+    Irq,
+    Fiq,
+    SError,
+}
+
+/// Where the exception was taken from, selecting a group of four entries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExceptionSource {
+    CurrentElSp0,
+    CurrentElSpx,
+    LowerElAarch64,
+    LowerElAarch32,
+}
+
+pub struct VectorTable {
+    name: String,
+    handlers: [Option<String>; 16],
+}
+
+impl VectorTable {
+    /// A table placed at the label `name` with every entry unhandled.
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), handlers: Default::default() }
+    }
+
+    /// Routes one entry to the function at `label`. Entries without a
+    /// handler spin in place.
+    pub fn handler(&mut self, source: ExceptionSource, kind: ExceptionKind, label: &str) -> &mut Self {
+        self.handlers[Self::entry(source, kind)] = Some(label.to_string());
+        self
+    }
+
+    /// Offset of an entry from VBAR_EL1.
+    pub fn offset(source: ExceptionSource, kind: ExceptionKind) -> u64 {
+        Self::entry(source, kind) as u64 * 0x80
+    }
+
+    fn entry(source: ExceptionSource, kind: ExceptionKind) -> usize {
+        source as usize * 4 + kind as usize
+    }
+
+    /// Emits the table followed by the handler stubs.
+    pub fn emit(&self, arch: &mut ARM64) {
+        let unhandled = format!("L{}_unhandled", self.name);
+        arch.flush_literal_pool();
+        arch.push(Instruction::Data(DataDirective::Align(11)));
+        arch.label(&self.name);
+        for (index, handler) in self.handlers.iter().enumerate() {
+            match handler {
+                Some(_) => arch.b(&self.stub(index)),
+                None => arch.b(&unhandled),
+            }
+            // Each entry occupies 32 instructions
+            arch.push(Instruction::Data(DataDirective::Align(7)));
+        }
+
+        for (index, handler) in self.handlers.iter().enumerate() {
+            if let Some(handler) = handler {
+                arch.label(&self.stub(index));
+                save_context(arch);
+                arch.mov(Arm64Register::X0, Arm64Register::SP);
+                arch.bl(handler);
+                restore_context(arch);
+                arch.eret();
+            }
+        }
+
+        arch.label(&unhandled);
+        arch.b(&unhandled);
+    }
+
+    /// Points VBAR_EL1 at the table.
+    pub fn install(&self, arch: &mut ARM64, scratch: Arm64Register) {
+        arch.adr(scratch, &self.name);
+        arch.msr(SystemRegister::VbarEl1, scratch);
+        arch.isb();
+    }
+
+    fn stub(&self, index: usize) -> String {
+        format!("L{}_entry{}", self.name, index)
+    }
+}
+
+fn save_context(arch: &mut ARM64) {
+    let (first, second) = PAIRS[0];
+    arch.stp(first, second, MemOperand::PreIndex(Arm64Register::SP, -FRAME_SIZE));
+    for (index, (first, second)) in PAIRS.iter().enumerate().skip(1) {
+        arch.stp(*first, *second, MemOperand::Offset(Arm64Register::SP, 16 * index as i64));
+    }
+    // X0 and X1 are saved, so they can carry the exception state
+    arch.mrs(Arm64Register::X0, SystemRegister::ElrEl1);
+    arch.mrs(Arm64Register::X1, SystemRegister::SpsrEl1);
+    arch.stp(Arm64Register::X30, Arm64Register::X0, MemOperand::Offset(Arm64Register::SP, FRAME_ELR - 8));
+    arch.store(MemSize::Double, Arm64Register::X1, MemOperand::Offset(Arm64Register::SP, FRAME_SPSR));
+}
+
+fn restore_context(arch: &mut ARM64) {
+    arch.load(MemSize::Double, false, Arm64Register::X1, MemOperand::Offset(Arm64Register::SP, FRAME_SPSR));
+    arch.ldp(Arm64Register::X30, Arm64Register::X0, MemOperand::Offset(Arm64Register::SP, FRAME_ELR - 8));
+    arch.msr(SystemRegister::ElrEl1, Arm64Register::X0);
+    arch.msr(SystemRegister::SpsrEl1, Arm64Register::X1);
+    for (index, (first, second)) in PAIRS.iter().enumerate().skip(1) {
+        arch.ldp(*first, *second, MemOperand::Offset(Arm64Register::SP, 16 * index as i64));
+    }
+    let (first, second) = PAIRS[0];
+    arch.ldp(first, second, MemOperand::PostIndex(Arm64Register::SP, FRAME_SIZE));
+}
+
+/// Drops from EL2 to `target` at EL1, running AArch64 on SP_EL1 with
+/// interrupts masked.
+pub fn enter_el1(arch: &mut ARM64, target: &str, scratch: Arm64Register) {
+    arch.mov_imm(scratch, HCR_EL2_RW);
+    arch.msr(SystemRegister::HcrEl2, scratch);
+    arch.mov_imm(scratch, SPSR_EL1H_MASKED);
+    arch.msr(SystemRegister::SpsrEl2, scratch);
+    arch.adr(scratch, target);
+    arch.msr(SystemRegister::ElrEl2, scratch);
+    arch.eret();
+}
+
+/// Drops from EL1 to `target` at EL0 with `stack` as SP_EL0.
+pub fn enter_el0(arch: &mut ARM64, target: &str, stack: Arm64Register, scratch: Arm64Register) {
+    arch.msr(SystemRegister::SpEl0, stack);
+    arch.mov_imm(scratch, SPSR_EL0T);
+    arch.msr(SystemRegister::SpsrEl1, scratch);
+    arch.adr(scratch, target);
+    arch.msr(SystemRegister::ElrEl1, scratch);
+    arch.eret();
+}
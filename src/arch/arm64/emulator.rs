@@ -0,0 +1,1097 @@
+//! A small interpreter for [`ARM64`] instruction streams.
+//!
+//! The emulator works on the instruction enums directly rather than on
+//! encoded machine code, so anything the builders can produce can be run and
+//! checked on a host of any architecture. Text is placed at [`TEXT_BASE`],
+//! read-only data on the following page and a stack below [`STACK_TOP`].
+//!
+//! Code runs at EL0 unless CurrentEL is set. Once VBAR_EL1 points at a vector
+//! table, `svc` enters EL1 through it and `eret` returns using the ELR/SPSR
+//! of the current level; other exceptions are not modelled.
+
+use super::layout::Layout;
+use super::validate::{validate, ValidationError};
+use super::*;
+use std::collections::HashMap;
+
+pub const TEXT_BASE: u64 = 0x10000;
+pub const STACK_TOP: u64 = 0x8000_0000;
+pub const STACK_SIZE: u64 = 1 << 20;
+
+/// Link register value installed by [`Emulator::call`]; returning to it ends
+/// the call.
+const RETURN_ADDRESS: u64 = 0xdead_0000;
+
+const DEFAULT_STEP_LIMIT: u64 = 10_000_000;
+
+#[derive(Debug, PartialEq)]
+pub enum EmulatorError {
+    UndefinedLabel(String),
+    DuplicateLabel(String),
+    UnmappedMemory(u64),
+    InvalidBranchTarget(u64),
+    Unsupported(String),
+    StepLimit(u64),
+    Invalid(ValidationError),
+}
+
+struct Region {
+    base: u64,
+    bytes: Vec<u8>,
+}
+
+/// NZCV condition flags.
+#[derive(Default, Clone, Copy)]
+struct Flags {
+    n: bool,
+    z: bool,
+    c: bool,
+    v: bool,
+}
+
+enum Flow {
+    Next,
+    Jump(u64),
+}
+
+pub struct Emulator {
+    layout: Layout,
+    /// Taken out of `layout` so instructions can be borrowed while executing
+    text: Vec<Instruction>,
+    /// Index of the first instruction placed at each text address
+    address_index: HashMap<u64, usize>,
+    label_index: HashMap<String, usize>,
+    regs: [u64; 31],
+    sp: u64,
+    vregs: [u128; 32],
+    flags: Flags,
+    memory: Vec<Region>,
+    /// Address and length marked by the last load exclusive. Any write that
+    /// overlaps it clears the mark, as another observer's store would.
+    exclusive: Option<(u64, usize)>,
+    /// System registers without special behaviour hold whatever was written
+    system: HashMap<SystemRegister, u64>,
+    /// Instructions executed across all calls, read back as CNTVCT_EL0
+    retired: u64,
+    step_limit: u64,
+}
+
+impl Emulator {
+    pub fn new(arch: &ARM64) -> Result<Self, EmulatorError> {
+        validate(arch).map_err(EmulatorError::Invalid)?;
+        let mut layout = Layout::new(arch, TEXT_BASE).map_err(EmulatorError::DuplicateLabel)?;
+        let text = std::mem::take(&mut layout.text);
+
+        let mut address_index = HashMap::new();
+        let mut label_index = HashMap::new();
+        for (index, inst) in text.iter().enumerate() {
+            address_index.entry(layout.text_addresses[index]).or_insert(index);
+            if let Instruction::Label(name) = inst {
+                label_index.insert(name.clone(), index);
+            }
+        }
+
+        let mut emulator = Self {
+            layout,
+            text,
+            address_index,
+            label_index,
+            regs: [0; 31],
+            sp: STACK_TOP,
+            vregs: [0; 32],
+            flags: Flags::default(),
+            memory: Vec::new(),
+            exclusive: None,
+            system: HashMap::new(),
+            retired: 0,
+            step_limit: DEFAULT_STEP_LIMIT,
+        };
+
+        let (text_base, text_end) = (emulator.layout.text_base, emulator.layout.text_end);
+        let (rodata_base, rodata_end) = (emulator.layout.rodata_base, emulator.layout.rodata_end);
+        emulator.map(text_base, text_end - text_base);
+        emulator.map(rodata_base, rodata_end - rodata_base);
+        emulator.map(STACK_TOP - STACK_SIZE, STACK_SIZE);
+
+        let mut data = Vec::new();
+        for (index, inst) in emulator.text.iter().enumerate() {
+            if let Instruction::Data(directive) = inst {
+                data.push((directive.clone(), emulator.layout.text_addresses[index]));
+            }
+        }
+        for (index, inst) in emulator.layout.rodata.iter().enumerate() {
+            if let Instruction::Data(directive) = inst {
+                data.push((directive.clone(), emulator.layout.rodata_addresses[index]));
+            }
+        }
+        for (directive, addr) in data {
+            let bytes = emulator.layout.data_bytes(&directive, addr).map_err(EmulatorError::UndefinedLabel)?;
+            emulator.write_memory(addr, &bytes)?;
+        }
+
+        Ok(emulator)
+    }
+
+    /// Maps `size` zeroed bytes at `base`.
+    pub fn map(&mut self, base: u64, size: u64) {
+        self.memory.push(Region { base, bytes: vec![0; size as usize] });
+    }
+
+    pub fn read_memory(&self, addr: u64, len: usize) -> Result<Vec<u8>, EmulatorError> {
+        let region = self
+            .memory
+            .iter()
+            .find(|region| contains(region, addr, len))
+            .ok_or(EmulatorError::UnmappedMemory(addr))?;
+        let start = (addr - region.base) as usize;
+        Ok(region.bytes[start..start + len].to_vec())
+    }
+
+    pub fn write_memory(&mut self, addr: u64, bytes: &[u8]) -> Result<(), EmulatorError> {
+        let region = self
+            .memory
+            .iter_mut()
+            .find(|region| contains(region, addr, bytes.len()))
+            .ok_or(EmulatorError::UnmappedMemory(addr))?;
+        let start = (addr - region.base) as usize;
+        region.bytes[start..start + bytes.len()].copy_from_slice(bytes);
+        if let Some((marked, len)) = self.exclusive {
+            if addr < marked + len as u64 && marked < addr + bytes.len() as u64 {
+                self.exclusive = None;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn label_address(&self, name: &str) -> Option<u64> {
+        self.layout.label(name)
+    }
+
+    pub fn reg(&self, reg: impl Into<Arm64Register>) -> u64 {
+        self.get(reg.into())
+    }
+
+    pub fn set_reg(&mut self, reg: impl Into<Arm64Register>, value: u64) {
+        self.set(reg.into(), value);
+    }
+
+    /// The full 128-bit contents of a SIMD&FP register.
+    pub fn vreg(&self, reg: impl Into<Arm64Register>) -> u128 {
+        self.vregs[reg.into().index() as usize]
+    }
+
+    pub fn set_vreg(&mut self, reg: impl Into<Arm64Register>, value: u128) {
+        self.vregs[reg.into().index() as usize] = value;
+    }
+
+    /// The condition flags in the layout of the NZCV system register.
+    pub fn nzcv(&self) -> u64 {
+        let Flags { n, z, c, v } = self.flags;
+        (n as u64) << 31 | (z as u64) << 30 | (c as u64) << 29 | (v as u64) << 28
+    }
+
+    /// The value `mrs` would read from a system register.
+    pub fn sysreg(&self, reg: SystemRegister) -> u64 {
+        match reg {
+            SystemRegister::Nzcv => self.nzcv(),
+            SystemRegister::CntvctEl0 => self.retired,
+            _ => self.system.get(&reg).copied().unwrap_or(0),
+        }
+    }
+
+    pub fn set_sysreg(&mut self, reg: SystemRegister, value: u64) {
+        match reg {
+            SystemRegister::Nzcv => {
+                let bit = |n: u32| value >> n & 1 != 0;
+                self.flags = Flags { n: bit(31), z: bit(30), c: bit(29), v: bit(28) };
+            }
+            // The counter is read-only
+            SystemRegister::CntvctEl0 => {}
+            _ => {
+                self.system.insert(reg, value);
+            }
+        }
+    }
+
+    /// Caps the number of instructions a single [`Self::call`] may execute.
+    pub fn set_step_limit(&mut self, limit: u64) {
+        self.step_limit = limit;
+    }
+
+    /// Calls the function at `label` with `args` in X0.. and runs until it
+    /// returns, yielding X0.
+    pub fn call(&mut self, label: &str, args: &[u64]) -> Result<u64, EmulatorError> {
+        if args.len() > 8 {
+            return Err(EmulatorError::Unsupported(format!("{} arguments", args.len())));
+        }
+        for (index, arg) in args.iter().enumerate() {
+            self.regs[index] = *arg;
+        }
+        self.regs[30] = RETURN_ADDRESS;
+        self.sp = STACK_TOP;
+        self.exclusive = None;
+
+        let start = *self
+            .label_index
+            .get(label)
+            .ok_or_else(|| EmulatorError::UndefinedLabel(label.to_string()))?;
+        let text = std::mem::take(&mut self.text);
+        let result = self.run(&text, start);
+        self.text = text;
+        result.map(|()| self.regs[0])
+    }
+
+    fn run(&mut self, text: &[Instruction], mut index: usize) -> Result<(), EmulatorError> {
+        let mut steps = 0;
+        loop {
+            let Some(inst) = text.get(index) else {
+                return Err(EmulatorError::InvalidBranchTarget(self.layout.text_end));
+            };
+            let addr = self.layout.text_addresses[index];
+            let flow = match inst {
+                Instruction::Label(_) | Instruction::Data(DataDirective::Align(_)) => Flow::Next,
+                Instruction::Data(_) => {
+                    return Err(EmulatorError::Unsupported(format!("executing data at {:#x}", addr)));
+                }
+                _ => {
+                    steps += 1;
+                    if steps > self.step_limit {
+                        return Err(EmulatorError::StepLimit(self.step_limit));
+                    }
+                    self.retired += 1;
+                    self.execute(inst, addr)?
+                }
+            };
+            index = match flow {
+                Flow::Next => index + 1,
+                Flow::Jump(RETURN_ADDRESS) => return Ok(()),
+                Flow::Jump(target) => *self
+                    .address_index
+                    .get(&target)
+                    .ok_or(EmulatorError::InvalidBranchTarget(target))?,
+            };
+        }
+    }
+
+    fn execute(&mut self, inst: &Instruction, addr: u64) -> Result<Flow, EmulatorError> {
+        match inst {
+            Instruction::Arithmetic(op) => self.arithmetic(op)?,
+            Instruction::Float(op) => self.float(op),
+            Instruction::Vector(op) => self.vector(op)?,
+            Instruction::Logical(op) => self.logical(op),
+            Instruction::Shift(op) => self.shift(op),
+            Instruction::Bitfield(op) => self.bitfield(op),
+            Instruction::Bit(op) => self.bit(op),
+            Instruction::Branch(op) => return self.branch(op, addr),
+            Instruction::LoadStore(op) => self.load_store(op)?,
+            Instruction::Address(op) => self.address(op)?,
+            Instruction::Move(op) => self.mov(op),
+            Instruction::Atomic(op) => self.atomic(op)?,
+            // With a single observer every access is already ordered
+            Instruction::System(SystemOp::Svc { number }) => return self.supervisor_call(*number, addr),
+            Instruction::System(SystemOp::Eret) => return self.exception_return(),
+            Instruction::System(SystemOp::Dmb { .. } | SystemOp::Dsb { .. } | SystemOp::Isb) => {}
+            Instruction::System(SystemOp::Msr { dst, src }) => self.set_sysreg(*dst, self.get(*src)),
+            Instruction::System(SystemOp::Mrs { dst, src }) => self.set(*dst, self.sysreg(*src)),
+            Instruction::System(op) => return Err(EmulatorError::Unsupported(op.to_string())),
+            Instruction::Label(_) | Instruction::Data(_) => {}
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Takes a synchronous exception to EL1 through VBAR_EL1, as from EL0
+    /// or from EL1 using SP_EL1. Without a vector table the call is
+    /// unsupported.
+    fn supervisor_call(&mut self, number: u32, addr: u64) -> Result<Flow, EmulatorError> {
+        let vbar = self.sysreg(SystemRegister::VbarEl1);
+        if vbar == 0 {
+            return Err(EmulatorError::Unsupported(format!("svc #{} without a vector table", number)));
+        }
+        let el = self.exception_level();
+        // SPSR.M: EL0t for EL0, EL1h for EL1
+        let mode = if el == 0 { 0 } else { el << 2 | 1 };
+        let spsr = self.nzcv() | self.sysreg(SystemRegister::Daif) | mode;
+        self.set_sysreg(SystemRegister::SpsrEl1, spsr);
+        self.set_sysreg(SystemRegister::ElrEl1, addr + 4);
+        // EC 0x15 is an SVC from AArch64; IL marks a 32-bit instruction
+        self.set_sysreg(SystemRegister::EsrEl1, 0x15 << 26 | 1 << 25 | number as u64);
+        self.set_sysreg(SystemRegister::Daif, 0x3c0);
+        self.set_sysreg(SystemRegister::CurrentEl, 1 << 2);
+        let offset = if el == 0 { 0x400 } else { 0x200 };
+        Ok(Flow::Jump(vbar + offset))
+    }
+
+    /// Restores the flags, interrupt masks and exception level saved in
+    /// SPSR and branches to ELR of the current exception level.
+    fn exception_return(&mut self) -> Result<Flow, EmulatorError> {
+        let (spsr, elr) = match self.exception_level() {
+            1 => (SystemRegister::SpsrEl1, SystemRegister::ElrEl1),
+            2 => (SystemRegister::SpsrEl2, SystemRegister::ElrEl2),
+            el => return Err(EmulatorError::Unsupported(format!("eret at EL{}", el))),
+        };
+        let (spsr, elr) = (self.sysreg(spsr), self.sysreg(elr));
+        self.set_sysreg(SystemRegister::Nzcv, spsr & 0xf000_0000);
+        self.set_sysreg(SystemRegister::Daif, spsr & 0x3c0);
+        self.set_sysreg(SystemRegister::CurrentEl, spsr & 0b1100);
+        Ok(Flow::Jump(elr))
+    }
+
+    fn exception_level(&self) -> u64 {
+        self.sysreg(SystemRegister::CurrentEl) >> 2 & 0b11
+    }
+
+    fn arithmetic(&mut self, op: &ArithmeticOp) -> Result<(), EmulatorError> {
+        match op {
+            ArithmeticOp::Add { dst, src1, src2 } => {
+                self.set(*dst, self.get(*src1).wrapping_add(self.get(*src2)))
+            }
+            ArithmeticOp::AddImm { dst, src1, imm } => {
+                let imm = self.resolve_imm(imm)?;
+                self.set(*dst, self.get(*src1).wrapping_add(imm))
+            }
+            ArithmeticOp::AddShifted { dst, src1, src2, shift, amount } => {
+                let src2 = shifted(self.get(*src2), *shift, *amount, width(*src2));
+                self.set(*dst, self.get(*src1).wrapping_add(src2))
+            }
+            ArithmeticOp::Sub { dst, src1, src2 } => {
+                self.set(*dst, self.get(*src1).wrapping_sub(self.get(*src2)))
+            }
+            ArithmeticOp::Mul { dst, src1, src2 } => {
+                self.set(*dst, self.get(*src1).wrapping_mul(self.get(*src2)))
+            }
+            // Division by zero yields zero rather than trapping
+            ArithmeticOp::Sdiv { dst, src1, src2 } => {
+                let (n, d) = (self.signed(*src1), self.signed(*src2));
+                self.set(*dst, if d == 0 { 0 } else { n.wrapping_div(d) as u64 })
+            }
+            ArithmeticOp::Udiv { dst, src1, src2 } => {
+                let (n, d) = (self.get(*src1), self.get(*src2));
+                self.set(*dst, n.checked_div(d).unwrap_or(0))
+            }
+            ArithmeticOp::Madd { dst, src1, src2, acc } => {
+                let product = self.get(*src1).wrapping_mul(self.get(*src2));
+                self.set(*dst, self.get(*acc).wrapping_add(product))
+            }
+            ArithmeticOp::Msub { dst, src1, src2, acc } => {
+                let product = self.get(*src1).wrapping_mul(self.get(*src2));
+                self.set(*dst, self.get(*acc).wrapping_sub(product))
+            }
+            ArithmeticOp::Mneg { dst, src1, src2 } => {
+                self.set(*dst, self.get(*src1).wrapping_mul(self.get(*src2)).wrapping_neg())
+            }
+            ArithmeticOp::Smull { dst, src1, src2 } => {
+                self.set(*dst, self.smull(*src1, *src2))
+            }
+            ArithmeticOp::Umull { dst, src1, src2 } => {
+                self.set(*dst, self.umull(*src1, *src2))
+            }
+            ArithmeticOp::Smulh { dst, src1, src2 } => {
+                let product = self.get(*src1) as i64 as i128 * self.get(*src2) as i64 as i128;
+                self.set(*dst, (product >> 64) as u64)
+            }
+            ArithmeticOp::Umulh { dst, src1, src2 } => {
+                let product = self.get(*src1) as u128 * self.get(*src2) as u128;
+                self.set(*dst, (product >> 64) as u64)
+            }
+            ArithmeticOp::Smaddl { dst, src1, src2, acc } => {
+                self.set(*dst, self.get(*acc).wrapping_add(self.smull(*src1, *src2)))
+            }
+            ArithmeticOp::Umaddl { dst, src1, src2, acc } => {
+                self.set(*dst, self.get(*acc).wrapping_add(self.umull(*src1, *src2)))
+            }
+            ArithmeticOp::Cmp { src1, src2 } => self.compare(self.get(*src1), self.get(*src2), width(*src1)),
+            ArithmeticOp::CmpImm { src1, imm } => {
+                let imm = self.resolve_imm(imm)?;
+                self.compare(self.get(*src1), imm, width(*src1))
+            }
+            ArithmeticOp::CmpShifted { src1, src2, shift, amount } => {
+                let src2 = shifted(self.get(*src2), *shift, *amount, width(*src2));
+                self.compare(self.get(*src1), src2, width(*src1))
+            }
+            ArithmeticOp::Cset { dst, cond } => self.set(*dst, self.holds(*cond) as u64),
+            ArithmeticOp::Csel { dst, src1, src2, cond } => {
+                let value = if self.holds(*cond) { self.get(*src1) } else { self.get(*src2) };
+                self.set(*dst, value)
+            }
+        }
+        Ok(())
+    }
+
+    fn smull(&self, src1: Arm64Register, src2: Arm64Register) -> u64 {
+        (self.get(src1) as i32 as i64).wrapping_mul(self.get(src2) as i32 as i64) as u64
+    }
+
+    fn umull(&self, src1: Arm64Register, src2: Arm64Register) -> u64 {
+        (self.get(src1) as u32 as u64) * (self.get(src2) as u32 as u64)
+    }
+
+    /// Sets the flags as `subs xzr, lhs, rhs` (or `subs wzr, ..` for a
+    /// width of 32) would.
+    fn compare(&mut self, lhs: u64, rhs: u64, width: u32) {
+        if width == 32 {
+            let (lhs, rhs) = (lhs as u32, rhs as u32);
+            let result = lhs.wrapping_sub(rhs);
+            self.flags = Flags {
+                n: (result as i32) < 0,
+                z: result == 0,
+                c: lhs >= rhs,
+                v: (lhs as i32).checked_sub(rhs as i32).is_none(),
+            };
+            return;
+        }
+        let result = lhs.wrapping_sub(rhs);
+        self.flags = Flags {
+            n: (result as i64) < 0,
+            z: result == 0,
+            c: lhs >= rhs,
+            v: (lhs as i64).checked_sub(rhs as i64).is_none(),
+        };
+    }
+
+    fn holds(&self, cond: Condition) -> bool {
+        let Flags { n, z, c, v } = self.flags;
+        match cond {
+            Condition::Eq => z,
+            Condition::Ne => !z,
+            Condition::Hs => c,
+            Condition::Lo => !c,
+            Condition::Mi => n,
+            Condition::Pl => !n,
+            Condition::Vs => v,
+            Condition::Vc => !v,
+            Condition::Hi => c && !z,
+            Condition::Ls => !c || z,
+            Condition::Ge => n == v,
+            Condition::Lt => n != v,
+            Condition::Gt => !z && n == v,
+            Condition::Le => z || n != v,
+            Condition::Al => true,
+        }
+    }
+
+    fn float(&mut self, op: &FloatOp) {
+        match op {
+            FloatOp::Fadd { size, dst, src1, src2 } => self.fp_binary(*size, *dst, *src1, *src2, |a, b| a + b),
+            FloatOp::Fsub { size, dst, src1, src2 } => self.fp_binary(*size, *dst, *src1, *src2, |a, b| a - b),
+            FloatOp::Fmul { size, dst, src1, src2 } => self.fp_binary(*size, *dst, *src1, *src2, |a, b| a * b),
+            FloatOp::Fdiv { size, dst, src1, src2 } => self.fp_binary(*size, *dst, *src1, *src2, |a, b| a / b),
+            FloatOp::Fsqrt { size, dst, src } => {
+                let value = self.fp(*src, *size).sqrt();
+                self.set_fp(*dst, *size, value)
+            }
+            // Sign manipulation works on the bits so NaN payloads survive
+            FloatOp::Fneg { size, dst, src } => {
+                let sign = sign_bit(*size);
+                self.set_fp_bits(*dst, *size, self.fp_bits(*src, *size) ^ sign)
+            }
+            FloatOp::Fabs { size, dst, src } => {
+                let sign = sign_bit(*size);
+                self.set_fp_bits(*dst, *size, self.fp_bits(*src, *size) & !sign)
+            }
+            FloatOp::Fmadd { size, dst, src1, src2, acc } => {
+                let (a, b, c) = (self.fp(*src1, *size), self.fp(*src2, *size), self.fp(*acc, *size));
+                let value = match size {
+                    FpSize::Double => a.mul_add(b, c),
+                    _ => (a as f32).mul_add(b as f32, c as f32) as f64,
+                };
+                self.set_fp(*dst, *size, value)
+            }
+            FloatOp::Fcmp { size, src1, src2 } => {
+                let (a, b) = (self.fp(*src1, *size), self.fp(*src2, *size));
+                let (n, z, c, v) = match a.partial_cmp(&b) {
+                    Some(std::cmp::Ordering::Less) => (true, false, false, false),
+                    Some(std::cmp::Ordering::Equal) => (false, true, true, false),
+                    Some(std::cmp::Ordering::Greater) => (false, false, true, false),
+                    None => (false, false, true, true),
+                };
+                self.flags = Flags { n, z, c, v };
+            }
+            FloatOp::Fcsel { size, dst, src1, src2, cond } => {
+                let src = if self.holds(*cond) { src1 } else { src2 };
+                self.set_fp_bits(*dst, *size, self.fp_bits(*src, *size))
+            }
+            FloatOp::Fmov { size, dst, src } | FloatOp::FmovToFp { size, dst, src } => {
+                self.set_fp_bits(*dst, *size, self.fp_bits(*src, *size))
+            }
+            FloatOp::FmovFromFp { size, dst, src } => self.set(*dst, self.fp_bits(*src, *size)),
+            FloatOp::FmovImm { size, dst, imm } => self.set_fp(*dst, *size, *imm),
+            FloatOp::Scvtf { size, dst, src } => {
+                let value = self.signed(*src);
+                // Convert straight to the target precision to round once
+                match size {
+                    FpSize::Single => self.set_fp(*dst, *size, value as f32 as f64),
+                    _ => self.set_fp(*dst, *size, value as f64),
+                }
+            }
+            FloatOp::Ucvtf { size, dst, src } => {
+                let value = self.get(*src);
+                match size {
+                    FpSize::Single => self.set_fp(*dst, *size, value as f32 as f64),
+                    _ => self.set_fp(*dst, *size, value as f64),
+                }
+            }
+            // Rust's float to integer casts saturate and map NaN to zero,
+            // exactly as the architecture does
+            FloatOp::Fcvtzs { size, dst, src } => {
+                let value = self.fp(*src, *size);
+                let bits = if dst.is_32bit() { value as i32 as u32 as u64 } else { value as i64 as u64 };
+                self.set(*dst, bits)
+            }
+            FloatOp::Fcvtzu { size, dst, src } => {
+                let value = self.fp(*src, *size);
+                let bits = if dst.is_32bit() { value as u32 as u64 } else { value as u64 };
+                self.set(*dst, bits)
+            }
+            FloatOp::Fcvt { dst_size, dst, src_size, src } => {
+                let value = self.fp(*src, *src_size);
+                self.set_fp(*dst, *dst_size, value)
+            }
+        }
+    }
+
+    fn vector(&mut self, op: &VectorOp) -> Result<(), EmulatorError> {
+        match op {
+            VectorOp::Add { arrangement, dst, src1, src2 } => {
+                self.lanewise(*arrangement, *dst, *src1, *src2, |a, b| a.wrapping_add(b))
+            }
+            VectorOp::Sub { arrangement, dst, src1, src2 } => {
+                self.lanewise(*arrangement, *dst, *src1, *src2, |a, b| a.wrapping_sub(b))
+            }
+            VectorOp::Mul { arrangement, dst, src1, src2 } => {
+                self.lanewise(*arrangement, *dst, *src1, *src2, |a, b| a.wrapping_mul(b))
+            }
+            VectorOp::Fadd { arrangement, dst, src1, src2 } => {
+                self.lanewise_fp(*arrangement, *dst, *src1, *src2, |a, b| a + b)
+            }
+            VectorOp::Fsub { arrangement, dst, src1, src2 } => {
+                self.lanewise_fp(*arrangement, *dst, *src1, *src2, |a, b| a - b)
+            }
+            VectorOp::Fmul { arrangement, dst, src1, src2 } => {
+                self.lanewise_fp(*arrangement, *dst, *src1, *src2, |a, b| a * b)
+            }
+            // The registers of a multiple-structure access cover consecutive
+            // memory, so the element size only matters for byte order within
+            // lanes, which is little-endian either way
+            VectorOp::Ld1 { arrangement, first, count, addr } => {
+                let base = self.effective_address(addr);
+                let size = arrangement.bytes() as usize;
+                for offset in 0..*count {
+                    let bytes = self.read_memory(base + (offset as usize * size) as u64, size)?;
+                    let mut value = [0; 16];
+                    value[..size].copy_from_slice(&bytes);
+                    self.vregs[first.vector_offset(offset).index() as usize] = u128::from_le_bytes(value);
+                }
+            }
+            VectorOp::St1 { arrangement, first, count, addr } => {
+                let base = self.effective_address(addr);
+                let size = arrangement.bytes() as usize;
+                for offset in 0..*count {
+                    let value = self.vregs[first.vector_offset(offset).index() as usize].to_le_bytes();
+                    self.write_memory(base + (offset as usize * size) as u64, &value[..size])?;
+                }
+            }
+            VectorOp::Dup { arrangement, dst, src } => {
+                let value = self.get(*src);
+                self.set_lanes(*arrangement, *dst, |_| value);
+            }
+            VectorOp::DupLane { arrangement, dst, src, index } => {
+                let value = get_lane(self.vregs[src.index() as usize], arrangement.element(), *index as u32);
+                self.set_lanes(*arrangement, *dst, |_| value);
+            }
+            VectorOp::Ins { lane, dst, index, src } => {
+                let value = self.get(*src);
+                let reg = &mut self.vregs[dst.index() as usize];
+                *reg = set_lane(*reg, *lane, *index as u32, value);
+            }
+            VectorOp::Umov { lane, dst, src, index } => {
+                let value = get_lane(self.vregs[src.index() as usize], *lane, *index as u32);
+                self.set(*dst, value);
+            }
+            VectorOp::Addv { arrangement, dst, src } => {
+                let value = self.vregs[src.index() as usize];
+                let element = arrangement.element();
+                let sum = (0..arrangement.lanes()).fold(0u64, |sum, i| sum.wrapping_add(get_lane(value, element, i)));
+                self.vregs[dst.index() as usize] = set_lane(0, element, 0, sum);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes `lane(i)` to every lane of `dst`, clearing the upper half for
+    /// 64-bit arrangements.
+    fn set_lanes(&mut self, arrangement: VectorArrangement, dst: Arm64Register, lane: impl Fn(u32) -> u64) {
+        let element = arrangement.element();
+        let value = (0..arrangement.lanes()).fold(0, |value, i| set_lane(value, element, i, lane(i)));
+        self.vregs[dst.index() as usize] = value;
+    }
+
+    fn lanewise(&mut self, arrangement: VectorArrangement, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, op: fn(u64, u64) -> u64) {
+        let (a, b) = (self.vregs[src1.index() as usize], self.vregs[src2.index() as usize]);
+        let element = arrangement.element();
+        self.set_lanes(arrangement, dst, |i| op(get_lane(a, element, i), get_lane(b, element, i)));
+    }
+
+    fn lanewise_fp(&mut self, arrangement: VectorArrangement, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, op: fn(f64, f64) -> f64) {
+        let size = match arrangement.element() {
+            MemSize::Half => FpSize::Half,
+            MemSize::Word => FpSize::Single,
+            _ => FpSize::Double,
+        };
+        let (a, b) = (self.vregs[src1.index() as usize], self.vregs[src2.index() as usize]);
+        let element = arrangement.element();
+        self.set_lanes(arrangement, dst, |i| {
+            let lhs = bits_to_fp(get_lane(a, element, i), size);
+            let rhs = bits_to_fp(get_lane(b, element, i), size);
+            fp_to_bits(op(lhs, rhs), size)
+        });
+    }
+
+    fn fp_binary(&mut self, size: FpSize, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register, op: fn(f64, f64) -> f64) {
+        // Computing in double precision and rounding the result is exact
+        // for the basic operations on the narrower formats
+        let value = op(self.fp(src1, size), self.fp(src2, size));
+        self.set_fp(dst, size, value);
+    }
+
+    /// The low `size` bits of a register; general purpose registers are
+    /// accepted for the source of `fmov`.
+    fn fp_bits(&self, reg: Arm64Register, size: FpSize) -> u64 {
+        let bits = if reg.is_vector() { self.vregs[reg.index() as usize] as u64 } else { self.get(reg) };
+        match size {
+            FpSize::Half => bits & 0xffff,
+            FpSize::Single => bits & 0xffff_ffff,
+            FpSize::Double => bits,
+        }
+    }
+
+    /// Writes the scalar view of a V register, clearing the upper bits.
+    fn set_fp_bits(&mut self, reg: Arm64Register, size: FpSize, bits: u64) {
+        let mask = match size {
+            FpSize::Half => 0xffff,
+            FpSize::Single => 0xffff_ffff,
+            FpSize::Double => u64::MAX,
+        };
+        self.vregs[reg.index() as usize] = (bits & mask) as u128;
+    }
+
+    fn fp(&self, reg: Arm64Register, size: FpSize) -> f64 {
+        bits_to_fp(self.fp_bits(reg, size), size)
+    }
+
+    fn set_fp(&mut self, reg: Arm64Register, size: FpSize, value: f64) {
+        self.set_fp_bits(reg, size, fp_to_bits(value, size));
+    }
+
+    fn logical(&mut self, op: &LogicalOp) {
+        let (dst, lhs, rhs, result): (_, _, _, fn(u64, u64) -> u64) = match op {
+            LogicalOp::And { dst, src1, src2, shift } => (dst, self.get(*src1), self.operand(*src2, *shift), |a, b| a & b),
+            LogicalOp::Orr { dst, src1, src2, shift } => (dst, self.get(*src1), self.operand(*src2, *shift), |a, b| a | b),
+            LogicalOp::Eor { dst, src1, src2, shift } => (dst, self.get(*src1), self.operand(*src2, *shift), |a, b| a ^ b),
+            LogicalOp::Bic { dst, src1, src2, shift } => (dst, self.get(*src1), self.operand(*src2, *shift), |a, b| a & !b),
+            LogicalOp::Orn { dst, src1, src2, shift } => (dst, self.get(*src1), self.operand(*src2, *shift), |a, b| a | !b),
+            LogicalOp::Eon { dst, src1, src2, shift } => (dst, self.get(*src1), self.operand(*src2, *shift), |a, b| a ^ !b),
+            LogicalOp::AndImm { dst, src, imm } => (dst, self.get(*src), *imm, |a, b| a & b),
+            LogicalOp::OrrImm { dst, src, imm } => (dst, self.get(*src), *imm, |a, b| a | b),
+            LogicalOp::EorImm { dst, src, imm } => (dst, self.get(*src), *imm, |a, b| a ^ b),
+        };
+        self.set(*dst, result(lhs, rhs));
+    }
+
+    fn operand(&self, reg: Arm64Register, shift: Option<(ShiftKind, u8)>) -> u64 {
+        match shift {
+            Some((kind, amount)) => shifted(self.get(reg), kind, amount, width(reg)),
+            None => self.get(reg),
+        }
+    }
+
+    fn shift(&mut self, op: &ShiftOp) {
+        let (dst, value, kind, amount) = match op {
+            ShiftOp::LslImm { dst, src, amount } => (dst, self.get(*src), ShiftKind::Lsl, *amount),
+            ShiftOp::LsrImm { dst, src, amount } => (dst, self.get(*src), ShiftKind::Lsr, *amount),
+            ShiftOp::AsrImm { dst, src, amount } => (dst, self.get(*src), ShiftKind::Asr, *amount),
+            ShiftOp::RorImm { dst, src, amount } => (dst, self.get(*src), ShiftKind::Ror, *amount),
+            // Register shift amounts are taken modulo the register width
+            ShiftOp::Lsl { dst, src1, src2 } => (dst, self.get(*src1), ShiftKind::Lsl, self.shift_register(*dst, *src2)),
+            ShiftOp::Lsr { dst, src1, src2 } => (dst, self.get(*src1), ShiftKind::Lsr, self.shift_register(*dst, *src2)),
+            ShiftOp::Asr { dst, src1, src2 } => (dst, self.get(*src1), ShiftKind::Asr, self.shift_register(*dst, *src2)),
+            ShiftOp::Ror { dst, src1, src2 } => (dst, self.get(*src1), ShiftKind::Ror, self.shift_register(*dst, *src2)),
+        };
+        self.set(*dst, shifted(value, kind, amount, width(*dst)));
+    }
+
+    fn shift_register(&self, dst: Arm64Register, amount: Arm64Register) -> u8 {
+        (self.get(amount) % width(dst) as u64) as u8
+    }
+
+    fn bitfield(&mut self, op: &BitfieldOp) {
+        match op {
+            BitfieldOp::Ubfx { dst, src, lsb, width } => {
+                self.set(*dst, (self.get(*src) >> lsb) & mask(*width))
+            }
+            BitfieldOp::Sbfx { dst, src, lsb, width } => {
+                let field = (self.get(*src) >> lsb) & mask(*width);
+                let unused = 64 - *width as u32;
+                self.set(*dst, (((field << unused) as i64) >> unused) as u64)
+            }
+            BitfieldOp::Bfi { dst, src, lsb, width } => {
+                let field = mask(*width) << lsb;
+                let inserted = (self.get(*src) << lsb) & field;
+                self.set(*dst, (self.get(*dst) & !field) | inserted)
+            }
+            BitfieldOp::Ubfiz { dst, src, lsb, width } => {
+                self.set(*dst, (self.get(*src) & mask(*width)) << lsb)
+            }
+            BitfieldOp::Extr { dst, src1, src2, lsb } => {
+                let value = match lsb {
+                    0 => self.get(*src2),
+                    _ => (self.get(*src2) >> lsb) | (self.get(*src1) << (width(*dst) - *lsb as u32)),
+                };
+                self.set(*dst, value)
+            }
+        }
+    }
+
+    fn bit(&mut self, op: &BitOp) {
+        // 32-bit operands are moved to the top of the word so the 64-bit
+        // operations see the same bit positions
+        let (dst, src) = match op {
+            BitOp::Clz { dst, src }
+            | BitOp::Cls { dst, src }
+            | BitOp::Rbit { dst, src }
+            | BitOp::Rev { dst, src }
+            | BitOp::Rev16 { dst, src }
+            | BitOp::Rev32 { dst, src } => (dst, src),
+        };
+        let unused = 64 - width(*dst);
+        let value = self.get(*src);
+        let result = match op {
+            BitOp::Clz { .. } => (value << unused).leading_zeros().min(width(*dst)) as u64,
+            // Leading bits equal to the sign bit, not counting the sign bit
+            BitOp::Cls { .. } => {
+                let value = value << unused;
+                (((value ^ (value << 1)) | 1 << unused).leading_zeros()) as u64
+            }
+            BitOp::Rbit { .. } => (value << unused).reverse_bits(),
+            BitOp::Rev { .. } => (value << unused).swap_bytes(),
+            BitOp::Rev16 { .. } => {
+                ((value >> 8) & 0x00ff_00ff_00ff_00ff) | ((value & 0x00ff_00ff_00ff_00ff) << 8)
+            }
+            BitOp::Rev32 { .. } => {
+                let low = (value as u32).swap_bytes() as u64;
+                let high = ((value >> 32) as u32).swap_bytes() as u64;
+                high << 32 | low
+            }
+        };
+        self.set(*dst, result);
+    }
+
+    fn branch(&mut self, op: &BranchOp, addr: u64) -> Result<Flow, EmulatorError> {
+        let next = addr + 4;
+        Ok(match op {
+            BranchOp::Bl { label } => {
+                let target = self.label(label)?;
+                self.regs[30] = next;
+                Flow::Jump(target)
+            }
+            BranchOp::B { label } => Flow::Jump(self.label(label)?),
+            BranchOp::Ret => Flow::Jump(self.regs[30]),
+            BranchOp::Cbz { reg, label } => match self.get(*reg) {
+                0 => Flow::Jump(self.label(label)?),
+                _ => Flow::Next,
+            },
+            BranchOp::Cbnz { reg, label } => match self.get(*reg) {
+                0 => Flow::Next,
+                _ => Flow::Jump(self.label(label)?),
+            },
+            BranchOp::BCond { cond, label } => match self.holds(*cond) {
+                true => Flow::Jump(self.label(label)?),
+                false => Flow::Next,
+            },
+            BranchOp::Br { reg } => Flow::Jump(self.get(*reg)),
+            BranchOp::Blr { reg } => {
+                let target = self.get(*reg);
+                self.regs[30] = next;
+                Flow::Jump(target)
+            }
+        })
+    }
+
+    fn load_store(&mut self, op: &LoadStoreOp) -> Result<(), EmulatorError> {
+        match op {
+            LoadStoreOp::Ldr { dst, src } => {
+                let value = match src.strip_prefix('=') {
+                    Some(imm) => self.resolve_imm(imm)?,
+                    None => {
+                        let addr = self.symbolic_address(src)?;
+                        self.read(addr, width(*dst) as usize / 8)?
+                    }
+                };
+                self.set(*dst, value);
+            }
+            LoadStoreOp::Str { src, dst } => {
+                let addr = self.symbolic_address(dst)?;
+                self.write(addr, 8, self.get(*src))?;
+            }
+            LoadStoreOp::Load { size, signed, dst, addr } => {
+                let addr = self.effective_address(addr);
+                let bytes = size.bytes() as usize;
+                let value = self.read(addr, bytes)?;
+                let value = match (signed, bytes) {
+                    (true, 1) => value as i8 as u64,
+                    (true, 2) => value as i16 as u64,
+                    (true, 4) => value as i32 as u64,
+                    _ => value,
+                };
+                self.set(*dst, value);
+            }
+            LoadStoreOp::Store { size, src, addr } => {
+                let addr = self.effective_address(addr);
+                self.write(addr, size.bytes() as usize, self.get(*src))?;
+            }
+            LoadStoreOp::Ldp { dst1, dst2, addr } => {
+                let addr = self.effective_address(addr);
+                let bytes = width(*dst1) as usize / 8;
+                let (first, second) = (self.read(addr, bytes)?, self.read(addr + bytes as u64, bytes)?);
+                self.set(*dst1, first);
+                self.set(*dst2, second);
+            }
+            LoadStoreOp::Stp { src1, src2, addr } => {
+                let addr = self.effective_address(addr);
+                let bytes = width(*src1) as usize / 8;
+                self.write(addr, bytes, self.get(*src1))?;
+                self.write(addr + bytes as u64, bytes, self.get(*src2))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn atomic(&mut self, op: &AtomicOp) -> Result<(), EmulatorError> {
+        match op {
+            AtomicOp::Ldxr { size, dst, addr, .. } => {
+                let (addr, bytes) = (self.get(*addr), size.bytes() as usize);
+                let value = self.read(addr, bytes)?;
+                self.exclusive = Some((addr, bytes));
+                self.set(*dst, value);
+            }
+            AtomicOp::Stxr { size, status, src, addr, .. } => {
+                let (addr, bytes) = (self.get(*addr), size.bytes() as usize);
+                // The store only happens while the exact location is still marked
+                let marked = self.exclusive.take() == Some((addr, bytes));
+                if marked {
+                    self.write(addr, bytes, self.get(*src))?;
+                }
+                self.set(*status, !marked as u64);
+            }
+            AtomicOp::Ldadd { size, src, dst, addr, .. } => {
+                let (addr, bytes) = (self.get(*addr), size.bytes() as usize);
+                let old = self.read(addr, bytes)?;
+                self.write(addr, bytes, old.wrapping_add(self.get(*src)))?;
+                self.set(*dst, old);
+            }
+            AtomicOp::Swp { size, src, dst, addr, .. } => {
+                let (addr, bytes) = (self.get(*addr), size.bytes() as usize);
+                let old = self.read(addr, bytes)?;
+                self.write(addr, bytes, self.get(*src))?;
+                self.set(*dst, old);
+            }
+            AtomicOp::Cas { size, expected, new, addr, .. } => {
+                let (addr, bytes) = (self.get(*addr), size.bytes() as usize);
+                let old = self.read(addr, bytes)?;
+                if old == self.get(*expected) & mask(bytes as u8 * 8) {
+                    self.write(addr, bytes, self.get(*new))?;
+                }
+                self.set(*expected, old);
+            }
+        }
+        Ok(())
+    }
+
+    /// The address `operand` refers to, applying any base register writeback.
+    fn effective_address(&mut self, operand: &MemOperand<Arm64Register>) -> u64 {
+        match operand {
+            MemOperand::Offset(base, offset) => self.get(*base).wrapping_add(*offset as u64),
+            MemOperand::PreIndex(base, offset) => {
+                let addr = self.get(*base).wrapping_add(*offset as u64);
+                self.set(*base, addr);
+                addr
+            }
+            MemOperand::PostIndex(base, offset) => {
+                let addr = self.get(*base);
+                self.set(*base, addr.wrapping_add(*offset as u64));
+                addr
+            }
+            MemOperand::Indexed(base, index, shift) => {
+                self.get(*base).wrapping_add(self.get(*index) << shift)
+            }
+        }
+    }
+
+    fn address(&mut self, op: &AddressOp) -> Result<(), EmulatorError> {
+        match op {
+            AddressOp::Adr { dst, label } => {
+                let addr = self.label(label)?;
+                self.set(*dst, addr);
+            }
+            AddressOp::Adrp { dst, label } => {
+                let label = label.strip_suffix("@PAGE").unwrap_or(label);
+                let addr = self.label(label)?;
+                self.set(*dst, addr & !0xfff);
+            }
+            AddressOp::AdrpAdd { dst, base, label } => {
+                let addr = self.label(label)?;
+                self.set(*base, addr & !0xfff);
+                self.set(*dst, addr);
+            }
+            // Without a GOT entry the address is used directly, as a linker
+            // relaxes the load
+            AddressOp::AdrpGot { dst, label } => {
+                let addr = match self.layout.label(&format!("{}@GOT", label)) {
+                    Some(entry) => self.read(entry, 8)?,
+                    None => self.label(label)?,
+                };
+                self.set(*dst, addr);
+            }
+        }
+        Ok(())
+    }
+
+    fn mov(&mut self, op: &MoveOp) {
+        match op {
+            MoveOp::Movz { dst, imm, shift } => self.set(*dst, (*imm as u64) << shift),
+            MoveOp::Movn { dst, imm, shift } => self.set(*dst, !((*imm as u64) << shift)),
+            MoveOp::Movk { dst, imm, shift } => {
+                let kept = self.get(*dst) & !(0xffff << shift);
+                self.set(*dst, kept | (*imm as u64) << shift)
+            }
+        }
+    }
+
+    fn label(&self, name: &str) -> Result<u64, EmulatorError> {
+        self.layout
+            .label(name)
+            .ok_or_else(|| EmulatorError::UndefinedLabel(name.to_string()))
+    }
+
+    /// The address of a bare label used as a literal `ldr`/`str` operand.
+    fn symbolic_address(&self, operand: &str) -> Result<u64, EmulatorError> {
+        if operand.starts_with('[') {
+            return Err(EmulatorError::Unsupported(format!("address operand {}", operand)));
+        }
+        self.label(operand)
+    }
+
+    /// Evaluates a numeric immediate or a symbolic one such as `L0@PAGEOFF`.
+    fn resolve_imm(&self, imm: &str) -> Result<u64, EmulatorError> {
+        if let Some(value) = parse_imm(imm) {
+            return Ok(value as u64);
+        }
+        if let Some(label) = imm.strip_suffix("@PAGEOFF").or_else(|| imm.strip_prefix(":lo12:")) {
+            return Ok(self.label(label)? & 0xfff);
+        }
+        if let Some(label) = imm.strip_suffix("@PAGE") {
+            return Ok(self.label(label)? & !0xfff);
+        }
+        self.label(imm)
+    }
+
+    fn read(&self, addr: u64, len: usize) -> Result<u64, EmulatorError> {
+        let mut bytes = [0; 8];
+        bytes[..len].copy_from_slice(&self.read_memory(addr, len)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn write(&mut self, addr: u64, len: usize, value: u64) -> Result<(), EmulatorError> {
+        self.write_memory(addr, &value.to_le_bytes()[..len])
+    }
+
+    /// Reads a register; W registers read the low 32 bits.
+    fn get(&self, reg: Arm64Register) -> u64 {
+        let value = match reg {
+            _ if reg.is_zero() => 0,
+            _ if reg.is_sp() => self.sp,
+            _ if reg.is_vector() => self.vregs[reg.index() as usize] as u64,
+            _ => self.regs[reg.index() as usize],
+        };
+        if reg.is_32bit() { value as u32 as u64 } else { value }
+    }
+
+    /// Reads a register as a signed value of its width.
+    fn signed(&self, reg: Arm64Register) -> i64 {
+        if reg.is_32bit() { self.get(reg) as i32 as i64 } else { self.get(reg) as i64 }
+    }
+
+    /// Writes a register; W register writes zero the upper 32 bits.
+    fn set(&mut self, reg: Arm64Register, value: u64) {
+        let value = if reg.is_32bit() { value as u32 as u64 } else { value };
+        match reg {
+            _ if reg.is_zero() => {}
+            _ if reg.is_sp() => self.sp = value,
+            // Scalar writes clear the upper lanes
+            _ if reg.is_vector() => self.vregs[reg.index() as usize] = value as u128,
+            _ => self.regs[reg.index() as usize] = value,
+        }
+    }
+}
+
+fn contains(region: &Region, addr: u64, len: usize) -> bool {
+    addr >= region.base && addr + len as u64 <= region.base + region.bytes.len() as u64
+}
+
+/// Shifts a `width`-bit value; 32-bit results are left for [`Emulator::set`]
+/// to truncate.
+fn shifted(value: u64, kind: ShiftKind, amount: u8, width: u32) -> u64 {
+    let amount = amount as u32 % width;
+    match (kind, width) {
+        (ShiftKind::Lsl, _) => value << amount,
+        (ShiftKind::Lsr, _) => value >> amount,
+        (ShiftKind::Asr, 32) => ((value as i32) >> amount) as u64,
+        (ShiftKind::Asr, _) => ((value as i64) >> amount) as u64,
+        (ShiftKind::Ror, 32) => (value as u32).rotate_right(amount) as u64,
+        (ShiftKind::Ror, _) => value.rotate_right(amount),
+    }
+}
+
+/// The operation width selected by a general purpose register.
+fn width(reg: Arm64Register) -> u32 {
+    if reg.is_32bit() { 32 } else { 64 }
+}
+
+fn bits_to_fp(bits: u64, size: FpSize) -> f64 {
+    match size {
+        FpSize::Half => imm::f16_to_f64(bits as u16),
+        FpSize::Single => f32::from_bits(bits as u32) as f64,
+        FpSize::Double => f64::from_bits(bits),
+    }
+}
+
+fn fp_to_bits(value: f64, size: FpSize) -> u64 {
+    match size {
+        FpSize::Half => imm::f64_to_f16(value) as u64,
+        FpSize::Single => (value as f32).to_bits() as u64,
+        FpSize::Double => value.to_bits(),
+    }
+}
+
+fn get_lane(value: u128, size: MemSize, index: u32) -> u64 {
+    let bits = size.bytes() * 8;
+    let mask = if bits == 64 { u64::MAX } else { (1 << bits) - 1 };
+    (value >> (index * bits)) as u64 & mask
+}
+
+fn set_lane(value: u128, size: MemSize, index: u32, lane: u64) -> u128 {
+    let bits = size.bytes() * 8;
+    let mask = if bits == 64 { u64::MAX as u128 } else { (1u128 << bits) - 1 };
+    let shift = index * bits;
+    (value & !(mask << shift)) | ((lane as u128 & mask) << shift)
+}
+
+fn sign_bit(size: FpSize) -> u64 {
+    1 << (size.bytes() * 8 - 1)
+}
+
+fn mask(width: u8) -> u64 {
+    match width {
+        64 => u64::MAX,
+        _ => (1 << width) - 1,
+    }
+}
@@ -0,0 +1,776 @@
+//! Machine code generation for [`ARM64`] instruction streams.
+//!
+//! Labels are resolved against a [`Layout`], so the output is position
+//! dependent: text is assembled for the base address it will be loaded at.
+//! Operand forms that have no single-instruction encoding (such as `ldr` of
+//! an `=imm` pseudo literal) are reported rather than expanded.
+
+use super::layout::Layout;
+use super::validate::{validate, ValidationError};
+use super::*;
+use std::collections::HashMap;
+
+const NOP: u32 = 0xd503201f;
+
+#[derive(Debug, PartialEq)]
+pub enum EncodeError {
+    UndefinedLabel(String),
+    DuplicateLabel(String),
+    /// An immediate, offset or branch distance that does not fit its field
+    OutOfRange { inst: String, value: i64 },
+    Unsupported(String),
+    Invalid(ValidationError),
+}
+
+/// Text and read-only data assembled for fixed load addresses.
+pub struct Assembled {
+    pub text: Vec<u8>,
+    pub text_base: u64,
+    pub rodata: Vec<u8>,
+    pub rodata_base: u64,
+    pub labels: HashMap<String, u64>,
+}
+
+/// Assembles `arch` with text at `text_base` and read-only data on the
+/// following page.
+pub fn assemble(arch: &ARM64, text_base: u64) -> Result<Assembled, EncodeError> {
+    validate(arch).map_err(EncodeError::Invalid)?;
+    let layout = Layout::new(arch, text_base).map_err(EncodeError::DuplicateLabel)?;
+    let resolve = |name: &str| layout.label(name);
+
+    let mut text = Vec::new();
+    for (inst, addr) in layout.text.iter().zip(&layout.text_addresses) {
+        match inst {
+            // Padding in code is filled with `nop` so it stays executable
+            Instruction::Data(DataDirective::Align(_)) => {
+                let padding = layout::advance(*addr, inst) - addr;
+                for _ in 0..padding / 4 {
+                    text.extend_from_slice(&NOP.to_le_bytes());
+                }
+            }
+            Instruction::Data(directive) => {
+                text.extend(layout.data_bytes(directive, *addr).map_err(EncodeError::UndefinedLabel)?);
+            }
+            _ => {
+                for word in encode(inst, *addr, &resolve)? {
+                    text.extend_from_slice(&word.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    let mut rodata = Vec::new();
+    for (inst, addr) in layout.rodata.iter().zip(&layout.rodata_addresses) {
+        match inst {
+            Instruction::Label(_) => {}
+            Instruction::Data(directive) => {
+                rodata.extend(layout.data_bytes(directive, *addr).map_err(EncodeError::UndefinedLabel)?);
+            }
+            _ => return Err(EncodeError::Unsupported(format!("instruction in rodata: {}", inst))),
+        }
+    }
+
+    Ok(Assembled {
+        text,
+        text_base: layout.text_base,
+        rodata,
+        rodata_base: layout.rodata_base,
+        labels: layout.labels.clone(),
+    })
+}
+
+/// Encodes one instruction placed at `addr`. Labels and data directives
+/// produce no words; `adrp`+`add` pairs produce two.
+pub fn encode(inst: &Instruction, addr: u64, resolve: &dyn Fn(&str) -> Option<u64>) -> Result<Vec<u32>, EncodeError> {
+    let encoder = Encoder { inst, addr, resolve };
+    Ok(match inst {
+        Instruction::Label(_) | Instruction::Data(_) => Vec::new(),
+        Instruction::Arithmetic(op) => vec![encoder.arithmetic(op)?],
+        Instruction::Float(op) => vec![encoder.float(op)?],
+        Instruction::Vector(op) => vec![encoder.vector(op)?],
+        Instruction::Logical(op) => vec![encoder.logical(op)?],
+        Instruction::Shift(op) => vec![encoder.shift(op)?],
+        Instruction::Bitfield(op) => vec![encoder.bitfield(op)?],
+        Instruction::Bit(op) => vec![encoder.bit(op)?],
+        Instruction::Branch(op) => vec![encoder.branch(op)?],
+        Instruction::LoadStore(op) => vec![encoder.load_store(op)?],
+        Instruction::Atomic(op) => vec![encoder.atomic(op)],
+        Instruction::System(op) => vec![encoder.system(op)?],
+        Instruction::Address(op) => encoder.address(op)?,
+        Instruction::Move(op) => vec![encoder.mov(op)],
+    })
+}
+
+struct Encoder<'a> {
+    inst: &'a Instruction,
+    addr: u64,
+    resolve: &'a dyn Fn(&str) -> Option<u64>,
+}
+
+impl Encoder<'_> {
+    fn arithmetic(&self, op: &ArithmeticOp) -> Result<u32, EncodeError> {
+        Ok(match op {
+            ArithmeticOp::Add { dst, src1, src2 } if src2.is_zero() => {
+                // `mov`: an add of #0 when SP is involved, `orr` otherwise
+                if dst.is_sp() || src1.is_sp() {
+                    sized(0x91000000, *dst) | rn(*src1) | rd(*dst)
+                } else {
+                    sized(0xaa0003e0, *dst) | rm(*src1) | rd(*dst)
+                }
+            }
+            ArithmeticOp::Add { dst, src1, src2 } => add_sub_register(0x8b000000, *dst, *src1, *src2),
+            ArithmeticOp::AddImm { dst, src1, imm } => {
+                let value = self.immediate(imm)?;
+                let (base, magnitude) = if value < 0 { (0xd1000000, -value) } else { (0x91000000, value) };
+                sized(base, *dst) | self.arith_imm(magnitude)? | rn(*src1) | rd(*dst)
+            }
+            ArithmeticOp::AddShifted { dst, src1, src2, shift, amount } => {
+                sized(0x8b000000, *dst) | self.arith_shift(*shift)? | rm(*src2) | (*amount as u32) << 10 | rn(*src1) | rd(*dst)
+            }
+            ArithmeticOp::Sub { dst, src1, src2 } => add_sub_register(0xcb000000, *dst, *src1, *src2),
+            ArithmeticOp::Mul { dst, src1, src2 } => sized(0x9b007c00, *dst) | rm(*src2) | rn(*src1) | rd(*dst),
+            ArithmeticOp::Sdiv { dst, src1, src2 } => sized(0x9ac00c00, *dst) | rm(*src2) | rn(*src1) | rd(*dst),
+            ArithmeticOp::Udiv { dst, src1, src2 } => sized(0x9ac00800, *dst) | rm(*src2) | rn(*src1) | rd(*dst),
+            ArithmeticOp::Madd { dst, src1, src2, acc } => sized(0x9b000000, *dst) | rm(*src2) | ra(*acc) | rn(*src1) | rd(*dst),
+            ArithmeticOp::Msub { dst, src1, src2, acc } => sized(0x9b008000, *dst) | rm(*src2) | ra(*acc) | rn(*src1) | rd(*dst),
+            ArithmeticOp::Mneg { dst, src1, src2 } => sized(0x9b00fc00, *dst) | rm(*src2) | rn(*src1) | rd(*dst),
+            ArithmeticOp::Smull { dst, src1, src2 } => 0x9b207c00 | rm(*src2) | rn(*src1) | rd(*dst),
+            ArithmeticOp::Umull { dst, src1, src2 } => 0x9ba07c00 | rm(*src2) | rn(*src1) | rd(*dst),
+            ArithmeticOp::Smulh { dst, src1, src2 } => 0x9b407c00 | rm(*src2) | rn(*src1) | rd(*dst),
+            ArithmeticOp::Umulh { dst, src1, src2 } => 0x9bc07c00 | rm(*src2) | rn(*src1) | rd(*dst),
+            ArithmeticOp::Smaddl { dst, src1, src2, acc } => 0x9b200000 | rm(*src2) | ra(*acc) | rn(*src1) | rd(*dst),
+            ArithmeticOp::Umaddl { dst, src1, src2, acc } => 0x9ba00000 | rm(*src2) | ra(*acc) | rn(*src1) | rd(*dst),
+            ArithmeticOp::Cmp { src1, src2 } => add_sub_register(0xeb000000, Arm64Register::XZR, *src1, *src2),
+            ArithmeticOp::CmpImm { src1, imm } => {
+                // `cmn` for negative values
+                let value = self.immediate(imm)?;
+                let (base, magnitude) = if value < 0 { (0xb100001f, -value) } else { (0xf100001f, value) };
+                sized(base, *src1) | self.arith_imm(magnitude)? | rn(*src1)
+            }
+            ArithmeticOp::CmpShifted { src1, src2, shift, amount } => {
+                sized(0xeb00001f, *src1) | self.arith_shift(*shift)? | rm(*src2) | (*amount as u32) << 10 | rn(*src1)
+            }
+            ArithmeticOp::Cset { dst, cond } => match cond.invert() {
+                Some(inverse) => sized(0x9a9f07e0, *dst) | condition(inverse) << 12 | rd(*dst),
+                // Always set: `mov dst, #1`
+                None => sized(0xd2800020, *dst) | rd(*dst),
+            },
+            ArithmeticOp::Csel { dst, src1, src2, cond } => {
+                sized(0x9a800000, *dst) | rm(*src2) | condition(*cond) << 12 | rn(*src1) | rd(*dst)
+            }
+        })
+    }
+
+    /// The `sh:imm12` field of an add/sub immediate.
+    fn arith_imm(&self, value: i64) -> Result<u32, EncodeError> {
+        match value {
+            0..=0xfff => Ok((value as u32) << 10),
+            _ if value & 0xfff == 0 && value >> 12 <= 0xfff => Ok(1 << 22 | ((value >> 12) as u32) << 10),
+            _ => Err(self.out_of_range(value)),
+        }
+    }
+
+    fn arith_shift(&self, shift: ShiftKind) -> Result<u32, EncodeError> {
+        match shift {
+            ShiftKind::Ror => Err(EncodeError::Unsupported(self.inst.to_string())),
+            _ => Ok(shift_type(shift) << 22),
+        }
+    }
+
+    fn float(&self, op: &FloatOp) -> Result<u32, EncodeError> {
+        Ok(match op {
+            FloatOp::Fadd { size, dst, src1, src2 } => 0x1e202800 | ftype(*size) | rm(*src2) | rn(*src1) | rd(*dst),
+            FloatOp::Fsub { size, dst, src1, src2 } => 0x1e203800 | ftype(*size) | rm(*src2) | rn(*src1) | rd(*dst),
+            FloatOp::Fmul { size, dst, src1, src2 } => 0x1e200800 | ftype(*size) | rm(*src2) | rn(*src1) | rd(*dst),
+            FloatOp::Fdiv { size, dst, src1, src2 } => 0x1e201800 | ftype(*size) | rm(*src2) | rn(*src1) | rd(*dst),
+            FloatOp::Fsqrt { size, dst, src } => 0x1e21c000 | ftype(*size) | rn(*src) | rd(*dst),
+            FloatOp::Fneg { size, dst, src } => 0x1e214000 | ftype(*size) | rn(*src) | rd(*dst),
+            FloatOp::Fabs { size, dst, src } => 0x1e20c000 | ftype(*size) | rn(*src) | rd(*dst),
+            FloatOp::Fmadd { size, dst, src1, src2, acc } => {
+                0x1f000000 | ftype(*size) | rm(*src2) | ra(*acc) | rn(*src1) | rd(*dst)
+            }
+            FloatOp::Fcmp { size, src1, src2 } => 0x1e202000 | ftype(*size) | rm(*src2) | rn(*src1),
+            FloatOp::Fcsel { size, dst, src1, src2, cond } => {
+                0x1e200c00 | ftype(*size) | rm(*src2) | condition(*cond) << 12 | rn(*src1) | rd(*dst)
+            }
+            FloatOp::Fmov { size, dst, src } => 0x1e204000 | ftype(*size) | rn(*src) | rd(*dst),
+            FloatOp::FmovToFp { size, dst, src } => 0x1e270000 | fmov_sf(*size) | ftype(*size) | rn(*src) | rd(*dst),
+            FloatOp::FmovFromFp { size, dst, src } => 0x1e260000 | fmov_sf(*size) | ftype(*size) | rn(*src) | rd(*dst),
+            FloatOp::FmovImm { size, dst, imm } => {
+                let imm8 = imm::encode_fp_imm(*imm).ok_or_else(|| self.out_of_range(*imm as i64))?;
+                0x1e201000 | ftype(*size) | (imm8 as u32) << 13 | rd(*dst)
+            }
+            FloatOp::Scvtf { size, dst, src } => sized(0x9e220000, *src) | ftype(*size) | rn(*src) | rd(*dst),
+            FloatOp::Ucvtf { size, dst, src } => sized(0x9e230000, *src) | ftype(*size) | rn(*src) | rd(*dst),
+            FloatOp::Fcvtzs { size, dst, src } => sized(0x9e380000, *dst) | ftype(*size) | rn(*src) | rd(*dst),
+            FloatOp::Fcvtzu { size, dst, src } => sized(0x9e390000, *dst) | ftype(*size) | rn(*src) | rd(*dst),
+            FloatOp::Fcvt { dst_size, dst, src_size, src } => {
+                if dst_size == src_size {
+                    return Err(EncodeError::Unsupported(self.inst.to_string()));
+                }
+                0x1e224000 | ftype(*src_size) | ftype(*dst_size) >> 7 | rn(*src) | rd(*dst)
+            }
+        })
+    }
+
+    fn vector(&self, op: &VectorOp) -> Result<u32, EncodeError> {
+        let unsupported = || EncodeError::Unsupported(self.inst.to_string());
+        Ok(match op {
+            VectorOp::Add { arrangement, dst, src1, src2 } => {
+                0x0e208400 | vector_size(*arrangement) | rm(*src2) | rn(*src1) | rd(*dst)
+            }
+            VectorOp::Sub { arrangement, dst, src1, src2 } => {
+                0x2e208400 | vector_size(*arrangement) | rm(*src2) | rn(*src1) | rd(*dst)
+            }
+            VectorOp::Mul { arrangement, dst, src1, src2 } => {
+                if *arrangement == VectorArrangement::D2 {
+                    return Err(unsupported());
+                }
+                0x0e209c00 | vector_size(*arrangement) | rm(*src2) | rn(*src1) | rd(*dst)
+            }
+            VectorOp::Fadd { arrangement, dst, src1, src2 } => {
+                self.vector_fp(*arrangement, 0x0e401400, 0x0e20d400)? | rm(*src2) | rn(*src1) | rd(*dst)
+            }
+            VectorOp::Fsub { arrangement, dst, src1, src2 } => {
+                self.vector_fp(*arrangement, 0x0ec01400, 0x0ea0d400)? | rm(*src2) | rn(*src1) | rd(*dst)
+            }
+            VectorOp::Fmul { arrangement, dst, src1, src2 } => {
+                self.vector_fp(*arrangement, 0x2e401c00, 0x2e20dc00)? | rm(*src2) | rn(*src1) | rd(*dst)
+            }
+            VectorOp::Ld1 { arrangement, first, count, addr } => {
+                0x0c400000 | self.multiple_structures(*arrangement, *first, *count, addr)?
+            }
+            VectorOp::St1 { arrangement, first, count, addr } => {
+                0x0c000000 | self.multiple_structures(*arrangement, *first, *count, addr)?
+            }
+            VectorOp::Dup { arrangement, dst, src } => {
+                q(*arrangement) | 0x0e000c00 | lane_imm5(arrangement.element(), 0) << 16 | rn(*src) | rd(*dst)
+            }
+            VectorOp::DupLane { arrangement, dst, src, index } => {
+                let imm5 = self.lane_imm5(arrangement.element(), *index)?;
+                q(*arrangement) | 0x0e000400 | imm5 << 16 | rn(*src) | rd(*dst)
+            }
+            VectorOp::Ins { lane, dst, index, src } => {
+                0x4e001c00 | self.lane_imm5(*lane, *index)? << 16 | rn(*src) | rd(*dst)
+            }
+            VectorOp::Umov { lane, dst, src, index } => {
+                let q = if *lane == MemSize::Double { 1 << 30 } else { 0 };
+                q | 0x0e003c00 | self.lane_imm5(*lane, *index)? << 16 | rn(*src) | rd(*dst)
+            }
+            VectorOp::Addv { arrangement, dst, src } => {
+                if matches!(arrangement, VectorArrangement::S2 | VectorArrangement::D2) {
+                    return Err(unsupported());
+                }
+                0x0e31b800 | vector_size(*arrangement) | rn(*src) | rd(*dst)
+            }
+        })
+    }
+
+    /// Picks the half-precision or single/double form of a vector FP
+    /// operation; `sz` selects between single and double.
+    fn vector_fp(&self, arrangement: VectorArrangement, half: u32, single_double: u32) -> Result<u32, EncodeError> {
+        Ok(q(arrangement) | match arrangement {
+            VectorArrangement::H4 | VectorArrangement::H8 => half,
+            VectorArrangement::S2 | VectorArrangement::S4 => single_double,
+            VectorArrangement::D2 => single_double | 1 << 22,
+            _ => return Err(EncodeError::Unsupported(self.inst.to_string())),
+        })
+    }
+
+    /// The fields shared by `ld1`/`st1` with one to four registers.
+    fn multiple_structures(
+        &self,
+        arrangement: VectorArrangement,
+        first: Arm64Register,
+        count: u8,
+        addr: &MemOperand<Arm64Register>,
+    ) -> Result<u32, EncodeError> {
+        let opcode = match count {
+            1 => 0b0111,
+            2 => 0b1010,
+            3 => 0b0110,
+            4 => 0b0010,
+            _ => return Err(EncodeError::Unsupported(self.inst.to_string())),
+        };
+        let size = element_size(arrangement.element()) << 10;
+        let fields = q(arrangement) | opcode << 12 | size | rd(first);
+        match addr {
+            MemOperand::Offset(base, 0) => Ok(fields | rn(*base)),
+            // Post-increment by the size of the whole list
+            MemOperand::PostIndex(base, offset) if *offset == (arrangement.bytes() * count as u32) as i64 => {
+                Ok(fields | 1 << 23 | 0x1f << 16 | rn(*base))
+            }
+            _ => Err(EncodeError::Unsupported(self.inst.to_string())),
+        }
+    }
+
+    fn lane_imm5(&self, lane: MemSize, index: u8) -> Result<u32, EncodeError> {
+        if index as u32 >= 16 / lane.bytes() {
+            return Err(self.out_of_range(index as i64));
+        }
+        Ok(lane_imm5(lane, index))
+    }
+
+    fn logical(&self, op: &LogicalOp) -> Result<u32, EncodeError> {
+        let (base, dst, src1, src2, shift) = match op {
+            LogicalOp::And { dst, src1, src2, shift } => (0x8a000000, dst, src1, src2, shift),
+            LogicalOp::Bic { dst, src1, src2, shift } => (0x8a200000, dst, src1, src2, shift),
+            LogicalOp::Orr { dst, src1, src2, shift } => (0xaa000000, dst, src1, src2, shift),
+            LogicalOp::Orn { dst, src1, src2, shift } => (0xaa200000, dst, src1, src2, shift),
+            LogicalOp::Eor { dst, src1, src2, shift } => (0xca000000, dst, src1, src2, shift),
+            LogicalOp::Eon { dst, src1, src2, shift } => (0xca200000, dst, src1, src2, shift),
+            LogicalOp::AndImm { dst, src, imm } => return self.logical_imm(0x92000000, *dst, *src, *imm),
+            LogicalOp::OrrImm { dst, src, imm } => return self.logical_imm(0xb2000000, *dst, *src, *imm),
+            LogicalOp::EorImm { dst, src, imm } => return self.logical_imm(0xd2000000, *dst, *src, *imm),
+        };
+        let (kind, amount) = shift.unwrap_or((ShiftKind::Lsl, 0));
+        Ok(sized(base, *dst) | shift_type(kind) << 22 | rm(*src2) | (amount as u32) << 10 | rn(*src1) | rd(*dst))
+    }
+
+    fn logical_imm(&self, base: u32, dst: Arm64Register, src: Arm64Register, value: u64) -> Result<u32, EncodeError> {
+        let fields = imm::encode_logical_imm(value, width(dst)).ok_or_else(|| self.out_of_range(value as i64))?;
+        Ok(sized(base, dst) | fields << 10 | rn(src) | rd(dst))
+    }
+
+    fn shift(&self, op: &ShiftOp) -> Result<u32, EncodeError> {
+        Ok(match op {
+            ShiftOp::LslImm { dst, src, amount } => {
+                let (w, amount) = (width(*dst), self.below_width(*amount, *dst)?);
+                ubfm(*dst, *src, (w - amount) % w, w - 1 - amount)
+            }
+            ShiftOp::LsrImm { dst, src, amount } => ubfm(*dst, *src, self.below_width(*amount, *dst)?, width(*dst) - 1),
+            ShiftOp::AsrImm { dst, src, amount } => {
+                let amount = self.below_width(*amount, *dst)?;
+                bitfield_sized(0x93400000, *dst) | amount << 16 | (width(*dst) - 1) << 10 | rn(*src) | rd(*dst)
+            }
+            ShiftOp::RorImm { dst, src, amount } => {
+                let amount = self.below_width(*amount, *dst)?;
+                bitfield_sized(0x93c00000, *dst) | rm(*src) | amount << 10 | rn(*src) | rd(*dst)
+            }
+            ShiftOp::Lsl { dst, src1, src2 } => sized(0x9ac02000, *dst) | rm(*src2) | rn(*src1) | rd(*dst),
+            ShiftOp::Lsr { dst, src1, src2 } => sized(0x9ac02400, *dst) | rm(*src2) | rn(*src1) | rd(*dst),
+            ShiftOp::Asr { dst, src1, src2 } => sized(0x9ac02800, *dst) | rm(*src2) | rn(*src1) | rd(*dst),
+            ShiftOp::Ror { dst, src1, src2 } => sized(0x9ac02c00, *dst) | rm(*src2) | rn(*src1) | rd(*dst),
+        })
+    }
+
+    /// Checks a shift amount or bit position against the operand width.
+    fn below_width(&self, value: u8, reg: Arm64Register) -> Result<u32, EncodeError> {
+        if value as u32 >= width(reg) {
+            return Err(self.out_of_range(value as i64));
+        }
+        Ok(value as u32)
+    }
+
+    fn bitfield(&self, op: &BitfieldOp) -> Result<u32, EncodeError> {
+        let field = |dst: &Arm64Register, lsb: &u8, bits: &u8| {
+            if *bits == 0 || (*lsb as u32 + *bits as u32) > width(*dst) {
+                return Err(self.out_of_range(*bits as i64));
+            }
+            Ok((*lsb as u32, *bits as u32))
+        };
+        Ok(match op {
+            BitfieldOp::Ubfx { dst, src, lsb, width: bits } => {
+                let (lsb, bits) = field(dst, lsb, bits)?;
+                ubfm(*dst, *src, lsb, lsb + bits - 1)
+            }
+            BitfieldOp::Sbfx { dst, src, lsb, width: bits } => {
+                let (lsb, bits) = field(dst, lsb, bits)?;
+                bitfield_sized(0x93400000, *dst) | lsb << 16 | (lsb + bits - 1) << 10 | rn(*src) | rd(*dst)
+            }
+            BitfieldOp::Bfi { dst, src, lsb, width: bits } => {
+                let (lsb, bits) = field(dst, lsb, bits)?;
+                let immr = (width(*dst) - lsb) % width(*dst);
+                bitfield_sized(0xb3400000, *dst) | immr << 16 | (bits - 1) << 10 | rn(*src) | rd(*dst)
+            }
+            BitfieldOp::Ubfiz { dst, src, lsb, width: bits } => {
+                let (lsb, bits) = field(dst, lsb, bits)?;
+                ubfm(*dst, *src, (width(*dst) - lsb) % width(*dst), bits - 1)
+            }
+            BitfieldOp::Extr { dst, src1, src2, lsb } => {
+                let lsb = self.below_width(*lsb, *dst)?;
+                bitfield_sized(0x93c00000, *dst) | rm(*src2) | lsb << 10 | rn(*src1) | rd(*dst)
+            }
+        })
+    }
+
+    fn bit(&self, op: &BitOp) -> Result<u32, EncodeError> {
+        let (base, dst, src) = match op {
+            BitOp::Clz { dst, src } => (0xdac01000, dst, src),
+            BitOp::Cls { dst, src } => (0xdac01400, dst, src),
+            BitOp::Rbit { dst, src } => (0xdac00000, dst, src),
+            // The 32-bit byte reverse uses the opcode of the 64-bit `rev32`
+            BitOp::Rev { dst, src } if dst.is_32bit() => (0xdac00800, dst, src),
+            BitOp::Rev { dst, src } => (0xdac00c00, dst, src),
+            BitOp::Rev16 { dst, src } => (0xdac00400, dst, src),
+            BitOp::Rev32 { dst, .. } if dst.is_32bit() => return Err(EncodeError::Unsupported(self.inst.to_string())),
+            BitOp::Rev32 { dst, src } => (0xdac00800, dst, src),
+        };
+        Ok(sized(base, *dst) | rn(*src) | rd(*dst))
+    }
+
+    fn branch(&self, op: &BranchOp) -> Result<u32, EncodeError> {
+        Ok(match op {
+            BranchOp::B { label } => 0x14000000 | self.branch_offset(label, 26)?,
+            BranchOp::Bl { label } => 0x94000000 | self.branch_offset(label, 26)?,
+            BranchOp::Ret => 0xd65f03c0,
+            BranchOp::Cbz { reg, label } => sized(0xb4000000, *reg) | self.branch_offset(label, 19)? << 5 | rd(*reg),
+            BranchOp::Cbnz { reg, label } => sized(0xb5000000, *reg) | self.branch_offset(label, 19)? << 5 | rd(*reg),
+            BranchOp::BCond { cond, label } => 0x54000000 | self.branch_offset(label, 19)? << 5 | condition(*cond),
+            BranchOp::Br { reg } => 0xd61f0000 | rn(*reg),
+            BranchOp::Blr { reg } => 0xd63f0000 | rn(*reg),
+        })
+    }
+
+    fn load_store(&self, op: &LoadStoreOp) -> Result<u32, EncodeError> {
+        match op {
+            LoadStoreOp::Ldr { dst, src } if !src.starts_with('=') && !src.starts_with('[') => {
+                // opc 0b00 loads 32 bits
+                let opc = if dst.is_32bit() { 0x18000000 } else { 0x58000000 };
+                Ok(opc | self.branch_offset(src, 19)? << 5 | rd(*dst))
+            }
+            LoadStoreOp::Ldr { .. } | LoadStoreOp::Str { .. } => Err(EncodeError::Unsupported(self.inst.to_string())),
+            LoadStoreOp::Load { size, signed, dst, addr } => {
+                let opc = match (size, signed) {
+                    (MemSize::Double, _) if dst.is_32bit() => return Err(EncodeError::Unsupported(self.inst.to_string())),
+                    (MemSize::Word, true) if dst.is_32bit() => return Err(EncodeError::Unsupported(self.inst.to_string())),
+                    (MemSize::Double, _) | (_, false) => 0b01,
+                    // Sign extension to 32 bits for a W destination, 64 otherwise
+                    (_, true) if dst.is_32bit() => 0b11,
+                    (_, true) => 0b10,
+                };
+                self.single_register(*size, opc, *dst, addr)
+            }
+            LoadStoreOp::Store { size: MemSize::Double, src, .. } if src.is_32bit() => {
+                Err(EncodeError::Unsupported(self.inst.to_string()))
+            }
+            LoadStoreOp::Store { size, src, addr } => self.single_register(*size, 0b00, *src, addr),
+            LoadStoreOp::Ldp { dst1, dst2, addr } => self.pair(0xa8400000, *dst1, *dst2, addr),
+            LoadStoreOp::Stp { src1, src2, addr } => self.pair(0xa8000000, *src1, *src2, addr),
+        }
+    }
+
+    /// `ldr`/`str` and their narrow and sign-extending variants. Offsets
+    /// that are not a multiple of the access size use the unscaled form.
+    fn single_register(&self, size: MemSize, opc: u32, rt: Arm64Register, addr: &MemOperand<Arm64Register>) -> Result<u32, EncodeError> {
+        let scale = size.bytes().trailing_zeros();
+        let base = element_size(size) << 30 | 0x38000000 | opc << 22 | rd(rt);
+        match addr {
+            MemOperand::Offset(reg, offset) => {
+                let scaled = offset >> scale;
+                if *offset >= 0 && scaled << scale == *offset && scaled <= 0xfff {
+                    Ok(base | 1 << 24 | (scaled as u32) << 10 | rn(*reg))
+                } else {
+                    Ok(base | self.imm9(*offset)? | rn(*reg))
+                }
+            }
+            MemOperand::PreIndex(reg, offset) => Ok(base | self.imm9(*offset)? | 0b11 << 10 | rn(*reg)),
+            MemOperand::PostIndex(reg, offset) => Ok(base | self.imm9(*offset)? | 0b01 << 10 | rn(*reg)),
+            MemOperand::Indexed(reg, index, shift) => {
+                let scaled = match *shift as u32 {
+                    0 => 0,
+                    shift if shift == scale => 1 << 12,
+                    _ => return Err(self.out_of_range(*shift as i64)),
+                };
+                // Option 0b011 is LSL, extending the index as a 64-bit register
+                Ok(base | 1 << 21 | rm(*index) | 0b011 << 13 | scaled | 0b10 << 10 | rn(*reg))
+            }
+        }
+    }
+
+    fn imm9(&self, offset: i64) -> Result<u32, EncodeError> {
+        if !(-256..=255).contains(&offset) {
+            return Err(self.out_of_range(offset));
+        }
+        Ok(((offset as u32) & 0x1ff) << 12)
+    }
+
+    fn pair(&self, base: u32, rt: Arm64Register, rt2: Arm64Register, addr: &MemOperand<Arm64Register>) -> Result<u32, EncodeError> {
+        let (mode, reg, offset) = match addr {
+            MemOperand::PostIndex(reg, offset) => (0b01, reg, offset),
+            MemOperand::Offset(reg, offset) => (0b10, reg, offset),
+            MemOperand::PreIndex(reg, offset) => (0b11, reg, offset),
+            MemOperand::Indexed(..) => return Err(EncodeError::Unsupported(self.inst.to_string())),
+        };
+        // The offset is scaled by the register size
+        let scale = width(rt) as i64 / 8;
+        if offset % scale != 0 || !(-64..=63).contains(&(offset / scale)) {
+            return Err(self.out_of_range(*offset));
+        }
+        let imm7 = ((offset / scale) as u32) & 0x7f;
+        Ok(sized(base, rt) | mode << 23 | imm7 << 15 | (rt2.index() as u32) << 10 | rn(*reg) | rd(rt))
+    }
+
+    fn atomic(&self, op: &AtomicOp) -> u32 {
+        match op {
+            AtomicOp::Ldxr { size, acquire, dst, addr } => {
+                element_size(*size) << 30 | 0x085f7c00 | (*acquire as u32) << 15 | rn(*addr) | rd(*dst)
+            }
+            AtomicOp::Stxr { size, release, status, src, addr } => {
+                element_size(*size) << 30 | 0x08007c00 | rm(*status) | (*release as u32) << 15 | rn(*addr) | rd(*src)
+            }
+            AtomicOp::Ldadd { order, size, src, dst, addr } => lse(0x38200000, *order, *size) | rm(*src) | rn(*addr) | rd(*dst),
+            AtomicOp::Swp { order, size, src, dst, addr } => lse(0x38208000, *order, *size) | rm(*src) | rn(*addr) | rd(*dst),
+            // Compare and swap keeps acquire in bit 22 and release in bit 15
+            AtomicOp::Cas { order, size, expected, new, addr } => {
+                let ordering = (order.acquires() as u32) << 22 | (order.releases() as u32) << 15;
+                element_size(*size) << 30 | 0x08a07c00 | ordering | rm(*expected) | rn(*addr) | rd(*new)
+            }
+        }
+    }
+
+    fn system(&self, op: &SystemOp) -> Result<u32, EncodeError> {
+        match op {
+            SystemOp::Svc { number } if *number <= 0xffff => Ok(0xd4000001 | number << 5),
+            SystemOp::Svc { number } => Err(self.out_of_range(*number as i64)),
+            SystemOp::Msr { dst, src } => Ok(0xd5000000 | self.system_register(*dst)? | rd(*src)),
+            SystemOp::Mrs { dst, src } => Ok(0xd5200000 | self.system_register(*src)? | rd(*dst)),
+            SystemOp::Sys { op1, crn, crm, op2, src } => Ok(0xd5080000 | self.system_fields(*op1, *crn, *crm, *op2)? | rd(*src)),
+            SystemOp::Sysl { dst, op1, crn, crm, op2 } => Ok(0xd5280000 | self.system_fields(*op1, *crn, *crm, *op2)? | rd(*dst)),
+            SystemOp::Dmb { domain } => Ok(0xd50330bf | barrier(*domain) << 8),
+            SystemOp::Dsb { domain } => Ok(0xd503309f | barrier(*domain) << 8),
+            SystemOp::Isb => Ok(0xd5033fdf),
+            SystemOp::Eret => Ok(0xd69f03e0),
+        }
+    }
+
+    fn system_register(&self, reg: SystemRegister) -> Result<u32, EncodeError> {
+        let (op0, op1, crn, crm, op2) = reg.encoding();
+        // `mrs`/`msr` only reach op0 = 2 and 3; lower values are `sys` space
+        if !(2..=3).contains(&op0) {
+            return Err(self.out_of_range(op0 as i64));
+        }
+        Ok(1 << 20 | (op0 as u32 - 2) << 19 | self.system_fields(op1, crn, crm, op2)?)
+    }
+
+    fn system_fields(&self, op1: u8, crn: u8, crm: u8, op2: u8) -> Result<u32, EncodeError> {
+        for (value, limit) in [(op1, 7), (crn, 15), (crm, 15), (op2, 7)] {
+            if value > limit {
+                return Err(self.out_of_range(value as i64));
+            }
+        }
+        Ok((op1 as u32) << 16 | (crn as u32) << 12 | (crm as u32) << 8 | (op2 as u32) << 5)
+    }
+
+    fn address(&self, op: &AddressOp) -> Result<Vec<u32>, EncodeError> {
+        Ok(match op {
+            AddressOp::Adr { dst, label } => {
+                let offset = self.label(label)?.wrapping_sub(self.addr) as i64;
+                vec![0x10000000 | self.adr_imm(offset, 21)? | rd(*dst)]
+            }
+            AddressOp::Adrp { dst, label } => {
+                let label = label.strip_suffix("@PAGE").unwrap_or(label);
+                vec![self.adrp(*dst, self.label(label)?)?]
+            }
+            AddressOp::AdrpAdd { dst, base, label } => {
+                let target = self.label(label)?;
+                vec![
+                    self.adrp(*base, target)?,
+                    0x91000000 | ((target & 0xfff) as u32) << 10 | rn(*base) | rd(*dst),
+                ]
+            }
+            AddressOp::AdrpGot { dst, label } => {
+                let entry = self.label(&format!("{}@GOT", label))?;
+                if entry % 8 != 0 {
+                    return Err(self.out_of_range(entry as i64));
+                }
+                vec![
+                    self.adrp(*dst, entry)?,
+                    0xf9400000 | (((entry & 0xfff) / 8) as u32) << 10 | rn(*dst) | rd(*dst),
+                ]
+            }
+        })
+    }
+
+    fn adrp(&self, dst: Arm64Register, target: u64) -> Result<u32, EncodeError> {
+        let pages = ((target & !0xfff) as i64).wrapping_sub((self.addr & !0xfff) as i64) >> 12;
+        Ok(0x90000000 | self.adr_imm(pages, 21)? | rd(dst))
+    }
+
+    /// The split `immlo:immhi` field of `adr`/`adrp`.
+    fn adr_imm(&self, value: i64, bits: u32) -> Result<u32, EncodeError> {
+        if value < -(1 << (bits - 1)) || value >= 1 << (bits - 1) {
+            return Err(self.out_of_range(value));
+        }
+        let value = value as u32;
+        Ok((value & 0b11) << 29 | ((value >> 2) & 0x7ffff) << 5)
+    }
+
+    fn mov(&self, op: &MoveOp) -> u32 {
+        let (base, dst, imm, shift) = match op {
+            MoveOp::Movz { dst, imm, shift } => (0xd2800000, dst, imm, shift),
+            MoveOp::Movn { dst, imm, shift } => (0x92800000, dst, imm, shift),
+            MoveOp::Movk { dst, imm, shift } => (0xf2800000, dst, imm, shift),
+        };
+        sized(base, *dst) | (*shift as u32 / 16) << 21 | (*imm as u32) << 5 | rd(*dst)
+    }
+
+    fn label(&self, name: &str) -> Result<u64, EncodeError> {
+        (self.resolve)(name).ok_or_else(|| EncodeError::UndefinedLabel(name.to_string()))
+    }
+
+    /// A word offset to `label` that fits in a signed `bits`-bit field.
+    fn branch_offset(&self, label: &str, bits: u32) -> Result<u32, EncodeError> {
+        let offset = self.label(label)?.wrapping_sub(self.addr) as i64;
+        let words = offset >> 2;
+        if offset & 3 != 0 || words < -(1 << (bits - 1)) || words >= 1 << (bits - 1) {
+            return Err(self.out_of_range(offset));
+        }
+        Ok((words as u32) & ((1 << bits) - 1))
+    }
+
+    /// Evaluates a numeric or `label@PAGEOFF`/`:lo12:label` immediate.
+    fn immediate(&self, imm: &str) -> Result<i64, EncodeError> {
+        if let Some(value) = parse_imm(imm) {
+            return Ok(value);
+        }
+        match imm.strip_suffix("@PAGEOFF").or_else(|| imm.strip_prefix(":lo12:")) {
+            Some(label) => Ok((self.label(label)? & 0xfff) as i64),
+            None => Err(EncodeError::Unsupported(self.inst.to_string())),
+        }
+    }
+
+    fn out_of_range(&self, value: i64) -> EncodeError {
+        EncodeError::OutOfRange { inst: self.inst.to_string(), value }
+    }
+}
+
+/// An LSE memory operation with the acquire (A) and release (R) bits set.
+fn lse(base: u32, order: MemoryOrder, size: MemSize) -> u32 {
+    element_size(size) << 30 | base | (order.acquires() as u32) << 23 | (order.releases() as u32) << 22
+}
+
+/// The CRm field selecting a barrier's domain.
+fn barrier(domain: BarrierDomain) -> u32 {
+    match domain {
+        BarrierDomain::Sy => 0b1111,
+        BarrierDomain::St => 0b1110,
+        BarrierDomain::Ld => 0b1101,
+        BarrierDomain::Ish => 0b1011,
+        BarrierDomain::IshSt => 0b1010,
+        BarrierDomain::IshLd => 0b1001,
+        BarrierDomain::Nsh => 0b0111,
+        BarrierDomain::NshSt => 0b0110,
+        BarrierDomain::NshLd => 0b0101,
+        BarrierDomain::Osh => 0b0011,
+        BarrierDomain::OshSt => 0b0010,
+        BarrierDomain::OshLd => 0b0001,
+    }
+}
+
+fn rd(reg: Arm64Register) -> u32 {
+    reg.index() as u32
+}
+
+fn rn(reg: Arm64Register) -> u32 {
+    (reg.index() as u32) << 5
+}
+
+fn ra(reg: Arm64Register) -> u32 {
+    (reg.index() as u32) << 10
+}
+
+fn rm(reg: Arm64Register) -> u32 {
+    (reg.index() as u32) << 16
+}
+
+/// The operation width selected by a general purpose register.
+fn width(reg: Arm64Register) -> u32 {
+    if reg.is_32bit() { 32 } else { 64 }
+}
+
+/// Clears the `sf` bit of a 64-bit encoding when `reg` is a W register.
+fn sized(base: u32, reg: Arm64Register) -> u32 {
+    if reg.is_32bit() { base & !(1 << 31) } else { base }
+}
+
+/// As [`sized`], for the bitfield and `extr` encodings whose `N` bit must
+/// match `sf`.
+fn bitfield_sized(base: u32, reg: Arm64Register) -> u32 {
+    if reg.is_32bit() { base & !(1 << 31 | 1 << 22) } else { base }
+}
+
+/// Add/sub with a register operand, sized by `src1` so compares against
+/// the zero register work too. The shifted-register form reads register 31
+/// as XZR, so SP operands need the extended-register form.
+fn add_sub_register(base: u32, dst: Arm64Register, src1: Arm64Register, src2: Arm64Register) -> u32 {
+    let base = sized(base, src1);
+    if dst.is_sp() || src1.is_sp() {
+        // UXTW or UXTX #0, matching the operand width
+        let option = if src1.is_32bit() { 0b010 } else { 0b011 };
+        base | 1 << 21 | option << 13 | rm(src2) | rn(src1) | rd(dst)
+    } else {
+        base | rm(src2) | rn(src1) | rd(dst)
+    }
+}
+
+fn ubfm(dst: Arm64Register, src: Arm64Register, immr: u32, imms: u32) -> u32 {
+    bitfield_sized(0xd3400000, dst) | immr << 16 | imms << 10 | rn(src) | rd(dst)
+}
+
+fn shift_type(shift: ShiftKind) -> u32 {
+    match shift {
+        ShiftKind::Lsl => 0b00,
+        ShiftKind::Lsr => 0b01,
+        ShiftKind::Asr => 0b10,
+        ShiftKind::Ror => 0b11,
+    }
+}
+
+fn condition(cond: Condition) -> u32 {
+    match cond {
+        Condition::Eq => 0,
+        Condition::Ne => 1,
+        Condition::Hs => 2,
+        Condition::Lo => 3,
+        Condition::Mi => 4,
+        Condition::Pl => 5,
+        Condition::Vs => 6,
+        Condition::Vc => 7,
+        Condition::Hi => 8,
+        Condition::Ls => 9,
+        Condition::Ge => 10,
+        Condition::Lt => 11,
+        Condition::Gt => 12,
+        Condition::Le => 13,
+        Condition::Al => 14,
+    }
+}
+
+/// The `ftype` field in bits 23:22.
+fn ftype(size: FpSize) -> u32 {
+    match size {
+        FpSize::Single => 0b00 << 22,
+        FpSize::Double => 0b01 << 22,
+        FpSize::Half => 0b11 << 22,
+    }
+}
+
+/// The `sf` bit of `fmov` between general purpose and FP registers.
+fn fmov_sf(size: FpSize) -> u32 {
+    match size {
+        FpSize::Double => 1 << 31,
+        FpSize::Single | FpSize::Half => 0,
+    }
+}
+
+/// log2 of an element size, as used by the `size` fields.
+fn element_size(size: MemSize) -> u32 {
+    size.bytes().trailing_zeros()
+}
+
+fn q(arrangement: VectorArrangement) -> u32 {
+    if arrangement.bytes() == 16 { 1 << 30 } else { 0 }
+}
+
+/// `Q` and the element `size` field of a vector arithmetic instruction.
+fn vector_size(arrangement: VectorArrangement) -> u32 {
+    q(arrangement) | element_size(arrangement.element()) << 22
+}
+
+/// The `imm5` lane selector: a one marking the element size, with the
+/// index in the bits above it.
+fn lane_imm5(lane: MemSize, index: u8) -> u32 {
+    let size = element_size(lane);
+    (index as u32) << (size + 1) | 1 << size
+}
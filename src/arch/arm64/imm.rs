@@ -0,0 +1,116 @@
+//! Immediate encodings that are not simple bit fields.
+
+/// Encodes `value` as a logical (bitmask) immediate for a `width`-bit
+/// operation, returning the packed `N:immr:imms` field, or `None` when the
+/// value is not a rotated, replicated run of ones.
+pub fn encode_logical_imm(value: u64, width: u32) -> Option<u32> {
+    let value = if width == 32 {
+        // A 32-bit pattern is encoded as if replicated to 64 bits
+        let low = value & 0xffff_ffff;
+        if value >> 32 != 0 {
+            return None;
+        }
+        low | (low << 32)
+    } else {
+        value
+    };
+    if value == 0 || value == u64::MAX {
+        return None;
+    }
+
+    // Find the smallest element size that the value is a replication of
+    let mut size = 64;
+    while size > 2 {
+        let half = size / 2;
+        let mask = (1u64 << half) - 1;
+        if value & mask != (value >> half) & mask {
+            break;
+        }
+        size = half;
+    }
+
+    let mask = if size == 64 { u64::MAX } else { (1u64 << size) - 1 };
+    let element = value & mask;
+
+    // Rotate the element right until its run of ones starts at bit zero
+    let ones = element.count_ones();
+    let rotation = (0..size).find(|r| {
+        let rotated = rotate_right(element, *r, size);
+        rotated == (1u64 << ones) - 1
+    })?;
+
+    let immr = (size - rotation) % size;
+    let imms = ((!(size - 1) << 1) | (ones - 1)) & 0x3f;
+    let n = u32::from(size == 64);
+    Some((n << 12) | (immr << 6) | imms)
+}
+
+pub fn is_logical_imm(value: u64, width: u32) -> bool {
+    encode_logical_imm(value, width).is_some()
+}
+
+fn rotate_right(value: u64, amount: u32, size: u32) -> u64 {
+    if amount == 0 {
+        return value;
+    }
+    let mask = if size == 64 { u64::MAX } else { (1u64 << size) - 1 };
+    ((value >> amount) | (value << (size - amount))) & mask
+}
+
+/// Encodes `value` as the 8-bit `fmov` immediate `±(16 + n) / 16 × 2^r`
+/// with `n` in 0..16 and `r` in -3..=4. The same values are encodable at
+/// every precision.
+pub fn encode_fp_imm(value: f64) -> Option<u8> {
+    (0..=255u8).find(|imm8| decode_fp_imm(*imm8) == value)
+}
+
+pub fn is_fp_imm(value: f64) -> bool {
+    encode_fp_imm(value).is_some()
+}
+
+/// The value of an 8-bit `fmov` immediate.
+pub fn decode_fp_imm(imm8: u8) -> f64 {
+    let fraction = (imm8 & 0xf) as f64;
+    let cd = ((imm8 >> 4) & 0x3) as i32;
+    let exponent = if imm8 & 0x40 == 0 { cd + 1 } else { cd - 3 };
+    let magnitude = (16.0 + fraction) / 16.0 * 2f64.powi(exponent);
+    if imm8 & 0x80 == 0 { magnitude } else { -magnitude }
+}
+
+/// Rounds `value` to the nearest IEEE half-precision bit pattern, ties to
+/// even.
+pub fn f64_to_f16(value: f64) -> u16 {
+    let sign = ((value.to_bits() >> 48) & 0x8000) as u16;
+    let magnitude = value.abs();
+    if magnitude.is_nan() {
+        return sign | 0x7e00;
+    }
+    // Values from the midpoint between 65504 and 2^16 upward round to infinity
+    if magnitude >= 65520.0 {
+        return sign | 0x7c00;
+    }
+    if magnitude < 2f64.powi(-14) {
+        // Subnormal: a multiple of 2^-24. Rounding up to 1024 yields the
+        // smallest normal, whose encoding is the same bit pattern.
+        return sign | (magnitude * 2f64.powi(24)).round_ties_even() as u16;
+    }
+    let mut exponent = ((magnitude.to_bits() >> 52) & 0x7ff) as i32 - 1023;
+    let mut mantissa = (magnitude * 2f64.powi(10 - exponent)).round_ties_even() as u16;
+    if mantissa == 2048 {
+        mantissa = 1024;
+        exponent += 1;
+    }
+    sign | ((exponent + 15) as u16) << 10 | (mantissa - 1024)
+}
+
+pub fn f16_to_f64(bits: u16) -> f64 {
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f64;
+    let magnitude = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        0x1f if mantissa == 0.0 => f64::INFINITY,
+        0x1f => f64::NAN,
+        _ => (1024.0 + mantissa) * 2f64.powi(exponent - 25),
+    };
+    if bits & 0x8000 == 0 { magnitude } else { -magnitude }
+}
@@ -0,0 +1,109 @@
+//! Address assignment for the text and read-only data of an [`ARM64`] stream.
+
+use super::*;
+use std::collections::HashMap;
+
+pub(crate) struct Layout {
+    /// Text followed by any literals still pending in the pool
+    pub text: Vec<Instruction>,
+    pub text_addresses: Vec<u64>,
+    pub rodata: Vec<Instruction>,
+    pub rodata_addresses: Vec<u64>,
+    pub labels: HashMap<String, u64>,
+    pub text_base: u64,
+    pub text_end: u64,
+    pub rodata_base: u64,
+    pub rodata_end: u64,
+}
+
+impl Layout {
+    /// Places text at `text_base` and read-only data on the following page.
+    /// Fails with the offending name when a label is defined twice.
+    pub fn new(arch: &ARM64, text_base: u64) -> Result<Self, String> {
+        let mut text: Vec<Instruction> = arch.get_instructions().iter().cloned().collect();
+        text.extend(arch.trailing_pool());
+        let rodata = arch.get_rodata().to_vec();
+
+        let mut labels = HashMap::new();
+        let (text_addresses, text_end) = assign(&text, text_base, &mut labels)?;
+        let rodata_base = (text_end + 0xfff) & !0xfff;
+        let (rodata_addresses, rodata_end) = assign(&rodata, rodata_base, &mut labels)?;
+
+        Ok(Self {
+            text,
+            text_addresses,
+            rodata,
+            rodata_addresses,
+            labels,
+            text_base,
+            text_end,
+            rodata_base,
+            rodata_end,
+        })
+    }
+
+    pub fn label(&self, name: &str) -> Option<u64> {
+        self.labels.get(name).copied()
+    }
+
+    /// The bytes a data directive at `addr` assembles to. Fails with the name
+    /// of an undefined label.
+    pub fn data_bytes(&self, directive: &DataDirective, addr: u64) -> Result<Vec<u8>, String> {
+        data_bytes(directive, addr, &self.labels)
+    }
+}
+
+/// The bytes `directive` assembles to at `addr`, with label addresses from
+/// `labels`. Fails with the name of an undefined label.
+pub(crate) fn data_bytes(directive: &DataDirective, addr: u64, labels: &HashMap<String, u64>) -> Result<Vec<u8>, String> {
+    let label = |name: &String| labels.get(name).copied().ok_or_else(|| name.clone());
+    Ok(match directive {
+        DataDirective::Align(power) => {
+            let align = 1u64 << power;
+            vec![0; (((addr + align - 1) & !(align - 1)) - addr) as usize]
+        }
+        DataDirective::Byte(bytes) => bytes.clone(),
+        DataDirective::Hword(value) => value.to_le_bytes().to_vec(),
+        DataDirective::Word(value) => value.to_le_bytes().to_vec(),
+        DataDirective::Quad(value) => value.to_le_bytes().to_vec(),
+        DataDirective::LabelDiff { target, base } => (label(target)?.wrapping_sub(label(base)?) as i32).to_le_bytes().to_vec(),
+        DataDirective::Asciz(value) => value.bytes().chain([0]).collect(),
+        DataDirective::Zero(size) => vec![0; *size as usize],
+        DataDirective::Pointer { symbol, offset } => label(symbol)?.wrapping_add(*offset as u64).to_le_bytes().to_vec(),
+    })
+}
+
+/// The offset just past `inst` when it is placed at `offset`.
+pub(crate) fn advance(offset: u64, inst: &Instruction) -> u64 {
+    match inst {
+        Instruction::Label(_) => offset,
+        Instruction::Data(DataDirective::Align(power)) => {
+            let align = 1u64 << power;
+            (offset + align - 1) & !(align - 1)
+        }
+        Instruction::Data(DataDirective::Byte(bytes)) => offset + bytes.len() as u64,
+        Instruction::Data(DataDirective::Hword(_)) => offset + 2,
+        Instruction::Data(DataDirective::Word(_) | DataDirective::LabelDiff { .. }) => offset + 4,
+        Instruction::Data(DataDirective::Quad(_) | DataDirective::Pointer { .. }) => offset + 8,
+        Instruction::Data(DataDirective::Asciz(value)) => offset + value.len() as u64 + 1,
+        Instruction::Data(DataDirective::Zero(size)) => offset + size,
+        // `adrp` followed by `add` or `ldr`
+        Instruction::Address(AddressOp::AdrpAdd { .. } | AddressOp::AdrpGot { .. }) => offset + 8,
+        _ => offset + 4,
+    }
+}
+
+fn assign(insts: &[Instruction], base: u64, labels: &mut HashMap<String, u64>) -> Result<(Vec<u64>, u64), String> {
+    let mut addresses = Vec::with_capacity(insts.len());
+    let mut addr = base;
+    for inst in insts {
+        if let Instruction::Label(name) = inst {
+            if labels.insert(name.clone(), addr).is_some() {
+                return Err(name.clone());
+            }
+        }
+        addresses.push(addr);
+        addr = advance(addr, inst);
+    }
+    Ok((addresses, addr))
+}
@@ -0,0 +1,447 @@
+//! Operand checks for [`ARM64`] instruction streams.
+//!
+//! Builders accept any [`Arm64Register`], so combinations the architecture
+//! cannot encode, such as `add x0, w1, x2` or `fadd d0, x1, d2`, are caught
+//! here before encoding or emulation. [`operands`] is the table of which
+//! register class each operand position takes. [`diagnose`] goes further
+//! and trial-encodes the stream, collecting every problem as a
+//! [`BuildError`].
+
+use super::encoder::{encode, EncodeError};
+use super::layout::Layout;
+use super::*;
+
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    /// General purpose operands of different widths, or a width the
+    /// instruction does not support
+    WidthMismatch { index: usize, inst: String },
+    /// An operand outside the register class of its position
+    RegisterClass { index: usize, inst: String, expected: OperandKind },
+    /// An `msr` to a read-only system register
+    ReadOnlyRegister { index: usize, inst: String },
+}
+
+/// The registers an operand position accepts. Register number 31 names
+/// SP in some positions and the zero register in others.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperandKind {
+    /// X0-X30 or their W views
+    Gpr,
+    GprOrSp,
+    GprOrZr,
+    /// A SIMD&FP register. Whether it is used as a scalar or a vector is
+    /// up to the instruction's size or arrangement, not the register.
+    Fpr,
+}
+
+impl OperandKind {
+    pub fn accepts(&self, reg: Arm64Register) -> bool {
+        match self {
+            Self::Gpr => reg.is_general_purpose(),
+            Self::GprOrSp => reg.is_general_purpose() || reg.is_sp(),
+            Self::GprOrZr => reg.is_general_purpose() || reg.is_zero(),
+            Self::Fpr => reg.is_floating_point(),
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Gpr => "general purpose register",
+            Self::GprOrSp => "general purpose register or SP",
+            Self::GprOrZr => "general purpose register or zero register",
+            Self::Fpr => "SIMD&FP register",
+        }
+    }
+}
+
+/// Checks every instruction in `arch`, reporting the first one that fails.
+pub fn validate(arch: &ARM64) -> Result<(), ValidationError> {
+    for (index, inst) in arch.get_instructions().iter().enumerate() {
+        if let Some((_, expected)) = misclassified(inst) {
+            return Err(ValidationError::RegisterClass { index, inst: inst.to_string(), expected });
+        }
+        if !widths_agree(inst) {
+            return Err(ValidationError::WidthMismatch { index, inst: inst.to_string() });
+        }
+        if read_only_target(inst).is_some() {
+            return Err(ValidationError::ReadOnlyRegister { index, inst: inst.to_string() });
+        }
+    }
+    Ok(())
+}
+
+/// Checks every instruction in `arch` without stopping at the first
+/// failure: operand widths, labels that are neither defined in `arch` nor
+/// listed in `external`, and immediates or distances that do not fit their
+/// fields. Forms the encoder does not implement are left to the assembler.
+pub fn diagnose(arch: &ARM64, external: &[String]) -> Vec<BuildError> {
+    let emitted = arch.emitted();
+    let site = |index: usize| Site { index, location: emitted.get(index).and_then(|e| e.source_location) };
+    let layout = match Layout::new(arch, 0) {
+        Ok(layout) => layout,
+        Err(label) => {
+            let index = emitted
+                .iter()
+                .rposition(|e| matches!(&e.inst, Instruction::Label(name) if *name == label))
+                .unwrap_or(0);
+            return vec![BuildError::DuplicateLabel { site: site(index), label }];
+        }
+    };
+
+    let mut errors = Vec::new();
+    for (index, (inst, addr)) in layout.text.iter().zip(&layout.text_addresses).enumerate() {
+        if let Some((reg, expected)) = misclassified(inst) {
+            errors.push(BuildError::InvalidRegisterClass {
+                site: site(index),
+                operand: reg.to_string(),
+                expected: expected.description(),
+            });
+            continue;
+        }
+        if !widths_agree(inst) {
+            errors.push(BuildError::WidthMismatch { site: site(index), inst: inst.to_string() });
+            continue;
+        }
+        if let Some(register) = read_only_target(inst) {
+            errors.push(BuildError::ReadOnlyRegister { site: site(index), register: register.to_string() });
+            continue;
+        }
+        // External symbols are placed by the linker; resolving them to the
+        // instruction itself keeps any distance in range
+        let resolve = |name: &str| layout.label(name).or_else(|| external.iter().any(|e| e == name).then_some(*addr));
+        let result = match inst {
+            Instruction::Data(directive) => layout.data_bytes(directive, *addr).map(|_| ()).map_err(EncodeError::UndefinedLabel),
+            _ => encode(inst, *addr, &resolve).map(|_| ()),
+        };
+        match result {
+            Err(EncodeError::UndefinedLabel(label)) => errors.push(BuildError::UndefinedLabel { site: site(index), label }),
+            Err(EncodeError::OutOfRange { value, .. }) => {
+                errors.push(BuildError::ImmediateOutOfRange { site: site(index), value: value.to_string() })
+            }
+            _ => {}
+        }
+    }
+    errors
+}
+
+/// The first operand of `inst` outside the class its position accepts.
+pub fn misclassified(inst: &Instruction) -> Option<(Arm64Register, OperandKind)> {
+    operands(inst).into_iter().find(|(reg, kind)| !kind.accepts(*reg))
+}
+
+/// The system register `inst` writes, if it is read-only.
+pub fn read_only_target(inst: &Instruction) -> Option<SystemRegister> {
+    match inst {
+        Instruction::System(SystemOp::Msr { dst, .. }) if !dst.is_writable() => Some(*dst),
+        _ => None,
+    }
+}
+
+/// Each register operand of `inst` with the class its position accepts.
+pub fn operands(inst: &Instruction) -> Vec<(Arm64Register, OperandKind)> {
+    use OperandKind::{Fpr, Gpr, GprOrSp, GprOrZr};
+    match inst {
+        Instruction::Arithmetic(op) => match op {
+            // Register 31 is SP in the extended form the encoder switches to
+            // when either operand is SP, and the zero register otherwise
+            ArithmeticOp::Add { dst, src1, src2 } | ArithmeticOp::Sub { dst, src1, src2 } => {
+                let kind = if dst.is_sp() || src1.is_sp() { GprOrSp } else { GprOrZr };
+                vec![(*dst, kind), (*src1, kind), (*src2, GprOrZr)]
+            }
+            ArithmeticOp::Cmp { src1, src2 } => {
+                let kind = if src1.is_sp() { GprOrSp } else { GprOrZr };
+                vec![(*src1, kind), (*src2, GprOrZr)]
+            }
+            ArithmeticOp::AddImm { dst, src1, .. } => vec![(*dst, GprOrSp), (*src1, GprOrSp)],
+            ArithmeticOp::CmpImm { src1, .. } => vec![(*src1, GprOrSp)],
+            ArithmeticOp::AddShifted { dst, src1, src2, .. }
+            | ArithmeticOp::Mul { dst, src1, src2 }
+            | ArithmeticOp::Sdiv { dst, src1, src2 }
+            | ArithmeticOp::Udiv { dst, src1, src2 }
+            | ArithmeticOp::Mneg { dst, src1, src2 }
+            | ArithmeticOp::Smull { dst, src1, src2 }
+            | ArithmeticOp::Umull { dst, src1, src2 }
+            | ArithmeticOp::Smulh { dst, src1, src2 }
+            | ArithmeticOp::Umulh { dst, src1, src2 }
+            | ArithmeticOp::Csel { dst, src1, src2, .. } => vec![(*dst, GprOrZr), (*src1, GprOrZr), (*src2, GprOrZr)],
+            ArithmeticOp::Madd { dst, src1, src2, acc }
+            | ArithmeticOp::Msub { dst, src1, src2, acc }
+            | ArithmeticOp::Smaddl { dst, src1, src2, acc }
+            | ArithmeticOp::Umaddl { dst, src1, src2, acc } => {
+                vec![(*dst, GprOrZr), (*src1, GprOrZr), (*src2, GprOrZr), (*acc, GprOrZr)]
+            }
+            ArithmeticOp::CmpShifted { src1, src2, .. } => vec![(*src1, GprOrZr), (*src2, GprOrZr)],
+            ArithmeticOp::Cset { dst, .. } => vec![(*dst, GprOrZr)],
+        },
+        Instruction::Float(op) => match op {
+            FloatOp::Fadd { dst, src1, src2, .. }
+            | FloatOp::Fsub { dst, src1, src2, .. }
+            | FloatOp::Fmul { dst, src1, src2, .. }
+            | FloatOp::Fdiv { dst, src1, src2, .. }
+            | FloatOp::Fcsel { dst, src1, src2, .. } => vec![(*dst, Fpr), (*src1, Fpr), (*src2, Fpr)],
+            FloatOp::Fmadd { dst, src1, src2, acc, .. } => vec![(*dst, Fpr), (*src1, Fpr), (*src2, Fpr), (*acc, Fpr)],
+            FloatOp::Fsqrt { dst, src, .. }
+            | FloatOp::Fneg { dst, src, .. }
+            | FloatOp::Fabs { dst, src, .. }
+            | FloatOp::Fmov { dst, src, .. }
+            | FloatOp::Fcvt { dst, src, .. } => vec![(*dst, Fpr), (*src, Fpr)],
+            FloatOp::Fcmp { src1, src2, .. } => vec![(*src1, Fpr), (*src2, Fpr)],
+            FloatOp::FmovToFp { dst, src, .. } | FloatOp::Scvtf { dst, src, .. } | FloatOp::Ucvtf { dst, src, .. } => {
+                vec![(*dst, Fpr), (*src, GprOrZr)]
+            }
+            FloatOp::FmovFromFp { dst, src, .. } | FloatOp::Fcvtzs { dst, src, .. } | FloatOp::Fcvtzu { dst, src, .. } => {
+                vec![(*dst, GprOrZr), (*src, Fpr)]
+            }
+            FloatOp::FmovImm { dst, .. } => vec![(*dst, Fpr)],
+        },
+        Instruction::Vector(op) => match op {
+            VectorOp::Add { dst, src1, src2, .. }
+            | VectorOp::Sub { dst, src1, src2, .. }
+            | VectorOp::Mul { dst, src1, src2, .. }
+            | VectorOp::Fadd { dst, src1, src2, .. }
+            | VectorOp::Fsub { dst, src1, src2, .. }
+            | VectorOp::Fmul { dst, src1, src2, .. } => vec![(*dst, Fpr), (*src1, Fpr), (*src2, Fpr)],
+            VectorOp::Ld1 { first, addr, .. } | VectorOp::St1 { first, addr, .. } => {
+                let mut operands = vec![(*first, Fpr)];
+                operands.extend(address_operands(addr));
+                operands
+            }
+            VectorOp::Dup { dst, src, .. } => vec![(*dst, Fpr), (*src, GprOrZr)],
+            VectorOp::DupLane { dst, src, .. } => vec![(*dst, Fpr), (*src, Fpr)],
+            VectorOp::Ins { dst, src, .. } => vec![(*dst, Fpr), (*src, GprOrZr)],
+            VectorOp::Umov { dst, src, .. } => vec![(*dst, GprOrZr), (*src, Fpr)],
+            // The sum is a scalar
+            VectorOp::Addv { dst, src, .. } => vec![(*dst, Fpr), (*src, Fpr)],
+        },
+        Instruction::Logical(op) => match op {
+            LogicalOp::And { dst, src1, src2, .. }
+            | LogicalOp::Orr { dst, src1, src2, .. }
+            | LogicalOp::Eor { dst, src1, src2, .. }
+            | LogicalOp::Bic { dst, src1, src2, .. }
+            | LogicalOp::Orn { dst, src1, src2, .. }
+            | LogicalOp::Eon { dst, src1, src2, .. } => vec![(*dst, GprOrZr), (*src1, GprOrZr), (*src2, GprOrZr)],
+            // The immediate forms can write SP, as in `mov sp, #imm`
+            LogicalOp::AndImm { dst, src, .. } | LogicalOp::OrrImm { dst, src, .. } | LogicalOp::EorImm { dst, src, .. } => {
+                vec![(*dst, GprOrSp), (*src, GprOrZr)]
+            }
+        },
+        Instruction::Shift(op) => match op {
+            ShiftOp::LslImm { dst, src, .. }
+            | ShiftOp::LsrImm { dst, src, .. }
+            | ShiftOp::AsrImm { dst, src, .. }
+            | ShiftOp::RorImm { dst, src, .. } => vec![(*dst, GprOrZr), (*src, GprOrZr)],
+            ShiftOp::Lsl { dst, src1, src2 }
+            | ShiftOp::Lsr { dst, src1, src2 }
+            | ShiftOp::Asr { dst, src1, src2 }
+            | ShiftOp::Ror { dst, src1, src2 } => vec![(*dst, GprOrZr), (*src1, GprOrZr), (*src2, GprOrZr)],
+        },
+        Instruction::Bitfield(op) => match op {
+            BitfieldOp::Ubfx { dst, src, .. }
+            | BitfieldOp::Sbfx { dst, src, .. }
+            | BitfieldOp::Bfi { dst, src, .. }
+            | BitfieldOp::Ubfiz { dst, src, .. } => vec![(*dst, GprOrZr), (*src, GprOrZr)],
+            BitfieldOp::Extr { dst, src1, src2, .. } => vec![(*dst, GprOrZr), (*src1, GprOrZr), (*src2, GprOrZr)],
+        },
+        Instruction::Bit(op) => match op {
+            BitOp::Clz { dst, src }
+            | BitOp::Cls { dst, src }
+            | BitOp::Rbit { dst, src }
+            | BitOp::Rev { dst, src }
+            | BitOp::Rev16 { dst, src }
+            | BitOp::Rev32 { dst, src } => vec![(*dst, GprOrZr), (*src, GprOrZr)],
+        },
+        Instruction::Branch(op) => match op {
+            BranchOp::Cbz { reg, .. } | BranchOp::Cbnz { reg, .. } => vec![(*reg, GprOrZr)],
+            BranchOp::Br { reg } | BranchOp::Blr { reg } => vec![(*reg, Gpr)],
+            BranchOp::Bl { .. } | BranchOp::B { .. } | BranchOp::Ret | BranchOp::BCond { .. } => Vec::new(),
+        },
+        Instruction::LoadStore(op) => match op {
+            LoadStoreOp::Ldr { dst: reg, .. } | LoadStoreOp::Str { src: reg, .. } => vec![(*reg, GprOrZr)],
+            LoadStoreOp::Load { dst: reg, addr, .. } | LoadStoreOp::Store { src: reg, addr, .. } => {
+                let mut operands = vec![(*reg, GprOrZr)];
+                operands.extend(address_operands(addr));
+                operands
+            }
+            LoadStoreOp::Ldp { dst1: first, dst2: second, addr } | LoadStoreOp::Stp { src1: first, src2: second, addr } => {
+                let mut operands = vec![(*first, GprOrZr), (*second, GprOrZr)];
+                operands.extend(address_operands(addr));
+                operands
+            }
+        },
+        Instruction::Atomic(op) => match op {
+            AtomicOp::Ldxr { dst, addr, .. } => vec![(*dst, GprOrZr), (*addr, GprOrSp)],
+            AtomicOp::Stxr { status, src, addr, .. } => vec![(*status, GprOrZr), (*src, GprOrZr), (*addr, GprOrSp)],
+            AtomicOp::Ldadd { src, dst, addr, .. } | AtomicOp::Swp { src, dst, addr, .. } => {
+                vec![(*src, GprOrZr), (*dst, GprOrZr), (*addr, GprOrSp)]
+            }
+            AtomicOp::Cas { expected, new, addr, .. } => vec![(*expected, GprOrZr), (*new, GprOrZr), (*addr, GprOrSp)],
+        },
+        Instruction::System(op) => match op {
+            SystemOp::Msr { src: reg, .. }
+            | SystemOp::Mrs { dst: reg, .. }
+            | SystemOp::Sys { src: reg, .. }
+            | SystemOp::Sysl { dst: reg, .. } => vec![(*reg, GprOrZr)],
+            SystemOp::Svc { .. } | SystemOp::Eret | SystemOp::Dmb { .. } | SystemOp::Dsb { .. } | SystemOp::Isb => Vec::new(),
+        },
+        Instruction::Address(op) => match op {
+            AddressOp::Adr { dst, .. } | AddressOp::Adrp { dst, .. } => vec![(*dst, GprOrZr)],
+            // `base` is written by the `adrp` and read by the `add`, which
+            // disagree on register 31
+            AddressOp::AdrpAdd { dst, base, .. } => vec![(*dst, GprOrSp), (*base, Gpr)],
+            // Both the `adrp` and the `ldr` base treat register 31 differently
+            AddressOp::AdrpGot { dst, .. } => vec![(*dst, Gpr)],
+        },
+        Instruction::Move(op) => match op {
+            MoveOp::Movz { dst, .. } | MoveOp::Movn { dst, .. } | MoveOp::Movk { dst, .. } => vec![(*dst, GprOrZr)],
+        },
+        Instruction::Label(_) | Instruction::Data(_) => Vec::new(),
+    }
+}
+
+fn address_operands(addr: &MemOperand<Arm64Register>) -> Vec<(Arm64Register, OperandKind)> {
+    match addr {
+        MemOperand::Offset(base, _) | MemOperand::PreIndex(base, _) | MemOperand::PostIndex(base, _) => {
+            vec![(*base, OperandKind::GprOrSp)]
+        }
+        MemOperand::Indexed(base, index, _) => vec![(*base, OperandKind::GprOrSp), (*index, OperandKind::GprOrZr)],
+    }
+}
+
+/// Whether the general purpose operands of `inst` have widths it can encode.
+pub fn widths_agree(inst: &Instruction) -> bool {
+    match inst {
+        Instruction::Arithmetic(op) => match op {
+            ArithmeticOp::Add { dst, src1, src2 }
+            | ArithmeticOp::Sub { dst, src1, src2 }
+            | ArithmeticOp::Mul { dst, src1, src2 }
+            | ArithmeticOp::Sdiv { dst, src1, src2 }
+            | ArithmeticOp::Udiv { dst, src1, src2 }
+            | ArithmeticOp::Mneg { dst, src1, src2 }
+            | ArithmeticOp::AddShifted { dst, src1, src2, .. }
+            | ArithmeticOp::Csel { dst, src1, src2, .. } => same(&[*dst, *src1, *src2]),
+            ArithmeticOp::Madd { dst, src1, src2, acc } | ArithmeticOp::Msub { dst, src1, src2, acc } => {
+                same(&[*dst, *src1, *src2, *acc])
+            }
+            ArithmeticOp::AddImm { dst, src1, .. } => same(&[*dst, *src1]),
+            // The widening multiplies read W views of any source register
+            ArithmeticOp::Smull { dst, .. } | ArithmeticOp::Umull { dst, .. } => wide(&[*dst]),
+            ArithmeticOp::Smaddl { dst, acc, .. } | ArithmeticOp::Umaddl { dst, acc, .. } => wide(&[*dst, *acc]),
+            ArithmeticOp::Smulh { dst, src1, src2 } | ArithmeticOp::Umulh { dst, src1, src2 } => {
+                wide(&[*dst, *src1, *src2])
+            }
+            ArithmeticOp::Cmp { src1, src2 } | ArithmeticOp::CmpShifted { src1, src2, .. } => same(&[*src1, *src2]),
+            ArithmeticOp::CmpImm { .. } | ArithmeticOp::Cset { .. } => true,
+        },
+        Instruction::Float(op) => match op {
+            FloatOp::FmovToFp { size: FpSize::Double, src: reg, .. }
+            | FloatOp::FmovFromFp { size: FpSize::Double, dst: reg, .. } => !reg.is_32bit(),
+            _ => true,
+        },
+        Instruction::Vector(op) => match op {
+            VectorOp::Ld1 { addr, .. } | VectorOp::St1 { addr, .. } => address(addr),
+            VectorOp::Dup { arrangement, src: reg, .. } => lane_register(arrangement.element(), *reg),
+            VectorOp::Ins { lane, src: reg, .. } | VectorOp::Umov { lane, dst: reg, .. } => lane_register(*lane, *reg),
+            _ => true,
+        },
+        Instruction::Logical(op) => match op {
+            LogicalOp::And { dst, src1, src2, .. }
+            | LogicalOp::Orr { dst, src1, src2, .. }
+            | LogicalOp::Eor { dst, src1, src2, .. }
+            | LogicalOp::Bic { dst, src1, src2, .. }
+            | LogicalOp::Orn { dst, src1, src2, .. }
+            | LogicalOp::Eon { dst, src1, src2, .. } => same(&[*dst, *src1, *src2]),
+            LogicalOp::AndImm { dst, src, .. } | LogicalOp::OrrImm { dst, src, .. } | LogicalOp::EorImm { dst, src, .. } => {
+                same(&[*dst, *src])
+            }
+        },
+        Instruction::Shift(op) => match op {
+            ShiftOp::LslImm { dst, src, .. }
+            | ShiftOp::LsrImm { dst, src, .. }
+            | ShiftOp::AsrImm { dst, src, .. }
+            | ShiftOp::RorImm { dst, src, .. } => same(&[*dst, *src]),
+            ShiftOp::Lsl { dst, src1, src2 }
+            | ShiftOp::Lsr { dst, src1, src2 }
+            | ShiftOp::Asr { dst, src1, src2 }
+            | ShiftOp::Ror { dst, src1, src2 } => same(&[*dst, *src1, *src2]),
+        },
+        Instruction::Bitfield(op) => match op {
+            BitfieldOp::Ubfx { dst, src, .. }
+            | BitfieldOp::Sbfx { dst, src, .. }
+            | BitfieldOp::Bfi { dst, src, .. }
+            | BitfieldOp::Ubfiz { dst, src, .. } => same(&[*dst, *src]),
+            BitfieldOp::Extr { dst, src1, src2, .. } => same(&[*dst, *src1, *src2]),
+        },
+        Instruction::Bit(op) => match op {
+            BitOp::Rev32 { dst, src } => wide(&[*dst, *src]),
+            BitOp::Clz { dst, src }
+            | BitOp::Cls { dst, src }
+            | BitOp::Rbit { dst, src }
+            | BitOp::Rev { dst, src }
+            | BitOp::Rev16 { dst, src } => same(&[*dst, *src]),
+        },
+        Instruction::Branch(op) => match op {
+            BranchOp::Br { reg } | BranchOp::Blr { reg } => wide(&[*reg]),
+            _ => true,
+        },
+        Instruction::LoadStore(op) => match op {
+            LoadStoreOp::Ldr { .. } | LoadStoreOp::Str { .. } => true,
+            // A W register holds neither a doubleword nor a sign-extended word
+            LoadStoreOp::Load { size, signed, dst, addr } => {
+                let too_wide = *size == MemSize::Double || (*size == MemSize::Word && *signed);
+                !(dst.is_32bit() && too_wide) && address(addr)
+            }
+            LoadStoreOp::Store { size, src, addr } => !(src.is_32bit() && *size == MemSize::Double) && address(addr),
+            LoadStoreOp::Ldp { dst1, dst2, addr } => same(&[*dst1, *dst2]) && address(addr),
+            LoadStoreOp::Stp { src1, src2, addr } => same(&[*src1, *src2]) && address(addr),
+        },
+        // Doubleword accesses need X registers; the status result is always a W
+        Instruction::Atomic(op) => match op {
+            AtomicOp::Ldxr { size, dst: reg, addr, .. } => sized(*size, &[*reg]) && wide(&[*addr]),
+            AtomicOp::Stxr { size, src: reg, addr, .. } => sized(*size, &[*reg]) && wide(&[*addr]),
+            AtomicOp::Ldadd { size, src, dst, addr, .. } | AtomicOp::Swp { size, src, dst, addr, .. } => {
+                sized(*size, &[*src, *dst]) && wide(&[*addr])
+            }
+            AtomicOp::Cas { size, expected, new, addr, .. } => sized(*size, &[*expected, *new]) && wide(&[*addr]),
+        },
+        Instruction::Address(op) => match op {
+            AddressOp::Adr { dst, .. } | AddressOp::Adrp { dst, .. } => wide(&[*dst]),
+            AddressOp::AdrpAdd { dst, base, .. } => wide(&[*dst, *base]),
+            AddressOp::AdrpGot { dst, .. } => wide(&[*dst]),
+        },
+        Instruction::System(op) => match op {
+            SystemOp::Msr { src: reg, .. }
+            | SystemOp::Mrs { dst: reg, .. }
+            | SystemOp::Sys { src: reg, .. }
+            | SystemOp::Sysl { dst: reg, .. } => wide(&[*reg]),
+            _ => true,
+        },
+        Instruction::Label(_) | Instruction::Move(_) | Instruction::Data(_) => true,
+    }
+}
+
+fn same(regs: &[Arm64Register]) -> bool {
+    regs.windows(2).all(|pair| pair[0].is_32bit() == pair[1].is_32bit())
+}
+
+fn wide(regs: &[Arm64Register]) -> bool {
+    regs.iter().all(|reg| !reg.is_32bit())
+}
+
+fn sized(size: MemSize, regs: &[Arm64Register]) -> bool {
+    size != MemSize::Double || wide(regs)
+}
+
+/// Addresses are always formed from 64-bit registers.
+fn address(addr: &MemOperand<Arm64Register>) -> bool {
+    match addr {
+        MemOperand::Offset(base, _) | MemOperand::PreIndex(base, _) | MemOperand::PostIndex(base, _) => wide(&[*base]),
+        MemOperand::Indexed(base, index, _) => wide(&[*base, *index]),
+    }
+}
+
+/// Only a 64-bit lane needs an X register; narrower lanes are written with
+/// the W view of whatever register is given.
+fn lane_register(lane: MemSize, reg: Arm64Register) -> bool {
+    lane != MemSize::Double || !reg.is_32bit()
+}
@@ -1 +1,2 @@
-pub mod arm64; 
\ No newline at end of file
+pub mod arm64;
+pub mod x86_64;
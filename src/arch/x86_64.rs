@@ -0,0 +1,974 @@
+//! An x86_64 backend for static Linux executables.
+//!
+//! [`X86_64`] implements the builder traits whose operations have a direct
+//! x86_64 counterpart, so generic code written against
+//! [`InstructionBuilder`](crate::InstructionBuilder) can also be built for
+//! x86_64 and run natively on an x86_64 host. The generic registers follow
+//! the Linux system call convention: X8 holds the call number in `rax`,
+//! X0-X5 the arguments in `rdi`, `rsi`, `rdx`, `r10`, `r8` and `r9`, and
+//! `svc` becomes `syscall`, which returns its result in X8. X16 and X17 are
+//! `r11` and `rcx`, the registers `syscall` clobbers, and X9-X13 and X29 the
+//! remaining general purpose registers. Other generic registers have no
+//! counterpart and are reported as build errors where they are used, as are
+//! operations with no short x86_64 equivalent.
+//!
+//! Unlike on ARM64, `sub`, `mul`, `cbz` and `cbnz` change the flags.
+//! Listings use the Intel syntax.
+
+use crate::arch::arm64::{parse_imm, DataDirective};
+use crate::context::Value;
+use crate::instruction::*;
+use std::fmt::{self, Display};
+use std::panic::Location;
+
+pub mod encoder;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum X86Register {
+    Rax, Rcx, Rdx, Rbx, Rsp, Rbp, Rsi, Rdi,
+    R8, R9, R10, R11, R12, R13, R14, R15,
+    Eax, Ecx, Edx, Ebx, Esp, Ebp, Esi, Edi,
+    R8d, R9d, R10d, R11d, R12d, R13d, R14d, R15d,
+    /// A generic register with no x86_64 counterpart, reported when used
+    Unmapped(GenericRegister),
+}
+
+impl Register for X86Register {
+    fn is_general_purpose(&self) -> bool {
+        !matches!(self, Self::Unmapped(_))
+    }
+
+    fn is_floating_point(&self) -> bool {
+        false
+    }
+
+    fn is_special(&self) -> bool {
+        matches!(self, Self::Rsp | Self::Esp)
+    }
+}
+
+impl X86Register {
+    /// The register number used in encodings, or `None` for an unmapped
+    /// register.
+    pub fn number(&self) -> Option<u8> {
+        match self {
+            Self::Rax | Self::Eax => Some(0),
+            Self::Rcx | Self::Ecx => Some(1),
+            Self::Rdx | Self::Edx => Some(2),
+            Self::Rbx | Self::Ebx => Some(3),
+            Self::Rsp | Self::Esp => Some(4),
+            Self::Rbp | Self::Ebp => Some(5),
+            Self::Rsi | Self::Esi => Some(6),
+            Self::Rdi | Self::Edi => Some(7),
+            Self::R8 | Self::R8d => Some(8),
+            Self::R9 | Self::R9d => Some(9),
+            Self::R10 | Self::R10d => Some(10),
+            Self::R11 | Self::R11d => Some(11),
+            Self::R12 | Self::R12d => Some(12),
+            Self::R13 | Self::R13d => Some(13),
+            Self::R14 | Self::R14d => Some(14),
+            Self::R15 | Self::R15d => Some(15),
+            Self::Unmapped(_) => None,
+        }
+    }
+
+    /// Whether this is the 32-bit view of a register.
+    pub fn is_32bit(&self) -> bool {
+        matches!(self,
+            Self::Eax | Self::Ecx | Self::Edx | Self::Ebx | Self::Esp | Self::Ebp | Self::Esi | Self::Edi |
+            Self::R8d | Self::R9d | Self::R10d | Self::R11d | Self::R12d | Self::R13d | Self::R14d | Self::R15d
+        )
+    }
+
+    /// The 32-bit view of the register.
+    pub fn to_32bit(&self) -> Self {
+        match self {
+            Self::Rax | Self::Eax => Self::Eax,
+            Self::Rcx | Self::Ecx => Self::Ecx,
+            Self::Rdx | Self::Edx => Self::Edx,
+            Self::Rbx | Self::Ebx => Self::Ebx,
+            Self::Rsp | Self::Esp => Self::Esp,
+            Self::Rbp | Self::Ebp => Self::Ebp,
+            Self::Rsi | Self::Esi => Self::Esi,
+            Self::Rdi | Self::Edi => Self::Edi,
+            Self::R8 | Self::R8d => Self::R8d,
+            Self::R9 | Self::R9d => Self::R9d,
+            Self::R10 | Self::R10d => Self::R10d,
+            Self::R11 | Self::R11d => Self::R11d,
+            Self::R12 | Self::R12d => Self::R12d,
+            Self::R13 | Self::R13d => Self::R13d,
+            Self::R14 | Self::R14d => Self::R14d,
+            Self::R15 | Self::R15d => Self::R15d,
+            Self::Unmapped(reg) => Self::Unmapped(*reg),
+        }
+    }
+
+    /// The name of the low byte, as written by `setcc`.
+    fn byte_name(&self) -> String {
+        const NAMES: [&str; 16] = [
+            "al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil",
+            "r8b", "r9b", "r10b", "r11b", "r12b", "r13b", "r14b", "r15b",
+        ];
+        self.number().map_or_else(|| self.to_string(), |number| NAMES[number as usize].to_string())
+    }
+
+    /// The name of the low 16 bits.
+    fn half_name(&self) -> String {
+        const NAMES: [&str; 16] = [
+            "ax", "cx", "dx", "bx", "sp", "bp", "si", "di",
+            "r8w", "r9w", "r10w", "r11w", "r12w", "r13w", "r14w", "r15w",
+        ];
+        self.number().map_or_else(|| self.to_string(), |number| NAMES[number as usize].to_string())
+    }
+}
+
+impl Display for X86Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rax => write!(f, "rax"),
+            Self::Rcx => write!(f, "rcx"),
+            Self::Rdx => write!(f, "rdx"),
+            Self::Rbx => write!(f, "rbx"),
+            Self::Rsp => write!(f, "rsp"),
+            Self::Rbp => write!(f, "rbp"),
+            Self::Rsi => write!(f, "rsi"),
+            Self::Rdi => write!(f, "rdi"),
+            Self::R8 => write!(f, "r8"),
+            Self::R9 => write!(f, "r9"),
+            Self::R10 => write!(f, "r10"),
+            Self::R11 => write!(f, "r11"),
+            Self::R12 => write!(f, "r12"),
+            Self::R13 => write!(f, "r13"),
+            Self::R14 => write!(f, "r14"),
+            Self::R15 => write!(f, "r15"),
+            Self::Eax => write!(f, "eax"),
+            Self::Ecx => write!(f, "ecx"),
+            Self::Edx => write!(f, "edx"),
+            Self::Ebx => write!(f, "ebx"),
+            Self::Esp => write!(f, "esp"),
+            Self::Ebp => write!(f, "ebp"),
+            Self::Esi => write!(f, "esi"),
+            Self::Edi => write!(f, "edi"),
+            Self::R8d => write!(f, "r8d"),
+            Self::R9d => write!(f, "r9d"),
+            Self::R10d => write!(f, "r10d"),
+            Self::R11d => write!(f, "r11d"),
+            Self::R12d => write!(f, "r12d"),
+            Self::R13d => write!(f, "r13d"),
+            Self::R14d => write!(f, "r14d"),
+            Self::R15d => write!(f, "r15d"),
+            Self::Unmapped(reg) => write!(f, "{}", format!("{:?}", reg).to_lowercase()),
+        }
+    }
+}
+
+impl From<GenericRegister> for X86Register {
+    fn from(reg: GenericRegister) -> Self {
+        match reg {
+            GenericRegister::X0 => X86Register::Rdi,
+            GenericRegister::X1 => X86Register::Rsi,
+            GenericRegister::X2 => X86Register::Rdx,
+            GenericRegister::X3 => X86Register::R10,
+            GenericRegister::X4 => X86Register::R8,
+            GenericRegister::X5 => X86Register::R9,
+            GenericRegister::X8 => X86Register::Rax,
+            GenericRegister::X9 => X86Register::Rbx,
+            GenericRegister::X10 => X86Register::R12,
+            GenericRegister::X11 => X86Register::R13,
+            GenericRegister::X12 => X86Register::R14,
+            GenericRegister::X13 => X86Register::R15,
+            GenericRegister::X16 => X86Register::R11,
+            GenericRegister::X17 => X86Register::Rcx,
+            GenericRegister::X29 => X86Register::Rbp,
+            GenericRegister::SP => X86Register::Rsp,
+            GenericRegister::W0 => X86Register::Edi,
+            GenericRegister::W1 => X86Register::Esi,
+            GenericRegister::W2 => X86Register::Edx,
+            GenericRegister::W3 => X86Register::R10d,
+            GenericRegister::W4 => X86Register::R8d,
+            GenericRegister::W5 => X86Register::R9d,
+            GenericRegister::W8 => X86Register::Eax,
+            GenericRegister::W9 => X86Register::Ebx,
+            GenericRegister::W10 => X86Register::R12d,
+            GenericRegister::W11 => X86Register::R13d,
+            GenericRegister::W12 => X86Register::R14d,
+            GenericRegister::W13 => X86Register::R15d,
+            GenericRegister::W16 => X86Register::R11d,
+            GenericRegister::W17 => X86Register::Ecx,
+            GenericRegister::W29 => X86Register::Ebp,
+            GenericRegister::WSP => X86Register::Esp,
+            reg => X86Register::Unmapped(reg),
+        }
+    }
+}
+
+impl RegisterMapping<X86Register> for GenericRegister {
+    fn to_arch_reg(&self) -> X86Register {
+        X86Register::from(*self)
+    }
+}
+
+impl From<String> for Operand<X86Register> {
+    fn from(s: String) -> Self {
+        Operand::Immediate(s)
+    }
+}
+
+impl<'a> From<&'a str> for Operand<X86Register> {
+    fn from(s: &'a str) -> Self {
+        Operand::Immediate(s.to_string())
+    }
+}
+
+impl From<GenericRegister> for Operand<X86Register> {
+    fn from(reg: GenericRegister) -> Self {
+        Operand::Register(reg.to_arch_reg())
+    }
+}
+
+impl From<(GenericRegister, ShiftKind, u8)> for Operand<X86Register> {
+    fn from((reg, shift, amount): (GenericRegister, ShiftKind, u8)) -> Self {
+        Operand::Shifted(reg.to_arch_reg(), shift, amount)
+    }
+}
+
+/// A memory operand, `[base + index * scale + disp]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mem {
+    pub base: X86Register,
+    /// The index register and its scale, 1, 2, 4 or 8
+    pub index: Option<(X86Register, u8)>,
+    pub disp: i32,
+}
+
+impl Mem {
+    pub fn new(base: X86Register, disp: i32) -> Self {
+        Self { base, index: None, disp }
+    }
+}
+
+impl Display for Mem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}", self.base)?;
+        match self.index {
+            Some((index, 1)) => write!(f, " + {}", index)?,
+            Some((index, scale)) => write!(f, " + {}*{}", index, scale)?,
+            None => {}
+        }
+        match self.disp {
+            0 => write!(f, "]"),
+            disp if disp < 0 => write!(f, " - {}]", disp.unsigned_abs()),
+            disp => write!(f, " + {}]", disp),
+        }
+    }
+}
+
+/// The two-operand integer operations sharing the `/r` and `/digit`
+/// encodings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AluOp {
+    Add,
+    Or,
+    And,
+    Sub,
+    Xor,
+    Cmp,
+}
+
+impl AluOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Or => "or",
+            Self::And => "and",
+            Self::Sub => "sub",
+            Self::Xor => "xor",
+            Self::Cmp => "cmp",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Register(X86Register),
+    Immediate(i32),
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Register(reg) => write!(f, "{}", reg),
+            Self::Immediate(imm) => write!(f, "{}", imm),
+        }
+    }
+}
+
+/// The x86_64 condition code suffix for `cond` after a `cmp`, or `None`
+/// for [`Condition::Al`].
+pub fn condition_code(cond: Condition) -> Option<(u8, &'static str)> {
+    match cond {
+        Condition::Vs => Some((0x0, "o")),
+        Condition::Vc => Some((0x1, "no")),
+        Condition::Lo => Some((0x2, "b")),
+        Condition::Hs => Some((0x3, "ae")),
+        Condition::Eq => Some((0x4, "e")),
+        Condition::Ne => Some((0x5, "ne")),
+        Condition::Ls => Some((0x6, "be")),
+        Condition::Hi => Some((0x7, "a")),
+        Condition::Mi => Some((0x8, "s")),
+        Condition::Pl => Some((0x9, "ns")),
+        Condition::Lt => Some((0xc, "l")),
+        Condition::Ge => Some((0xd, "ge")),
+        Condition::Le => Some((0xe, "le")),
+        Condition::Gt => Some((0xf, "g")),
+        Condition::Al => None,
+    }
+}
+
+/// An x86_64 instruction. Conditions are never [`Condition::Al`]; the
+/// builders emit the unconditional form instead.
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    Label(String),
+    Mov { dst: X86Register, src: X86Register },
+    MovImm { dst: X86Register, imm: u64 },
+    Lea { dst: X86Register, addr: Mem },
+    /// `lea dst, [rip + label]`
+    LeaLabel { dst: X86Register, label: String },
+    Alu { op: AluOp, dst: X86Register, src: Source },
+    Imul { dst: X86Register, src: X86Register },
+    Neg { dst: X86Register },
+    /// `test reg, reg`
+    Test { reg: X86Register },
+    /// `setcc` of the low byte followed by `movzx` of the whole register
+    Cset { cond: Condition, dst: X86Register },
+    Cmov { cond: Condition, dst: X86Register, src: X86Register },
+    Jmp { label: String },
+    Jcc { cond: Condition, label: String },
+    Call { label: String },
+    JmpReg { target: X86Register },
+    CallReg { target: X86Register },
+    Ret,
+    Syscall,
+    /// Loads `size` bytes, zero- or sign-extended to the width of `dst`
+    Load { size: MemSize, signed: bool, dst: X86Register, addr: Mem },
+    Store { size: MemSize, src: X86Register, addr: Mem },
+    /// `mov dst, [rip + label]`
+    LoadLabel { dst: X86Register, label: String },
+    /// `mov [rip + label], src`
+    StoreLabel { src: X86Register, label: String },
+    Data(DataDirective),
+}
+
+impl Instruction {
+    /// The registers the instruction names.
+    pub fn registers(&self) -> Vec<X86Register> {
+        let mem = |addr: &Mem| {
+            let mut regs = vec![addr.base];
+            regs.extend(addr.index.map(|(index, _)| index));
+            regs
+        };
+        match self {
+            Self::Mov { dst, src } | Self::Imul { dst, src } | Self::Cmov { dst, src, .. } => vec![*dst, *src],
+            Self::Alu { dst, src: Source::Register(src), .. } => vec![*dst, *src],
+            Self::MovImm { dst, .. }
+            | Self::LeaLabel { dst, .. }
+            | Self::Alu { dst, .. }
+            | Self::Neg { dst }
+            | Self::Cset { dst, .. }
+            | Self::LoadLabel { dst, .. } => vec![*dst],
+            Self::Test { reg } | Self::StoreLabel { src: reg, .. } => vec![*reg],
+            Self::JmpReg { target } | Self::CallReg { target } => vec![*target],
+            Self::Lea { dst, addr } | Self::Load { dst, addr, .. } => [vec![*dst], mem(addr)].concat(),
+            Self::Store { src, addr, .. } => [vec![*src], mem(addr)].concat(),
+            Self::Label(_)
+            | Self::Jmp { .. }
+            | Self::Jcc { .. }
+            | Self::Call { .. }
+            | Self::Ret
+            | Self::Syscall
+            | Self::Data(_) => Vec::new(),
+        }
+    }
+
+    /// Whether the register operands have the widths the encoding needs:
+    /// the same width for both sides of a register operation, and 64-bit
+    /// address registers and branch targets.
+    pub fn widths_agree(&self) -> bool {
+        let address = |addr: &Mem| !addr.base.is_32bit() && addr.index.is_none_or(|(index, _)| !index.is_32bit());
+        match self {
+            Self::Mov { dst, src } | Self::Imul { dst, src } | Self::Cmov { dst, src, .. } => dst.is_32bit() == src.is_32bit(),
+            Self::Alu { dst, src: Source::Register(src), .. } => dst.is_32bit() == src.is_32bit(),
+            Self::Lea { addr, .. } | Self::Load { addr, .. } | Self::Store { addr, .. } => address(addr),
+            Self::JmpReg { target } | Self::CallReg { target } => !target.is_32bit(),
+            _ => true,
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cc = |cond: &Condition| condition_code(*cond).map_or("mp", |(_, suffix)| suffix);
+        let ptr = |size: &MemSize| match size {
+            MemSize::Byte => "byte ptr",
+            MemSize::Half => "word ptr",
+            MemSize::Word => "dword ptr",
+            MemSize::Double => "qword ptr",
+        };
+        match self {
+            Self::Label(name) => write!(f, "{}:", name),
+            Self::Mov { dst, src } => write!(f, "mov {}, {}", dst, src),
+            Self::MovImm { dst, imm } if *imm <= u32::MAX as u64 => write!(f, "mov {}, {:#x}", dst.to_32bit(), imm),
+            Self::MovImm { dst, imm } if i32::try_from(*imm as i64).is_ok() => write!(f, "mov {}, {}", dst, *imm as i64),
+            Self::MovImm { dst, imm } => write!(f, "movabs {}, {:#x}", dst, imm),
+            Self::Lea { dst, addr } => write!(f, "lea {}, {}", dst, addr),
+            Self::LeaLabel { dst, label } => write!(f, "lea {}, [rip + {}]", dst, label),
+            Self::Alu { op, dst, src } => write!(f, "{} {}, {}", op.as_str(), dst, src),
+            Self::Imul { dst, src } => write!(f, "imul {}, {}", dst, src),
+            Self::Neg { dst } => write!(f, "neg {}", dst),
+            Self::Test { reg } => write!(f, "test {}, {}", reg, reg),
+            Self::Cset { cond, dst } => {
+                write!(f, "set{} {}\n    movzx {}, {}", cc(cond), dst.byte_name(), dst.to_32bit(), dst.byte_name())
+            }
+            Self::Cmov { cond, dst, src } => write!(f, "cmov{} {}, {}", cc(cond), dst, src),
+            Self::Jmp { label } => write!(f, "jmp {}", label),
+            Self::Jcc { cond, label } => write!(f, "j{} {}", cc(cond), label),
+            Self::Call { label } => write!(f, "call {}", label),
+            Self::JmpReg { target } => write!(f, "jmp {}", target),
+            Self::CallReg { target } => write!(f, "call {}", target),
+            Self::Ret => write!(f, "ret"),
+            Self::Syscall => write!(f, "syscall"),
+            Self::Load { size: MemSize::Double, dst, addr, .. } => write!(f, "mov {}, qword ptr {}", dst, addr),
+            Self::Load { size: MemSize::Word, signed: true, dst, addr } if !dst.is_32bit() => {
+                write!(f, "movsxd {}, dword ptr {}", dst, addr)
+            }
+            Self::Load { size: MemSize::Word, dst, addr, .. } => write!(f, "mov {}, dword ptr {}", dst.to_32bit(), addr),
+            Self::Load { size, signed: true, dst, addr } => write!(f, "movsx {}, {} {}", dst, ptr(size), addr),
+            Self::Load { size, signed: false, dst, addr } => write!(f, "movzx {}, {} {}", dst.to_32bit(), ptr(size), addr),
+            Self::Store { size: MemSize::Byte, src, addr } => write!(f, "mov byte ptr {}, {}", addr, src.byte_name()),
+            Self::Store { size: MemSize::Half, src, addr } => write!(f, "mov word ptr {}, {}", addr, src.half_name()),
+            Self::Store { size: MemSize::Word, src, addr } => write!(f, "mov dword ptr {}, {}", addr, src.to_32bit()),
+            Self::Store { size: MemSize::Double, src, addr } => write!(f, "mov qword ptr {}, {}", addr, src),
+            Self::LoadLabel { dst, label } => write!(f, "mov {}, [rip + {}]", dst, label),
+            Self::StoreLabel { src, label } => write!(f, "mov [rip + {}], {}", label, src),
+            Self::Data(directive) => write!(f, "{}", directive),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct X86_64 {
+    instructions: Vec<Emitted<Instruction>>,
+    rodata: Vec<Instruction>,
+    /// Metadata for the next instruction pushed
+    pending_comment: Option<String>,
+    pending_tags: Vec<String>,
+    errors: Vec<BuildError>,
+}
+
+impl Default for X86_64 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl X86_64 {
+    pub fn new() -> Self {
+        Self {
+            instructions: Vec::new(),
+            rodata: Vec::new(),
+            pending_comment: None,
+            pending_tags: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// The text instructions with their comments, tags and call sites.
+    pub fn emitted(&self) -> &[Emitted<Instruction>] {
+        &self.instructions
+    }
+
+    /// Labels and data placed in the read-only data section.
+    pub fn get_rodata(&self) -> &[Instruction] {
+        &self.rodata
+    }
+
+    /// Problems the builders found in their operands, in emission order.
+    pub fn build_errors(&self) -> &[BuildError] {
+        &self.errors
+    }
+
+    /// The site the next instruction will be pushed at.
+    #[track_caller]
+    fn site(&self) -> Site {
+        Site { index: self.instructions.len(), location: Some(Location::caller()) }
+    }
+
+    /// Appends `inst` with the pending comment and tags and the location of
+    /// the generator code that emitted it. Registers with no x86_64
+    /// counterpart and operands of the wrong width are recorded as errors
+    /// at its site.
+    #[track_caller]
+    fn push(&mut self, inst: Instruction) {
+        let site = self.site();
+        for reg in inst.registers() {
+            if let X86Register::Unmapped(_) = reg {
+                self.errors.push(BuildError::InvalidRegisterClass {
+                    site,
+                    operand: reg.to_string(),
+                    expected: "register with an x86_64 counterpart",
+                });
+            }
+        }
+        if !inst.widths_agree() {
+            self.errors.push(BuildError::WidthMismatch { site, inst: inst.to_string() });
+        }
+        self.instructions.push(Emitted {
+            inst,
+            comment: self.pending_comment.take(),
+            source_location: site.location,
+            tags: std::mem::take(&mut self.pending_tags),
+        });
+    }
+
+    /// Records that `inst` has no x86_64 form. Nothing is emitted for it.
+    #[track_caller]
+    fn unsupported(&mut self, inst: &str) {
+        let site = self.site();
+        self.errors.push(BuildError::Unsupported { site, inst: inst.to_string() });
+    }
+
+    /// `imm` as a sign-extended 32-bit immediate, recording an error when it
+    /// is not a number or does not fit.
+    #[track_caller]
+    fn imm32(&mut self, imm: &str) -> i32 {
+        match parse_imm(imm).and_then(|value| i32::try_from(value).ok()) {
+            Some(value) => value,
+            None => {
+                let site = self.site();
+                self.errors.push(BuildError::ImmediateOutOfRange { site, value: imm.to_string() });
+                0
+            }
+        }
+    }
+
+    /// `addr` as a memory operand, with the base register to update before
+    /// (pre-index) or after (post-index) the access.
+    #[track_caller]
+    fn mem(&mut self, addr: MemOperand<X86Register>) -> (Mem, Option<i32>, Option<i32>) {
+        let disp = |this: &mut Self, offset: i64| this.imm32(&offset.to_string());
+        match addr {
+            MemOperand::Offset(base, offset) => (Mem::new(base, disp(self, offset)), None, None),
+            MemOperand::PreIndex(base, offset) => (Mem::new(base, 0), Some(disp(self, offset)), None),
+            MemOperand::PostIndex(base, offset) => (Mem::new(base, 0), None, Some(disp(self, offset))),
+            MemOperand::Indexed(base, index, shift) => {
+                let scale = match shift {
+                    0..=3 => 1 << shift,
+                    _ => {
+                        self.unsupported(&format!("[{}, {}, lsl #{}]", base, index, shift));
+                        1
+                    }
+                };
+                (Mem { base, index: Some((index, scale)), disp: 0 }, None, None)
+            }
+        }
+    }
+
+    /// Copies `src` into `dst` unless they are the same register.
+    #[track_caller]
+    fn copy(&mut self, dst: X86Register, src: X86Register) {
+        if dst != src {
+            self.push(Instruction::Mov { dst, src });
+        }
+    }
+
+    /// Places `value` in read-only data under `label`, aligned to `align`
+    /// bytes.
+    pub fn global(&mut self, label: &str, value: &Value, align: u64) {
+        if align > 1 {
+            self.rodata.push(Instruction::Data(DataDirective::Align(align.trailing_zeros())));
+        }
+        self.rodata.push(Instruction::Label(label.to_string()));
+        let directives = match value {
+            Value::Asciz(value) => vec![DataDirective::Asciz(value.clone())],
+            Value::Byte(items) => vec![DataDirective::Byte(items.clone())],
+            Value::Hword(items) => items.iter().map(|item| DataDirective::Hword(*item)).collect(),
+            Value::Word(items) => items.iter().map(|item| DataDirective::Word(*item as i32)).collect(),
+            Value::Quad(items) => items.iter().map(|item| DataDirective::Quad(*item)).collect(),
+            Value::Float(items) => items.iter().map(|item| DataDirective::Word(item.to_bits() as i32)).collect(),
+            Value::Double(items) => items.iter().map(|item| DataDirective::Quad(item.to_bits())).collect(),
+            Value::Zero(size) => vec![DataDirective::Zero(*size)],
+            Value::Pointer { symbol, offset } => vec![DataDirective::Pointer { symbol: symbol.clone(), offset: *offset }],
+        };
+        self.rodata.extend(directives.into_iter().map(Instruction::Data));
+    }
+}
+
+impl Backend for X86_64 {
+    type Register = X86Register;
+}
+
+impl AnnotationBuilder for X86_64 {
+    fn comment(&mut self, comment: &str) {
+        self.pending_comment = Some(comment.to_string());
+    }
+
+    fn tag(&mut self, tag: &str) {
+        self.pending_tags.push(tag.to_string());
+    }
+}
+
+impl LabelBuilder for X86_64 {
+    #[track_caller]
+    fn label(&mut self, name: &str) {
+        self.push(Instruction::Label(name.to_string()));
+    }
+}
+
+impl MovBuilder<X86Register> for X86_64 {
+    #[track_caller]
+    fn mov(&mut self, dst: X86Register, src: X86Register) {
+        self.push(Instruction::Mov { dst, src });
+    }
+
+    #[track_caller]
+    fn mov_imm(&mut self, dst: X86Register, imm: u64) {
+        let imm = if dst.is_32bit() { imm & 0xffff_ffff } else { imm };
+        self.push(Instruction::MovImm { dst, imm });
+    }
+}
+
+impl ArithmeticBuilder<X86Register> for X86_64 {
+    // `lea` adds three operands without touching the flags, as `add` does
+    // on ARM64
+    #[track_caller]
+    fn add(&mut self, dst: X86Register, src1: X86Register, src2: Operand<X86Register>) {
+        let addr = match src2 {
+            Operand::Register(src2) => Mem { base: src1, index: Some((src2, 1)), disp: 0 },
+            Operand::Immediate(imm) => Mem::new(src1, self.imm32(&imm)),
+            Operand::Shifted(src2, ShiftKind::Lsl, shift @ 0..=3) => Mem { base: src1, index: Some((src2, 1 << shift)), disp: 0 },
+            Operand::Shifted(src2, shift, amount) => {
+                self.unsupported(&format!("add {}, {}, {}, {} #{}", dst, src1, src2, shift.as_str(), amount));
+                return;
+            }
+        };
+        // The address registers are always the 64-bit views
+        let addr = Mem { base: to_64bit(addr.base), index: addr.index.map(|(index, scale)| (to_64bit(index), scale)), ..addr };
+        self.push(Instruction::Lea { dst, addr });
+    }
+
+    #[track_caller]
+    fn sub(&mut self, dst: X86Register, src1: X86Register, src2: X86Register) {
+        if dst == src2 && dst != src1 {
+            self.push(Instruction::Neg { dst });
+            self.push(Instruction::Alu { op: AluOp::Add, dst, src: Source::Register(src1) });
+        } else {
+            self.copy(dst, src1);
+            self.push(Instruction::Alu { op: AluOp::Sub, dst, src: Source::Register(src2) });
+        }
+    }
+
+    #[track_caller]
+    fn mul(&mut self, dst: X86Register, src1: X86Register, src2: X86Register) {
+        let other = if dst == src2 { src1 } else { src2 };
+        if dst != src2 {
+            self.copy(dst, src1);
+        }
+        self.push(Instruction::Imul { dst, src: other });
+    }
+
+    #[track_caller]
+    fn sdiv(&mut self, dst: X86Register, src1: X86Register, src2: X86Register) {
+        self.unsupported(&format!("sdiv {}, {}, {}", dst, src1, src2));
+    }
+
+    #[track_caller]
+    fn udiv(&mut self, dst: X86Register, src1: X86Register, src2: X86Register) {
+        self.unsupported(&format!("udiv {}, {}, {}", dst, src1, src2));
+    }
+
+    #[track_caller]
+    fn madd(&mut self, dst: X86Register, src1: X86Register, src2: X86Register, acc: X86Register) {
+        self.unsupported(&format!("madd {}, {}, {}, {}", dst, src1, src2, acc));
+    }
+
+    #[track_caller]
+    fn msub(&mut self, dst: X86Register, src1: X86Register, src2: X86Register, acc: X86Register) {
+        self.unsupported(&format!("msub {}, {}, {}, {}", dst, src1, src2, acc));
+    }
+
+    #[track_caller]
+    fn mneg(&mut self, dst: X86Register, src1: X86Register, src2: X86Register) {
+        self.unsupported(&format!("mneg {}, {}, {}", dst, src1, src2));
+    }
+
+    #[track_caller]
+    fn smull(&mut self, dst: X86Register, src1: X86Register, src2: X86Register) {
+        self.unsupported(&format!("smull {}, {}, {}", dst, src1, src2));
+    }
+
+    #[track_caller]
+    fn umull(&mut self, dst: X86Register, src1: X86Register, src2: X86Register) {
+        self.unsupported(&format!("umull {}, {}, {}", dst, src1, src2));
+    }
+
+    #[track_caller]
+    fn smulh(&mut self, dst: X86Register, src1: X86Register, src2: X86Register) {
+        self.unsupported(&format!("smulh {}, {}, {}", dst, src1, src2));
+    }
+
+    #[track_caller]
+    fn umulh(&mut self, dst: X86Register, src1: X86Register, src2: X86Register) {
+        self.unsupported(&format!("umulh {}, {}, {}", dst, src1, src2));
+    }
+
+    #[track_caller]
+    fn smaddl(&mut self, dst: X86Register, src1: X86Register, src2: X86Register, acc: X86Register) {
+        self.unsupported(&format!("smaddl {}, {}, {}, {}", dst, src1, src2, acc));
+    }
+
+    #[track_caller]
+    fn umaddl(&mut self, dst: X86Register, src1: X86Register, src2: X86Register, acc: X86Register) {
+        self.unsupported(&format!("umaddl {}, {}, {}, {}", dst, src1, src2, acc));
+    }
+}
+
+impl CompareBuilder<X86Register> for X86_64 {
+    #[track_caller]
+    fn cmp(&mut self, src1: X86Register, src2: Operand<X86Register>) {
+        let src = match src2 {
+            Operand::Register(src2) => Source::Register(src2),
+            Operand::Immediate(imm) => Source::Immediate(self.imm32(&imm)),
+            Operand::Shifted(src2, shift, amount) => {
+                self.unsupported(&format!("cmp {}, {}, {} #{}", src1, src2, shift.as_str(), amount));
+                return;
+            }
+        };
+        self.push(Instruction::Alu { op: AluOp::Cmp, dst: src1, src });
+    }
+
+    #[track_caller]
+    fn cset(&mut self, dst: X86Register, cond: Condition) {
+        match cond {
+            Condition::Al => self.push(Instruction::MovImm { dst, imm: 1 }),
+            cond => self.push(Instruction::Cset { cond, dst }),
+        }
+    }
+
+    #[track_caller]
+    fn csel(&mut self, dst: X86Register, src1: X86Register, src2: X86Register, cond: Condition) {
+        let Some(inverse) = cond.invert() else {
+            self.copy(dst, src1);
+            return;
+        };
+        // `mov` leaves the flags for the `cmov`
+        if dst == src1 {
+            self.push(Instruction::Cmov { cond: inverse, dst, src: src2 });
+        } else {
+            self.copy(dst, src2);
+            self.push(Instruction::Cmov { cond, dst, src: src1 });
+        }
+    }
+}
+
+impl BranchBuilder<X86Register> for X86_64 {
+    #[track_caller]
+    fn bl(&mut self, label: &str) {
+        self.push(Instruction::Call { label: label.to_string() });
+    }
+
+    #[track_caller]
+    fn b(&mut self, label: &str) {
+        self.push(Instruction::Jmp { label: label.to_string() });
+    }
+
+    #[track_caller]
+    fn ret(&mut self) {
+        self.push(Instruction::Ret);
+    }
+
+    #[track_caller]
+    fn cbz(&mut self, reg: X86Register, label: &str) {
+        self.push(Instruction::Test { reg });
+        self.push(Instruction::Jcc { cond: Condition::Eq, label: label.to_string() });
+    }
+
+    #[track_caller]
+    fn cbnz(&mut self, reg: X86Register, label: &str) {
+        self.push(Instruction::Test { reg });
+        self.push(Instruction::Jcc { cond: Condition::Ne, label: label.to_string() });
+    }
+
+    #[track_caller]
+    fn b_cond(&mut self, cond: Condition, label: &str) {
+        match cond {
+            Condition::Al => self.push(Instruction::Jmp { label: label.to_string() }),
+            cond => self.push(Instruction::Jcc { cond, label: label.to_string() }),
+        }
+    }
+
+    #[track_caller]
+    fn br(&mut self, reg: X86Register) {
+        self.push(Instruction::JmpReg { target: reg });
+    }
+
+    #[track_caller]
+    fn blr(&mut self, reg: X86Register) {
+        self.push(Instruction::CallReg { target: reg });
+    }
+}
+
+impl SystemBuilder for X86_64 {
+    /// `syscall`; x86_64 has no comment field, so `number` must be 0.
+    #[track_caller]
+    fn svc(&mut self, number: u32) {
+        match number {
+            0 => self.push(Instruction::Syscall),
+            number => self.unsupported(&format!("svc #{:#x}", number)),
+        }
+    }
+}
+
+impl AddressBuilder<X86Register> for X86_64 {
+    #[track_caller]
+    fn adr(&mut self, dst: X86Register, label: &str) {
+        self.push(Instruction::LeaLabel { dst, label: label.to_string() });
+    }
+
+    #[track_caller]
+    fn adrp(&mut self, dst: X86Register, label: &str) {
+        self.push(Instruction::LeaLabel { dst, label: label.to_string() });
+        self.push(Instruction::Alu { op: AluOp::And, dst, src: Source::Immediate(-0x1000) });
+    }
+
+    /// `lea`, which reaches the whole executable; `base` is left alone.
+    #[track_caller]
+    fn adrp_add(&mut self, dst: X86Register, _base: X86Register, label: &str) {
+        self.push(Instruction::LeaLabel { dst, label: label.to_string() });
+    }
+
+    /// `lea`, as a linker relaxes a GOT load in a static executable.
+    #[track_caller]
+    fn adrp_got(&mut self, dst: X86Register, label: &str) {
+        self.push(Instruction::LeaLabel { dst, label: label.to_string() });
+    }
+}
+
+impl LoadStoreBuilder<X86Register> for X86_64 {
+    #[track_caller]
+    fn str(&mut self, src: X86Register, addr: &str) {
+        self.push(Instruction::StoreLabel { src, label: addr.to_string() });
+    }
+
+    #[track_caller]
+    fn ldr(&mut self, dst: X86Register, addr: &str) {
+        self.push(Instruction::LoadLabel { dst, label: addr.to_string() });
+    }
+
+    #[track_caller]
+    fn load(&mut self, size: MemSize, signed: bool, dst: X86Register, addr: MemOperand<X86Register>) {
+        let (addr, pre, post) = self.mem(addr);
+        self.write_back(addr.base, pre);
+        self.push(Instruction::Load { size, signed, dst, addr });
+        self.write_back(addr.base, post);
+    }
+
+    #[track_caller]
+    fn store(&mut self, size: MemSize, src: X86Register, addr: MemOperand<X86Register>) {
+        let (addr, pre, post) = self.mem(addr);
+        self.write_back(addr.base, pre);
+        self.push(Instruction::Store { size, src, addr });
+        self.write_back(addr.base, post);
+    }
+
+    #[track_caller]
+    fn ldp(&mut self, dst1: X86Register, dst2: X86Register, addr: MemOperand<X86Register>) {
+        let size = if dst1.is_32bit() { MemSize::Word } else { MemSize::Double };
+        let (first, pre, post) = self.mem(addr);
+        let second = Mem { disp: first.disp.wrapping_add(size.bytes() as i32), ..first };
+        self.write_back(first.base, pre);
+        // Load the base register last so the other address is still valid
+        if to_64bit(dst1) == to_64bit(first.base) {
+            self.push(Instruction::Load { size, signed: false, dst: dst2, addr: second });
+            self.push(Instruction::Load { size, signed: false, dst: dst1, addr: first });
+        } else {
+            self.push(Instruction::Load { size, signed: false, dst: dst1, addr: first });
+            self.push(Instruction::Load { size, signed: false, dst: dst2, addr: second });
+        }
+        self.write_back(first.base, post);
+    }
+
+    #[track_caller]
+    fn stp(&mut self, src1: X86Register, src2: X86Register, addr: MemOperand<X86Register>) {
+        let size = if src1.is_32bit() { MemSize::Word } else { MemSize::Double };
+        let (first, pre, post) = self.mem(addr);
+        let second = Mem { disp: first.disp.wrapping_add(size.bytes() as i32), ..first };
+        self.write_back(first.base, pre);
+        self.push(Instruction::Store { size, src: src1, addr: first });
+        self.push(Instruction::Store { size, src: src2, addr: second });
+        self.write_back(first.base, post);
+    }
+}
+
+impl X86_64 {
+    /// Adds the pre- or post-index `offset` to `base`, leaving the flags.
+    #[track_caller]
+    fn write_back(&mut self, base: X86Register, offset: Option<i32>) {
+        if let Some(offset) = offset {
+            self.push(Instruction::Lea { dst: base, addr: Mem::new(base, offset) });
+        }
+    }
+}
+
+/// The 64-bit view of a register.
+fn to_64bit(reg: X86Register) -> X86Register {
+    match reg {
+        X86Register::Eax => X86Register::Rax,
+        X86Register::Ecx => X86Register::Rcx,
+        X86Register::Edx => X86Register::Rdx,
+        X86Register::Ebx => X86Register::Rbx,
+        X86Register::Esp => X86Register::Rsp,
+        X86Register::Ebp => X86Register::Rbp,
+        X86Register::Esi => X86Register::Rsi,
+        X86Register::Edi => X86Register::Rdi,
+        X86Register::R8d => X86Register::R8,
+        X86Register::R9d => X86Register::R9,
+        X86Register::R10d => X86Register::R10,
+        X86Register::R11d => X86Register::R11,
+        X86Register::R12d => X86Register::R12,
+        X86Register::R13d => X86Register::R13,
+        X86Register::R14d => X86Register::R14,
+        X86Register::R15d => X86Register::R15,
+        reg => reg,
+    }
+}
+
+fn write_listing(f: &mut fmt::Formatter<'_>, instructions: &[Instruction]) -> fmt::Result {
+    for inst in instructions {
+        match inst {
+            Instruction::Label(_) => writeln!(f, "{}", inst)?,
+            _ => writeln!(f, "    {}", inst)?,
+        }
+    }
+    Ok(())
+}
+
+impl Display for X86_64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for emitted in &self.instructions {
+            let line = match &emitted.inst {
+                Instruction::Label(_) => emitted.inst.to_string(),
+                inst => format!("    {}", inst),
+            };
+            match &emitted.comment {
+                Some(comment) => writeln!(f, "{} # {}", line, comment)?,
+                None => writeln!(f, "{}", line)?,
+            }
+        }
+        if !self.rodata.is_empty() {
+            writeln!(f, ".section .rodata")?;
+            write_listing(f, &self.rodata)?;
+        }
+        Ok(())
+    }
+}
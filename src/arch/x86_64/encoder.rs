@@ -0,0 +1,308 @@
+//! Machine code generation for [`X86_64`] instruction streams.
+//!
+//! Every label reference is encoded with a 32-bit displacement, so the size
+//! of an instruction never depends on where its target ends up and a single
+//! pass assigns the addresses. As on ARM64 the output is position dependent:
+//! text is assembled for the base address it will be loaded at, with
+//! read-only data on the following page.
+
+use super::*;
+use crate::arch::arm64::encoder::{Assembled, EncodeError};
+use crate::arch::arm64::layout::data_bytes;
+use std::collections::HashMap;
+
+const NOP: u8 = 0x90;
+
+/// Assembles `arch` with text at `text_base` and read-only data on the
+/// following page.
+pub fn assemble(arch: &X86_64, text_base: u64) -> Result<Assembled, EncodeError> {
+    let text: Vec<Instruction> = arch.emitted().iter().map(|emitted| emitted.inst.clone()).collect();
+    let mut labels = HashMap::new();
+    let (text_addresses, text_end) = assign(&text, text_base, &mut labels)?;
+    let rodata_base = (text_end + 0xfff) & !0xfff;
+    let (rodata_addresses, _) = assign(arch.get_rodata(), rodata_base, &mut labels)?;
+    let resolve = |name: &str| labels.get(name).copied();
+
+    let mut out = Vec::new();
+    for (inst, addr) in text.iter().zip(&text_addresses) {
+        match inst {
+            // Padding in code is filled with `nop` so it stays executable
+            Instruction::Data(DataDirective::Align(_)) => {
+                let padding = size(inst, *addr)?;
+                out.extend(std::iter::repeat_n(NOP, padding as usize));
+            }
+            Instruction::Data(directive) => {
+                out.extend(data_bytes(directive, *addr, &labels).map_err(EncodeError::UndefinedLabel)?);
+            }
+            _ => out.extend(encode(inst, *addr, &resolve)?),
+        }
+    }
+
+    let mut rodata = Vec::new();
+    for (inst, addr) in arch.get_rodata().iter().zip(&rodata_addresses) {
+        match inst {
+            Instruction::Label(_) => {}
+            Instruction::Data(directive) => {
+                rodata.extend(data_bytes(directive, *addr, &labels).map_err(EncodeError::UndefinedLabel)?);
+            }
+            _ => return Err(EncodeError::Unsupported(format!("instruction in rodata: {}", inst))),
+        }
+    }
+
+    Ok(Assembled { text: out, text_base, rodata, rodata_base, labels })
+}
+
+/// The size in bytes of `inst` when it is placed at `addr`.
+fn size(inst: &Instruction, addr: u64) -> Result<u64, EncodeError> {
+    match inst {
+        Instruction::Label(_) => Ok(0),
+        Instruction::Data(directive) => {
+            // Only label differences and pointers look labels up, and their
+            // size does not depend on the value
+            let bytes = match directive {
+                DataDirective::LabelDiff { .. } => 4,
+                DataDirective::Pointer { .. } => 8,
+                directive => data_bytes(directive, addr, &HashMap::new()).map_err(EncodeError::UndefinedLabel)?.len(),
+            };
+            Ok(bytes as u64)
+        }
+        inst => Ok(encode(inst, addr, &|_| Some(addr))?.len() as u64),
+    }
+}
+
+fn assign(insts: &[Instruction], base: u64, labels: &mut HashMap<String, u64>) -> Result<(Vec<u64>, u64), EncodeError> {
+    let mut addresses = Vec::with_capacity(insts.len());
+    let mut addr = base;
+    for inst in insts {
+        if let Instruction::Label(name) = inst {
+            if labels.insert(name.clone(), addr).is_some() {
+                return Err(EncodeError::DuplicateLabel(name.clone()));
+            }
+        }
+        addresses.push(addr);
+        addr += size(inst, addr)?;
+    }
+    Ok((addresses, addr))
+}
+
+/// Encodes one instruction placed at `addr`. Labels and data directives
+/// produce no bytes.
+pub fn encode(inst: &Instruction, addr: u64, resolve: &dyn Fn(&str) -> Option<u64>) -> Result<Vec<u8>, EncodeError> {
+    let encoder = Encoder { inst, addr, resolve };
+    let wide = |reg: &X86Register| !reg.is_32bit();
+    match inst {
+        Instruction::Label(_) | Instruction::Data(_) => Ok(Vec::new()),
+        Instruction::Mov { dst, src } => encoder.modrm(None, wide(dst), false, &[0x89], *src, Rm::Register(*dst)),
+        Instruction::MovImm { dst, imm } => encoder.mov_imm(*dst, *imm),
+        Instruction::Lea { dst, addr } => encoder.modrm(None, wide(dst), false, &[0x8d], *dst, Rm::Memory(addr)),
+        Instruction::LeaLabel { dst, label } => encoder.modrm(None, wide(dst), false, &[0x8d], *dst, Rm::Label(label)),
+        Instruction::Alu { op, dst, src: Source::Register(src) } => {
+            encoder.modrm(None, wide(dst), false, &[alu_digit(*op) << 3 | 1], *src, Rm::Register(*dst))
+        }
+        Instruction::Alu { op, dst, src: Source::Immediate(imm) } => encoder.alu_imm(*op, *dst, *imm),
+        Instruction::Imul { dst, src } => encoder.modrm(None, wide(dst), false, &[0x0f, 0xaf], *dst, Rm::Register(*src)),
+        Instruction::Neg { dst } => encoder.digit(wide(dst), false, &[0xf7], 3, Rm::Register(*dst)),
+        Instruction::Test { reg } => encoder.modrm(None, wide(reg), false, &[0x85], *reg, Rm::Register(*reg)),
+        Instruction::Cset { cond, dst } => {
+            let byte = encoder.needs_byte_rex(*dst)?;
+            let mut out = encoder.digit(false, byte, &[0x0f, 0x90 | encoder.cc(*cond)?], 0, Rm::Register(*dst))?;
+            out.extend(encoder.modrm(None, false, byte, &[0x0f, 0xb6], *dst, Rm::Register(*dst))?);
+            Ok(out)
+        }
+        Instruction::Cmov { cond, dst, src } => {
+            encoder.modrm(None, wide(dst), false, &[0x0f, 0x40 | encoder.cc(*cond)?], *dst, Rm::Register(*src))
+        }
+        Instruction::Jmp { label } => encoder.relative(&[0xe9], label),
+        Instruction::Jcc { cond, label } => encoder.relative(&[0x0f, 0x80 | encoder.cc(*cond)?], label),
+        Instruction::Call { label } => encoder.relative(&[0xe8], label),
+        Instruction::JmpReg { target } => encoder.digit(false, false, &[0xff], 4, Rm::Register(*target)),
+        Instruction::CallReg { target } => encoder.digit(false, false, &[0xff], 2, Rm::Register(*target)),
+        Instruction::Ret => Ok(vec![0xc3]),
+        Instruction::Syscall => Ok(vec![0x0f, 0x05]),
+        Instruction::Load { size, signed, dst, addr } => {
+            let (wide, opcode): (bool, &[u8]) = match (size, signed) {
+                (MemSize::Double, _) => (true, &[0x8b]),
+                (MemSize::Word, true) if wide(dst) => (true, &[0x63]),
+                (MemSize::Word, _) => (false, &[0x8b]),
+                (MemSize::Half, true) => (wide(dst), &[0x0f, 0xbf]),
+                (MemSize::Byte, true) => (wide(dst), &[0x0f, 0xbe]),
+                (MemSize::Half, false) => (false, &[0x0f, 0xb7]),
+                (MemSize::Byte, false) => (false, &[0x0f, 0xb6]),
+            };
+            encoder.modrm(None, wide, false, opcode, *dst, Rm::Memory(addr))
+        }
+        Instruction::Store { size, src, addr } => match size {
+            MemSize::Byte => encoder.modrm(None, false, encoder.needs_byte_rex(*src)?, &[0x88], *src, Rm::Memory(addr)),
+            MemSize::Half => encoder.modrm(Some(0x66), false, false, &[0x89], *src, Rm::Memory(addr)),
+            MemSize::Word => encoder.modrm(None, false, false, &[0x89], *src, Rm::Memory(addr)),
+            MemSize::Double => encoder.modrm(None, true, false, &[0x89], *src, Rm::Memory(addr)),
+        },
+        Instruction::LoadLabel { dst, label } => encoder.modrm(None, wide(dst), false, &[0x8b], *dst, Rm::Label(label)),
+        Instruction::StoreLabel { src, label } => encoder.modrm(None, wide(src), false, &[0x89], *src, Rm::Label(label)),
+    }
+}
+
+/// The `/digit` opcode extension of an ALU operation, which is also the
+/// operation's row in the one-byte opcode map.
+fn alu_digit(op: AluOp) -> u8 {
+    match op {
+        AluOp::Add => 0,
+        AluOp::Or => 1,
+        AluOp::And => 4,
+        AluOp::Sub => 5,
+        AluOp::Xor => 6,
+        AluOp::Cmp => 7,
+    }
+}
+
+/// The operand encoded in the ModRM `rm` field.
+enum Rm<'a> {
+    Register(X86Register),
+    Memory(&'a Mem),
+    /// `[rip + label]`
+    Label(&'a str),
+}
+
+struct Encoder<'a> {
+    inst: &'a Instruction,
+    addr: u64,
+    resolve: &'a dyn Fn(&str) -> Option<u64>,
+}
+
+impl Encoder<'_> {
+    fn number(&self, reg: X86Register) -> Result<u8, EncodeError> {
+        reg.number().ok_or_else(|| EncodeError::Unsupported(format!("register {} in `{}`", reg, self.inst)))
+    }
+
+    fn cc(&self, cond: Condition) -> Result<u8, EncodeError> {
+        condition_code(cond).map(|(code, _)| code).ok_or_else(|| EncodeError::Unsupported(self.inst.to_string()))
+    }
+
+    /// Whether the low byte of `reg` needs a REX prefix to name it: `spl`,
+    /// `bpl`, `sil` and `dil` are `ah`, `ch`, `dh` and `bh` without one.
+    fn needs_byte_rex(&self, reg: X86Register) -> Result<bool, EncodeError> {
+        Ok((4..8).contains(&self.number(reg)?))
+    }
+
+    /// The signed distance from the end of an instruction `len` bytes long
+    /// to `label`.
+    fn displacement(&self, label: &str, len: usize) -> Result<i32, EncodeError> {
+        let target = (self.resolve)(label).ok_or_else(|| EncodeError::UndefinedLabel(label.to_string()))?;
+        let distance = target.wrapping_sub(self.addr + len as u64) as i64;
+        i32::try_from(distance).map_err(|_| EncodeError::OutOfRange { inst: self.inst.to_string(), value: distance })
+    }
+
+    /// `opcode rel32`, a branch to `label`.
+    fn relative(&self, opcode: &[u8], label: &str) -> Result<Vec<u8>, EncodeError> {
+        let mut out = opcode.to_vec();
+        out.extend(self.displacement(label, opcode.len() + 4)?.to_le_bytes());
+        Ok(out)
+    }
+
+    /// `[prefix] [REX] opcode ModRM [SIB] [disp]` with `reg` in the ModRM
+    /// `reg` field. REX.W is set for `wide` operations, and a REX prefix is
+    /// always written when `byte` says a byte register needs one.
+    fn modrm(&self, prefix: Option<u8>, wide: bool, byte: bool, opcode: &[u8], reg: X86Register, rm: Rm) -> Result<Vec<u8>, EncodeError> {
+        let reg = self.number(reg)?;
+        self.encode_modrm(prefix, wide, byte, opcode, reg, rm)
+    }
+
+    /// An instruction with an opcode extension in the ModRM `reg` field.
+    fn digit(&self, wide: bool, byte: bool, opcode: &[u8], digit: u8, rm: Rm) -> Result<Vec<u8>, EncodeError> {
+        self.encode_modrm(None, wide, byte, opcode, digit, rm)
+    }
+
+    fn encode_modrm(&self, prefix: Option<u8>, wide: bool, byte: bool, opcode: &[u8], reg: u8, rm: Rm) -> Result<Vec<u8>, EncodeError> {
+        let mut rex = (wide as u8) << 3 | (reg >> 3) << 2;
+        let mut tail = Vec::new();
+        let modrm = match rm {
+            Rm::Register(rm) => {
+                let rm = self.number(rm)?;
+                rex |= rm >> 3;
+                0xc0 | (reg & 7) << 3 | (rm & 7)
+            }
+            Rm::Memory(mem) => {
+                let base = self.number(mem.base)?;
+                rex |= base >> 3;
+                // `[rbp]` and `[r13]` have no form without a displacement
+                let (mode, disp) = match mem.disp {
+                    0 if base & 7 != 5 => (0x00, Vec::new()),
+                    disp if i8::try_from(disp).is_ok() => (0x40, vec![disp as u8]),
+                    disp => (0x80, disp.to_le_bytes().to_vec()),
+                };
+                // `rsp` and `r12` as a base, and any index, need a SIB byte
+                let modrm = match mem.index {
+                    Some((index, scale)) => {
+                        let index = self.number(index)?;
+                        if index == 4 {
+                            return Err(EncodeError::Unsupported(format!("rsp as an index in `{}`", self.inst)));
+                        }
+                        rex |= (index >> 3) << 1;
+                        tail.push((scale.trailing_zeros() as u8) << 6 | (index & 7) << 3 | (base & 7));
+                        mode | (reg & 7) << 3 | 4
+                    }
+                    None if base & 7 == 4 => {
+                        tail.push(0x24);
+                        mode | (reg & 7) << 3 | 4
+                    }
+                    None => mode | (reg & 7) << 3 | (base & 7),
+                };
+                tail.extend(disp);
+                modrm
+            }
+            Rm::Label(_) => (reg & 7) << 3 | 5,
+        };
+
+        let mut out: Vec<u8> = prefix.into_iter().collect();
+        if rex != 0 || byte {
+            out.push(0x40 | rex);
+        }
+        out.extend_from_slice(opcode);
+        out.push(modrm);
+        out.extend(tail);
+        if let Rm::Label(label) = rm {
+            let disp = self.displacement(label, out.len() + 4)?;
+            out.extend(disp.to_le_bytes());
+        }
+        Ok(out)
+    }
+
+    /// The shortest of `mov r32, imm32` (zero-extending), `mov r64, imm32`
+    /// (sign-extending) and `movabs r64, imm64`.
+    fn mov_imm(&self, dst: X86Register, imm: u64) -> Result<Vec<u8>, EncodeError> {
+        let number = self.number(dst)?;
+        if imm <= u32::MAX as u64 {
+            let mut out: Vec<u8> = if number >= 8 { vec![0x41] } else { Vec::new() };
+            out.push(0xb8 | (number & 7));
+            out.extend((imm as u32).to_le_bytes());
+            Ok(out)
+        } else if let Ok(imm) = i32::try_from(imm as i64) {
+            let mut out = self.digit(true, false, &[0xc7], 0, Rm::Register(dst))?;
+            out.extend(imm.to_le_bytes());
+            Ok(out)
+        } else {
+            let mut out = vec![0x48 | number >> 3, 0xb8 | (number & 7)];
+            out.extend(imm.to_le_bytes());
+            Ok(out)
+        }
+    }
+
+    /// `op dst, imm` with a sign-extended 8-bit immediate where it fits, and
+    /// the short `rax` form otherwise.
+    fn alu_imm(&self, op: AluOp, dst: X86Register, imm: i32) -> Result<Vec<u8>, EncodeError> {
+        let wide = !dst.is_32bit();
+        let digit = alu_digit(op);
+        if let Ok(imm) = i8::try_from(imm) {
+            let mut out = self.digit(wide, false, &[0x83], digit, Rm::Register(dst))?;
+            out.push(imm as u8);
+            return Ok(out);
+        }
+        let mut out = match self.number(dst)? {
+            0 if wide => vec![0x48, digit << 3 | 5],
+            0 => vec![digit << 3 | 5],
+            _ => self.digit(wide, false, &[0x81], digit, Rm::Register(dst))?,
+        };
+        out.extend(imm.to_le_bytes());
+        Ok(out)
+    }
+}
@@ -1,62 +1,1317 @@
 use crate::instruction::*;
+use std::panic::Location;
 
 pub struct InstructionBuilder<A, R: Register> {
     pub arch: A,
-    pub current_comment: Option<String>,
+    label_counter: usize,
+    errors: Vec<BuildError>,
     _phantom: std::marker::PhantomData<R>,
 }
 
+/// Right-hand side of a [`Cond`] comparison.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CondOperand {
+    Register(GenericRegister),
+    Immediate(i64),
+}
+
+impl From<GenericRegister> for CondOperand {
+    fn from(reg: GenericRegister) -> Self {
+        CondOperand::Register(reg)
+    }
+}
+
+impl From<i64> for CondOperand {
+    fn from(imm: i64) -> Self {
+        CondOperand::Immediate(imm)
+    }
+}
+
+/// A condition tested by the structured control-flow helpers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cond {
+    /// `cmp lhs, rhs` followed by a test of `cond`
+    Compare(GenericRegister, Condition, CondOperand),
+    Zero(GenericRegister),
+    NonZero(GenericRegister),
+    /// Flags already set by a previous instruction
+    Flags(Condition),
+}
+
+impl Cond {
+    pub fn eq(lhs: GenericRegister, rhs: impl Into<CondOperand>) -> Self {
+        Cond::Compare(lhs, Condition::Eq, rhs.into())
+    }
+
+    pub fn ne(lhs: GenericRegister, rhs: impl Into<CondOperand>) -> Self {
+        Cond::Compare(lhs, Condition::Ne, rhs.into())
+    }
+
+    pub fn lt(lhs: GenericRegister, rhs: impl Into<CondOperand>) -> Self {
+        Cond::Compare(lhs, Condition::Lt, rhs.into())
+    }
+
+    pub fn le(lhs: GenericRegister, rhs: impl Into<CondOperand>) -> Self {
+        Cond::Compare(lhs, Condition::Le, rhs.into())
+    }
+
+    pub fn gt(lhs: GenericRegister, rhs: impl Into<CondOperand>) -> Self {
+        Cond::Compare(lhs, Condition::Gt, rhs.into())
+    }
+
+    pub fn ge(lhs: GenericRegister, rhs: impl Into<CondOperand>) -> Self {
+        Cond::Compare(lhs, Condition::Ge, rhs.into())
+    }
+}
+
+/// Returned by [`InstructionBuilder::if_`]. The "then" body has already been
+/// emitted; call [`IfElse::else_`] to add an alternative, or drop it to close
+/// the `if`.
+pub struct IfElse<'a, A, R: Register>
+where
+    A: BranchBuilder<R> + LabelBuilder,
+{
+    builder: Option<&'a mut InstructionBuilder<A, R>>,
+    else_label: String,
+}
+
+impl<'a, A, R: Register> IfElse<'a, A, R>
+where
+    A: BranchBuilder<R> + LabelBuilder,
+    GenericRegister: RegisterMapping<R>,
+{
+    pub fn else_<F>(mut self, body: F) -> &'a mut InstructionBuilder<A, R>
+    where
+        F: FnOnce(&mut InstructionBuilder<A, R>),
+    {
+        let builder = self.builder.take().expect("if_ builder is present until closed");
+        let end_label = builder.fresh_label("endif");
+        builder.arch.b(&end_label);
+        builder.arch.label(&self.else_label);
+        body(builder);
+        builder.arch.label(&end_label);
+        builder
+    }
+}
+
+impl<A, R: Register> Drop for IfElse<'_, A, R>
+where
+    A: BranchBuilder<R> + LabelBuilder,
+{
+    fn drop(&mut self) {
+        if let Some(builder) = self.builder.take() {
+            builder.arch.label(&self.else_label);
+        }
+    }
+}
+
+type Arm<'s, A, R> = Box<dyn FnOnce(&mut InstructionBuilder<A, R>) + 's>;
+
+/// Collects the arms of an [`InstructionBuilder::switch_`].
+pub struct Switch<'s, A, R: Register> {
+    cases: Vec<(i64, Arm<'s, A, R>)>,
+    default: Option<Arm<'s, A, R>>,
+    errors: Vec<BuildError>,
+}
+
+impl<'s, A, R: Register> Switch<'s, A, R> {
+    /// Adds the arm for `value`. A value given twice is recorded as a
+    /// [`BuildError::DuplicateCase`] and its second arm dropped.
+    #[track_caller]
+    pub fn case<F>(&mut self, value: i64, body: F) -> &mut Self
+    where
+        F: FnOnce(&mut InstructionBuilder<A, R>) + 's,
+    {
+        if self.cases.iter().any(|(existing, _)| *existing == value) {
+            self.errors.push(BuildError::DuplicateCase { location: Some(Location::caller()), value });
+        } else {
+            self.cases.push((value, Box::new(body)));
+        }
+        self
+    }
+
+    pub fn default<F>(&mut self, body: F) -> &mut Self
+    where
+        F: FnOnce(&mut InstructionBuilder<A, R>) + 's,
+    {
+        self.default = Some(Box::new(body));
+        self
+    }
+
+    /// A jump table pays off once there are a few cases and at least a third
+    /// of the table entries are real cases.
+    fn use_jump_table(&self) -> bool {
+        let (Some(min), Some(max)) = (self.min(), self.max()) else { return false };
+        let span = max as i128 - min as i128 + 1;
+        self.cases.len() >= 4 && span <= 3 * self.cases.len() as i128 && span <= 4096
+    }
+
+    fn min(&self) -> Option<i64> {
+        self.cases.iter().map(|(value, _)| *value).min()
+    }
+
+    fn max(&self) -> Option<i64> {
+        self.cases.iter().map(|(value, _)| *value).max()
+    }
+}
+
 impl<A, R: Register> InstructionBuilder<A, R> 
 where
     GenericRegister: RegisterMapping<R>
 {
-    pub fn new(arch: A) -> Self {
+    pub fn new(arch: A) -> Self
+    where
+        A: Backend<Register = R>
+    {
         Self {
             arch,
-            current_comment: None,
+            label_counter: 0,
+            errors: Vec::new(),
             _phantom: std::marker::PhantomData,
         }
     }
 
-    pub fn comment(&mut self, comment: &str) -> &mut Self {
-        self.current_comment = Some(comment.to_string());
+    /// Problems found in the arguments of the structured control flow
+    /// helpers, in the order they were made.
+    pub fn build_errors(&self) -> &[BuildError] {
+        &self.errors
+    }
+
+    /// Attaches `comment` to the next instruction emitted.
+    pub fn comment(&mut self, comment: &str) -> &mut Self
+    where
+        A: AnnotationBuilder
+    {
+        self.arch.comment(comment);
         self
     }
 
-    pub fn adrp(&mut self, dst: GenericRegister, label: &str) -> &mut Self 
-    where 
-        A: AddressBuilder<R>
+    /// Tags the next instruction emitted, for tools reading the metadata.
+    pub fn tag(&mut self, tag: &str) -> &mut Self
+    where
+        A: AnnotationBuilder
     {
-        self.arch.adrp(dst.to_arch_reg(), label);
+        self.arch.tag(tag);
         self
     }
 
-    pub fn add(&mut self, dst: GenericRegister, src1: GenericRegister, src2: impl Into<Operand<R>>) -> &mut Self 
-    where 
+    #[track_caller]
+    pub fn add(&mut self, dst: GenericRegister, src1: GenericRegister, src2: impl Into<Operand<R>>) -> &mut Self
+    where
         A: ArithmeticBuilder<R>
     {
-        let src2_op = match src2.into() {
-            Operand::Register(r) => Operand::Register(r),
-            Operand::Immediate(s) => Operand::Immediate(s),
-        };
-        
-        self.arch.add(dst.to_arch_reg(), src1.to_arch_reg(), src2_op);
+        self.arch.add(dst.to_arch_reg(), src1.to_arch_reg(), src2.into());
         self
     }
 
-    pub fn bl(&mut self, label: &str) -> &mut Self 
-    where 
-        A: BranchBuilder<R>
+    #[track_caller]
+    pub fn sub(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: ArithmeticBuilder<R>
     {
-        self.arch.bl(label);
+        self.arch.sub(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
         self
     }
 
-    pub fn mov(&mut self, dst: GenericRegister, src: GenericRegister) -> &mut Self 
-    where 
-        A: MovBuilder<R>
+    #[track_caller]
+    pub fn mul(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: ArithmeticBuilder<R>
     {
-        self.arch.mov(dst.to_arch_reg(), src.to_arch_reg());
+        self.arch.mul(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn sdiv(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: ArithmeticBuilder<R>
+    {
+        self.arch.sdiv(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn udiv(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: ArithmeticBuilder<R>
+    {
+        self.arch.udiv(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn madd(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister, acc: GenericRegister) -> &mut Self
+    where
+        A: ArithmeticBuilder<R>
+    {
+        self.arch.madd(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg(), acc.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn msub(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister, acc: GenericRegister) -> &mut Self
+    where
+        A: ArithmeticBuilder<R>
+    {
+        self.arch.msub(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg(), acc.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn mneg(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: ArithmeticBuilder<R>
+    {
+        self.arch.mneg(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn smull(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: ArithmeticBuilder<R>
+    {
+        self.arch.smull(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn umull(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: ArithmeticBuilder<R>
+    {
+        self.arch.umull(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn smulh(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: ArithmeticBuilder<R>
+    {
+        self.arch.smulh(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn umulh(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: ArithmeticBuilder<R>
+    {
+        self.arch.umulh(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn smaddl(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister, acc: GenericRegister) -> &mut Self
+    where
+        A: ArithmeticBuilder<R>
+    {
+        self.arch.smaddl(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg(), acc.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn umaddl(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister, acc: GenericRegister) -> &mut Self
+    where
+        A: ArithmeticBuilder<R>
+    {
+        self.arch.umaddl(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg(), acc.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn fadd(&mut self, size: FpSize, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fadd(size, dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn fsub(&mut self, size: FpSize, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fsub(size, dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn fmul(&mut self, size: FpSize, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fmul(size, dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn fdiv(&mut self, size: FpSize, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fdiv(size, dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn fsqrt(&mut self, size: FpSize, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fsqrt(size, dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn fneg(&mut self, size: FpSize, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fneg(size, dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn fabs(&mut self, size: FpSize, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fabs(size, dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn fmadd(&mut self, size: FpSize, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister, acc: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fmadd(size, dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg(), acc.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn fcmp(&mut self, size: FpSize, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fcmp(size, src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn fcsel(&mut self, size: FpSize, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister, cond: Condition) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fcsel(size, dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg(), cond);
+        self
+    }
+
+    #[track_caller]
+    pub fn fmov(&mut self, size: FpSize, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fmov(size, dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn fmov_imm(&mut self, size: FpSize, dst: GenericRegister, imm: f64) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fmov_imm(size, dst.to_arch_reg(), imm);
+        self
+    }
+
+    #[track_caller]
+    pub fn scvtf(&mut self, size: FpSize, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.scvtf(size, dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn ucvtf(&mut self, size: FpSize, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.ucvtf(size, dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn fcvtzs(&mut self, size: FpSize, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fcvtzs(size, dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn fcvtzu(&mut self, size: FpSize, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fcvtzu(size, dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn fcvt(&mut self, dst_size: FpSize, dst: GenericRegister, src_size: FpSize, src: GenericRegister) -> &mut Self
+    where
+        A: FloatBuilder<R>
+    {
+        self.arch.fcvt(dst_size, dst.to_arch_reg(), src_size, src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn vadd(&mut self, arrangement: VectorArrangement, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: VectorBuilder<R>
+    {
+        self.arch.vadd(arrangement, dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn vsub(&mut self, arrangement: VectorArrangement, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: VectorBuilder<R>
+    {
+        self.arch.vsub(arrangement, dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn vmul(&mut self, arrangement: VectorArrangement, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: VectorBuilder<R>
+    {
+        self.arch.vmul(arrangement, dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn vfadd(&mut self, arrangement: VectorArrangement, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: VectorBuilder<R>
+    {
+        self.arch.vfadd(arrangement, dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn vfsub(&mut self, arrangement: VectorArrangement, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: VectorBuilder<R>
+    {
+        self.arch.vfsub(arrangement, dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn vfmul(&mut self, arrangement: VectorArrangement, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister) -> &mut Self
+    where
+        A: VectorBuilder<R>
+    {
+        self.arch.vfmul(arrangement, dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn ld1(&mut self, arrangement: VectorArrangement, first: GenericRegister, count: u8, addr: MemOperand<GenericRegister>) -> &mut Self
+    where
+        A: VectorBuilder<R>
+    {
+        self.arch.ld1(arrangement, first.to_arch_reg(), count, addr.map(|reg| reg.to_arch_reg()));
+        self
+    }
+
+    #[track_caller]
+    pub fn st1(&mut self, arrangement: VectorArrangement, first: GenericRegister, count: u8, addr: MemOperand<GenericRegister>) -> &mut Self
+    where
+        A: VectorBuilder<R>
+    {
+        self.arch.st1(arrangement, first.to_arch_reg(), count, addr.map(|reg| reg.to_arch_reg()));
+        self
+    }
+
+    #[track_caller]
+    pub fn dup(&mut self, arrangement: VectorArrangement, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: VectorBuilder<R>
+    {
+        self.arch.dup(arrangement, dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn dup_lane(&mut self, arrangement: VectorArrangement, dst: GenericRegister, src: GenericRegister, index: u8) -> &mut Self
+    where
+        A: VectorBuilder<R>
+    {
+        self.arch.dup_lane(arrangement, dst.to_arch_reg(), src.to_arch_reg(), index);
+        self
+    }
+
+    #[track_caller]
+    pub fn ins(&mut self, lane: MemSize, dst: GenericRegister, index: u8, src: GenericRegister) -> &mut Self
+    where
+        A: VectorBuilder<R>
+    {
+        self.arch.ins(lane, dst.to_arch_reg(), index, src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn umov(&mut self, lane: MemSize, dst: GenericRegister, src: GenericRegister, index: u8) -> &mut Self
+    where
+        A: VectorBuilder<R>
+    {
+        self.arch.umov(lane, dst.to_arch_reg(), src.to_arch_reg(), index);
+        self
+    }
+
+    #[track_caller]
+    pub fn addv(&mut self, arrangement: VectorArrangement, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: VectorBuilder<R>
+    {
+        self.arch.addv(arrangement, dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn ldxr(&mut self, size: MemSize, dst: GenericRegister, addr: GenericRegister) -> &mut Self
+    where
+        A: AtomicBuilder<R>
+    {
+        self.arch.ldxr(size, dst.to_arch_reg(), addr.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn ldaxr(&mut self, size: MemSize, dst: GenericRegister, addr: GenericRegister) -> &mut Self
+    where
+        A: AtomicBuilder<R>
+    {
+        self.arch.ldaxr(size, dst.to_arch_reg(), addr.to_arch_reg());
         self
     }
-} 
\ No newline at end of file
+
+    #[track_caller]
+    pub fn stxr(&mut self, size: MemSize, status: GenericRegister, src: GenericRegister, addr: GenericRegister) -> &mut Self
+    where
+        A: AtomicBuilder<R>
+    {
+        self.arch.stxr(size, status.to_arch_reg(), src.to_arch_reg(), addr.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn stlxr(&mut self, size: MemSize, status: GenericRegister, src: GenericRegister, addr: GenericRegister) -> &mut Self
+    where
+        A: AtomicBuilder<R>
+    {
+        self.arch.stlxr(size, status.to_arch_reg(), src.to_arch_reg(), addr.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn ldadd(&mut self, order: MemoryOrder, size: MemSize, src: GenericRegister, dst: GenericRegister, addr: GenericRegister) -> &mut Self
+    where
+        A: AtomicBuilder<R>
+    {
+        self.arch.ldadd(order, size, src.to_arch_reg(), dst.to_arch_reg(), addr.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn swp(&mut self, order: MemoryOrder, size: MemSize, src: GenericRegister, dst: GenericRegister, addr: GenericRegister) -> &mut Self
+    where
+        A: AtomicBuilder<R>
+    {
+        self.arch.swp(order, size, src.to_arch_reg(), dst.to_arch_reg(), addr.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn cas(&mut self, order: MemoryOrder, size: MemSize, expected: GenericRegister, new: GenericRegister, addr: GenericRegister) -> &mut Self
+    where
+        A: AtomicBuilder<R>
+    {
+        self.arch.cas(order, size, expected.to_arch_reg(), new.to_arch_reg(), addr.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn dmb(&mut self, domain: BarrierDomain) -> &mut Self
+    where
+        A: BarrierBuilder
+    {
+        self.arch.dmb(domain);
+        self
+    }
+
+    #[track_caller]
+    pub fn dsb(&mut self, domain: BarrierDomain) -> &mut Self
+    where
+        A: BarrierBuilder
+    {
+        self.arch.dsb(domain);
+        self
+    }
+
+    #[track_caller]
+    pub fn isb(&mut self) -> &mut Self
+    where
+        A: BarrierBuilder
+    {
+        self.arch.isb();
+        self
+    }
+
+    #[track_caller]
+    pub fn svc(&mut self, number: u32) -> &mut Self
+    where
+        A: SystemBuilder
+    {
+        self.arch.svc(number);
+        self
+    }
+
+    #[track_caller]
+    pub fn and(&mut self, dst: GenericRegister, src1: GenericRegister, src2: impl Into<Operand<R>>) -> &mut Self
+    where
+        A: LogicalBuilder<R>
+    {
+        self.arch.and(dst.to_arch_reg(), src1.to_arch_reg(), src2.into());
+        self
+    }
+
+    #[track_caller]
+    pub fn orr(&mut self, dst: GenericRegister, src1: GenericRegister, src2: impl Into<Operand<R>>) -> &mut Self
+    where
+        A: LogicalBuilder<R>
+    {
+        self.arch.orr(dst.to_arch_reg(), src1.to_arch_reg(), src2.into());
+        self
+    }
+
+    #[track_caller]
+    pub fn eor(&mut self, dst: GenericRegister, src1: GenericRegister, src2: impl Into<Operand<R>>) -> &mut Self
+    where
+        A: LogicalBuilder<R>
+    {
+        self.arch.eor(dst.to_arch_reg(), src1.to_arch_reg(), src2.into());
+        self
+    }
+
+    #[track_caller]
+    pub fn bic(&mut self, dst: GenericRegister, src1: GenericRegister, src2: impl Into<Operand<R>>) -> &mut Self
+    where
+        A: LogicalBuilder<R>
+    {
+        self.arch.bic(dst.to_arch_reg(), src1.to_arch_reg(), src2.into());
+        self
+    }
+
+    #[track_caller]
+    pub fn orn(&mut self, dst: GenericRegister, src1: GenericRegister, src2: impl Into<Operand<R>>) -> &mut Self
+    where
+        A: LogicalBuilder<R>
+    {
+        self.arch.orn(dst.to_arch_reg(), src1.to_arch_reg(), src2.into());
+        self
+    }
+
+    #[track_caller]
+    pub fn eon(&mut self, dst: GenericRegister, src1: GenericRegister, src2: impl Into<Operand<R>>) -> &mut Self
+    where
+        A: LogicalBuilder<R>
+    {
+        self.arch.eon(dst.to_arch_reg(), src1.to_arch_reg(), src2.into());
+        self
+    }
+
+    #[track_caller]
+    pub fn lsl(&mut self, dst: GenericRegister, src: GenericRegister, amount: impl Into<Operand<R>>) -> &mut Self
+    where
+        A: ShiftBuilder<R>
+    {
+        self.arch.lsl(dst.to_arch_reg(), src.to_arch_reg(), amount.into());
+        self
+    }
+
+    #[track_caller]
+    pub fn lsr(&mut self, dst: GenericRegister, src: GenericRegister, amount: impl Into<Operand<R>>) -> &mut Self
+    where
+        A: ShiftBuilder<R>
+    {
+        self.arch.lsr(dst.to_arch_reg(), src.to_arch_reg(), amount.into());
+        self
+    }
+
+    #[track_caller]
+    pub fn asr(&mut self, dst: GenericRegister, src: GenericRegister, amount: impl Into<Operand<R>>) -> &mut Self
+    where
+        A: ShiftBuilder<R>
+    {
+        self.arch.asr(dst.to_arch_reg(), src.to_arch_reg(), amount.into());
+        self
+    }
+
+    #[track_caller]
+    pub fn ror(&mut self, dst: GenericRegister, src: GenericRegister, amount: impl Into<Operand<R>>) -> &mut Self
+    where
+        A: ShiftBuilder<R>
+    {
+        self.arch.ror(dst.to_arch_reg(), src.to_arch_reg(), amount.into());
+        self
+    }
+
+    #[track_caller]
+    pub fn ubfx(&mut self, dst: GenericRegister, src: GenericRegister, lsb: u8, width: u8) -> &mut Self
+    where
+        A: BitfieldBuilder<R>
+    {
+        self.arch.ubfx(dst.to_arch_reg(), src.to_arch_reg(), lsb, width);
+        self
+    }
+
+    #[track_caller]
+    pub fn sbfx(&mut self, dst: GenericRegister, src: GenericRegister, lsb: u8, width: u8) -> &mut Self
+    where
+        A: BitfieldBuilder<R>
+    {
+        self.arch.sbfx(dst.to_arch_reg(), src.to_arch_reg(), lsb, width);
+        self
+    }
+
+    #[track_caller]
+    pub fn bfi(&mut self, dst: GenericRegister, src: GenericRegister, lsb: u8, width: u8) -> &mut Self
+    where
+        A: BitfieldBuilder<R>
+    {
+        self.arch.bfi(dst.to_arch_reg(), src.to_arch_reg(), lsb, width);
+        self
+    }
+
+    #[track_caller]
+    pub fn ubfiz(&mut self, dst: GenericRegister, src: GenericRegister, lsb: u8, width: u8) -> &mut Self
+    where
+        A: BitfieldBuilder<R>
+    {
+        self.arch.ubfiz(dst.to_arch_reg(), src.to_arch_reg(), lsb, width);
+        self
+    }
+
+    #[track_caller]
+    pub fn extr(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister, lsb: u8) -> &mut Self
+    where
+        A: BitfieldBuilder<R>
+    {
+        self.arch.extr(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg(), lsb);
+        self
+    }
+
+    #[track_caller]
+    pub fn clz(&mut self, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: BitBuilder<R>
+    {
+        self.arch.clz(dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn cls(&mut self, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: BitBuilder<R>
+    {
+        self.arch.cls(dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn rbit(&mut self, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: BitBuilder<R>
+    {
+        self.arch.rbit(dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn rev(&mut self, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: BitBuilder<R>
+    {
+        self.arch.rev(dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn rev16(&mut self, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: BitBuilder<R>
+    {
+        self.arch.rev16(dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn rev32(&mut self, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: BitBuilder<R>
+    {
+        self.arch.rev32(dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn cmp(&mut self, src1: GenericRegister, src2: impl Into<Operand<R>>) -> &mut Self
+    where
+        A: CompareBuilder<R>
+    {
+        self.arch.cmp(src1.to_arch_reg(), src2.into());
+        self
+    }
+
+    #[track_caller]
+    pub fn cset(&mut self, dst: GenericRegister, cond: Condition) -> &mut Self
+    where
+        A: CompareBuilder<R>
+    {
+        self.arch.cset(dst.to_arch_reg(), cond);
+        self
+    }
+
+    #[track_caller]
+    pub fn csel(&mut self, dst: GenericRegister, src1: GenericRegister, src2: GenericRegister, cond: Condition) -> &mut Self
+    where
+        A: CompareBuilder<R>
+    {
+        self.arch.csel(dst.to_arch_reg(), src1.to_arch_reg(), src2.to_arch_reg(), cond);
+        self
+    }
+
+    #[track_caller]
+    pub fn bl(&mut self, label: &str) -> &mut Self
+    where
+        A: BranchBuilder<R>
+    {
+        self.arch.bl(label);
+        self
+    }
+
+    #[track_caller]
+    pub fn b(&mut self, label: &str) -> &mut Self
+    where
+        A: BranchBuilder<R>
+    {
+        self.arch.b(label);
+        self
+    }
+
+    #[track_caller]
+    pub fn ret(&mut self) -> &mut Self
+    where
+        A: BranchBuilder<R>
+    {
+        self.arch.ret();
+        self
+    }
+
+    #[track_caller]
+    pub fn cbz(&mut self, reg: GenericRegister, label: &str) -> &mut Self
+    where
+        A: BranchBuilder<R>
+    {
+        self.arch.cbz(reg.to_arch_reg(), label);
+        self
+    }
+
+    #[track_caller]
+    pub fn cbnz(&mut self, reg: GenericRegister, label: &str) -> &mut Self
+    where
+        A: BranchBuilder<R>
+    {
+        self.arch.cbnz(reg.to_arch_reg(), label);
+        self
+    }
+
+    #[track_caller]
+    pub fn b_cond(&mut self, cond: Condition, label: &str) -> &mut Self
+    where
+        A: BranchBuilder<R>
+    {
+        self.arch.b_cond(cond, label);
+        self
+    }
+
+    #[track_caller]
+    pub fn br(&mut self, reg: GenericRegister) -> &mut Self
+    where
+        A: BranchBuilder<R>
+    {
+        self.arch.br(reg.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn blr(&mut self, reg: GenericRegister) -> &mut Self
+    where
+        A: BranchBuilder<R>
+    {
+        self.arch.blr(reg.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn label(&mut self, name: &str) -> &mut Self
+    where
+        A: LabelBuilder
+    {
+        self.arch.label(name);
+        self
+    }
+
+    #[track_caller]
+    pub fn jump_table(&mut self, table: &str, targets: &[String]) -> &mut Self
+    where
+        A: JumpTableBuilder
+    {
+        self.arch.jump_table(table, targets);
+        self
+    }
+
+    #[track_caller]
+    pub fn str(&mut self, src: GenericRegister, addr: &str) -> &mut Self
+    where
+        A: LoadStoreBuilder<R>
+    {
+        self.arch.str(src.to_arch_reg(), addr);
+        self
+    }
+
+    #[track_caller]
+    pub fn ldr(&mut self, dst: GenericRegister, addr: &str) -> &mut Self
+    where
+        A: LoadStoreBuilder<R>
+    {
+        self.arch.ldr(dst.to_arch_reg(), addr);
+        self
+    }
+
+    #[track_caller]
+    pub fn load(&mut self, size: MemSize, signed: bool, dst: GenericRegister, addr: MemOperand<GenericRegister>) -> &mut Self
+    where
+        A: LoadStoreBuilder<R>
+    {
+        self.arch.load(size, signed, dst.to_arch_reg(), addr.map(|reg| reg.to_arch_reg()));
+        self
+    }
+
+    #[track_caller]
+    pub fn store(&mut self, size: MemSize, src: GenericRegister, addr: MemOperand<GenericRegister>) -> &mut Self
+    where
+        A: LoadStoreBuilder<R>
+    {
+        self.arch.store(size, src.to_arch_reg(), addr.map(|reg| reg.to_arch_reg()));
+        self
+    }
+
+    #[track_caller]
+    pub fn ldp(&mut self, dst1: GenericRegister, dst2: GenericRegister, addr: MemOperand<GenericRegister>) -> &mut Self
+    where
+        A: LoadStoreBuilder<R>
+    {
+        self.arch.ldp(dst1.to_arch_reg(), dst2.to_arch_reg(), addr.map(|reg| reg.to_arch_reg()));
+        self
+    }
+
+    #[track_caller]
+    pub fn stp(&mut self, src1: GenericRegister, src2: GenericRegister, addr: MemOperand<GenericRegister>) -> &mut Self
+    where
+        A: LoadStoreBuilder<R>
+    {
+        self.arch.stp(src1.to_arch_reg(), src2.to_arch_reg(), addr.map(|reg| reg.to_arch_reg()));
+        self
+    }
+
+    #[track_caller]
+    pub fn mov(&mut self, dst: GenericRegister, src: GenericRegister) -> &mut Self
+    where
+        A: MovBuilder<R>
+    {
+        self.arch.mov(dst.to_arch_reg(), src.to_arch_reg());
+        self
+    }
+
+    #[track_caller]
+    pub fn mov_imm(&mut self, dst: GenericRegister, imm: u64) -> &mut Self
+    where
+        A: MovBuilder<R>
+    {
+        self.arch.mov_imm(dst.to_arch_reg(), imm);
+        self
+    }
+
+    #[track_caller]
+    pub fn adr(&mut self, dst: GenericRegister, label: &str) -> &mut Self
+    where
+        A: AddressBuilder<R>
+    {
+        self.arch.adr(dst.to_arch_reg(), label);
+        self
+    }
+
+    #[track_caller]
+    pub fn adrp(&mut self, dst: GenericRegister, label: &str) -> &mut Self
+    where
+        A: AddressBuilder<R>
+    {
+        self.arch.adrp(dst.to_arch_reg(), label);
+        self
+    }
+
+    #[track_caller]
+    pub fn adrp_add(&mut self, dst: GenericRegister, base: GenericRegister, label: &str) -> &mut Self
+    where
+        A: AddressBuilder<R>
+    {
+        self.arch.adrp_add(dst.to_arch_reg(), base.to_arch_reg(), label);
+        self
+    }
+
+    #[track_caller]
+    pub fn adrp_got(&mut self, dst: GenericRegister, label: &str) -> &mut Self
+    where
+        A: AddressBuilder<R>
+    {
+        self.arch.adrp_got(dst.to_arch_reg(), label);
+        self
+    }
+
+    /// Returns a label name that has not been handed out by this builder.
+    pub fn fresh_label(&mut self, prefix: &str) -> String {
+        let label = format!("L{}{}", prefix, self.label_counter);
+        self.label_counter += 1;
+        label
+    }
+
+    /// Emits `body` guarded by `cond`. Comparing against an immediate that
+    /// does not fit `cmp` clobbers X16, or X17 when the register compared is
+    /// X16.
+    #[track_caller]
+    pub fn if_<F>(&mut self, cond: Cond, body: F) -> IfElse<'_, A, R>
+    where
+        A: CompareBuilder<R> + BranchBuilder<R> + MovBuilder<R> + LabelBuilder,
+        F: FnOnce(&mut Self),
+    {
+        let else_label = self.fresh_label("else");
+        self.branch_unless(cond, &else_label);
+        body(self);
+        IfElse { builder: Some(self), else_label }
+    }
+
+    /// Emits `body` repeatedly for as long as `cond` holds, testing it first.
+    /// X16 or X17 may be clobbered by the test, as for [`Self::if_`].
+    #[track_caller]
+    pub fn while_<F>(&mut self, cond: Cond, body: F) -> &mut Self
+    where
+        A: CompareBuilder<R> + BranchBuilder<R> + MovBuilder<R> + LabelBuilder,
+        F: FnOnce(&mut Self),
+    {
+        let head_label = self.fresh_label("while");
+        let end_label = self.fresh_label("endwhile");
+        self.arch.label(&head_label);
+        self.branch_unless(cond, &end_label);
+        body(self);
+        self.arch.b(&head_label);
+        self.arch.label(&end_label);
+        self
+    }
+
+    /// Emits `body` with `reg` counting from `start` up to, but not including,
+    /// `end`. The comparison is signed, and may clobber X16 or X17 as for
+    /// [`Self::if_`].
+    #[track_caller]
+    pub fn for_range<F>(&mut self, reg: GenericRegister, start: impl Into<CondOperand>, end: impl Into<CondOperand>, body: F) -> &mut Self
+    where
+        A: ArithmeticBuilder<R> + CompareBuilder<R> + BranchBuilder<R> + MovBuilder<R> + LabelBuilder,
+        F: FnOnce(&mut Self),
+    {
+        match start.into() {
+            CondOperand::Register(src) => self.arch.mov(reg.to_arch_reg(), src.to_arch_reg()),
+            CondOperand::Immediate(imm) => self.arch.mov_imm(reg.to_arch_reg(), imm as u64),
+        }
+        let end = end.into();
+        self.while_(Cond::Compare(reg, Condition::Lt, end), |b| {
+            body(b);
+            b.arch.add(reg.to_arch_reg(), reg.to_arch_reg(), Operand::Immediate("1".to_string()));
+        })
+    }
+
+    /// Branches to `label` when `cond` does not hold.
+    #[track_caller]
+    fn branch_unless(&mut self, cond: Cond, label: &str)
+    where
+        A: CompareBuilder<R> + BranchBuilder<R> + MovBuilder<R>,
+    {
+        match cond {
+            Cond::Compare(lhs, condition, rhs) => {
+                // There is no "never" condition to branch on, and nothing to
+                // compare for a condition that always holds
+                let Some(inverse) = condition.invert() else { return };
+                match rhs {
+                    CondOperand::Register(reg) => self.arch.cmp(lhs.to_arch_reg(), Operand::Register(reg.to_arch_reg())),
+                    CondOperand::Immediate(imm) => self.compare_imm(lhs, imm),
+                }
+                self.arch.b_cond(inverse, label);
+            }
+            Cond::Zero(reg) => self.arch.cbnz(reg.to_arch_reg(), label),
+            Cond::NonZero(reg) => self.arch.cbz(reg.to_arch_reg(), label),
+            Cond::Flags(condition) => {
+                if let Some(inverse) = condition.invert() {
+                    self.arch.b_cond(inverse, label);
+                }
+            }
+        }
+    }
+
+    /// Dispatches on the value in `reg` to the arms registered by `arms`.
+    /// Arms do not fall through. Dense switches use a PC-relative jump table
+    /// in read-only data, sparse ones a chain of compares. X16 and X17 are
+    /// used as scratch registers.
+    #[track_caller]
+    pub fn switch_<'s, F>(&mut self, reg: GenericRegister, arms: F) -> &mut Self
+    where
+        A: ArithmeticBuilder<R> + CompareBuilder<R> + BranchBuilder<R> + LoadStoreBuilder<R> + MovBuilder<R> + AddressBuilder<R> + LabelBuilder + JumpTableBuilder,
+        F: FnOnce(&mut Switch<'s, A, R>),
+    {
+        let mut switch = Switch { cases: Vec::new(), default: None, errors: Vec::new() };
+        arms(&mut switch);
+        self.errors.append(&mut switch.errors);
+
+        let case_labels: Vec<String> = switch.cases.iter().map(|_| self.fresh_label("case")).collect();
+        let default_label = self.fresh_label("default");
+        let end_label = self.fresh_label("endswitch");
+
+        if switch.use_jump_table() {
+            self.jump_table_dispatch(reg, &switch, &case_labels, &default_label);
+        } else {
+            for ((value, _), label) in switch.cases.iter().zip(&case_labels) {
+                self.compare_imm(reg, *value);
+                self.arch.b_cond(Condition::Eq, label);
+            }
+            self.arch.b(&default_label);
+        }
+
+        for ((_, body), label) in switch.cases.into_iter().zip(&case_labels) {
+            self.arch.label(label);
+            body(self);
+            self.arch.b(&end_label);
+        }
+        self.arch.label(&default_label);
+        if let Some(body) = switch.default {
+            body(self);
+        }
+        self.arch.label(&end_label);
+        self
+    }
+
+    #[track_caller]
+    fn jump_table_dispatch<'s>(&mut self, reg: GenericRegister, switch: &Switch<'s, A, R>, case_labels: &[String], default_label: &str)
+    where
+        A: ArithmeticBuilder<R> + CompareBuilder<R> + BranchBuilder<R> + LoadStoreBuilder<R> + MovBuilder<R> + AddressBuilder<R> + LabelBuilder + JumpTableBuilder,
+    {
+        let (min, max) = (switch.min().unwrap(), switch.max().unwrap());
+        let (x16, x17) = (GenericRegister::X16.to_arch_reg(), GenericRegister::X17.to_arch_reg());
+        // A W value is rebased in W16, which zero-extends it into X16
+        let (scratch16, scratch17) = match reg.is_32bit() {
+            true => (GenericRegister::W16, GenericRegister::W17),
+            false => (GenericRegister::X16, GenericRegister::X17),
+        };
+
+        // Rebase the index into X16 so the table starts at zero, leaving X17
+        // free for the table address
+        let index = if min == 0 && !reg.is_32bit() && reg != GenericRegister::X17 {
+            reg
+        } else if min == 0 {
+            self.arch.mov(scratch16.to_arch_reg(), reg.to_arch_reg());
+            scratch16
+        } else if (-4095..=4095).contains(&min) {
+            self.arch.add(scratch16.to_arch_reg(), reg.to_arch_reg(), Operand::Immediate((-min).to_string()));
+            scratch16
+        } else {
+            // The constant goes in whichever scratch register does not hold
+            // the value
+            let scratch = match reg {
+                GenericRegister::X17 | GenericRegister::W17 => scratch16,
+                _ => scratch17,
+            };
+            self.arch.mov_imm(scratch.to_arch_reg(), min as u64);
+            self.arch.sub(scratch16.to_arch_reg(), reg.to_arch_reg(), scratch.to_arch_reg());
+            scratch16
+        };
+        // Unsigned, so values below `min` also take the default
+        self.arch.cmp(index.to_arch_reg(), Operand::Immediate((max - min).to_string()));
+        self.arch.b_cond(Condition::Hi, default_label);
+        let index = match index.is_32bit() {
+            true => x16,
+            false => index.to_arch_reg(),
+        };
+
+        // The table is in read-only data, out of `adr` range once the text
+        // is large and in another section on Mach-O
+        let table = self.fresh_label("jumptable");
+        self.arch.adrp_add(x17, x17, &table);
+        self.arch.load(MemSize::Word, true, x16, MemOperand::Indexed(x17, index, 2));
+        self.arch.add(x16, x17, Operand::Register(x16));
+        self.arch.br(x16);
+
+        let targets: Vec<String> = (min..=max)
+            .map(|value| {
+                switch.cases.iter().zip(case_labels)
+                    .find(|((case, _), _)| *case == value)
+                    .map_or(default_label.to_string(), |(_, label)| label.clone())
+            })
+            .collect();
+        self.arch.jump_table(&table, &targets);
+    }
+
+    /// `cmp reg, #value`, going through X16, or X17 when `reg` is X16, when
+    /// the value does not fit an immediate. The scratch register has the
+    /// width of `reg`.
+    #[track_caller]
+    fn compare_imm(&mut self, reg: GenericRegister, value: i64)
+    where
+        A: CompareBuilder<R> + MovBuilder<R>,
+    {
+        if (-4095..=4095).contains(&value) {
+            // A negative immediate is compared with `cmn` and its magnitude
+            self.arch.cmp(reg.to_arch_reg(), Operand::Immediate(value.to_string()));
+        } else {
+            let scratch = match reg {
+                GenericRegister::X16 => GenericRegister::X17,
+                GenericRegister::W16 => GenericRegister::W17,
+                reg if reg.is_32bit() => GenericRegister::W16,
+                _ => GenericRegister::X16,
+            };
+            self.arch.mov_imm(scratch.to_arch_reg(), value as u64);
+            self.arch.cmp(reg.to_arch_reg(), Operand::Register(scratch.to_arch_reg()));
+        }
+    }
+}
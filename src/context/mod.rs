@@ -1,64 +1,343 @@
+use crate::instruction::BuildError;
 use std::collections::HashMap;
+use std::fmt;
+use std::panic::Location;
+
+pub mod symbol;
+
+pub use symbol::{Binding, Symbol, SymbolKind, SymbolTable, Visibility};
 
 #[derive(Debug)]
 pub struct Context {
     pub variables: HashMap<String, Variable>,
     pub sections: Sections,
     pub label_counter: usize,
-    pub externs: Vec<String>,
+    pub symbols: SymbolTable,
+    /// Problems with the globals added, in the order they were found
+    errors: Vec<BuildError>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Variable {
     pub name: String,
-    pub value: String,
+    pub value: Value,
     pub label: String,
+    /// Alignment in bytes, a power of two
+    pub align: u64,
+    pub mutable: bool,
+}
+
+/// The initial contents of a global. Arrays hold any number of items, and
+/// raw blobs are byte arrays.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A NUL-terminated string
+    Asciz(String),
+    Byte(Vec<u8>),
+    Hword(Vec<u16>),
+    Word(Vec<u32>),
+    Quad(Vec<u64>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+    /// `size` zero bytes
+    Zero(u64),
+    /// The address of `symbol` plus `offset`, filled in by relocation
+    Pointer { symbol: String, offset: i64 },
+}
+
+impl Value {
+    /// Size in bytes.
+    pub fn size(&self) -> u64 {
+        match self {
+            Value::Asciz(value) => value.len() as u64 + 1,
+            Value::Byte(items) => items.len() as u64,
+            Value::Hword(items) => 2 * items.len() as u64,
+            Value::Word(items) => 4 * items.len() as u64,
+            Value::Quad(items) => 8 * items.len() as u64,
+            Value::Float(items) => 4 * items.len() as u64,
+            Value::Double(items) => 8 * items.len() as u64,
+            Value::Zero(size) => *size,
+            Value::Pointer { .. } => 8,
+        }
+    }
+
+    /// The alignment of a single item.
+    pub fn natural_align(&self) -> u64 {
+        match self {
+            Value::Asciz(_) | Value::Byte(_) | Value::Zero(_) => 1,
+            Value::Hword(_) => 2,
+            Value::Word(_) | Value::Float(_) => 4,
+            Value::Quad(_) | Value::Double(_) | Value::Pointer { .. } => 8,
+        }
+    }
+}
+
+/// Renders the directive that assembles to the value. Floats are written as
+/// their bit patterns so every value, NaNs included, survives exactly.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Asciz(value) => write!(f, ".asciz {}", quote(value)),
+            Value::Byte(items) => write!(f, ".byte {}", list(items.iter().map(|item| format!("{:#x}", item)))),
+            Value::Hword(items) => write!(f, ".hword {}", list(items.iter().map(|item| format!("{:#x}", item)))),
+            Value::Word(items) => write!(f, ".word {}", list(items.iter().map(|item| format!("{:#x}", item)))),
+            Value::Quad(items) => write!(f, ".quad {}", list(items.iter().map(|item| format!("{:#x}", item)))),
+            Value::Float(items) => write!(f, ".word {}", list(items.iter().map(|item| format!("{:#x}", item.to_bits())))),
+            Value::Double(items) => write!(f, ".quad {}", list(items.iter().map(|item| format!("{:#x}", item.to_bits())))),
+            Value::Zero(size) => write!(f, ".zero {}", size),
+            Value::Pointer { symbol, offset } => match offset {
+                0 => write!(f, ".quad {}", symbol),
+                offset if *offset < 0 => write!(f, ".quad {}-{}", symbol, offset.unsigned_abs()),
+                offset => write!(f, ".quad {}+{}", symbol, offset),
+            },
+        }
+    }
+}
+
+fn list(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join(", ")
+}
+
+/// Quotes `value` as an assembler string literal. Quotes, backslashes and
+/// every byte outside printable ASCII are escaped, the latter in octal,
+/// which GNU and Apple `as` both read back unchanged.
+pub fn quote(value: &str) -> String {
+    let mut out = String::from("\"");
+    for byte in value.bytes() {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'\r' => out.push_str("\\r"),
+            b' '..=b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A global's contents with its placement, built up from [`Global::new`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub value: Value,
+    pub align: u64,
+    pub mutable: bool,
+    /// A custom section to place the global in
+    pub section: Option<String>,
+}
+
+impl Global {
+    /// A read-only global at the natural alignment of its items.
+    pub fn new(value: Value) -> Self {
+        let align = value.natural_align();
+        Self { value, align, mutable: false, section: None }
+    }
+
+    /// Places the global in `.data`, or `.bss` for a [`Value::Zero`], so the
+    /// program can write it.
+    pub fn mutable(mut self) -> Self {
+        self.mutable = true;
+        self
+    }
+
+    /// Aligns the global to `align` bytes, which must be a power of two.
+    /// Other values are reported when the global is added.
+    pub fn align(mut self, align: u64) -> Self {
+        self.align = align;
+        self
+    }
+
+    /// Places the global in the section `name` instead of a standard one.
+    pub fn in_section(mut self, name: &str) -> Self {
+        self.section = Some(name.to_string());
+        self
+    }
+}
+
+/// What a section holds, which decides how it is loaded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SectionKind {
+    Text,
+    Rodata,
+    Data,
+    /// Zero-initialized data that takes no space in the file
+    Bss,
 }
 
+#[derive(Debug, Clone)]
+pub struct Section {
+    /// `text`, `rodata`, `data` and `bss` for the standard sections, or the
+    /// assembler's name for a custom one
+    pub name: String,
+    pub kind: SectionKind,
+    variables: Vec<Variable>,
+}
+
+impl Section {
+    pub fn new(name: &str, kind: SectionKind) -> Self {
+        Self { name: name.to_string(), kind, variables: Vec::new() }
+    }
+
+    /// Variables in the order they were added.
+    pub fn variables(&self) -> &[Variable] {
+        &self.variables
+    }
+
+    /// The largest alignment of any variable.
+    pub fn align(&self) -> u64 {
+        self.variables.iter().map(|var| var.align).max().unwrap_or(1)
+    }
+
+    /// Offset of each variable from the start of the section, in order.
+    pub fn offsets(&self) -> Vec<u64> {
+        let mut offset = 0;
+        self.variables
+            .iter()
+            .map(|var| {
+                let start = (offset + var.align - 1) & !(var.align - 1);
+                offset = start + var.value.size();
+                start
+            })
+            .collect()
+    }
+
+    /// Size in bytes with every variable at its alignment.
+    pub fn size(&self) -> u64 {
+        match (self.variables.last(), self.offsets().last()) {
+            (Some(var), Some(offset)) => offset + var.value.size(),
+            _ => 0,
+        }
+    }
+}
+
+/// The standard sections, then custom sections in the order they were
+/// first used.
 #[derive(Debug)]
 pub struct Sections {
-    text: Vec<(Option<String>, String)>,  // (comment, instruction)
-    data: Vec<Variable>,
-    bss: Vec<Variable>,
+    sections: Vec<Section>,
+}
+
+impl Sections {
+    fn new() -> Self {
+        let sections = vec![
+            Section::new("text", SectionKind::Text),
+            Section::new("rodata", SectionKind::Rodata),
+            Section::new("data", SectionKind::Data),
+            Section::new("bss", SectionKind::Bss),
+        ];
+        Self { sections }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// The section `name`, created with `kind` if it does not exist yet.
+    pub fn get_or_insert(&mut self, name: &str, kind: SectionKind) -> &mut Section {
+        let index = match self.sections.iter().position(|section| section.name == name) {
+            Some(index) => index,
+            None => {
+                self.sections.push(Section::new(name, kind));
+                self.sections.len() - 1
+            }
+        };
+        &mut self.sections[index]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Section> {
+        self.sections.iter()
+    }
+
+    /// Every variable, section by section.
+    pub fn variables(&self) -> impl Iterator<Item = &Variable> {
+        self.sections.iter().flat_map(|section| section.variables.iter())
+    }
 }
 
 impl Context {
     pub fn new() -> Self {
         Self {
             variables: HashMap::new(),
-            sections: Sections {
-                text: Vec::new(),
-                data: Vec::new(),
-                bss: Vec::new(),
-            },
+            sections: Sections::new(),
             label_counter: 0,
-            externs: Vec::new(),
+            symbols: SymbolTable::new(),
+            errors: Vec::new(),
         }
     }
 
+    /// Adds a mutable string, as [`Self::add_global`] with a
+    /// [`Value::Asciz`].
+    #[track_caller]
     pub fn add_variable(&mut self, name: &str, value: &str) -> String {
+        self.add_global(name, Global::new(Value::Asciz(value.to_string())).mutable())
+    }
+
+    /// Adds `global`, returning its label. An alignment that is not a power
+    /// of two or a name already in use is recorded as a [`BuildError`]; the
+    /// global is still placed, at its natural alignment in the first case,
+    /// so building can go on.
+    #[track_caller]
+    pub fn add_global(&mut self, name: &str, global: Global) -> String {
         let label = format!("L{}", self.label_counter);
         self.label_counter += 1;
 
+        let location = Some(Location::caller());
+        let mut align = global.align;
+        if !align.is_power_of_two() {
+            self.errors.push(BuildError::InvalidAlignment { location, global: name.to_string(), align });
+            align = global.value.natural_align();
+        }
+        if self.variables.contains_key(name) {
+            self.errors.push(BuildError::DuplicateGlobal { location, global: name.to_string() });
+        }
+
         let var = Variable {
             name: name.to_string(),
-            value: value.to_string(),
+            value: global.value,
             label: label.clone(),
+            align,
+            mutable: global.mutable,
         };
 
-        self.sections.data.push(var.clone());
+        let kind = match (global.mutable, &var.value) {
+            (true, Value::Zero(_)) => SectionKind::Bss,
+            (true, _) => SectionKind::Data,
+            (false, _) => SectionKind::Rodata,
+        };
+        let section = match &global.section {
+            Some(section) => section.as_str(),
+            None => match kind {
+                SectionKind::Bss => "bss",
+                SectionKind::Data => "data",
+                _ => "rodata",
+            },
+        };
+        self.symbols.declare(Symbol::object(&label).size(var.value.size()));
+        self.sections.get_or_insert(section, kind).variables.push(var.clone());
         self.variables.insert(name.to_string(), var);
         label
     }
 
-    pub fn add_instruction(&mut self, instruction: String, comment: Option<String>) {
-        self.sections.text.push((comment, instruction));
+    /// Adds `var`, already labelled, to the section `section`, created with
+    /// `kind` if it does not exist yet.
+    pub(crate) fn insert_variable(&mut self, section: &str, kind: SectionKind, var: Variable) {
+        self.sections.get_or_insert(section, kind).variables.push(var.clone());
+        self.variables.insert(var.name.clone(), var);
     }
 
     pub fn add_extern(&mut self, name: &str) {
-        if !self.externs.contains(&name.to_string()) {
-            self.externs.push(name.to_string());
-        }
+        self.symbols.refer(name);
+    }
+
+    pub fn add_symbol(&mut self, symbol: Symbol) {
+        self.symbols.declare(symbol);
+    }
+
+    /// Problems with the globals added so far.
+    pub fn build_errors(&self) -> &[BuildError] {
+        &self.errors
     }
 
     pub fn get_variable(&self, name: &str) -> Option<&Variable> {
@@ -69,7 +348,11 @@ impl Context {
         &self.sections
     }
 
-    pub fn get_externs(&self) -> &[String] {
-        &self.externs
+    pub fn get_externs(&self) -> Vec<String> {
+        self.symbols.externs().map(|symbol| symbol.name.clone()).collect()
+    }
+
+    pub fn get_symbols(&self) -> &SymbolTable {
+        &self.symbols
     }
 } 
\ No newline at end of file
@@ -0,0 +1,132 @@
+//! Symbols a program defines or refers to.
+//!
+//! A [`SymbolTable`] keeps each symbol's binding, visibility, type and size
+//! in the order symbols were first seen. The platform turns it into
+//! assembler directives, and object writers into their symbol tables.
+
+/// Who can see a symbol from outside its object.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    Local,
+    Global,
+    /// Global, but yields to a global definition and may stay undefined
+    Weak,
+}
+
+/// Who can see a global symbol from outside its linked image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Visibility {
+    Default,
+    Hidden,
+    /// Visible, but always bound to the definition in its own image
+    Protected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Func,
+    Object,
+}
+
+/// A symbol, built up from [`Symbol::function`], [`Symbol::object`] or
+/// [`Symbol::external`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub binding: Binding,
+    pub visibility: Visibility,
+    /// Unknown for external references
+    pub kind: Option<SymbolKind>,
+    /// Size in bytes, if known
+    pub size: Option<u64>,
+    /// Whether the program defines the symbol or only refers to it
+    pub defined: bool,
+}
+
+impl Symbol {
+    fn new(name: &str, binding: Binding, kind: Option<SymbolKind>, defined: bool) -> Self {
+        Self { name: name.to_string(), binding, visibility: Visibility::Default, kind, size: None, defined }
+    }
+
+    /// A local function defined by the program.
+    pub fn function(name: &str) -> Self {
+        Self::new(name, Binding::Local, Some(SymbolKind::Func), true)
+    }
+
+    /// A local data object defined by the program.
+    pub fn object(name: &str) -> Self {
+        Self::new(name, Binding::Local, Some(SymbolKind::Object), true)
+    }
+
+    /// A symbol the program refers to but another object defines.
+    pub fn external(name: &str) -> Self {
+        Self::new(name, Binding::Global, None, false)
+    }
+
+    pub fn global(mut self) -> Self {
+        self.binding = Binding::Global;
+        self
+    }
+
+    pub fn weak(mut self) -> Self {
+        self.binding = Binding::Weak;
+        self
+    }
+
+    pub fn hidden(mut self) -> Self {
+        self.visibility = Visibility::Hidden;
+        self
+    }
+
+    pub fn protected(mut self) -> Self {
+        self.visibility = Visibility::Protected;
+        self
+    }
+
+    pub fn size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+}
+
+/// Symbols in the order they were first declared or referred to.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `symbol`, replacing an earlier declaration of the same name in
+    /// place. A reference never replaces a definition.
+    pub fn declare(&mut self, symbol: Symbol) {
+        match self.symbols.iter_mut().find(|existing| existing.name == symbol.name) {
+            Some(existing) if existing.defined && !symbol.defined => {}
+            Some(existing) => *existing = symbol,
+            None => self.symbols.push(symbol),
+        }
+    }
+
+    /// Records a reference to `name` unless the symbol is already known.
+    pub fn refer(&mut self, name: &str) {
+        if self.get(name).is_none() {
+            self.symbols.push(Symbol::external(name));
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    /// Symbols the program refers to without defining.
+    pub fn externs(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter().filter(|symbol| !symbol.defined)
+    }
+}
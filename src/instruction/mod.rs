@@ -1,4 +1,5 @@
-use std::fmt::Display;
+use std::fmt::{self, Display};
+use std::panic::Location;
 
 pub trait Register: Display + Copy {
     fn is_general_purpose(&self) -> bool;
@@ -40,6 +41,38 @@ pub enum GenericRegister {
     X28,
     X29,
     X30,
+    // 32-bit views of the general purpose registers (W0-W30)
+    W0,
+    W1,
+    W2,
+    W3,
+    W4,
+    W5,
+    W6,
+    W7,
+    W8,
+    W9,
+    W10,
+    W11,
+    W12,
+    W13,
+    W14,
+    W15,
+    W16,
+    W17,
+    W18,
+    W19,
+    W20,
+    W21,
+    W22,
+    W23,
+    W24,
+    W25,
+    W26,
+    W27,
+    W28,
+    W29,
+    W30,
     // SIMD/FP registers (V0-V31)
     V0,
     V1,
@@ -77,48 +110,20 @@ pub enum GenericRegister {
     SP,  // Stack Pointer
     LR,  // Link Register (alias for X30)
     XZR, // Zero Register
+    WSP, // 32-bit view of SP
+    WZR, // 32-bit Zero Register
 }
 
 impl GenericRegister {
-    pub fn validate_for_arm64(&self) -> Result<(), &'static str> {
-        match self {
-            Self::X0
-            | Self::X1
-            | Self::X2
-            | Self::X3
-            | Self::X4
-            | Self::X5
-            | Self::X6
-            | Self::X7
-            | Self::X8
-            | Self::X9
-            | Self::X10
-            | Self::X11
-            | Self::X12
-            | Self::X13
-            | Self::X14
-            | Self::X15
-            | Self::X16
-            | Self::X17
-            | Self::X18
-            | Self::X19
-            | Self::X20
-            | Self::X21
-            | Self::X22
-            | Self::X23
-            | Self::X24
-            | Self::X25
-            | Self::X26
-            | Self::X27
-            | Self::X28
-            | Self::X29
-            | Self::X30
-            | Self::SP
-            | Self::LR
-            | Self::XZR => Ok(()),
-            Self::SP | Self::LR | Self::XZR => Ok(()),
-            _ => Err("Invalid register for ARM64 architecture"),
-        }
+    /// Whether this is the 32-bit view of a general purpose register.
+    pub fn is_32bit(&self) -> bool {
+        matches!(self,
+            Self::W0 | Self::W1 | Self::W2 | Self::W3 | Self::W4 | Self::W5 | Self::W6 | Self::W7 |
+            Self::W8 | Self::W9 | Self::W10 | Self::W11 | Self::W12 | Self::W13 | Self::W14 | Self::W15 |
+            Self::W16 | Self::W17 | Self::W18 | Self::W19 | Self::W20 | Self::W21 | Self::W22 | Self::W23 |
+            Self::W24 | Self::W25 | Self::W26 | Self::W27 | Self::W28 | Self::W29 | Self::W30 |
+            Self::WSP | Self::WZR
+        )
     }
 }
 
@@ -126,16 +131,559 @@ pub trait RegisterMapping<R: Register> {
     fn to_arch_reg(&self) -> R;
 }
 
+/// A backend and the registers its instructions name, so a builder can be
+/// made from the backend alone.
+pub trait Backend {
+    type Register: Register;
+}
+
 pub enum Operand<R> {
     Register(R),
     Immediate(String),
+    /// A register shifted by a constant amount, e.g. `x2, lsl #3`
+    Shifted(R, ShiftKind, u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShiftKind {
+    Lsl,
+    Lsr,
+    Asr,
+    Ror,
+}
+
+impl ShiftKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Lsl => "lsl",
+            Self::Lsr => "lsr",
+            Self::Asr => "asr",
+            Self::Ror => "ror",
+        }
+    }
+}
+
+/// Condition codes shared by conditional branches, selects and compares.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    Eq, // Equal
+    Ne, // Not equal
+    Hs, // Unsigned higher or same
+    Lo, // Unsigned lower
+    Mi, // Negative
+    Pl, // Positive or zero
+    Vs, // Overflow
+    Vc, // No overflow
+    Hi, // Unsigned higher
+    Ls, // Unsigned lower or same
+    Ge, // Signed greater or equal
+    Lt, // Signed less than
+    Gt, // Signed greater than
+    Le, // Signed less or equal
+    Al, // Always
+}
+
+impl Condition {
+    /// The condition that holds exactly when `self` does not, or `None`
+    /// for `Al`, as there is no condition that never holds.
+    pub fn invert(&self) -> Option<Self> {
+        match self {
+            Self::Eq => Some(Self::Ne),
+            Self::Ne => Some(Self::Eq),
+            Self::Hs => Some(Self::Lo),
+            Self::Lo => Some(Self::Hs),
+            Self::Mi => Some(Self::Pl),
+            Self::Pl => Some(Self::Mi),
+            Self::Vs => Some(Self::Vc),
+            Self::Vc => Some(Self::Vs),
+            Self::Hi => Some(Self::Ls),
+            Self::Ls => Some(Self::Hi),
+            Self::Ge => Some(Self::Lt),
+            Self::Lt => Some(Self::Ge),
+            Self::Gt => Some(Self::Le),
+            Self::Le => Some(Self::Gt),
+            Self::Al => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Eq => "eq",
+            Self::Ne => "ne",
+            Self::Hs => "hs",
+            Self::Lo => "lo",
+            Self::Mi => "mi",
+            Self::Pl => "pl",
+            Self::Vs => "vs",
+            Self::Vc => "vc",
+            Self::Hi => "hi",
+            Self::Ls => "ls",
+            Self::Ge => "ge",
+            Self::Lt => "lt",
+            Self::Gt => "gt",
+            Self::Le => "le",
+            Self::Al => "al",
+        }
+    }
+}
+
+/// Width of a memory access.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemSize {
+    Byte,
+    Half,
+    Word,
+    Double,
+}
+
+impl MemSize {
+    pub fn bytes(&self) -> u32 {
+        match self {
+            Self::Byte => 1,
+            Self::Half => 2,
+            Self::Word => 4,
+            Self::Double => 8,
+        }
+    }
+}
+
+/// Ordering constraints of an atomic access, written as the `a`, `l` or
+/// `al` mnemonic suffix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryOrder {
+    Relaxed,
+    Acquire,
+    Release,
+    AcquireRelease,
+}
+
+impl MemoryOrder {
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Self::Relaxed => "",
+            Self::Acquire => "a",
+            Self::Release => "l",
+            Self::AcquireRelease => "al",
+        }
+    }
+
+    pub fn acquires(&self) -> bool {
+        matches!(self, Self::Acquire | Self::AcquireRelease)
+    }
+
+    pub fn releases(&self) -> bool {
+        matches!(self, Self::Release | Self::AcquireRelease)
+    }
+}
+
+/// Shareability domain and access types ordered by a `dmb` or `dsb`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarrierDomain {
+    Sy,    // Full system, all accesses
+    St,    // Full system, stores
+    Ld,    // Full system, loads
+    Ish,   // Inner shareable
+    IshSt,
+    IshLd,
+    Nsh,   // Non-shareable
+    NshSt,
+    NshLd,
+    Osh,   // Outer shareable
+    OshSt,
+    OshLd,
+}
+
+impl BarrierDomain {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sy => "sy",
+            Self::St => "st",
+            Self::Ld => "ld",
+            Self::Ish => "ish",
+            Self::IshSt => "ishst",
+            Self::IshLd => "ishld",
+            Self::Nsh => "nsh",
+            Self::NshSt => "nshst",
+            Self::NshLd => "nshld",
+            Self::Osh => "osh",
+            Self::OshSt => "oshst",
+            Self::OshLd => "oshld",
+        }
+    }
+}
+
+/// Precision of a scalar floating-point operation, selecting the `h`, `s`
+/// or `d` view of the SIMD&FP registers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FpSize {
+    Half,
+    Single,
+    Double,
+}
+
+impl FpSize {
+    pub fn bytes(&self) -> u32 {
+        match self {
+            Self::Half => 2,
+            Self::Single => 4,
+            Self::Double => 8,
+        }
+    }
+}
+
+/// Lane layout of a NEON vector operand, written as the `.4s` suffix in
+/// `v0.4s`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VectorArrangement {
+    B8,
+    B16,
+    H4,
+    H8,
+    S2,
+    S4,
+    D2,
+}
+
+impl VectorArrangement {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::B8 => "8b",
+            Self::B16 => "16b",
+            Self::H4 => "4h",
+            Self::H8 => "8h",
+            Self::S2 => "2s",
+            Self::S4 => "4s",
+            Self::D2 => "2d",
+        }
+    }
+
+    /// Width of a single lane.
+    pub fn element(&self) -> MemSize {
+        match self {
+            Self::B8 | Self::B16 => MemSize::Byte,
+            Self::H4 | Self::H8 => MemSize::Half,
+            Self::S2 | Self::S4 => MemSize::Word,
+            Self::D2 => MemSize::Double,
+        }
+    }
+
+    pub fn lanes(&self) -> u32 {
+        self.bytes() / self.element().bytes()
+    }
+
+    /// Size of the whole vector: 8 or 16 bytes.
+    pub fn bytes(&self) -> u32 {
+        match self {
+            Self::B8 | Self::H4 | Self::S2 => 8,
+            Self::B16 | Self::H8 | Self::S4 | Self::D2 => 16,
+        }
+    }
+}
+
+/// A base-register memory operand.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MemOperand<R> {
+    /// `[base, #offset]`
+    Offset(R, i64),
+    /// `[base, #offset]!`, writing the address back to `base` before the access
+    PreIndex(R, i64),
+    /// `[base], #offset`, writing the address back to `base` after the access
+    PostIndex(R, i64),
+    /// `[base, index, lsl #shift]`
+    Indexed(R, R, u8),
+}
+
+impl<R> MemOperand<R> {
+    /// The same addressing mode with each register converted by `f`.
+    pub fn map<S>(self, f: impl Fn(R) -> S) -> MemOperand<S> {
+        match self {
+            Self::Offset(base, offset) => MemOperand::Offset(f(base), offset),
+            Self::PreIndex(base, offset) => MemOperand::PreIndex(f(base), offset),
+            Self::PostIndex(base, offset) => MemOperand::PostIndex(f(base), offset),
+            Self::Indexed(base, index, shift) => MemOperand::Indexed(f(base), f(index), shift),
+        }
+    }
+}
+
+/// An instruction as stored by a backend, with the metadata attached when it
+/// was emitted.
+#[derive(Debug, Clone)]
+pub struct Emitted<I> {
+    pub inst: I,
+    pub comment: Option<String>,
+    /// The generator code that emitted the instruction, captured through
+    /// `#[track_caller]`; `None` for instructions the backend adds itself
+    pub source_location: Option<&'static Location<'static>>,
+    pub tags: Vec<String>,
+}
+
+impl<I> Emitted<I> {
+    pub fn new(inst: I) -> Self {
+        Self { inst, comment: None, source_location: None, tags: Vec::new() }
+    }
+}
+
+/// Where a [`BuildError`] was found: the index of the offending instruction
+/// in the emitted stream and the generator code that emitted it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Site {
+    pub index: usize,
+    pub location: Option<&'static Location<'static>>,
+}
+
+impl fmt::Display for Site {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(location) = self.location {
+            write!(f, "{}: ", location)?;
+        }
+        write!(f, "instruction {}", self.index)
+    }
+}
+
+/// A problem with a generated program. Backends record these instead of
+/// panicking so every problem can be reported at once.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    /// An operand of the wrong kind, such as a general purpose register
+    /// where a floating-point one is needed
+    InvalidRegisterClass { site: Site, operand: String, expected: &'static str },
+    /// An immediate that is not a number or does not fit its field
+    ImmediateOutOfRange { site: Site, value: String },
+    UndefinedLabel { site: Site, label: String },
+    DuplicateLabel { site: Site, label: String },
+    /// General purpose operands of different widths, or a width the
+    /// instruction does not support
+    WidthMismatch { site: Site, inst: String },
+    /// An `msr` to a system register that cannot be written
+    ReadOnlyRegister { site: Site, register: String },
+    /// An operation the target has no encoding for, which is not emitted
+    Unsupported { site: Site, inst: String },
+    /// A global aligned to something other than a power of two, found
+    /// where it was added
+    InvalidAlignment { location: Option<&'static Location<'static>>, global: String, align: u64 },
+    /// A second global with the name of an earlier one
+    DuplicateGlobal { location: Option<&'static Location<'static>>, global: String },
+    /// A switch case with the value of an earlier one, whose arm is dropped
+    DuplicateCase { location: Option<&'static Location<'static>>, value: i64 },
+}
+
+impl BuildError {
+    /// The offending instruction, or `None` for errors in globals.
+    pub fn site(&self) -> Option<Site> {
+        match self {
+            BuildError::InvalidRegisterClass { site, .. }
+            | BuildError::ImmediateOutOfRange { site, .. }
+            | BuildError::UndefinedLabel { site, .. }
+            | BuildError::DuplicateLabel { site, .. }
+            | BuildError::WidthMismatch { site, .. }
+            | BuildError::ReadOnlyRegister { site, .. }
+            | BuildError::Unsupported { site, .. } => Some(*site),
+            BuildError::InvalidAlignment { .. }
+            | BuildError::DuplicateGlobal { .. }
+            | BuildError::DuplicateCase { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::InvalidAlignment { location: Some(location), .. }
+            | BuildError::DuplicateGlobal { location: Some(location), .. }
+            | BuildError::DuplicateCase { location: Some(location), .. } => write!(f, "{}: ", location)?,
+            _ => {}
+        }
+        if let Some(site) = self.site() {
+            write!(f, "{}: ", site)?;
+        }
+        match self {
+            BuildError::InvalidRegisterClass { operand, expected, .. } => {
+                write!(f, "expected a {}, found {}", expected, operand)
+            }
+            BuildError::ImmediateOutOfRange { value, .. } => write!(f, "immediate {} is out of range", value),
+            BuildError::UndefinedLabel { label, .. } => write!(f, "undefined label {}", label),
+            BuildError::DuplicateLabel { label, .. } => write!(f, "label {} is defined twice", label),
+            BuildError::WidthMismatch { inst, .. } => write!(f, "operand widths do not agree in `{}`", inst),
+            BuildError::ReadOnlyRegister { register, .. } => write!(f, "system register {} is read-only", register),
+            BuildError::Unsupported { inst, .. } => write!(f, "`{}` is not supported on this target", inst),
+            BuildError::InvalidAlignment { global, align, .. } => {
+                write!(f, "alignment {} of global {} is not a power of two", align, global)
+            }
+            BuildError::DuplicateGlobal { global, .. } => write!(f, "global {} is defined twice", global),
+            BuildError::DuplicateCase { value, .. } => write!(f, "switch case {} is given twice", value),
+        }
+    }
+}
+
+pub trait AnnotationBuilder {
+    /// Attaches `comment` to the next instruction emitted
+    fn comment(&mut self, comment: &str);
+    /// Adds `tag` to the next instruction emitted
+    fn tag(&mut self, tag: &str);
 }
 
 pub trait ArithmeticBuilder<R: Register> {
     fn add(&mut self, dst: R, src1: R, src2: Operand<R>);
     fn sub(&mut self, dst: R, src1: R, src2: R);
     fn mul(&mut self, dst: R, src1: R, src2: R);
-    fn fadd(&mut self, dst: R, src1: R, src2: R);
+    fn sdiv(&mut self, dst: R, src1: R, src2: R);
+    fn udiv(&mut self, dst: R, src1: R, src2: R);
+    /// `dst = acc + src1 * src2`
+    fn madd(&mut self, dst: R, src1: R, src2: R, acc: R);
+    /// `dst = acc - src1 * src2`
+    fn msub(&mut self, dst: R, src1: R, src2: R, acc: R);
+    /// `dst = -(src1 * src2)`
+    fn mneg(&mut self, dst: R, src1: R, src2: R);
+    /// Signed 32 x 32 -> 64-bit multiply of the low halves of the sources
+    fn smull(&mut self, dst: R, src1: R, src2: R);
+    /// Unsigned 32 x 32 -> 64-bit multiply of the low halves of the sources
+    fn umull(&mut self, dst: R, src1: R, src2: R);
+    /// High 64 bits of the signed 128-bit product
+    fn smulh(&mut self, dst: R, src1: R, src2: R);
+    /// High 64 bits of the unsigned 128-bit product
+    fn umulh(&mut self, dst: R, src1: R, src2: R);
+    /// `dst = acc + smull(src1, src2)`
+    fn smaddl(&mut self, dst: R, src1: R, src2: R, acc: R);
+    /// `dst = acc + umull(src1, src2)`
+    fn umaddl(&mut self, dst: R, src1: R, src2: R, acc: R);
+}
+
+/// Scalar floating-point operations. Conversions take the precision of the
+/// floating-point side; the integer side's width comes from its register.
+pub trait FloatBuilder<R: Register> {
+    fn fadd(&mut self, size: FpSize, dst: R, src1: R, src2: R);
+    fn fsub(&mut self, size: FpSize, dst: R, src1: R, src2: R);
+    fn fmul(&mut self, size: FpSize, dst: R, src1: R, src2: R);
+    fn fdiv(&mut self, size: FpSize, dst: R, src1: R, src2: R);
+    fn fsqrt(&mut self, size: FpSize, dst: R, src: R);
+    fn fneg(&mut self, size: FpSize, dst: R, src: R);
+    fn fabs(&mut self, size: FpSize, dst: R, src: R);
+    /// `dst = acc + src1 * src2` with a single rounding
+    fn fmadd(&mut self, size: FpSize, dst: R, src1: R, src2: R, acc: R);
+    fn fcmp(&mut self, size: FpSize, src1: R, src2: R);
+    fn fcsel(&mut self, size: FpSize, dst: R, src1: R, src2: R, cond: Condition);
+    /// Copies raw bits between any combination of general purpose and
+    /// floating-point registers.
+    fn fmov(&mut self, size: FpSize, dst: R, src: R);
+    fn fmov_imm(&mut self, size: FpSize, dst: R, imm: f64);
+    /// Signed integer to floating-point
+    fn scvtf(&mut self, size: FpSize, dst: R, src: R);
+    /// Unsigned integer to floating-point
+    fn ucvtf(&mut self, size: FpSize, dst: R, src: R);
+    /// Floating-point to signed integer, rounding toward zero
+    fn fcvtzs(&mut self, size: FpSize, dst: R, src: R);
+    /// Floating-point to unsigned integer, rounding toward zero
+    fn fcvtzu(&mut self, size: FpSize, dst: R, src: R);
+    /// Converts between precisions
+    fn fcvt(&mut self, dst_size: FpSize, dst: R, src_size: FpSize, src: R);
+}
+
+/// NEON operations on whole vectors. Lane operands (`v0.s[1]`) take the lane
+/// width and index separately.
+pub trait VectorBuilder<R: Register> {
+    fn vadd(&mut self, arrangement: VectorArrangement, dst: R, src1: R, src2: R);
+    fn vsub(&mut self, arrangement: VectorArrangement, dst: R, src1: R, src2: R);
+    fn vmul(&mut self, arrangement: VectorArrangement, dst: R, src1: R, src2: R);
+    fn vfadd(&mut self, arrangement: VectorArrangement, dst: R, src1: R, src2: R);
+    fn vfsub(&mut self, arrangement: VectorArrangement, dst: R, src1: R, src2: R);
+    fn vfmul(&mut self, arrangement: VectorArrangement, dst: R, src1: R, src2: R);
+    /// Loads `count` consecutive registers starting at `first`
+    fn ld1(&mut self, arrangement: VectorArrangement, first: R, count: u8, addr: MemOperand<R>);
+    fn st1(&mut self, arrangement: VectorArrangement, first: R, count: u8, addr: MemOperand<R>);
+    /// Broadcasts a general purpose register to every lane
+    fn dup(&mut self, arrangement: VectorArrangement, dst: R, src: R);
+    /// Broadcasts lane `index` of `src` to every lane
+    fn dup_lane(&mut self, arrangement: VectorArrangement, dst: R, src: R, index: u8);
+    /// Inserts a general purpose register into lane `index` of `dst`
+    fn ins(&mut self, lane: MemSize, dst: R, index: u8, src: R);
+    /// Moves lane `index` of `src` to a general purpose register, zero-extended
+    fn umov(&mut self, lane: MemSize, dst: R, src: R, index: u8);
+    /// Sums all lanes into the scalar view of `dst`
+    fn addv(&mut self, arrangement: VectorArrangement, dst: R, src: R);
+}
+
+/// Exclusive and LSE atomic accesses. The address is a base register
+/// with no offset; `size` selects the `b`/`h` forms and the register width
+/// as for [`LoadStoreBuilder::load`].
+pub trait AtomicBuilder<R: Register> {
+    /// Loads from `addr` and marks it in the exclusive monitor
+    fn ldxr(&mut self, size: MemSize, dst: R, addr: R);
+    /// `ldxr` with acquire semantics
+    fn ldaxr(&mut self, size: MemSize, dst: R, addr: R);
+    /// Stores `src` if `addr` is still marked, writing 0 to `status` on
+    /// success and 1 on failure
+    fn stxr(&mut self, size: MemSize, status: R, src: R, addr: R);
+    /// `stxr` with release semantics
+    fn stlxr(&mut self, size: MemSize, status: R, src: R, addr: R);
+    /// Adds `src` to memory, returning the previous value in `dst`
+    fn ldadd(&mut self, order: MemoryOrder, size: MemSize, src: R, dst: R, addr: R);
+    /// Stores `src`, returning the previous value in `dst`
+    fn swp(&mut self, order: MemoryOrder, size: MemSize, src: R, dst: R, addr: R);
+    /// Stores `new` if memory equals `expected`; `expected` receives the
+    /// previous value either way
+    fn cas(&mut self, order: MemoryOrder, size: MemSize, expected: R, new: R, addr: R);
+}
+
+pub trait BarrierBuilder {
+    /// Data memory barrier
+    fn dmb(&mut self, domain: BarrierDomain);
+    /// Data synchronization barrier
+    fn dsb(&mut self, domain: BarrierDomain);
+    /// Instruction synchronization barrier
+    fn isb(&mut self);
+}
+
+pub trait SystemBuilder {
+    /// Supervisor call, trapping to the kernel with `number` as the comment
+    /// field
+    fn svc(&mut self, number: u32);
+}
+
+/// Bitwise operations. The second operand may be a register, a shifted
+/// register or, for `and`/`orr`/`eor`, a logical (bitmask) immediate.
+pub trait LogicalBuilder<R: Register> {
+    fn and(&mut self, dst: R, src1: R, src2: Operand<R>);
+    fn orr(&mut self, dst: R, src1: R, src2: Operand<R>);
+    fn eor(&mut self, dst: R, src1: R, src2: Operand<R>);
+    fn bic(&mut self, dst: R, src1: R, src2: Operand<R>);
+    fn orn(&mut self, dst: R, src1: R, src2: Operand<R>);
+    fn eon(&mut self, dst: R, src1: R, src2: Operand<R>);
+}
+
+/// Shifts by an immediate amount or by the value of a register.
+pub trait ShiftBuilder<R: Register> {
+    fn lsl(&mut self, dst: R, src: R, amount: Operand<R>);
+    fn lsr(&mut self, dst: R, src: R, amount: Operand<R>);
+    fn asr(&mut self, dst: R, src: R, amount: Operand<R>);
+    fn ror(&mut self, dst: R, src: R, amount: Operand<R>);
+}
+
+pub trait BitfieldBuilder<R: Register> {
+    /// Extracts `width` bits starting at `lsb`, zero-extending
+    fn ubfx(&mut self, dst: R, src: R, lsb: u8, width: u8);
+    /// Extracts `width` bits starting at `lsb`, sign-extending
+    fn sbfx(&mut self, dst: R, src: R, lsb: u8, width: u8);
+    /// Inserts the low `width` bits of `src` at `lsb`, keeping the other bits of `dst`
+    fn bfi(&mut self, dst: R, src: R, lsb: u8, width: u8);
+    /// Places the low `width` bits of `src` at `lsb`, zeroing the other bits
+    fn ubfiz(&mut self, dst: R, src: R, lsb: u8, width: u8);
+    /// Extracts a register's worth of bits from `src1:src2` starting at `lsb`
+    fn extr(&mut self, dst: R, src1: R, src2: R, lsb: u8);
+}
+
+pub trait BitBuilder<R: Register> {
+    fn clz(&mut self, dst: R, src: R);
+    fn cls(&mut self, dst: R, src: R);
+    fn rbit(&mut self, dst: R, src: R);
+    fn rev(&mut self, dst: R, src: R);
+    fn rev16(&mut self, dst: R, src: R);
+    fn rev32(&mut self, dst: R, src: R);
+}
+
+pub trait CompareBuilder<R: Register> {
+    fn cmp(&mut self, src1: R, src2: Operand<R>);
+    fn cset(&mut self, dst: R, cond: Condition);
+    fn csel(&mut self, dst: R, src1: R, src2: R, cond: Condition);
 }
 
 pub trait BranchBuilder<R: Register> {
@@ -143,18 +691,44 @@ pub trait BranchBuilder<R: Register> {
     fn b(&mut self, label: &str);
     fn ret(&mut self);
     fn cbz(&mut self, reg: R, label: &str);
+    fn cbnz(&mut self, reg: R, label: &str);
+    fn b_cond(&mut self, cond: Condition, label: &str);
+    fn br(&mut self, reg: R);
+    fn blr(&mut self, reg: R);
+}
+
+pub trait LabelBuilder {
+    /// Defines `name` at the current position in the instruction stream.
+    fn label(&mut self, name: &str);
+}
+
+pub trait JumpTableBuilder {
+    /// Places `table` in read-only data, holding one 32-bit `target - table`
+    /// offset per entry of `targets`.
+    fn jump_table(&mut self, table: &str, targets: &[String]);
 }
 
 pub trait LoadStoreBuilder<R: Register> {
     fn str(&mut self, src: R, addr: &str);
     fn ldr(&mut self, dst: R, addr: &str);
+    /// Loads `size` bytes into `dst`, sign-extending when `signed` is set.
+    fn load(&mut self, size: MemSize, signed: bool, dst: R, addr: MemOperand<R>);
+    /// Stores the low `size` bytes of `src`.
+    fn store(&mut self, size: MemSize, src: R, addr: MemOperand<R>);
+    fn ldp(&mut self, dst1: R, dst2: R, addr: MemOperand<R>);
+    fn stp(&mut self, src1: R, src2: R, addr: MemOperand<R>);
 }
 
 pub trait MovBuilder<R: Register> {
     fn mov(&mut self, dst: R, src: R);
+    /// Materializes an arbitrary 64-bit constant in `dst`.
+    fn mov_imm(&mut self, dst: R, imm: u64);
 }
 
 pub trait AddressBuilder<R: Register> {
+    fn adr(&mut self, dst: R, label: &str);
     fn adrp(&mut self, dst: R, label: &str);
     fn adrp_add(&mut self, dst: R, base: R, label: &str);
+    /// Loads the address of `label` from the global offset table.
+    fn adrp_got(&mut self, dst: R, label: &str);
 }
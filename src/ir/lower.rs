@@ -0,0 +1,412 @@
+//! Instruction selection from the SSA IR onto the backend builder traits.
+//!
+//! Every value lives in its own 8-byte stack slot and is loaded into scratch
+//! registers around each operation. Narrow integers are stored at their own
+//! width and re-extended on every read, so slots never need normalising. Phis
+//! get a second "shadow" slot that predecessors write before branching; the
+//! phi's block copies it into place on entry, which sidesteps the
+//! parallel-copy problem.
+//!
+//! Floating-point values are kept in their slots as raw bits and moved
+//! into D16/D17 (or their `s` views) only for the operation itself.
+
+use super::*;
+use crate::instruction::*;
+use std::collections::HashMap;
+
+/// Integer arguments are passed in X0..X7 and floating-point ones in V0..V7.
+const MAX_ARGS: usize = 8;
+
+#[derive(Debug, PartialEq)]
+pub enum LowerError {
+    TooManyArguments(String),
+    FrameTooLarge(u32),
+}
+
+pub fn lower_function<A, R>(func: &Function, arch: &mut A) -> Result<(), LowerError>
+where
+    A: ArithmeticBuilder<R> + FloatBuilder<R> + CompareBuilder<R> + BranchBuilder<R> + LoadStoreBuilder<R> + MovBuilder<R> + LabelBuilder,
+    R: Register,
+    GenericRegister: RegisterMapping<R>,
+{
+    Lowering::new(func, arch)?.run()
+}
+
+struct Lowering<'a, A, R> {
+    func: &'a Function,
+    arch: &'a mut A,
+    slots: HashMap<Value, u32>,
+    shadow_slots: HashMap<Value, u32>,
+    frame_size: u32,
+    _phantom: std::marker::PhantomData<R>,
+}
+
+impl<'a, A, R> Lowering<'a, A, R>
+where
+    A: ArithmeticBuilder<R> + FloatBuilder<R> + CompareBuilder<R> + BranchBuilder<R> + LoadStoreBuilder<R> + MovBuilder<R> + LabelBuilder,
+    R: Register,
+    GenericRegister: RegisterMapping<R>,
+{
+    fn new(func: &'a Function, arch: &'a mut A) -> Result<Self, LowerError> {
+        if arg_registers::<R>(&func.params).iter().any(|reg| reg.is_none()) {
+            return Err(LowerError::TooManyArguments(func.name.clone()));
+        }
+
+        let mut slots = HashMap::new();
+        let mut shadow_slots = HashMap::new();
+        let mut next_slot = 0;
+        for block in func.blocks() {
+            for value in &func.block(block).insts {
+                let inst = func.inst(*value);
+                if inst.ty.is_none() {
+                    continue;
+                }
+                slots.insert(*value, next_slot);
+                next_slot += 8;
+                if let InstKind::Phi(_) = inst.kind {
+                    shadow_slots.insert(*value, next_slot);
+                    next_slot += 8;
+                }
+            }
+        }
+
+        let frame_size = (next_slot + 15) & !15;
+        if frame_size > 4095 {
+            return Err(LowerError::FrameTooLarge(frame_size));
+        }
+
+        Ok(Self {
+            func,
+            arch,
+            slots,
+            shadow_slots,
+            frame_size,
+            _phantom: std::marker::PhantomData,
+        })
+    }
+
+    fn run(mut self) -> Result<(), LowerError> {
+        self.prologue();
+        for block in self.func.blocks() {
+            self.arch.label(&self.block_label(block));
+            let data = self.func.block(block);
+            for value in &data.insts {
+                self.lower_inst(*value)?;
+            }
+            if let Some(terminator) = &data.terminator {
+                self.lower_terminator(block, terminator)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn prologue(&mut self) {
+        let (fp, lr, sp) = (reg(GenericRegister::X29), reg(GenericRegister::X30), reg(GenericRegister::SP));
+        self.arch.label(&self.func.name);
+        self.arch.stp(fp, lr, MemOperand::PreIndex(sp, -16));
+        self.arch.add(fp, sp, Operand::Immediate("0".to_string()));
+        if self.frame_size > 0 {
+            self.arch.add(sp, sp, Operand::Immediate(format!("-{}", self.frame_size)));
+        }
+        let x9 = reg(GenericRegister::X9);
+        for (index, arg) in arg_registers(&self.func.params).into_iter().enumerate() {
+            let slot = self.slots[&Value(index as u32)] as i64;
+            let ty = self.func.params[index];
+            match arg.expect("checked in new") {
+                ArgRegister::General(gpr) => {
+                    self.arch.store(MemSize::Double, gpr, MemOperand::Offset(sp, slot));
+                }
+                ArgRegister::Float(fpr) => {
+                    self.arch.fmov(fp_size(ty), x9, fpr);
+                    self.arch.store(mem_size(ty), x9, MemOperand::Offset(sp, slot));
+                }
+            }
+        }
+    }
+
+    fn epilogue(&mut self) {
+        let (fp, lr, sp) = (reg(GenericRegister::X29), reg(GenericRegister::X30), reg(GenericRegister::SP));
+        if self.frame_size > 0 {
+            self.arch.add(sp, sp, Operand::Immediate(self.frame_size.to_string()));
+        }
+        self.arch.ldp(fp, lr, MemOperand::PostIndex(sp, 16));
+        self.arch.ret();
+    }
+
+    fn lower_inst(&mut self, value: Value) -> Result<(), LowerError> {
+        let (x9, x10) = (reg(GenericRegister::X9), reg(GenericRegister::X10));
+        let inst = self.func.inst(value);
+        match &inst.kind {
+            // Parameters are spilled by the prologue
+            InstKind::Param(_) => {}
+            InstKind::Const(imm) => {
+                self.arch.mov_imm(x9, *imm as u64);
+                self.store_value(value, x9);
+            }
+            InstKind::FConst(imm) => {
+                let bits = match inst.ty {
+                    Some(Type::F32) => (*imm as f32).to_bits() as u64,
+                    _ => imm.to_bits(),
+                };
+                self.arch.mov_imm(x9, bits);
+                self.store_value(value, x9);
+            }
+            InstKind::Binary(op, lhs, rhs) if inst.ty.is_some_and(|ty| ty.is_float()) => {
+                let size = fp_size(inst.ty.unwrap());
+                let (d16, d17) = self.load_float_operands(*lhs, *rhs);
+                match op {
+                    BinaryOp::Add => self.arch.fadd(size, d16, d16, d17),
+                    BinaryOp::Sub => self.arch.fsub(size, d16, d16, d17),
+                    BinaryOp::Mul => self.arch.fmul(size, d16, d16, d17),
+                    BinaryOp::Div => self.arch.fdiv(size, d16, d16, d17),
+                    BinaryOp::UDiv => unreachable!("rejected by the verifier"),
+                }
+                self.arch.fmov(size, x9, d16);
+                self.store_value(value, x9);
+            }
+            InstKind::Cmp(pred, lhs, rhs) if self.func.value_type(*lhs).is_some_and(|ty| ty.is_float()) => {
+                let size = fp_size(self.func.value_type(*lhs).unwrap());
+                let (d16, d17) = self.load_float_operands(*lhs, *rhs);
+                self.arch.fcmp(size, d16, d17);
+                self.arch.cset(x9, float_condition(*pred));
+                self.store_value(value, x9);
+            }
+            InstKind::Binary(op, lhs, rhs) => {
+                let unsigned = *op == BinaryOp::UDiv;
+                self.load_value(*lhs, x9, unsigned);
+                self.load_value(*rhs, x10, unsigned);
+                match op {
+                    BinaryOp::Add => self.arch.add(x9, x9, Operand::Register(x10)),
+                    BinaryOp::Sub => self.arch.sub(x9, x9, x10),
+                    BinaryOp::Mul => self.arch.mul(x9, x9, x10),
+                    BinaryOp::Div => self.arch.sdiv(x9, x9, x10),
+                    BinaryOp::UDiv => self.arch.udiv(x9, x9, x10),
+                }
+                self.store_value(value, x9);
+            }
+            InstKind::Cmp(pred, lhs, rhs) => {
+                self.load_value(*lhs, x9, pred.is_unsigned());
+                self.load_value(*rhs, x10, pred.is_unsigned());
+                self.arch.cmp(x9, Operand::Register(x10));
+                self.arch.cset(x9, condition(*pred));
+                self.store_value(value, x9);
+            }
+            InstKind::Load(ptr) => {
+                let ty = inst.ty.expect("load produces a value");
+                self.load_value(*ptr, x10, false);
+                self.arch.load(mem_size(ty), ty.bytes() < 8, x9, MemOperand::Offset(x10, 0));
+                self.store_value(value, x9);
+            }
+            InstKind::Store(stored, ptr) => {
+                let ty = self.func.value_type(*stored).expect("verified store operand");
+                self.load_value(*stored, x9, false);
+                self.load_value(*ptr, x10, false);
+                self.arch.store(mem_size(ty), x9, MemOperand::Offset(x10, 0));
+            }
+            InstKind::Call(callee, args) => {
+                let types: Vec<Type> = args.iter().map(|arg| self.func.value_type(*arg).expect("verified argument")).collect();
+                let registers = arg_registers(&types);
+                for (arg, register) in args.iter().zip(registers) {
+                    match register {
+                        Some(ArgRegister::General(gpr)) => self.load_value(*arg, gpr, false),
+                        Some(ArgRegister::Float(fpr)) => {
+                            self.load_value(*arg, x9, true);
+                            self.arch.fmov(fp_size(self.func.value_type(*arg).unwrap()), fpr, x9);
+                        }
+                        None => return Err(LowerError::TooManyArguments(callee.clone())),
+                    }
+                }
+                self.arch.bl(callee);
+                match inst.ty {
+                    Some(ty) if ty.is_float() => {
+                        self.arch.fmov(fp_size(ty), x9, reg(GenericRegister::V0));
+                        self.store_value(value, x9);
+                    }
+                    Some(_) => self.store_value(value, reg(GenericRegister::X0)),
+                    None => {}
+                }
+            }
+            InstKind::Phi(_) => {
+                let sp = reg(GenericRegister::SP);
+                let shadow = self.shadow_slots[&value];
+                self.arch.load(MemSize::Double, false, x9, MemOperand::Offset(sp, shadow as i64));
+                self.store_value(value, x9);
+            }
+        }
+        Ok(())
+    }
+
+    fn lower_terminator(&mut self, block: Block, terminator: &Terminator) -> Result<(), LowerError> {
+        let x9 = reg(GenericRegister::X9);
+        for successor in terminator.successors() {
+            self.write_phi_shadows(block, successor);
+        }
+        match terminator {
+            Terminator::Br(target) => self.arch.b(&self.block_label(*target)),
+            Terminator::CondBr(cond, then_block, else_block) => {
+                self.load_value(*cond, x9, true);
+                self.arch.cbnz(x9, &self.block_label(*then_block));
+                self.arch.b(&self.block_label(*else_block));
+            }
+            Terminator::Ret(value) => {
+                match value.map(|value| (value, self.func.value_type(value).expect("verified return"))) {
+                    Some((value, ty)) if ty.is_float() => {
+                        self.load_value(value, x9, true);
+                        self.arch.fmov(fp_size(ty), reg(GenericRegister::V0), x9);
+                    }
+                    Some((value, _)) => self.load_value(value, reg(GenericRegister::X0), false),
+                    None => {}
+                }
+                self.epilogue();
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores the values flowing from `from` into the shadow slots of the
+    /// phis at the top of `to`.
+    fn write_phi_shadows(&mut self, from: Block, to: Block) {
+        let (x9, sp) = (reg(GenericRegister::X9), reg(GenericRegister::SP));
+        for value in &self.func.block(to).insts {
+            let InstKind::Phi(incoming) = &self.func.inst(*value).kind else { continue };
+            let Some((_, source)) = incoming.iter().find(|(pred, _)| *pred == from) else { continue };
+            self.load_value(*source, x9, false);
+            let shadow = self.shadow_slots[value];
+            self.arch.store(MemSize::Double, x9, MemOperand::Offset(sp, shadow as i64));
+        }
+    }
+
+    /// Loads `value` into `dst`, sign- or zero-extending narrow integers.
+    /// Floating-point values are loaded as their raw bits.
+    fn load_value(&mut self, value: Value, dst: R, unsigned: bool) {
+        let ty = self.func.value_type(value).expect("operand has a value");
+        let sp = reg(GenericRegister::SP);
+        let slot = self.slots[&value] as i64;
+        let signed = ty.is_integer() && ty.bytes() < 8 && !unsigned;
+        self.arch.load(mem_size(ty), signed, dst, MemOperand::Offset(sp, slot));
+    }
+
+    /// Moves two floating-point operands into D16 and D17 by way of X9/X10.
+    fn load_float_operands(&mut self, lhs: Value, rhs: Value) -> (R, R) {
+        let (x9, x10) = (reg(GenericRegister::X9), reg(GenericRegister::X10));
+        let (d16, d17) = (reg(GenericRegister::V16), reg(GenericRegister::V17));
+        let size = fp_size(self.func.value_type(lhs).expect("operand has a value"));
+        self.load_value(lhs, x9, true);
+        self.load_value(rhs, x10, true);
+        self.arch.fmov(size, d16, x9);
+        self.arch.fmov(size, d17, x10);
+        (d16, d17)
+    }
+
+    fn store_value(&mut self, value: Value, src: R) {
+        let ty = self.func.value_type(value).expect("result has a value");
+        let sp = reg(GenericRegister::SP);
+        let slot = self.slots[&value] as i64;
+        self.arch.store(mem_size(ty), src, MemOperand::Offset(sp, slot));
+    }
+
+    fn block_label(&self, block: Block) -> String {
+        format!("L{}_bb{}", self.func.name, block.index())
+    }
+}
+
+fn reg<R: Register>(reg: GenericRegister) -> R
+where
+    GenericRegister: RegisterMapping<R>,
+{
+    reg.to_arch_reg()
+}
+
+enum ArgRegister<R> {
+    General(R),
+    Float(R),
+}
+
+/// Assigns argument registers, counting integer and floating-point
+/// arguments separately. `None` marks an argument that would need the stack.
+fn arg_registers<R: Register>(types: &[Type]) -> Vec<Option<ArgRegister<R>>>
+where
+    GenericRegister: RegisterMapping<R>,
+{
+    const GPRS: [GenericRegister; MAX_ARGS] = [
+        GenericRegister::X0,
+        GenericRegister::X1,
+        GenericRegister::X2,
+        GenericRegister::X3,
+        GenericRegister::X4,
+        GenericRegister::X5,
+        GenericRegister::X6,
+        GenericRegister::X7,
+    ];
+    const FPRS: [GenericRegister; MAX_ARGS] = [
+        GenericRegister::V0,
+        GenericRegister::V1,
+        GenericRegister::V2,
+        GenericRegister::V3,
+        GenericRegister::V4,
+        GenericRegister::V5,
+        GenericRegister::V6,
+        GenericRegister::V7,
+    ];
+    let (mut next_gpr, mut next_fpr) = (0, 0);
+    types
+        .iter()
+        .map(|ty| {
+            if ty.is_float() {
+                next_fpr += 1;
+                FPRS.get(next_fpr - 1).map(|fpr| ArgRegister::Float(fpr.to_arch_reg()))
+            } else {
+                next_gpr += 1;
+                GPRS.get(next_gpr - 1).map(|gpr| ArgRegister::General(gpr.to_arch_reg()))
+            }
+        })
+        .collect()
+}
+
+fn mem_size(ty: Type) -> MemSize {
+    match ty.bytes() {
+        1 => MemSize::Byte,
+        2 => MemSize::Half,
+        4 => MemSize::Word,
+        _ => MemSize::Double,
+    }
+}
+
+fn fp_size(ty: Type) -> FpSize {
+    match ty {
+        Type::F32 => FpSize::Single,
+        _ => FpSize::Double,
+    }
+}
+
+/// Conditions after `fcmp`. The signed predicates are ordered and false
+/// when either operand is NaN; the unsigned ones also hold when unordered.
+fn float_condition(pred: CmpPred) -> Condition {
+    match pred {
+        CmpPred::Eq => Condition::Eq,
+        CmpPred::Ne => Condition::Ne,
+        CmpPred::Slt => Condition::Mi,
+        CmpPred::Sle => Condition::Ls,
+        CmpPred::Sgt => Condition::Gt,
+        CmpPred::Sge => Condition::Ge,
+        CmpPred::Ult => Condition::Lt,
+        CmpPred::Ule => Condition::Le,
+        CmpPred::Ugt => Condition::Hi,
+        CmpPred::Uge => Condition::Pl,
+    }
+}
+
+fn condition(pred: CmpPred) -> Condition {
+    match pred {
+        CmpPred::Eq => Condition::Eq,
+        CmpPred::Ne => Condition::Ne,
+        CmpPred::Slt => Condition::Lt,
+        CmpPred::Sle => Condition::Le,
+        CmpPred::Sgt => Condition::Gt,
+        CmpPred::Sge => Condition::Ge,
+        CmpPred::Ult => Condition::Lo,
+        CmpPred::Ule => Condition::Ls,
+        CmpPred::Ugt => Condition::Hi,
+        CmpPred::Uge => Condition::Hs,
+    }
+}
@@ -0,0 +1,561 @@
+//! A small typed SSA intermediate representation.
+//!
+//! Frontends build [`Function`]s with a [`FunctionBuilder`] and hand them to
+//! [`lower::lower_function`], which selects instructions for any backend that
+//! implements the builder traits in [`crate::instruction`].
+
+pub mod lower;
+
+pub use lower::{lower_function, LowerError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    Ptr,
+}
+
+impl Type {
+    pub fn bytes(&self) -> u32 {
+        match self {
+            Self::I8 => 1,
+            Self::I16 => 2,
+            Self::I32 | Self::F32 => 4,
+            Self::I64 | Self::F64 | Self::Ptr => 8,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Self::F32 | Self::F64)
+    }
+
+    /// Integers and pointers, i.e. everything held in general purpose registers.
+    pub fn is_integer(&self) -> bool {
+        !self.is_float()
+    }
+}
+
+/// An SSA value: the result of one instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Value(u32);
+
+impl Value {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Block(u32);
+
+impl Block {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    /// Signed integer or floating-point division
+    Div,
+    /// Unsigned integer division
+    UDiv,
+}
+
+/// Comparison predicates. The signed forms are also used for ordered
+/// floating-point comparisons.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CmpPred {
+    Eq,
+    Ne,
+    Slt,
+    Sle,
+    Sgt,
+    Sge,
+    Ult,
+    Ule,
+    Ugt,
+    Uge,
+}
+
+impl CmpPred {
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, Self::Ult | Self::Ule | Self::Ugt | Self::Uge)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstKind {
+    Param(usize),
+    Const(i64),
+    FConst(f64),
+    Binary(BinaryOp, Value, Value),
+    /// Produces an `i8` that is 1 when the predicate holds and 0 otherwise
+    Cmp(CmpPred, Value, Value),
+    Load(Value),
+    /// Stores the first value through the pointer in the second
+    Store(Value, Value),
+    Call(String, Vec<Value>),
+    Phi(Vec<(Block, Value)>),
+}
+
+impl InstKind {
+    /// The values the instruction reads, including a phi's incoming ones.
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            Self::Param(_) | Self::Const(_) | Self::FConst(_) => Vec::new(),
+            Self::Binary(_, lhs, rhs) | Self::Cmp(_, lhs, rhs) | Self::Store(lhs, rhs) => vec![*lhs, *rhs],
+            Self::Load(ptr) => vec![*ptr],
+            Self::Call(_, args) => args.clone(),
+            Self::Phi(incoming) => incoming.iter().map(|(_, value)| *value).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Br(Block),
+    CondBr(Value, Block, Block),
+    Ret(Option<Value>),
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<Block> {
+        match self {
+            Self::Br(target) => vec![*target],
+            Self::CondBr(_, then_block, else_block) => vec![*then_block, *else_block],
+            Self::Ret(_) => Vec::new(),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            Self::CondBr(cond, _, _) => vec![*cond],
+            Self::Ret(Some(value)) => vec![*value],
+            Self::Br(_) | Self::Ret(None) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InstData {
+    pub kind: InstKind,
+    /// `None` for instructions that produce no value (stores, void calls)
+    pub ty: Option<Type>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BlockData {
+    pub insts: Vec<Value>,
+    pub terminator: Option<Terminator>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Type>,
+    pub ret: Option<Type>,
+    insts: Vec<InstData>,
+    blocks: Vec<BlockData>,
+}
+
+impl Function {
+    pub fn entry_block(&self) -> Block {
+        Block(0)
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = Block> {
+        (0..self.blocks.len() as u32).map(Block)
+    }
+
+    pub fn block(&self, block: Block) -> &BlockData {
+        &self.blocks[block.index()]
+    }
+
+    pub fn inst(&self, value: Value) -> &InstData {
+        &self.insts[value.index()]
+    }
+
+    pub fn value_type(&self, value: Value) -> Option<Type> {
+        self.insts[value.index()].ty
+    }
+
+    pub fn num_values(&self) -> usize {
+        self.insts.len()
+    }
+
+    pub fn predecessors(&self, block: Block) -> Vec<Block> {
+        self.blocks()
+            .filter(|b| {
+                self.block(*b)
+                    .terminator
+                    .as_ref()
+                    .is_some_and(|t| t.successors().contains(&block))
+            })
+            .collect()
+    }
+
+    fn verify(&self) -> Result<(), IrError> {
+        let dominance = self.dominance();
+        for block in self.blocks() {
+            let data = self.block(block);
+            for (position, value) in data.insts.iter().enumerate() {
+                self.verify_uses(block, position, *value, &dominance)?;
+                self.verify_inst(block, *value)?;
+            }
+            if let Some(terminator) = &data.terminator {
+                for operand in terminator.operands() {
+                    dominance.check(self, operand, block, data.insts.len())?;
+                }
+            }
+            match &data.terminator {
+                None => return Err(IrError::UnterminatedBlock(block)),
+                Some(Terminator::CondBr(cond, _, _)) => self.expect_type(*cond, Type::I8)?,
+                Some(Terminator::Ret(value)) => {
+                    let ty = value.and_then(|v| self.value_type(v));
+                    if ty != self.ret {
+                        return Err(IrError::TypeMismatch(format!(
+                            "{} returns {:?} but the function returns {:?}",
+                            self.name, ty, self.ret
+                        )));
+                    }
+                }
+                Some(Terminator::Br(_)) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that every operand of `value` is a value of this function
+    /// defined before the use: earlier in the same block or in a block
+    /// dominating it. A phi uses each incoming value at the end of its
+    /// predecessor.
+    fn verify_uses(&self, block: Block, position: usize, value: Value, dominance: &Dominance) -> Result<(), IrError> {
+        match &self.inst(value).kind {
+            InstKind::Phi(incoming) => {
+                for (pred, incoming_value) in incoming {
+                    if pred.index() >= self.blocks.len() {
+                        return Err(IrError::InvalidPhi(value, *pred));
+                    }
+                    dominance.check(self, *incoming_value, *pred, self.block(*pred).insts.len())?;
+                }
+            }
+            kind => {
+                for operand in kind.operands() {
+                    dominance.check(self, operand, block, position)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The defining position of every value and the dominators of every
+    /// block reachable from the entry, by iterating to a fixed point.
+    fn dominance(&self) -> Dominance {
+        let mut defs = vec![(self.entry_block(), 0); self.insts.len()];
+        for block in self.blocks() {
+            for (position, value) in self.block(block).insts.iter().enumerate() {
+                defs[value.index()] = (block, position);
+            }
+        }
+
+        let count = self.blocks.len();
+        let mut reachable = vec![false; count];
+        let mut stack = vec![self.entry_block()];
+        while let Some(block) = stack.pop() {
+            if block.index() >= count || reachable[block.index()] {
+                continue;
+            }
+            reachable[block.index()] = true;
+            if let Some(terminator) = &self.block(block).terminator {
+                stack.extend(terminator.successors());
+            }
+        }
+
+        let mut dominators: Vec<Option<Vec<bool>>> =
+            reachable.iter().map(|reachable| reachable.then(|| vec![true; count])).collect();
+        if let Some(entry) = &mut dominators[0] {
+            entry.fill(false);
+            entry[0] = true;
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in self.blocks().skip(1).filter(|block| reachable[block.index()]) {
+                let mut new = vec![true; count];
+                for pred in self.predecessors(block) {
+                    if let Some(pred_dominators) = &dominators[pred.index()] {
+                        for (dominates, pred_dominates) in new.iter_mut().zip(pred_dominators) {
+                            *dominates &= *pred_dominates;
+                        }
+                    }
+                }
+                new[block.index()] = true;
+                if dominators[block.index()].as_ref() != Some(&new) {
+                    dominators[block.index()] = Some(new);
+                    changed = true;
+                }
+            }
+        }
+        Dominance { defs, dominators }
+    }
+
+    fn verify_inst(&self, block: Block, value: Value) -> Result<(), IrError> {
+        let inst = self.inst(value);
+        match &inst.kind {
+            InstKind::Binary(op, lhs, rhs) => {
+                let ty = self.same_type(*lhs, *rhs)?;
+                if ty == Type::Ptr || (ty.is_float() && *op == BinaryOp::UDiv) {
+                    return Err(IrError::InvalidOperand(format!("{:?} on {:?}", op, ty)));
+                }
+            }
+            InstKind::Cmp(_, lhs, rhs) => {
+                self.same_type(*lhs, *rhs)?;
+            }
+            InstKind::Load(ptr) => self.expect_type(*ptr, Type::Ptr)?,
+            InstKind::Store(value, ptr) => {
+                if self.value_type(*value).is_none() {
+                    return Err(IrError::InvalidOperand(format!("{:?} has no value to store", value)));
+                }
+                self.expect_type(*ptr, Type::Ptr)?;
+            }
+            InstKind::Call(_, args) => {
+                if args.iter().any(|arg| self.value_type(*arg).is_none()) {
+                    return Err(IrError::InvalidOperand("call argument has no value".to_string()));
+                }
+            }
+            InstKind::Phi(incoming) => {
+                let preds = self.predecessors(block);
+                for (pred, incoming_value) in incoming {
+                    if !preds.contains(pred) {
+                        return Err(IrError::InvalidPhi(value, *pred));
+                    }
+                    if self.value_type(*incoming_value) != inst.ty {
+                        return Err(IrError::TypeMismatch(format!(
+                            "phi {:?} has incoming {:?} of a different type",
+                            value, incoming_value
+                        )));
+                    }
+                }
+                if preds.iter().any(|pred| !incoming.iter().any(|(b, _)| b == pred)) {
+                    return Err(IrError::InvalidPhi(value, block));
+                }
+            }
+            InstKind::Param(_) | InstKind::Const(_) | InstKind::FConst(_) => {}
+        }
+        Ok(())
+    }
+
+    fn same_type(&self, lhs: Value, rhs: Value) -> Result<Type, IrError> {
+        match (self.value_type(lhs), self.value_type(rhs)) {
+            (Some(a), Some(b)) if a == b => Ok(a),
+            (a, b) => Err(IrError::TypeMismatch(format!(
+                "{:?} is {:?} but {:?} is {:?}",
+                lhs, a, rhs, b
+            ))),
+        }
+    }
+
+    fn expect_type(&self, value: Value, ty: Type) -> Result<(), IrError> {
+        match self.value_type(value) {
+            Some(actual) if actual == ty => Ok(()),
+            actual => Err(IrError::TypeMismatch(format!(
+                "expected {:?} to be {:?}, found {:?}",
+                value, ty, actual
+            ))),
+        }
+    }
+}
+
+/// Where values are defined and which blocks dominate which, for
+/// [`Function::verify`].
+struct Dominance {
+    /// The block of each value and its position there
+    defs: Vec<(Block, usize)>,
+    /// Whether each block dominates the indexed one, or `None` when the
+    /// indexed block is unreachable
+    dominators: Vec<Option<Vec<bool>>>,
+}
+
+impl Dominance {
+    /// Checks a use of `value` before the instruction at `position` in
+    /// `block`. Uses in unreachable blocks never execute and pass.
+    fn check(&self, func: &Function, value: Value, block: Block, position: usize) -> Result<(), IrError> {
+        if value.index() >= func.insts.len() {
+            return Err(IrError::ForeignValue(value));
+        }
+        let Some(dominators) = &self.dominators[block.index()] else {
+            return Ok(());
+        };
+        let (def_block, def_position) = self.defs[value.index()];
+        let dominates = match def_block == block {
+            true => def_position < position,
+            false => dominators[def_block.index()],
+        };
+        match dominates {
+            true => Ok(()),
+            false => Err(IrError::UndominatedUse(value, block)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum IrError {
+    TypeMismatch(String),
+    InvalidOperand(String),
+    UnterminatedBlock(Block),
+    /// A phi's incoming edges do not match the predecessors of its block
+    InvalidPhi(Value, Block),
+    /// An operand that is not a value of the function being verified
+    ForeignValue(Value),
+    /// A value used in a block its definition does not dominate, or
+    /// earlier in the block that defines it
+    UndominatedUse(Value, Block),
+}
+
+pub struct FunctionBuilder {
+    func: Function,
+    current: Block,
+}
+
+impl FunctionBuilder {
+    pub fn new(name: &str, params: &[Type], ret: Option<Type>) -> Self {
+        let mut builder = Self {
+            func: Function {
+                name: name.to_string(),
+                params: params.to_vec(),
+                ret,
+                insts: Vec::new(),
+                blocks: vec![BlockData::default()],
+            },
+            current: Block(0),
+        };
+        for (index, ty) in params.iter().enumerate() {
+            builder.push(InstKind::Param(index), Some(*ty));
+        }
+        builder
+    }
+
+    pub fn param(&self, index: usize) -> Value {
+        assert!(index < self.func.params.len(), "{} has no parameter {}", self.func.name, index);
+        Value(index as u32)
+    }
+
+    pub fn create_block(&mut self) -> Block {
+        self.func.blocks.push(BlockData::default());
+        Block(self.func.blocks.len() as u32 - 1)
+    }
+
+    pub fn switch_to_block(&mut self, block: Block) {
+        self.current = block;
+    }
+
+    pub fn current_block(&self) -> Block {
+        self.current
+    }
+
+    pub fn value_type(&self, value: Value) -> Option<Type> {
+        self.func.value_type(value)
+    }
+
+    pub fn iconst(&mut self, ty: Type, value: i64) -> Value {
+        self.push(InstKind::Const(value), Some(ty))
+    }
+
+    pub fn fconst(&mut self, ty: Type, value: f64) -> Value {
+        self.push(InstKind::FConst(value), Some(ty))
+    }
+
+    pub fn binary(&mut self, op: BinaryOp, lhs: Value, rhs: Value) -> Value {
+        let ty = self.value_type(lhs);
+        self.push(InstKind::Binary(op, lhs, rhs), ty)
+    }
+
+    pub fn add(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.binary(BinaryOp::Add, lhs, rhs)
+    }
+
+    pub fn sub(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.binary(BinaryOp::Sub, lhs, rhs)
+    }
+
+    pub fn mul(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.binary(BinaryOp::Mul, lhs, rhs)
+    }
+
+    pub fn div(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.binary(BinaryOp::Div, lhs, rhs)
+    }
+
+    pub fn udiv(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.binary(BinaryOp::UDiv, lhs, rhs)
+    }
+
+    pub fn cmp(&mut self, pred: CmpPred, lhs: Value, rhs: Value) -> Value {
+        self.push(InstKind::Cmp(pred, lhs, rhs), Some(Type::I8))
+    }
+
+    pub fn load(&mut self, ty: Type, ptr: Value) -> Value {
+        self.push(InstKind::Load(ptr), Some(ty))
+    }
+
+    pub fn store(&mut self, value: Value, ptr: Value) {
+        self.push(InstKind::Store(value, ptr), None);
+    }
+
+    /// Calls `callee`; the returned value has no type when `ret` is `None`.
+    pub fn call(&mut self, callee: &str, args: &[Value], ret: Option<Type>) -> Value {
+        self.push(InstKind::Call(callee.to_string(), args.to_vec()), ret)
+    }
+
+    /// Creates a phi with no incoming edges; add them with [`Self::add_incoming`]
+    /// once the predecessor values exist.
+    pub fn phi(&mut self, ty: Type) -> Value {
+        self.push(InstKind::Phi(Vec::new()), Some(ty))
+    }
+
+    pub fn add_incoming(&mut self, phi: Value, block: Block, value: Value) {
+        match &mut self.func.insts[phi.index()].kind {
+            InstKind::Phi(incoming) => incoming.push((block, value)),
+            other => panic!("{:?} is not a phi: {:?}", phi, other),
+        }
+    }
+
+    pub fn br(&mut self, target: Block) {
+        self.terminate(Terminator::Br(target));
+    }
+
+    pub fn cond_br(&mut self, cond: Value, then_block: Block, else_block: Block) {
+        self.terminate(Terminator::CondBr(cond, then_block, else_block));
+    }
+
+    pub fn ret(&mut self, value: Option<Value>) {
+        self.terminate(Terminator::Ret(value));
+    }
+
+    /// Verifies the function and returns it.
+    pub fn finish(self) -> Result<Function, IrError> {
+        self.func.verify()?;
+        Ok(self.func)
+    }
+
+    fn push(&mut self, kind: InstKind, ty: Option<Type>) -> Value {
+        let value = Value(self.func.insts.len() as u32);
+        self.func.insts.push(InstData { kind, ty });
+        self.func.blocks[self.current.index()].insts.push(value);
+        value
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        let block = &mut self.func.blocks[self.current.index()];
+        assert!(block.terminator.is_none(), "{:?} is already terminated", self.current);
+        block.terminator = Some(terminator);
+    }
+}
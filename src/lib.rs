@@ -5,10 +5,11 @@ pub mod compiler;
 pub mod context;
 pub mod builder;
 pub mod program;
+pub mod ir;
 
 pub use arch::arm64::ARM64;
 pub use instruction::GenericRegister;
-pub use builder::InstructionBuilder;
+pub use builder::{Cond, InstructionBuilder};
 pub use context::Context;
 pub use platform::Platform;
 pub use program::Program; 
\ No newline at end of file
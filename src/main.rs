@@ -1,5 +1,6 @@
 use asm_test::*;
 use asm_test::arch::arm64::ARM64;
+use asm_test::context::SectionKind;
 use asm_test::instruction::GenericRegister;
 use std::path::Path;
 use std::fs;
@@ -58,8 +59,8 @@ fn main() {
     let mut program = Program::new(ARM64::new());
 
 
-    let data_section = program.section("data");
-    let text_section = program.section("text");
+    let data_section = program.section("data", SectionKind::Data);
+    let text_section = program.section("text", SectionKind::Text);
     
     
     // Add string variable to data section
@@ -0,0 +1,59 @@
+use super::Platform;
+use crate::context::{Binding, SectionKind, Symbol, SymbolKind, Visibility};
+
+/// ELF targets assembled with GNU `as` or LLVM.
+pub struct Linux;
+
+impl Platform for Linux {
+    fn function_prefix(&self) -> &'static str { "" }
+    fn line_comment(&self) -> &'static str { "//" }
+    fn data_section(&self) -> &'static str { ".data" }
+    fn text_section(&self) -> &'static str { ".text" }
+    fn rodata_section(&self) -> &'static str { ".section .rodata" }
+    fn bss_section(&self) -> &'static str { ".bss" }
+
+    /// Without flags a new section is not allocated, so it would never be
+    /// loaded.
+    fn custom_section(&self, name: &str, kind: SectionKind) -> String {
+        match kind {
+            SectionKind::Text => format!(".section {},\"ax\",%progbits", name),
+            SectionKind::Rodata => format!(".section {},\"a\"", name),
+            SectionKind::Data => format!(".section {},\"aw\"", name),
+            SectionKind::Bss => format!(".section {},\"aw\",%nobits", name),
+        }
+    }
+
+    fn symbol_directives(&self, symbol: &Symbol) -> Vec<String> {
+        let name = &symbol.name;
+        let mut directives = Vec::new();
+        match (symbol.defined, symbol.binding) {
+            (false, Binding::Weak) => directives.push(format!(".weak {}", name)),
+            (false, _) => directives.push(format!(".extern {}", name)),
+            (true, Binding::Local) => {}
+            (true, Binding::Global) => directives.push(format!(".globl {}", name)),
+            (true, Binding::Weak) => directives.push(format!(".weak {}", name)),
+        }
+        match symbol.visibility {
+            Visibility::Default => {}
+            Visibility::Hidden => directives.push(format!(".hidden {}", name)),
+            Visibility::Protected => directives.push(format!(".protected {}", name)),
+        }
+        match symbol.kind {
+            Some(SymbolKind::Func) => directives.push(format!(".type {}, %function", name)),
+            Some(SymbolKind::Object) => directives.push(format!(".type {}, %object", name)),
+            None => {}
+        }
+        if let Some(size) = symbol.size {
+            directives.push(format!(".size {}, {}", name, size));
+        }
+        directives
+    }
+
+    fn page_operands(&self, label: &str) -> (String, String) {
+        (label.to_string(), format!(":lo12:{}", label))
+    }
+
+    fn got_page_operands(&self, label: &str) -> (String, String) {
+        (format!(":got:{}", label), format!(":got_lo12:{}", label))
+    }
+}
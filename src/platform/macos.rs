@@ -1,4 +1,5 @@
 use super::Platform;
+use crate::context::{Binding, SectionKind, Symbol, Visibility};
 
 pub struct MacOS;
 
@@ -7,4 +8,50 @@ impl Platform for MacOS {
     fn line_comment(&self) -> &'static str { "//" }
     fn data_section(&self) -> &'static str { ".section __DATA,__data" }
     fn text_section(&self) -> &'static str { ".section __TEXT,__text" }
-} 
\ No newline at end of file
+    fn rodata_section(&self) -> &'static str { ".section __TEXT,__const" }
+    fn bss_section(&self) -> &'static str { ".section __DATA,__bss" }
+
+    /// A name with its segment, such as `__DATA,__vectors`, is used as is;
+    /// otherwise read-only sections go in `__TEXT` and writable ones in
+    /// `__DATA`. Mach-O only makes zero-fill sections one symbol at a time
+    /// with `.zerofill`, so zero-filled ones hold their zeros in the file.
+    fn custom_section(&self, name: &str, kind: SectionKind) -> String {
+        if name.contains(',') {
+            return format!(".section {}", name);
+        }
+        match kind {
+            SectionKind::Text => format!(".section __TEXT,{},regular,pure_instructions", name),
+            SectionKind::Rodata => format!(".section __TEXT,{}", name),
+            SectionKind::Data | SectionKind::Bss => format!(".section __DATA,{}", name),
+        }
+    }
+
+    /// Mach-O records neither symbol types nor sizes, and has no protected
+    /// visibility, so those are left out.
+    fn symbol_directives(&self, symbol: &Symbol) -> Vec<String> {
+        let name = &symbol.name;
+        let mut directives = Vec::new();
+        match (symbol.defined, symbol.binding) {
+            (false, Binding::Weak) => directives.push(format!(".weak_reference {}", name)),
+            (false, _) => directives.push(format!(".extern {}", name)),
+            (true, Binding::Local) => {}
+            (true, Binding::Global) => directives.push(format!(".globl {}", name)),
+            (true, Binding::Weak) => {
+                directives.push(format!(".globl {}", name));
+                directives.push(format!(".weak_definition {}", name));
+            }
+        }
+        if symbol.defined && symbol.binding != Binding::Local && symbol.visibility == Visibility::Hidden {
+            directives.push(format!(".private_extern {}", name));
+        }
+        directives
+    }
+
+    fn page_operands(&self, label: &str) -> (String, String) {
+        (format!("{}@PAGE", label), format!("{}@PAGEOFF", label))
+    }
+
+    fn got_page_operands(&self, label: &str) -> (String, String) {
+        (format!("{}@GOTPAGE", label), format!("{}@GOTPAGEOFF", label))
+    }
+}
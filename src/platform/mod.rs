@@ -1,3 +1,6 @@
+use crate::context::{SectionKind, Symbol};
+
+pub mod linux;
 pub mod macos;
 
 pub trait Platform {
@@ -5,4 +8,17 @@ pub trait Platform {
     fn line_comment(&self) -> &'static str;
     fn data_section(&self) -> &'static str;
     fn text_section(&self) -> &'static str;
-} 
\ No newline at end of file
+    fn rodata_section(&self) -> &'static str;
+    fn bss_section(&self) -> &'static str;
+    /// The directive switching to the custom section `name`, loaded and
+    /// protected as `kind`.
+    fn custom_section(&self, name: &str, kind: SectionKind) -> String;
+    /// The directives that declare `symbol`, placed before its definition.
+    fn symbol_directives(&self, symbol: &Symbol) -> Vec<String>;
+    /// The `adrp` operand for the page of `label`, and the `add` operand
+    /// for its offset within that page.
+    fn page_operands(&self, label: &str) -> (String, String);
+    /// The same operands for the GOT entry of `label`, the second one
+    /// used as the `ldr` offset.
+    fn got_page_operands(&self, label: &str) -> (String, String);
+}
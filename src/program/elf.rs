@@ -0,0 +1,876 @@
+//! ELF executables for Linux.
+//!
+//! [`ElfExecutable`] writes loadable segments, the entry point and a symbol
+//! table for any machine, applying its relocations as it goes, so no
+//! external linker is needed. [`Program::elf`] and [`Module::elf`] fill one
+//! in for ARM64: text with the headers in front, read-only data from the
+//! next page, and `.data` followed by `.bss` on a writable page after that.
+//!
+//! A dynamically linked executable also names an interpreter and the
+//! shared libraries it needs. The writer adds a segment with the dynamic
+//! symbol table and the `GLOB_DAT` relocations filling GOT entries at
+//! start-up. Calls to imported functions go through PLT stubs that branch
+//! through those entries, so nothing is bound lazily.
+//!
+//! Programs built with the [`X86_64`] backend are written as static
+//! executables the same way, so on an x86_64 host their output can be run
+//! directly. ARM64 output is only checked structurally there. Dynamic
+//! linking and shared objects are only built for ARM64 programs; for other
+//! cases the segments are filled in by hand.
+
+use super::got::{self, Got};
+use super::{Module, Program};
+use crate::arch::arm64::encoder::{assemble, Assembled, EncodeError};
+use crate::arch::arm64::{Arm64Register, ARM64};
+use crate::arch::x86_64::{self, X86Register, X86_64};
+use crate::context::{Binding, Context, SectionKind, Sections, Symbol, SymbolKind, SymbolTable, Value, Variable, Visibility};
+use crate::instruction::BuildError;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Where executables are loaded, the usual base for non-PIE Linux programs.
+pub const LOAD_ADDRESS: u64 = 0x40_0000;
+const PAGE: u64 = 0x1000;
+
+const HEADER_SIZE: u64 = 64;
+const PROGRAM_HEADER_SIZE: u64 = 56;
+const SECTION_HEADER_SIZE: u64 = 64;
+const SYMBOL_SIZE: u64 = 24;
+const RELA_SIZE: u64 = 24;
+const DYNAMIC_SIZE: u64 = 16;
+
+/// Segment permissions, as in `p_flags`.
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Machine {
+    Aarch64,
+    X86_64,
+}
+
+impl Machine {
+    fn e_machine(&self) -> u16 {
+        match self {
+            Machine::Aarch64 => 183,
+            Machine::X86_64 => 62,
+        }
+    }
+
+    /// The relocation type setting a GOT entry to a symbol's address.
+    fn glob_dat(&self) -> u64 {
+        match self {
+            Machine::Aarch64 => 1025,
+            Machine::X86_64 => 6,
+        }
+    }
+
+    /// The relocation type adding the load address to a pointer.
+    fn relative(&self) -> u64 {
+        match self {
+            Machine::Aarch64 => 1027,
+            Machine::X86_64 => 8,
+        }
+    }
+}
+
+/// What the dynamic linker needs to load an executable or shared object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dynamic {
+    /// Path of the dynamic linker, written to `PT_INTERP` for executables
+    pub interpreter: Option<String>,
+    /// Shared libraries to load, in order
+    pub needed: Vec<String>,
+    /// The name a shared object is loaded by, as `DT_SONAME`
+    pub soname: Option<String>,
+}
+
+impl Dynamic {
+    /// The C library, loaded by the usual dynamic linker for `machine`.
+    pub fn libc(machine: Machine) -> Self {
+        let interpreter = match machine {
+            Machine::Aarch64 => "/lib/ld-linux-aarch64.so.1",
+            Machine::X86_64 => "/lib64/ld-linux-x86-64.so.2",
+        };
+        Self { interpreter: Some(interpreter.to_string()), needed: vec!["libc.so.6".to_string()], soname: None }
+    }
+
+    /// A shared object named `soname` that needs the C library.
+    pub fn shared(soname: &str) -> Self {
+        Self { interpreter: None, needed: vec!["libc.so.6".to_string()], soname: Some(soname.to_string()) }
+    }
+}
+
+/// A GOT entry the dynamic linker sets to the address of `symbol`.
+#[derive(Debug, Clone)]
+pub struct DynamicRelocation {
+    pub address: u64,
+    pub symbol: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ElfError {
+    /// Problems the builders recorded, reported before assembling
+    Build(Vec<BuildError>),
+    Encode(EncodeError),
+    UndefinedEntry(String),
+    /// A relocation refers to a symbol with no address
+    UndefinedSymbol(String),
+    /// A relocation at this address is outside every segment, or its value
+    /// does not fit
+    Relocation(u64),
+}
+
+/// A `PT_LOAD` segment. Memory past the end of `bytes` up to `mem_size` is
+/// zero-filled by the loader and written as a `.bss` section.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    /// The name of the section covering the file bytes
+    pub name: String,
+    pub vaddr: u64,
+    pub bytes: Vec<u8>,
+    pub mem_size: u64,
+    pub flags: u32,
+}
+
+impl Segment {
+    pub fn new(name: &str, vaddr: u64, bytes: Vec<u8>, flags: u32) -> Self {
+        let mem_size = bytes.len() as u64;
+        Self { name: name.to_string(), vaddr, bytes, mem_size, flags }
+    }
+
+    /// Extends the segment with `size` zero bytes that take no file space.
+    pub fn zero_fill(mut self, size: u64) -> Self {
+        self.mem_size += size;
+        self
+    }
+
+    fn contains(&self, addr: u64) -> bool {
+        addr >= self.vaddr && addr < self.vaddr + self.mem_size
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RelocationKind {
+    /// The 64-bit address of the symbol plus the addend
+    Abs64,
+    /// The 32-bit signed distance from the relocated field to the symbol
+    /// plus the addend, as `R_X86_64_PC32`
+    Pc32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Relocation {
+    /// Address of the field to patch
+    pub address: u64,
+    pub symbol: String,
+    pub addend: i64,
+    pub kind: RelocationKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct ElfExecutable {
+    pub machine: Machine,
+    pub entry: u64,
+    pub segments: Vec<Segment>,
+    /// Symbols with their addresses, written to `.symtab`
+    pub symbols: Vec<(Symbol, u64)>,
+    pub relocations: Vec<Relocation>,
+    /// Written as `ET_DYN`, a shared object loaded at any address
+    pub shared: bool,
+    /// Set for dynamically linked files
+    pub dynamic: Option<Dynamic>,
+    /// GOT entries for symbols from shared libraries
+    pub dynamic_relocations: Vec<DynamicRelocation>,
+    /// Addresses of pointers the dynamic linker adds the load address to
+    pub relative_relocations: Vec<u64>,
+}
+
+/// The segment read by the dynamic linker, and the sections within it.
+struct Linkage {
+    segment: Segment,
+    /// Offsets are relative to the segment
+    sections: Vec<Section>,
+    interp: Option<Section>,
+    dynamic: Section,
+}
+
+/// A section header with the data it describes, if it takes file space.
+#[derive(Clone)]
+struct Section {
+    name: String,
+    kind: u32,
+    flags: u64,
+    addr: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entsize: u64,
+}
+
+impl ElfExecutable {
+    pub fn new(machine: Machine, entry: u64) -> Self {
+        Self {
+            machine,
+            entry,
+            segments: Vec::new(),
+            symbols: Vec::new(),
+            relocations: Vec::new(),
+            shared: false,
+            dynamic: None,
+            dynamic_relocations: Vec::new(),
+            relative_relocations: Vec::new(),
+        }
+    }
+
+    /// The address of the symbol `name`.
+    pub fn symbol(&self, name: &str) -> Option<u64> {
+        self.symbols.iter().find(|(symbol, _)| symbol.name == name).map(|(_, addr)| *addr)
+    }
+
+    /// The segments with every relocation applied.
+    fn relocated(&self) -> Result<Vec<Segment>, ElfError> {
+        let mut segments = self.segments.clone();
+        for relocation in &self.relocations {
+            let target = self
+                .symbol(&relocation.symbol)
+                .ok_or_else(|| ElfError::UndefinedSymbol(relocation.symbol.clone()))?;
+            let value = target.wrapping_add(relocation.addend as u64);
+            let bytes = match relocation.kind {
+                RelocationKind::Abs64 => value.to_le_bytes().to_vec(),
+                RelocationKind::Pc32 => {
+                    let distance = value.wrapping_sub(relocation.address) as i64;
+                    let distance = i32::try_from(distance).map_err(|_| ElfError::Relocation(relocation.address))?;
+                    distance.to_le_bytes().to_vec()
+                }
+            };
+            let segment = segments
+                .iter_mut()
+                .find(|segment| segment.contains(relocation.address))
+                .ok_or(ElfError::Relocation(relocation.address))?;
+            let start = (relocation.address - segment.vaddr) as usize;
+            let field = segment
+                .bytes
+                .get_mut(start..start + bytes.len())
+                .ok_or(ElfError::Relocation(relocation.address))?;
+            field.copy_from_slice(&bytes);
+        }
+        Ok(segments)
+    }
+
+    /// The dynamic linking segment, placed at `vaddr`: the interpreter if
+    /// any, symbol and string tables, a hash table with a single bucket,
+    /// the relocations and the `_DYNAMIC` array. Shared objects export
+    /// their global symbols that are not hidden.
+    fn linkage(&self, dynamic: &Dynamic, vaddr: u64, segments: &[Segment], section_of: &[(u64, u64, usize)]) -> Result<Linkage, ElfError> {
+        let writable = |address: u64| {
+            segments
+                .iter()
+                .find(|segment| segment.flags & PF_W != 0 && segment.contains(address))
+                .ok_or(ElfError::Relocation(address))
+        };
+        let mut names: Vec<&str> = Vec::new();
+        for relocation in &self.dynamic_relocations {
+            writable(relocation.address)?;
+            if !names.contains(&relocation.symbol.as_str()) {
+                names.push(&relocation.symbol);
+            }
+        }
+        let exports: Vec<&(Symbol, u64)> = match self.shared {
+            true => self
+                .symbols
+                .iter()
+                .filter(|(symbol, _)| symbol.binding != Binding::Local && symbol.visibility != Visibility::Hidden)
+                .collect(),
+            false => Vec::new(),
+        };
+
+        let mut dynstr = vec![0u8];
+        let mut string = |name: &str| {
+            let offset = dynstr.len() as u32;
+            dynstr.extend(name.as_bytes());
+            dynstr.push(0);
+            offset as u64
+        };
+        let needed: Vec<u64> = dynamic.needed.iter().map(|name| string(name)).collect();
+        let soname = dynamic.soname.as_deref().map(&mut string);
+        let mut dynsym = vec![0u8; SYMBOL_SIZE as usize];
+        for name in &names {
+            let import = Symbol { binding: Binding::Global, ..Symbol::external(name) };
+            symbol_entry(&mut dynsym, string(name) as u32, &import, SHN_UNDEF, 0);
+        }
+        for (symbol, addr) in &exports {
+            symbol_entry(&mut dynsym, string(&symbol.name) as u32, symbol, section_index(section_of, *addr), *addr);
+        }
+        // Every symbol chains from the one bucket
+        let count = (names.len() + exports.len()) as u32 + 1;
+        let mut hash = Vec::new();
+        for word in [1, count, count - 1, 0].into_iter().chain(0..count - 1) {
+            hash.extend(word.to_le_bytes());
+        }
+        // Pointers are adjusted by the load address before symbols are bound
+        let mut rela = Vec::new();
+        for &address in &self.relative_relocations {
+            let segment = writable(address)?;
+            let at = (address - segment.vaddr) as usize;
+            let value = segment.bytes.get(at..at + 8).ok_or(ElfError::Relocation(address))?;
+            rela.extend(address.to_le_bytes());
+            rela.extend(self.machine.relative().to_le_bytes());
+            rela.extend(value);
+        }
+        for relocation in &self.dynamic_relocations {
+            let index = names.iter().position(|name| *name == relocation.symbol).unwrap() as u64 + 1;
+            rela.extend(relocation.address.to_le_bytes());
+            rela.extend((index << 32 | self.machine.glob_dat()).to_le_bytes());
+            rela.extend(0u64.to_le_bytes());
+        }
+
+        let mut bytes = Vec::new();
+        let place = |bytes: &mut Vec<u8>, section: Section, data: Vec<u8>, align_to: u64| {
+            align(bytes, align_to as usize);
+            let offset = bytes.len() as u64;
+            bytes.extend(data);
+            Section { addr: vaddr + offset, offset, size: bytes.len() as u64 - offset, align: align_to, ..section }
+        };
+        let interp = dynamic.interpreter.as_ref().map(|interpreter| {
+            let mut path = interpreter.as_bytes().to_vec();
+            path.push(0);
+            place(&mut bytes, Section::alloc(".interp", SHT_PROGBITS, SHF_ALLOC, 0, 0, 0), path, 1)
+        });
+        let dynstr_size = dynstr.len() as u64;
+        let mut sections = vec![
+            place(&mut bytes, Section::linked(".dynsym", SHT_DYNSYM, 1, SYMBOL_SIZE), dynsym, 8),
+            place(&mut bytes, Section::linked(".dynstr", SHT_STRTAB, 0, 0), dynstr, 1),
+            place(&mut bytes, Section::linked(".hash", SHT_HASH, 0, 4), hash, 8),
+            place(&mut bytes, Section::linked(".rela.dyn", SHT_RELA, 0, RELA_SIZE), rela, 8),
+        ];
+
+        let mut entries: Vec<(u64, u64)> = needed.into_iter().map(|name| (DT_NEEDED, name)).collect();
+        if let Some(soname) = soname {
+            entries.push((DT_SONAME, soname));
+        }
+        entries.extend([
+            (DT_HASH, sections[2].addr),
+            (DT_STRTAB, sections[1].addr),
+            (DT_SYMTAB, sections[0].addr),
+            (DT_RELA, sections[3].addr),
+            (DT_RELASZ, sections[3].size),
+            (DT_RELAENT, RELA_SIZE),
+            (DT_STRSZ, dynstr_size),
+            (DT_SYMENT, SYMBOL_SIZE),
+            (DT_NULL, 0),
+        ]);
+        let array = entries.iter().flat_map(|(tag, value)| tag.to_le_bytes().into_iter().chain(value.to_le_bytes())).collect();
+        let section = Section::linked(".dynamic", SHT_DYNAMIC, 0, DYNAMIC_SIZE);
+        let dynamic_section = place(&mut bytes, Section { flags: SHF_ALLOC | SHF_WRITE, ..section }, array, 8);
+        sections.push(dynamic_section.clone());
+        if let Some(interp) = &interp {
+            sections.insert(0, interp.clone());
+        }
+
+        Ok(Linkage { segment: Segment::new(".dynamic", vaddr, bytes, PF_R | PF_W), sections, interp, dynamic: dynamic_section })
+    }
+
+    /// The executable or shared object file.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ElfError> {
+        let mut segments = self.relocated()?;
+        let loaded = segments.len();
+        let phnum = program_header_count(loaded, self.dynamic.as_ref());
+        let mut out = vec![0; (HEADER_SIZE + PROGRAM_HEADER_SIZE * phnum) as usize];
+        let mut offsets: Vec<u64> = segments.iter().map(|segment| place_segment(&mut out, segment)).collect();
+
+        let mut sections = vec![Section::null()];
+        let mut section_of = Vec::new();
+        for (segment, offset) in segments.iter().zip(&offsets).take(loaded) {
+            let mut flags = SHF_ALLOC;
+            if segment.flags & PF_W != 0 {
+                flags |= SHF_WRITE;
+            }
+            if segment.flags & PF_X != 0 {
+                flags |= SHF_EXECINSTR;
+            }
+            let file_size = segment.bytes.len() as u64;
+            if file_size > 0 {
+                section_of.push((segment.vaddr, segment.vaddr + file_size, sections.len()));
+                sections.push(Section::alloc(&segment.name, SHT_PROGBITS, flags, segment.vaddr, *offset, file_size));
+            }
+            if segment.mem_size > file_size {
+                let addr = segment.vaddr + file_size;
+                section_of.push((addr, segment.vaddr + segment.mem_size, sections.len()));
+                sections.push(Section::alloc(".bss", SHT_NOBITS, flags, addr, offset + file_size, segment.mem_size - file_size));
+            }
+        }
+
+        // The dynamic linking segment goes on the page after the rest
+        let linkage = match &self.dynamic {
+            Some(dynamic) => {
+                let end = segments.iter().map(|segment| segment.vaddr + segment.mem_size).max().unwrap_or(LOAD_ADDRESS);
+                let linkage = self.linkage(dynamic, end.next_multiple_of(PAGE), &segments, &section_of)?;
+                offsets.push(place_segment(&mut out, &linkage.segment));
+                segments.push(linkage.segment.clone());
+                let first = sections.len();
+                let index = |kind: u32| (first + linkage.sections.iter().position(|section| section.kind == kind).unwrap()) as u32;
+                for section in &linkage.sections {
+                    let link = match section.kind {
+                        SHT_DYNSYM | SHT_DYNAMIC => index(SHT_STRTAB),
+                        SHT_HASH | SHT_RELA => index(SHT_DYNSYM),
+                        _ => 0,
+                    };
+                    sections.push(Section { offset: offsets[loaded] + section.offset, link, ..section.clone() });
+                }
+                Some(linkage)
+            }
+            None => None,
+        };
+
+        // Local symbols must come first
+        let mut symbols: Vec<&(Symbol, u64)> = self.symbols.iter().filter(|(symbol, _)| symbol.binding == Binding::Local).collect();
+        let locals = symbols.len() + 1;
+        symbols.extend(self.symbols.iter().filter(|(symbol, _)| symbol.binding != Binding::Local));
+        let mut strtab = vec![0u8];
+        let mut symtab = vec![0u8; SYMBOL_SIZE as usize];
+        for (symbol, addr) in symbols {
+            let name = strtab.len() as u32;
+            strtab.extend(symbol.name.as_bytes());
+            strtab.push(0);
+            symbol_entry(&mut symtab, name, symbol, section_index(&section_of, *addr), *addr);
+        }
+
+        let strtab_index = sections.len() as u32 + 1;
+        align(&mut out, 8);
+        sections.push(Section::table(".symtab", SHT_SYMTAB, out.len() as u64, symtab.len() as u64, strtab_index, locals as u32, SYMBOL_SIZE));
+        out.extend(symtab);
+        sections.push(Section::table(".strtab", SHT_STRTAB, out.len() as u64, strtab.len() as u64, 0, 0, 0));
+        out.extend(strtab);
+
+        let mut shstrtab = vec![0u8];
+        let mut names = Vec::new();
+        sections.push(Section::table(".shstrtab", SHT_STRTAB, 0, 0, 0, 0, 0));
+        for section in &sections {
+            match section.name.is_empty() {
+                true => names.push(0),
+                false => {
+                    names.push(shstrtab.len() as u32);
+                    shstrtab.extend(section.name.as_bytes());
+                    shstrtab.push(0);
+                }
+            }
+        }
+        let shstrndx = sections.len() - 1;
+        sections[shstrndx].offset = out.len() as u64;
+        sections[shstrndx].size = shstrtab.len() as u64;
+        out.extend(shstrtab);
+
+        align(&mut out, 8);
+        let shoff = out.len() as u64;
+        for (section, name) in sections.iter().zip(names) {
+            out.extend(name.to_le_bytes());
+            out.extend(section.kind.to_le_bytes());
+            out.extend(section.flags.to_le_bytes());
+            out.extend(section.addr.to_le_bytes());
+            out.extend(section.offset.to_le_bytes());
+            out.extend(section.size.to_le_bytes());
+            out.extend(section.link.to_le_bytes());
+            out.extend(section.info.to_le_bytes());
+            out.extend(section.align.to_le_bytes());
+            out.extend(section.entsize.to_le_bytes());
+        }
+
+        let mut header = Vec::new();
+        header.extend(b"\x7fELF");
+        // 64-bit, little-endian, version 1, System V ABI
+        header.extend([2, 1, 1, 0]);
+        header.extend([0; 8]);
+        let file_type = match self.shared {
+            true => ET_DYN,
+            false => ET_EXEC,
+        };
+        header.extend(file_type.to_le_bytes());
+        header.extend(self.machine.e_machine().to_le_bytes());
+        header.extend(1u32.to_le_bytes());
+        header.extend(self.entry.to_le_bytes());
+        header.extend(HEADER_SIZE.to_le_bytes());
+        header.extend(shoff.to_le_bytes());
+        header.extend(0u32.to_le_bytes());
+        header.extend((HEADER_SIZE as u16).to_le_bytes());
+        header.extend((PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+        header.extend((phnum as u16).to_le_bytes());
+        header.extend((SECTION_HEADER_SIZE as u16).to_le_bytes());
+        header.extend((sections.len() as u16).to_le_bytes());
+        header.extend((shstrndx as u16).to_le_bytes());
+
+        // `PT_INTERP` must come before the segments it is loaded with
+        let mut program_header = |kind: u32, flags: u32, offset: u64, vaddr: u64, file_size: u64, mem_size: u64, align: u64| {
+            header.extend(kind.to_le_bytes());
+            header.extend(flags.to_le_bytes());
+            header.extend(offset.to_le_bytes());
+            header.extend(vaddr.to_le_bytes());
+            header.extend(vaddr.to_le_bytes());
+            header.extend(file_size.to_le_bytes());
+            header.extend(mem_size.to_le_bytes());
+            header.extend(align.to_le_bytes());
+        };
+        if let Some(interp) = linkage.as_ref().and_then(|linkage| linkage.interp.as_ref()) {
+            program_header(PT_INTERP, PF_R, offsets[loaded] + interp.offset, interp.addr, interp.size, interp.size, 1);
+        }
+        for (segment, offset) in segments.iter().zip(&offsets) {
+            // The dynamic linker finds `PT_DYNAMIC` through the program
+            // headers, so a segment in the first page maps them too
+            let front = match linkage.is_some() && *offset < PAGE {
+                true => *offset,
+                false => 0,
+            };
+            let file_size = segment.bytes.len() as u64 + front;
+            program_header(PT_LOAD, segment.flags, offset - front, segment.vaddr - front, file_size, segment.mem_size + front, PAGE);
+        }
+        if let Some(linkage) = &linkage {
+            let dynamic = &linkage.dynamic;
+            program_header(PT_DYNAMIC, PF_R | PF_W, offsets[loaded] + dynamic.offset, dynamic.addr, dynamic.size, dynamic.size, 8);
+        }
+        program_header(PT_GNU_STACK, PF_R | PF_W, 0, 0, 0, 0, 16);
+
+        out[..header.len()].copy_from_slice(&header);
+        Ok(out)
+    }
+
+    /// Writes the executable to `path` and marks it executable.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let bytes = self.to_bytes().map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", error)))?;
+        fs::write(path, bytes)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(())
+    }
+}
+
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+const PT_GNU_STACK: u32 = 0x6474_e551;
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_HASH: u32 = 5;
+const SHT_DYNAMIC: u32 = 6;
+const SHT_NOBITS: u32 = 8;
+const SHT_DYNSYM: u32 = 11;
+const SHF_WRITE: u64 = 1;
+const SHF_ALLOC: u64 = 2;
+const SHF_EXECINSTR: u64 = 4;
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xfff1;
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_HASH: u64 = 4;
+const DT_STRTAB: u64 = 5;
+const DT_SYMTAB: u64 = 6;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+const DT_RELAENT: u64 = 9;
+const DT_STRSZ: u64 = 10;
+const DT_SYMENT: u64 = 11;
+const DT_SONAME: u64 = 14;
+
+/// One `PT_LOAD` per segment and a `PT_GNU_STACK` keeping the stack
+/// non-executable, with `PT_DYNAMIC` and the load of the dynamic linking
+/// segment when dynamically linked, and `PT_INTERP` if there is an
+/// interpreter.
+fn program_header_count(segments: usize, dynamic: Option<&Dynamic>) -> u64 {
+    match dynamic {
+        Some(Dynamic { interpreter: Some(_), .. }) => segments as u64 + 4,
+        Some(_) => segments as u64 + 3,
+        None => segments as u64 + 1,
+    }
+}
+
+impl Section {
+    fn null() -> Self {
+        Self::table("", 0, 0, 0, 0, 0, 0)
+    }
+
+    fn alloc(name: &str, kind: u32, flags: u64, addr: u64, offset: u64, size: u64) -> Self {
+        Self { name: name.to_string(), kind, flags, addr, offset, size, link: 0, info: 0, align: 1, entsize: 0 }
+    }
+
+    /// An allocated section of the dynamic linking segment.
+    fn linked(name: &str, kind: u32, info: u32, entsize: u64) -> Self {
+        Self { info, entsize, ..Self::alloc(name, kind, SHF_ALLOC, 0, 0, 0) }
+    }
+
+    fn table(name: &str, kind: u32, offset: u64, size: u64, link: u32, info: u32, entsize: u64) -> Self {
+        let align = match kind {
+            SHT_SYMTAB => 8,
+            _ => 1,
+        };
+        Self { name: name.to_string(), kind, flags: 0, addr: 0, offset, size, link, info, align, entsize }
+    }
+}
+
+/// Appends `segment` at the next file offset matching its address modulo
+/// the page, returning the offset.
+fn place_segment(out: &mut Vec<u8>, segment: &Segment) -> u64 {
+    let here = out.len() as u64;
+    let mut offset = here - here % PAGE + segment.vaddr % PAGE;
+    if offset < here {
+        offset += PAGE;
+    }
+    out.resize(offset as usize, 0);
+    out.extend(&segment.bytes);
+    offset
+}
+
+/// The index of the section holding `addr`, if any.
+fn section_index(section_of: &[(u64, u64, usize)], addr: u64) -> u16 {
+    section_of
+        .iter()
+        .find(|(start, end, _)| addr >= *start && (addr < *end || addr == *end && start == end))
+        .map_or(SHN_ABS, |(_, _, index)| *index as u16)
+}
+
+/// Appends a symbol table entry whose name is at `name` in the string
+/// table.
+fn symbol_entry(table: &mut Vec<u8>, name: u32, symbol: &Symbol, shndx: u16, addr: u64) {
+    let bind = match symbol.binding {
+        Binding::Local => 0,
+        Binding::Global => 1,
+        Binding::Weak => 2,
+    };
+    let kind = match symbol.kind {
+        None => 0,
+        Some(SymbolKind::Object) => 1,
+        Some(SymbolKind::Func) => 2,
+    };
+    let visibility = match symbol.visibility {
+        Visibility::Default => 0,
+        Visibility::Hidden => 2,
+        Visibility::Protected => 3,
+    };
+    table.extend(name.to_le_bytes());
+    table.push(bind << 4 | kind);
+    table.push(visibility);
+    table.extend(shndx.to_le_bytes());
+    table.extend(addr.to_le_bytes());
+    table.extend(symbol.size.unwrap_or(0).to_le_bytes());
+}
+
+fn align(out: &mut Vec<u8>, align: usize) {
+    out.resize(out.len().next_multiple_of(align), 0);
+}
+
+impl Program<ARM64, Arm64Register> {
+    /// A static aarch64 Linux executable starting at the label `entry`.
+    pub fn elf(&self, entry: &str) -> Result<ElfExecutable, ElfError> {
+        executable(&self.ins.arch, &self.ctx, &self.symbols(), Some(entry), Linking::Static)
+    }
+
+    /// An aarch64 Linux executable starting at the label `entry` that
+    /// imports whatever it calls or loads through the GOT without defining
+    /// from the libraries in `dynamic`.
+    pub fn dynamic_elf(&self, entry: &str, dynamic: Dynamic) -> Result<ElfExecutable, ElfError> {
+        executable(&self.ins.arch, &self.ctx, &self.symbols(), Some(entry), Linking::Dynamic(dynamic))
+    }
+
+    /// A position-independent aarch64 shared object exporting the
+    /// program's global symbols. Imports are found as for
+    /// [`Program::dynamic_elf`].
+    pub fn shared_elf(&self, dynamic: Dynamic) -> Result<ElfExecutable, ElfError> {
+        executable(&self.ins.arch, &self.ctx, &self.symbols(), None, Linking::Shared(dynamic))
+    }
+}
+
+impl Module<ARM64> {
+    /// A static aarch64 Linux executable starting at the label `entry`.
+    pub fn elf(&self, entry: &str) -> Result<ElfExecutable, ElfError> {
+        executable(&self.arch, &self.ctx, self.ctx.get_symbols(), Some(entry), Linking::Static)
+    }
+
+    /// A dynamically linked aarch64 Linux executable, as
+    /// [`Program::dynamic_elf`].
+    pub fn dynamic_elf(&self, entry: &str, dynamic: Dynamic) -> Result<ElfExecutable, ElfError> {
+        executable(&self.arch, &self.ctx, self.ctx.get_symbols(), Some(entry), Linking::Dynamic(dynamic))
+    }
+
+    /// A shared object, as [`Program::shared_elf`].
+    pub fn shared_elf(&self, dynamic: Dynamic) -> Result<ElfExecutable, ElfError> {
+        executable(&self.arch, &self.ctx, self.ctx.get_symbols(), None, Linking::Shared(dynamic))
+    }
+}
+
+/// Mark the end of the read-only data and the page where writable data
+/// starts.
+const RODATA_END: &str = "L__rodata_end";
+const DATA_START: &str = "L__data_start";
+
+/// How a file built from a program is linked.
+enum Linking {
+    Static,
+    Dynamic(Dynamic),
+    /// A position-independent shared object, loaded at any address
+    Shared(Dynamic),
+}
+
+fn executable(arch: &ARM64, ctx: &Context, symbols: &SymbolTable, entry: Option<&str>, linking: Linking) -> Result<ElfExecutable, ElfError> {
+    let mut arch = arch.clone();
+    let sections = ctx.get_sections();
+    let (dynamic, shared) = match linking {
+        Linking::Static => (None, false),
+        Linking::Dynamic(dynamic) => (Some(dynamic), false),
+        Linking::Shared(dynamic) => (Some(dynamic), true),
+    };
+
+    let got = Got::new(&mut arch, sections, dynamic.is_some());
+    let globals = Globals::new(sections, shared);
+    let has_rodata = !arch.get_rodata().is_empty() || !globals.readonly.is_empty();
+    let has_data = !got.entries.is_empty() || globals.has_data();
+    globals.place(&got.entries, |label, value, align| arch.global(label, value, align));
+
+    let base = match shared {
+        true => 0,
+        false => LOAD_ADDRESS,
+    };
+    let text_base = text_base(base, has_rodata, has_data, dynamic.as_ref());
+    let out = assemble(&arch, text_base).map_err(ElfError::Encode)?;
+    let entry = match entry {
+        Some(entry) => entry_address(&out, entry)?,
+        None => 0,
+    };
+
+    let mut elf = ElfExecutable::new(Machine::Aarch64, entry);
+    if shared {
+        let pointers = got.local().chain(globals.data.iter().filter(|var| pointer(var)).map(|var| &var.label));
+        elf.relative_relocations = pointers.map(|label| out.labels[label]).collect();
+    }
+    for label in got.imports {
+        elf.dynamic_relocations.push(DynamicRelocation { address: out.labels[&got::entry(&label)], symbol: label });
+    }
+    elf.shared = shared;
+    elf.dynamic = dynamic;
+    globals.load(&mut elf, out, has_rodata, has_data, symbols);
+    Ok(elf)
+}
+
+fn pointer(var: &Variable) -> bool {
+    matches!(var.value, Value::Pointer { .. })
+}
+
+/// A program's globals by the segment they are loaded into.
+struct Globals<'a> {
+    readonly: Vec<&'a Variable>,
+    data: Vec<&'a Variable>,
+    bss: Vec<&'a Variable>,
+}
+
+impl<'a> Globals<'a> {
+    /// A shared object's pointers are relocated at load time, so read-only
+    /// ones move to the writable page when `shared`.
+    fn new(sections: &'a Sections, shared: bool) -> Self {
+        let (relocated, readonly): (Vec<_>, Vec<_>) = sections
+            .iter()
+            .filter(|section| matches!(section.kind, SectionKind::Text | SectionKind::Rodata))
+            .flat_map(|section| section.variables())
+            .partition(|var| shared && pointer(var));
+        let mut data = relocated;
+        data.extend(sections.iter().filter(|section| section.kind == SectionKind::Data).flat_map(|section| section.variables()));
+        let bss = sections.iter().filter(|section| section.kind == SectionKind::Bss).flat_map(|section| section.variables()).collect();
+        Self { readonly, data, bss }
+    }
+
+    fn has_data(&self) -> bool {
+        !self.data.is_empty() || !self.bss.is_empty()
+    }
+
+    /// Adds the globals through `global`, after the architecture's own
+    /// read-only data: the read-only ones, then the GOT `entries`, data and
+    /// bss from the next page.
+    fn place(&self, entries: &[(String, Value)], mut global: impl FnMut(&str, &Value, u64)) {
+        for var in &self.readonly {
+            global(&var.label, &var.value, var.align);
+        }
+        if !entries.is_empty() || self.has_data() {
+            global(RODATA_END, &Value::Zero(0), 1);
+            global(DATA_START, &Value::Zero(0), PAGE);
+            for (label, value) in entries {
+                global(label, value, 8);
+            }
+            for var in self.data.iter().chain(&self.bss) {
+                global(&var.label, &var.value, var.align);
+            }
+        }
+    }
+
+    /// Adds the segments of `out`, assembled after [`Self::place`], and the
+    /// addresses of the defined `symbols` to `elf`.
+    fn load(&self, elf: &mut ElfExecutable, out: Assembled, has_rodata: bool, has_data: bool, symbols: &SymbolTable) {
+        elf.segments.push(Segment::new(".text", out.text_base, out.text, PF_R | PF_X));
+        let (rodata_end, data_start) = match has_data {
+            true => (out.labels[RODATA_END], out.labels[DATA_START]),
+            false => (out.rodata_base + out.rodata.len() as u64, 0),
+        };
+        if has_rodata {
+            let bytes = out.rodata[..(rodata_end - out.rodata_base) as usize].to_vec();
+            elf.segments.push(Segment::new(".rodata", out.rodata_base, bytes, PF_R));
+        }
+        if has_data {
+            // Zero-filled globals are left out of the file
+            let bss_start = match self.bss.first() {
+                Some(var) => out.labels[&var.label],
+                None => out.rodata_base + out.rodata.len() as u64,
+            };
+            let file_end = (bss_start - out.rodata_base) as usize;
+            let bytes = out.rodata[(data_start - out.rodata_base) as usize..file_end].to_vec();
+            let zero = out.rodata.len() - file_end;
+            elf.segments.push(Segment::new(".data", data_start, bytes, PF_R | PF_W).zero_fill(zero as u64));
+        }
+        for symbol in symbols.iter().filter(|symbol| symbol.defined) {
+            if let Some(addr) = out.labels.get(&symbol.name) {
+                elf.symbols.push((symbol.clone(), *addr));
+            }
+        }
+    }
+}
+
+/// Where the text starts: after the headers in the first page at `base`.
+fn text_base(base: u64, has_rodata: bool, has_data: bool, dynamic: Option<&Dynamic>) -> u64 {
+    let phnum = program_header_count(1 + has_rodata as usize + has_data as usize, dynamic);
+    base + (HEADER_SIZE + PROGRAM_HEADER_SIZE * phnum).next_multiple_of(16)
+}
+
+fn entry_address(out: &Assembled, entry: &str) -> Result<u64, ElfError> {
+    out.labels.get(entry).copied().ok_or_else(|| ElfError::UndefinedEntry(entry.to_string()))
+}
+
+impl Program<X86_64, X86Register> {
+    /// A static x86_64 Linux executable starting at the label `entry`. The
+    /// build errors of the program are reported before anything is
+    /// assembled.
+    pub fn elf(&self, entry: &str) -> Result<ElfExecutable, ElfError> {
+        let mut errors = self.ctx.build_errors().to_vec();
+        errors.extend(self.ins.build_errors().iter().cloned());
+        errors.extend(self.ins.arch.build_errors().iter().cloned());
+        if !errors.is_empty() {
+            return Err(ElfError::Build(errors));
+        }
+
+        let mut arch = self.ins.arch.clone();
+        let globals = Globals::new(self.ctx.get_sections(), false);
+        let has_rodata = !arch.get_rodata().is_empty() || !globals.readonly.is_empty();
+        let has_data = globals.has_data();
+        globals.place(&[], |label, value, align| arch.global(label, value, align));
+
+        let text_base = text_base(LOAD_ADDRESS, has_rodata, has_data, None);
+        let out = x86_64::encoder::assemble(&arch, text_base).map_err(ElfError::Encode)?;
+        let mut elf = ElfExecutable::new(Machine::X86_64, entry_address(&out, entry)?);
+        globals.load(&mut elf, out, has_rodata, has_data, self.ctx.get_symbols());
+        Ok(elf)
+    }
+}
@@ -0,0 +1,59 @@
+//! GOT entries and call stubs for dynamically linked files.
+//!
+//! Every label a program loads through the GOT gets an entry named
+//! `label@GOT`. When linking dynamically, labels defined nowhere and the
+//! targets of undefined calls are imports: their entries are left for the
+//! dynamic linker to fill, and each call goes through a stub named after
+//! the function that branches through the entry.
+
+use crate::arch::arm64::{Arm64Register, ARM64};
+use crate::context::{Sections, Value};
+use crate::instruction::{AddressBuilder, BranchBuilder, LabelBuilder};
+
+pub(super) struct Got {
+    /// Entry labels with their values, in order of first use
+    pub entries: Vec<(String, Value)>,
+    /// Imported symbols
+    pub imports: Vec<String>,
+}
+
+impl Got {
+    /// The entries `arch` needs, adding stubs for imported calls when
+    /// `dynamic`.
+    pub fn new(arch: &mut ARM64, sections: &Sections, dynamic: bool) -> Self {
+        let defined: Vec<String> = arch.defined_labels().into_iter().chain(sections.variables().map(|var| var.label.clone())).collect();
+        let mut got = Self { entries: Vec::new(), imports: Vec::new() };
+        for label in arch.got_references() {
+            match dynamic && !defined.contains(&label) {
+                true => got.import(&label),
+                false => got.entries.push((entry(&label), Value::Pointer { symbol: label, offset: 0 })),
+            }
+        }
+        if dynamic {
+            for label in arch.undefined_calls() {
+                if !got.imports.contains(&label) {
+                    got.import(&label);
+                }
+                arch.label(&label);
+                arch.adrp_got(Arm64Register::X16, &label);
+                arch.br(Arm64Register::X16);
+            }
+        }
+        got
+    }
+
+    fn import(&mut self, label: &str) {
+        self.entries.push((entry(label), Value::Quad(vec![0])));
+        self.imports.push(label.to_string());
+    }
+
+    /// Entries holding addresses within the program.
+    pub fn local(&self) -> impl Iterator<Item = &String> {
+        self.entries.iter().filter(|(_, value)| matches!(value, Value::Pointer { .. })).map(|(label, _)| label)
+    }
+}
+
+/// The GOT entry for `label`.
+pub(super) fn entry(label: &str) -> String {
+    format!("{}@GOT", label)
+}
@@ -0,0 +1,202 @@
+//! Flat images for bare-metal targets.
+//!
+//! [`Program::image`] assembles an ARM64 program in-process, resolving every
+//! label, and lays it out from a load address: text first, then read-only
+//! data from the next page. The program's globals join the read-only data
+//! section by section, mutable and zero-filled ones included, as a flat
+//! image has no protection and nothing to clear `.bss`. The
+//! result can be written as a raw `.bin`, Intel HEX or Motorola S-records.
+//! [`LinkerScript`] gives external `ld` links the same placement, all in one
+//! memory region, so startup code needs no `.data` copy or `.bss` clearing
+//! either way.
+
+use super::Program;
+use crate::arch::arm64::encoder::{assemble, EncodeError};
+use crate::arch::arm64::{Arm64Register, ARM64};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum ImageError {
+    Encode(EncodeError),
+    UndefinedEntry(String),
+    /// HEX and S-record images only address 32 bits
+    AddressRange(u64),
+}
+
+#[derive(Debug)]
+pub struct Image {
+    pub load_address: u64,
+    pub entry: u64,
+    /// Text, then read-only data at the following page with the gap
+    /// zero-filled
+    pub bytes: Vec<u8>,
+    pub labels: HashMap<String, u64>,
+}
+
+impl Program<ARM64, Arm64Register> {
+    /// Assembles the program at `load_address` with execution starting at
+    /// the label `entry`.
+    pub fn image(&self, load_address: u64, entry: &str) -> Result<Image, ImageError> {
+        let mut arch = self.ins.arch.clone();
+        for var in self.ctx.get_sections().variables() {
+            arch.global(&var.label, &var.value, var.align);
+        }
+        let out = assemble(&arch, load_address).map_err(ImageError::Encode)?;
+        let entry = *out.labels.get(entry).ok_or_else(|| ImageError::UndefinedEntry(entry.to_string()))?;
+
+        let mut bytes = out.text;
+        if !out.rodata.is_empty() {
+            bytes.resize((out.rodata_base - out.text_base) as usize, 0);
+            bytes.extend(out.rodata);
+        }
+        Ok(Image { load_address, entry, bytes, labels: out.labels })
+    }
+}
+
+impl Image {
+    /// The raw bytes of a `.bin` image.
+    pub fn to_bin(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Intel HEX with 16-byte data records, extended linear address records
+    /// at each 64KB boundary and a start linear address record for the entry.
+    pub fn to_ihex(&self) -> Result<String, ImageError> {
+        self.check_32bit()?;
+        let mut out = String::new();
+        let mut upper = None;
+        let mut offset = 0;
+        while offset < self.bytes.len() {
+            let addr = self.load_address + offset as u64;
+            if upper != Some(addr >> 16) {
+                upper = Some(addr >> 16);
+                out.push_str(&ihex_record(0, 0x04, &((addr >> 16) as u16).to_be_bytes()));
+            }
+            // Records never cross into the next 64KB segment
+            let len = 16.min(0x10000 - (addr & 0xffff) as usize).min(self.bytes.len() - offset);
+            out.push_str(&ihex_record(addr as u16, 0x00, &self.bytes[offset..offset + len]));
+            offset += len;
+        }
+        out.push_str(&ihex_record(0, 0x05, &(self.entry as u32).to_be_bytes()));
+        out.push_str(&ihex_record(0, 0x01, &[]));
+        Ok(out)
+    }
+
+    /// Motorola S-records: an empty S0 header, S3 data records with 32-bit
+    /// addresses, a record count and an S7 record for the entry. The count
+    /// is an S5 record, or S6 above 0xffff records. The count is optional,
+    /// so it is left out past the 24 bits of S6.
+    pub fn to_srec(&self) -> Result<String, ImageError> {
+        self.check_32bit()?;
+        let mut out = srec_record(0, &[0, 0], &[]);
+        let mut count = 0u32;
+        for (index, chunk) in self.bytes.chunks(16).enumerate() {
+            let addr = (self.load_address + 16 * index as u64) as u32;
+            out.push_str(&srec_record(3, &addr.to_be_bytes(), chunk));
+            count += 1;
+        }
+        match count {
+            0..=0xffff => out.push_str(&srec_record(5, &(count as u16).to_be_bytes(), &[])),
+            0x1_0000..=0xff_ffff => out.push_str(&srec_record(6, &count.to_be_bytes()[1..], &[])),
+            _ => {}
+        }
+        out.push_str(&srec_record(7, &(self.entry as u32).to_be_bytes(), &[]));
+        Ok(out)
+    }
+
+    /// The image may end exactly at 4GB, but the entry must be below it.
+    fn check_32bit(&self) -> Result<(), ImageError> {
+        let end = self.load_address + self.bytes.len() as u64;
+        if end > 1 << 32 {
+            return Err(ImageError::AddressRange(end));
+        }
+        if self.entry >= 1 << 32 {
+            return Err(ImageError::AddressRange(self.entry));
+        }
+        Ok(())
+    }
+}
+
+fn ihex_record(addr: u16, kind: u8, data: &[u8]) -> String {
+    let mut record = vec![data.len() as u8];
+    record.extend(addr.to_be_bytes());
+    record.push(kind);
+    record.extend(data);
+    let checksum = record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)).wrapping_neg();
+    record.push(checksum);
+    format!(":{}\n", hex(&record))
+}
+
+fn srec_record(kind: u8, addr: &[u8], data: &[u8]) -> String {
+    let mut record = vec![(addr.len() + data.len() + 1) as u8];
+    record.extend(addr);
+    record.extend(data);
+    let checksum = !record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    record.push(checksum);
+    format!("S{}{}\n", kind, hex(&record))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryRegion {
+    pub name: String,
+    /// `ld` attribute letters such as `rx` or `rwx`
+    pub attributes: String,
+    pub origin: u64,
+    pub length: u64,
+}
+
+impl MemoryRegion {
+    pub fn new(name: &str, attributes: &str, origin: u64, length: u64) -> Self {
+        Self { name: name.to_string(), attributes: attributes.to_string(), origin, length }
+    }
+}
+
+/// A GNU `ld` script placing sections as [`Program::image`] does: text at
+/// the start of `memory`, then from the next page read-only data followed by
+/// the writable and zero-filled data, all loaded with the image. A vector
+/// table's `.p2align 11` carries over to the alignment of `.text`, so it is
+/// 2KB aligned in the output as long as the origin is.
+#[derive(Debug, Clone)]
+pub struct LinkerScript {
+    pub entry: String,
+    pub memory: MemoryRegion,
+}
+
+impl LinkerScript {
+    pub fn new(entry: &str, memory: MemoryRegion) -> Self {
+        Self { entry: entry.to_string(), memory }
+    }
+}
+
+impl fmt::Display for LinkerScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let memory = &self.memory;
+        writeln!(f, "ENTRY({})", self.entry)?;
+        writeln!(f)?;
+        writeln!(f, "MEMORY")?;
+        writeln!(f, "{{")?;
+        writeln!(f, "    {} ({}) : ORIGIN = {:#x}, LENGTH = {:#x}", memory.name, memory.attributes, memory.origin, memory.length)?;
+        writeln!(f, "}}")?;
+        writeln!(f)?;
+        writeln!(f, "SECTIONS")?;
+        writeln!(f, "{{")?;
+        writeln!(f, "    .text : {{")?;
+        writeln!(f, "        KEEP(*(.text.boot))")?;
+        writeln!(f, "        *(.text*)")?;
+        writeln!(f, "    }} > {}", memory.name)?;
+        // Zero-filled input makes the section carry its zeros, as the
+        // image does
+        writeln!(f, "    .rodata : ALIGN(4096) {{")?;
+        writeln!(f, "        *(.rodata*)")?;
+        writeln!(f, "        *(.data*)")?;
+        writeln!(f, "        *(.bss*)")?;
+        writeln!(f, "        *(COMMON)")?;
+        writeln!(f, "    }} > {}", memory.name)?;
+        writeln!(f, "}}")
+    }
+}
@@ -0,0 +1,147 @@
+//! Linking modules in-process.
+//!
+//! A [`Linker`] merges finished modules into one, in the order they were
+//! added. Exported symbols keep their names and resolve references from
+//! every module. Everything else a module defines is local to it and is
+//! suffixed with the module's index, so the `L0` of one program cannot
+//! clash with another's. The names of local globals get the same suffix.
+//! A weak definition yields to a global one.
+
+use super::Module;
+use crate::arch::arm64::ARM64;
+use crate::context::{Binding, Context, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum LinkError {
+    /// Modules `first` and `second` both define `name` globally
+    DuplicateSymbol { name: String, first: usize, second: usize },
+    /// Module `module` refers to `name`, which no module defines
+    UndefinedSymbol { name: String, module: usize },
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::DuplicateSymbol { name, first, second } => {
+                write!(f, "duplicate symbol {} in modules {} and {}", name, first, second)
+            }
+            LinkError::UndefinedSymbol { name, module } => write!(f, "undefined symbol {} in module {}", name, module),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Linker {
+    modules: Vec<Module<ARM64>>,
+    /// Symbols left for the dynamic linker
+    dynamic: Vec<String>,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, module: Module<ARM64>) -> &mut Self {
+        self.modules.push(module);
+        self
+    }
+
+    /// Leaves `name` undefined, for a shared library to provide at run time.
+    pub fn import(&mut self, name: &str) -> &mut Self {
+        self.dynamic.push(name.to_string());
+        self
+    }
+
+    /// Merges the modules, returning every duplicate and undefined symbol
+    /// if any. Weak references and dynamic imports stay imports of the
+    /// result.
+    pub fn link(self) -> Result<Module<ARM64>, Vec<LinkError>> {
+        let mut errors = Vec::new();
+        // The module whose definition each exported name resolves to
+        let mut resolved: HashMap<String, (usize, Binding)> = HashMap::new();
+        for (index, module) in self.modules.iter().enumerate() {
+            for symbol in module.exports() {
+                match resolved.get(&symbol.name) {
+                    None => {
+                        resolved.insert(symbol.name.clone(), (index, symbol.binding));
+                    }
+                    Some(&(_, Binding::Weak)) if symbol.binding == Binding::Global => {
+                        resolved.insert(symbol.name.clone(), (index, symbol.binding));
+                    }
+                    Some(&(first, Binding::Global)) if symbol.binding == Binding::Global => {
+                        errors.push(LinkError::DuplicateSymbol { name: symbol.name.clone(), first, second: index });
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        for (index, module) in self.modules.iter().enumerate() {
+            for symbol in module.imports() {
+                let known = resolved.contains_key(&symbol.name)
+                    || symbol.binding == Binding::Weak
+                    || self.dynamic.contains(&symbol.name);
+                if !known {
+                    errors.push(LinkError::UndefinedSymbol { name: symbol.name.clone(), module: index });
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let mut arch = ARM64::new();
+        let mut ctx = Context::new();
+        for (index, mut module) in self.modules.into_iter().enumerate() {
+            let definitions = module.definitions();
+            let exported: Vec<String> = module.exports().iter().map(|symbol| symbol.name.clone()).collect();
+            let local = |name: &str| format!("{}.m{}", name, index);
+            let rename = |name: &str| match definitions.iter().any(|label| label == name) && !exported.iter().any(|export| export == name) {
+                true => Some(local(name)),
+                false => None,
+            };
+            // A weak definition that lost keeps its code under a local name
+            let lost = |name: &str| exported.iter().any(|export| export == name) && resolved[name].0 != index;
+
+            module.arch.relabel(&rename);
+            for name in exported.iter().filter(|name| lost(name)) {
+                module.arch.rename_definition(name, &local(name));
+            }
+            for section in module.ctx.get_sections().iter() {
+                for var in section.variables() {
+                    let mut var = var.clone();
+                    // Names are namespaced with their labels, so that
+                    // `get_variable` finds each module's own
+                    if let Some(label) = rename(&var.label) {
+                        var.label = label;
+                        var.name = local(&var.name);
+                    } else if lost(&var.label) {
+                        var.label = local(&var.label);
+                        var.name = local(&var.name);
+                    }
+                    if let Value::Pointer { symbol, .. } = &mut var.value {
+                        if let Some(name) = rename(symbol) {
+                            *symbol = name;
+                        }
+                    }
+                    ctx.insert_variable(&section.name, section.kind, var);
+                }
+            }
+            for symbol in module.ctx.get_symbols().iter() {
+                let mut symbol = symbol.clone();
+                if let Some(name) = rename(&symbol.name) {
+                    symbol.name = name;
+                } else if lost(&symbol.name) {
+                    symbol.name = local(&symbol.name);
+                    symbol.binding = Binding::Local;
+                }
+                ctx.add_symbol(symbol);
+            }
+            ctx.label_counter = ctx.label_counter.max(module.ctx.label_counter);
+            arch.append(module.arch);
+        }
+        Ok(Module { arch, ctx })
+    }
+}
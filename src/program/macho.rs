@@ -0,0 +1,1000 @@
+//! Mach-O images for arm64 macOS.
+//!
+//! [`MachO`] writes segments of sections, the symbol table and the
+//! information dyld needs to load an image: rebases sliding pointers by the
+//! load address, binds filling GOT entries with symbols from the dylibs it
+//! loads, and the export trie. Every image ends with an ad-hoc code
+//! signature, which arm64 macOS requires before it runs anything. Nothing
+//! depends on Apple tools, so the output can be byte-checked on any host.
+//! [`Program::dylib`] and [`Module::dylib`] fill one in as a dynamic
+//! library: `__TEXT` with the headers, code and constants, then `__DATA`
+//! with the GOT, data and zero-fill on the following page.
+//! [`Program::macho`] and [`Module::macho`] lay out an executable the same
+//! way above a 4 GiB `__PAGEZERO`, started by dyld through `LC_MAIN`.
+
+use super::got::{self, Got};
+use super::sha256::sha256;
+use super::{Module, Program};
+use crate::arch::arm64::encoder::{assemble, EncodeError};
+use crate::arch::arm64::{Arm64Register, ARM64};
+use crate::context::{Binding, Context, SectionKind, Symbol, SymbolTable, Value, Variable, Visibility};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The arm64 page size, which segments are aligned to.
+pub const PAGE: u64 = 0x4000;
+
+/// Where the `__TEXT` segment of an executable starts, above `__PAGEZERO`.
+pub const TEXT_ADDRESS: u64 = 0x1_0000_0000;
+
+/// The size of the pages the code signature hashes.
+pub const SIGNATURE_PAGE: u64 = 0x1000;
+
+/// Segment protections, as in `initprot`.
+pub const VM_PROT_READ: u32 = 1;
+pub const VM_PROT_WRITE: u32 = 2;
+pub const VM_PROT_EXECUTE: u32 = 4;
+
+const HEADER_SIZE: u64 = 32;
+const NLIST_SIZE: u64 = 16;
+
+#[derive(Debug, PartialEq)]
+pub enum MachOError {
+    Encode(EncodeError),
+    UndefinedEntry(String),
+    /// A rebase or bind at this address is outside every writable segment
+    Fixup(u64),
+    /// The load commands need this many bytes before the first section
+    HeaderSpace(u64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileType {
+    /// A dynamic library, loaded by `install_name`
+    Dylib { install_name: String },
+    /// A position-independent executable starting at the address `entry`
+    Execute { entry: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SectionType {
+    Code,
+    Data,
+    /// GOT entries, each bound or rebased by dyld
+    Got,
+    /// Zero-filled memory taking no file space
+    ZeroFill,
+}
+
+#[derive(Debug, Clone)]
+pub struct MachSection {
+    pub name: String,
+    pub kind: SectionType,
+    pub addr: u64,
+    /// Empty for zero fill
+    pub bytes: Vec<u8>,
+    pub size: u64,
+    /// Alignment as a power of two
+    pub align: u32,
+}
+
+impl MachSection {
+    pub fn new(name: &str, kind: SectionType, addr: u64, bytes: Vec<u8>) -> Self {
+        let align = match kind {
+            SectionType::Code => 2,
+            _ => 3,
+        };
+        Self { name: name.to_string(), kind, addr, size: bytes.len() as u64, bytes, align }
+    }
+
+    pub fn zero_fill(name: &str, addr: u64, size: u64) -> Self {
+        Self { name: name.to_string(), kind: SectionType::ZeroFill, addr, bytes: Vec::new(), size, align: 3 }
+    }
+
+    fn contains(&self, addr: u64) -> bool {
+        addr >= self.addr && addr < self.addr + self.size
+    }
+
+    fn flags(&self) -> u32 {
+        match self.kind {
+            SectionType::Code => S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS,
+            SectionType::Data => S_REGULAR,
+            SectionType::Got => S_NON_LAZY_SYMBOL_POINTERS,
+            SectionType::ZeroFill => S_ZEROFILL,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MachSegment {
+    pub name: String,
+    pub vmaddr: u64,
+    pub vmsize: u64,
+    pub prot: u32,
+    pub sections: Vec<MachSection>,
+}
+
+impl MachSegment {
+    pub fn new(name: &str, vmaddr: u64, vmsize: u64, prot: u32) -> Self {
+        Self { name: name.to_string(), vmaddr, vmsize, prot, sections: Vec::new() }
+    }
+
+    fn contains(&self, addr: u64) -> bool {
+        addr >= self.vmaddr && addr < self.vmaddr + self.vmsize
+    }
+
+    /// The end of the section bytes in the file, relative to the segment.
+    fn file_size(&self) -> u64 {
+        self.sections
+            .iter()
+            .filter(|section| section.kind != SectionType::ZeroFill)
+            .map(|section| section.addr + section.size - self.vmaddr)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// A GOT entry dyld sets to the address of `symbol` from the first dylib.
+#[derive(Debug, Clone)]
+pub struct Bind {
+    pub address: u64,
+    pub symbol: String,
+    /// Left zero if the symbol is missing
+    pub weak: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct MachO {
+    pub file_type: FileType,
+    pub segments: Vec<MachSegment>,
+    /// Defined symbols with their addresses
+    pub symbols: Vec<(Symbol, u64)>,
+    /// Addresses of pointers dyld adds the slide to
+    pub rebases: Vec<u64>,
+    pub binds: Vec<Bind>,
+    /// Dylibs to load, in order
+    pub dylibs: Vec<String>,
+    /// The name the code signature gives the image
+    pub identifier: String,
+}
+
+/// Where the `__LINKEDIT` tables are in the file, and their sizes.
+#[derive(Default)]
+struct Tables {
+    fileoff: u64,
+    rebase: (u64, u64),
+    bind: (u64, u64),
+    exports: (u64, u64),
+    symbols: (u64, u64),
+    indirect: (u64, u64),
+    strings: (u64, u64),
+    signature: (u64, u64),
+    locals: u64,
+    defined: u64,
+    undefined: u64,
+}
+
+impl Tables {
+    fn end(&self) -> u64 {
+        self.signature.0 + self.signature.1
+    }
+}
+
+impl MachO {
+    /// An image without segments, loading libSystem. Dylibs are identified
+    /// by the file name of their install name and executables as `a.out`.
+    pub fn new(file_type: FileType) -> Self {
+        let identifier = match &file_type {
+            FileType::Dylib { install_name } => install_name.rsplit('/').next().unwrap_or(install_name),
+            FileType::Execute { .. } => "a.out",
+        };
+        Self {
+            identifier: identifier.to_string(),
+            file_type,
+            segments: Vec::new(),
+            symbols: Vec::new(),
+            rebases: Vec::new(),
+            binds: Vec::new(),
+            dylibs: vec![LIB_SYSTEM.to_string()],
+        }
+    }
+
+    /// The address of the symbol `name`.
+    pub fn symbol(&self, name: &str) -> Option<u64> {
+        self.symbols.iter().find(|(symbol, _)| symbol.name == name).map(|(_, addr)| *addr)
+    }
+
+    /// The size of the header and load commands, which depends only on
+    /// the file type, the dylibs and the segments and sections.
+    pub fn header_size(&self) -> u64 {
+        HEADER_SIZE + self.commands(&Tables::default()).1.len() as u64
+    }
+
+    /// The address of the header: the first segment with contents.
+    fn base(&self) -> u64 {
+        self.segments.iter().find(|segment| !segment.sections.is_empty()).map_or(0, |segment| segment.vmaddr)
+    }
+
+    fn sections(&self) -> impl Iterator<Item = &MachSection> {
+        self.segments.iter().flat_map(|segment| segment.sections.iter())
+    }
+
+    /// The index of the section holding `addr`, counting from one, if any.
+    fn section_index(&self, addr: u64) -> Option<u8> {
+        let sections: Vec<&MachSection> = self.sections().collect();
+        sections
+            .iter()
+            .position(|section| section.contains(addr))
+            .or_else(|| sections.iter().position(|section| section.addr + section.size == addr))
+            .map(|index| index as u8 + 1)
+    }
+
+    /// The segment index and offset of a fixup at `address`.
+    fn fixup(&self, address: u64) -> Result<(u8, u64), MachOError> {
+        self.segments
+            .iter()
+            .position(|segment| segment.prot & VM_PROT_WRITE != 0 && segment.contains(address))
+            .map(|index| (index as u8, address - self.segments[index].vmaddr))
+            .ok_or(MachOError::Fixup(address))
+    }
+
+    /// The segment holding the code, whose pages the signature marks as
+    /// executable.
+    fn text_segment(&self) -> Option<&MachSegment> {
+        self.segments.iter().find(|segment| segment.sections.iter().any(|section| section.kind == SectionType::Code))
+    }
+
+    /// The size of the code signature for the `code_limit` bytes before it.
+    fn signature_size(&self, code_limit: u64) -> u64 {
+        let pages = code_limit.div_ceil(SIGNATURE_PAGE);
+        SUPER_BLOB_SIZE + CODE_DIRECTORY_SIZE + self.identifier.len() as u64 + 1 + pages * HASH_SIZE
+    }
+
+    /// An ad-hoc signature as the linker writes it: a code directory
+    /// hashing each page of `code` with SHA-256, and no requirements or
+    /// entitlements.
+    fn code_signature(&self, code: &[u8]) -> Vec<u8> {
+        let pages = code.len().div_ceil(SIGNATURE_PAGE as usize);
+        let length = self.signature_size(code.len() as u64) - SUPER_BLOB_SIZE;
+        let ident_offset = CODE_DIRECTORY_SIZE;
+        let hash_offset = ident_offset + self.identifier.len() as u64 + 1;
+        let (exec_base, exec_limit) = match self.text_segment() {
+            Some(segment) => (segment.vmaddr - self.base(), segment.file_size().next_multiple_of(PAGE)),
+            None => (0, 0),
+        };
+        let exec_flags = match self.file_type {
+            FileType::Execute { .. } => CS_EXECSEG_MAIN_BINARY,
+            FileType::Dylib { .. } => 0,
+        };
+
+        // Signatures are big-endian
+        let mut out = Vec::new();
+        for value in [CSMAGIC_EMBEDDED_SIGNATURE, (SUPER_BLOB_SIZE + length) as u32, 1, CSSLOT_CODEDIRECTORY, SUPER_BLOB_SIZE as u32] {
+            out.extend(value.to_be_bytes());
+        }
+        let fields = [
+            CSMAGIC_CODEDIRECTORY,
+            length as u32,
+            CS_SUPPORTSEXECSEG,
+            CS_ADHOC | CS_LINKER_SIGNED,
+            hash_offset as u32,
+            ident_offset as u32,
+            // No special slots
+            0,
+            pages as u32,
+            code.len() as u32,
+        ];
+        for value in fields {
+            out.extend(value.to_be_bytes());
+        }
+        out.extend([HASH_SIZE as u8, CS_HASHTYPE_SHA256, 0, SIGNATURE_PAGE.trailing_zeros() as u8]);
+        // Spare, scatter, team and spare fields, then the 64-bit code limit
+        // which is only needed past 4 GiB
+        out.extend([0; 24]);
+        for value in [exec_base, exec_limit, exec_flags] {
+            out.extend(value.to_be_bytes());
+        }
+        out.extend(self.identifier.as_bytes());
+        out.push(0);
+        for page in code.chunks(SIGNATURE_PAGE as usize) {
+            out.extend(sha256(page));
+        }
+        out
+    }
+
+    /// The `__LINKEDIT` contents, placed at `fileoff`, with where each table
+    /// is. The space for the code signature is left at the end.
+    fn linkedit(&self, fileoff: u64) -> Result<(Vec<u8>, Tables), MachOError> {
+        let mut rebase = vec![REBASE_OPCODE_SET_TYPE_IMM | REBASE_TYPE_POINTER];
+        for &address in &self.rebases {
+            let (segment, offset) = self.fixup(address)?;
+            rebase.push(REBASE_OPCODE_SET_SEGMENT_AND_OFFSET_ULEB | segment);
+            uleb(&mut rebase, offset);
+            rebase.push(REBASE_OPCODE_DO_REBASE_IMM_TIMES | 1);
+        }
+        rebase.push(REBASE_OPCODE_DONE);
+
+        let mut bind = Vec::new();
+        for entry in &self.binds {
+            let (segment, offset) = self.fixup(entry.address)?;
+            bind.push(BIND_OPCODE_SET_DYLIB_ORDINAL_IMM | 1);
+            let flags = match entry.weak {
+                true => BIND_SYMBOL_FLAGS_WEAK_IMPORT,
+                false => 0,
+            };
+            bind.push(BIND_OPCODE_SET_SYMBOL_TRAILING_FLAGS_IMM | flags);
+            bind.extend(entry.symbol.as_bytes());
+            bind.push(0);
+            bind.push(BIND_OPCODE_SET_TYPE_IMM | BIND_TYPE_POINTER);
+            bind.push(BIND_OPCODE_SET_SEGMENT_AND_OFFSET_ULEB | segment);
+            uleb(&mut bind, offset);
+            bind.push(BIND_OPCODE_DO_BIND);
+        }
+        bind.push(BIND_OPCODE_DONE);
+
+        // Locals, then defined external symbols, then undefined ones
+        let exported = |symbol: &Symbol| symbol.binding != Binding::Local && symbol.visibility != Visibility::Hidden;
+        let base = self.base();
+        let mut exports = Vec::new();
+        let mut strings = vec![0u8];
+        let mut nlist = Vec::new();
+        let string = |strings: &mut Vec<u8>, name: &str| {
+            let offset = strings.len() as u32;
+            strings.extend(name.as_bytes());
+            strings.push(0);
+            offset
+        };
+        let locals: Vec<&(Symbol, u64)> = self.symbols.iter().filter(|(symbol, _)| !exported(symbol)).collect();
+        let defined: Vec<&(Symbol, u64)> = self.symbols.iter().filter(|(symbol, _)| exported(symbol)).collect();
+        for (symbol, addr) in locals.iter().chain(&defined) {
+            let (kind, sect) = match self.section_index(*addr) {
+                Some(sect) => (N_SECT, sect),
+                None => (N_ABS, 0),
+            };
+            let external = match exported(symbol) {
+                true => N_EXT,
+                false => 0,
+            };
+            let desc = match symbol.binding {
+                Binding::Weak => N_WEAK_DEF,
+                _ => 0,
+            };
+            if exported(symbol) {
+                let flags = match symbol.binding {
+                    Binding::Weak => EXPORT_SYMBOL_FLAGS_WEAK_DEFINITION,
+                    _ => 0,
+                };
+                exports.push((symbol.name.as_str(), flags, addr - base));
+            }
+            nlist.extend(string(&mut strings, &symbol.name).to_le_bytes());
+            nlist.extend([kind | external, sect]);
+            nlist.extend(desc.to_le_bytes());
+            nlist.extend(addr.to_le_bytes());
+        }
+        let mut undefined: Vec<&Bind> = Vec::new();
+        for entry in &self.binds {
+            if !undefined.iter().any(|other| other.symbol == entry.symbol) {
+                undefined.push(entry);
+            }
+        }
+        for entry in &undefined {
+            // Found in the first dylib
+            let mut desc: u16 = 1 << 8;
+            if entry.weak {
+                desc |= N_WEAK_REF;
+            }
+            nlist.extend(string(&mut strings, &entry.symbol).to_le_bytes());
+            nlist.extend([N_UNDF | N_EXT, 0]);
+            nlist.extend(desc.to_le_bytes());
+            nlist.extend(0u64.to_le_bytes());
+        }
+
+        // One indirect entry per GOT slot, naming the symbol bound there
+        let first_undefined = (locals.len() + defined.len()) as u32;
+        let mut indirect = Vec::new();
+        for section in self.sections().filter(|section| section.kind == SectionType::Got) {
+            for slot in (section.addr..section.addr + section.size).step_by(8) {
+                let index = match self.binds.iter().find(|entry| entry.address == slot) {
+                    Some(entry) => first_undefined + undefined.iter().position(|other| other.symbol == entry.symbol).unwrap() as u32,
+                    None => INDIRECT_SYMBOL_LOCAL,
+                };
+                indirect.extend(index.to_le_bytes());
+            }
+        }
+
+        let mut bytes = Vec::new();
+        let place = |bytes: &mut Vec<u8>, table: Vec<u8>| {
+            align(bytes, 8);
+            let offset = fileoff + bytes.len() as u64;
+            let size = table.len() as u64;
+            bytes.extend(table);
+            (offset, size)
+        };
+        let tables = Tables {
+            fileoff,
+            rebase: place(&mut bytes, rebase),
+            bind: place(&mut bytes, bind),
+            exports: place(&mut bytes, export_trie(&exports)),
+            symbols: place(&mut bytes, nlist),
+            indirect: place(&mut bytes, indirect),
+            strings: place(&mut bytes, strings),
+            signature: (0, 0),
+            locals: locals.len() as u64,
+            defined: defined.len() as u64,
+            undefined: undefined.len() as u64,
+        };
+        align(&mut bytes, 16);
+        let offset = fileoff + bytes.len() as u64;
+        let tables = Tables { signature: (offset, self.signature_size(offset)), ..tables };
+        Ok((bytes, tables))
+    }
+
+    /// The number of load commands and their bytes.
+    fn commands(&self, tables: &Tables) -> (u32, Vec<u8>) {
+        let mut commands = Vec::new();
+        let mut count = 0;
+        let mut command = |kind: u32, body: Vec<u8>| {
+            commands.extend(kind.to_le_bytes());
+            commands.extend((body.len() as u32 + 8).to_le_bytes());
+            commands.extend(body);
+            count += 1;
+        };
+
+        let base = self.base();
+        let linkedit_vmaddr = self.segments.iter().map(|segment| segment.vmaddr + segment.vmsize).max().unwrap_or(0).next_multiple_of(PAGE);
+        let mut indirect = 0;
+        for segment in &self.segments {
+            let (fileoff, filesize) = match segment.sections.is_empty() {
+                true => (0, 0),
+                false => (segment.vmaddr - base, segment.file_size().next_multiple_of(PAGE)),
+            };
+            let mut body = segment_command(&segment.name, segment.vmaddr, segment.vmsize, fileoff, filesize, segment.prot);
+            body.extend((segment.sections.len() as u32).to_le_bytes());
+            body.extend(0u32.to_le_bytes());
+            for section in &segment.sections {
+                let offset = match section.kind {
+                    SectionType::ZeroFill => 0,
+                    _ => section.addr - base,
+                };
+                let reserved1 = match section.kind {
+                    SectionType::Got => indirect,
+                    _ => 0,
+                };
+                if section.kind == SectionType::Got {
+                    indirect += (section.size / 8) as u32;
+                }
+                body.extend(name16(&section.name));
+                body.extend(name16(&segment.name));
+                body.extend(section.addr.to_le_bytes());
+                body.extend(section.size.to_le_bytes());
+                body.extend((offset as u32).to_le_bytes());
+                body.extend(section.align.to_le_bytes());
+                // No relocations
+                body.extend([0; 8]);
+                body.extend(section.flags().to_le_bytes());
+                body.extend(reserved1.to_le_bytes());
+                body.extend([0; 8]);
+            }
+            command(LC_SEGMENT_64, body);
+        }
+        let linkedit_size = tables.end().saturating_sub(tables.fileoff);
+        let mut body = segment_command("__LINKEDIT", linkedit_vmaddr, linkedit_size.next_multiple_of(PAGE), tables.fileoff, linkedit_size, VM_PROT_READ);
+        body.extend([0; 8]);
+        command(LC_SEGMENT_64, body);
+
+        let mut body = Vec::new();
+        // Rebase, bind, weak bind, lazy bind and export information
+        for (offset, size) in [tables.rebase, tables.bind, (0, 0), (0, 0), tables.exports] {
+            body.extend((offset as u32).to_le_bytes());
+            body.extend((size as u32).to_le_bytes());
+        }
+        command(LC_DYLD_INFO_ONLY, body);
+
+        let mut body = Vec::new();
+        for value in [tables.symbols.0, tables.symbols.1 / NLIST_SIZE, tables.strings.0, tables.strings.1] {
+            body.extend((value as u32).to_le_bytes());
+        }
+        command(LC_SYMTAB, body);
+
+        let mut body = Vec::new();
+        let fields = [
+            0,
+            tables.locals,
+            tables.locals,
+            tables.defined,
+            tables.locals + tables.defined,
+            tables.undefined,
+            // No table of contents, modules or external references
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            tables.indirect.0,
+            tables.indirect.1 / 4,
+            // No relocations
+            0,
+            0,
+            0,
+            0,
+        ];
+        for value in fields {
+            body.extend((value as u32).to_le_bytes());
+        }
+        command(LC_DYSYMTAB, body);
+
+        match &self.file_type {
+            FileType::Dylib { install_name } => command(LC_ID_DYLIB, dylib_command(install_name)),
+            FileType::Execute { .. } => command(LC_LOAD_DYLINKER, dylinker_command(DYLD)),
+        }
+
+        let mut body = Vec::new();
+        // macOS 11.0 with the matching SDK and no tools
+        for value in [PLATFORM_MACOS, 0x000b_0000, 0x000b_0000, 0] {
+            body.extend(value.to_le_bytes());
+        }
+        command(LC_BUILD_VERSION, body);
+
+        if let FileType::Execute { entry } = self.file_type {
+            // The entry as a file offset, on the default stack
+            let mut body = entry.wrapping_sub(base).to_le_bytes().to_vec();
+            body.extend(0u64.to_le_bytes());
+            command(LC_MAIN, body);
+        }
+
+        for dylib in &self.dylibs {
+            command(LC_LOAD_DYLIB, dylib_command(dylib));
+        }
+
+        let mut body = Vec::new();
+        body.extend((tables.signature.0 as u32).to_le_bytes());
+        body.extend((tables.signature.1 as u32).to_le_bytes());
+        command(LC_CODE_SIGNATURE, body);
+        (count, commands)
+    }
+
+    /// The image file.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MachOError> {
+        let base = self.base();
+        // File offsets mirror addresses from the header
+        let file_end = self
+            .segments
+            .iter()
+            .filter(|segment| !segment.sections.is_empty())
+            .map(|segment| segment.vmaddr - base + segment.file_size().next_multiple_of(PAGE))
+            .max()
+            .unwrap_or(0);
+        let (linkedit, tables) = self.linkedit(file_end)?;
+        let (ncmds, commands) = self.commands(&tables);
+
+        let header_size = HEADER_SIZE + commands.len() as u64;
+        let first = self.sections().filter(|section| section.kind != SectionType::ZeroFill).map(|section| section.addr - base).min();
+        if first.is_some_and(|first| first < header_size) {
+            return Err(MachOError::HeaderSpace(header_size));
+        }
+
+        let (file_type, mut flags) = match self.file_type {
+            FileType::Dylib { .. } => (MH_DYLIB, MH_NOUNDEFS | MH_DYLDLINK | MH_TWOLEVEL | MH_NO_REEXPORTED_DYLIBS),
+            FileType::Execute { .. } => (MH_EXECUTE, MH_NOUNDEFS | MH_DYLDLINK | MH_TWOLEVEL | MH_PIE),
+        };
+        // dyld coalesces exported weak definitions only in images marked
+        // as having them
+        let weak = |symbol: &Symbol| symbol.binding == Binding::Weak && symbol.visibility != Visibility::Hidden;
+        if self.symbols.iter().any(|(symbol, _)| weak(symbol)) {
+            flags |= MH_WEAK_DEFINES;
+        }
+        let mut out = Vec::new();
+        out.extend(MH_MAGIC_64.to_le_bytes());
+        out.extend(CPU_TYPE_ARM64.to_le_bytes());
+        out.extend(CPU_SUBTYPE_ARM64_ALL.to_le_bytes());
+        out.extend(file_type.to_le_bytes());
+        out.extend(ncmds.to_le_bytes());
+        out.extend((commands.len() as u32).to_le_bytes());
+        out.extend(flags.to_le_bytes());
+        out.extend(0u32.to_le_bytes());
+        out.extend(commands);
+
+        out.resize(file_end as usize, 0);
+        for section in self.sections().filter(|section| section.kind != SectionType::ZeroFill) {
+            let offset = (section.addr - base) as usize;
+            out[offset..offset + section.bytes.len()].copy_from_slice(&section.bytes);
+        }
+        out.extend(linkedit);
+        let signature = self.code_signature(&out);
+        out.extend(signature);
+        Ok(out)
+    }
+
+    /// Writes the image to `path` and marks it executable.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let bytes = self.to_bytes().map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", error)))?;
+        fs::write(path, bytes)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(())
+    }
+}
+
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+const CPU_TYPE_ARM64: u32 = 0x0100_000c;
+const CPU_SUBTYPE_ARM64_ALL: u32 = 0;
+const MH_EXECUTE: u32 = 2;
+const MH_DYLIB: u32 = 6;
+const MH_NOUNDEFS: u32 = 0x1;
+const MH_DYLDLINK: u32 = 0x4;
+const MH_TWOLEVEL: u32 = 0x80;
+const MH_WEAK_DEFINES: u32 = 0x8000;
+const MH_PIE: u32 = 0x20_0000;
+const MH_NO_REEXPORTED_DYLIBS: u32 = 0x10_0000;
+const LC_SYMTAB: u32 = 0x2;
+const LC_DYSYMTAB: u32 = 0xb;
+const LC_LOAD_DYLIB: u32 = 0xc;
+const LC_ID_DYLIB: u32 = 0xd;
+const LC_LOAD_DYLINKER: u32 = 0xe;
+const LC_SEGMENT_64: u32 = 0x19;
+const LC_CODE_SIGNATURE: u32 = 0x1d;
+const LC_BUILD_VERSION: u32 = 0x32;
+const LC_DYLD_INFO_ONLY: u32 = 0x8000_0022;
+const LC_MAIN: u32 = 0x8000_0028;
+const PLATFORM_MACOS: u32 = 1;
+const S_REGULAR: u32 = 0x0;
+const S_ZEROFILL: u32 = 0x1;
+const S_NON_LAZY_SYMBOL_POINTERS: u32 = 0x6;
+const S_ATTR_PURE_INSTRUCTIONS: u32 = 0x8000_0000;
+const S_ATTR_SOME_INSTRUCTIONS: u32 = 0x400;
+const N_UNDF: u8 = 0x0;
+const N_EXT: u8 = 0x1;
+const N_ABS: u8 = 0x2;
+const N_SECT: u8 = 0xe;
+const N_WEAK_REF: u16 = 0x40;
+const N_WEAK_DEF: u16 = 0x80;
+const EXPORT_SYMBOL_FLAGS_WEAK_DEFINITION: u16 = 0x04;
+const INDIRECT_SYMBOL_LOCAL: u32 = 0x8000_0000;
+const REBASE_TYPE_POINTER: u8 = 1;
+const REBASE_OPCODE_DONE: u8 = 0x00;
+const REBASE_OPCODE_SET_TYPE_IMM: u8 = 0x10;
+const REBASE_OPCODE_SET_SEGMENT_AND_OFFSET_ULEB: u8 = 0x20;
+const REBASE_OPCODE_DO_REBASE_IMM_TIMES: u8 = 0x50;
+const BIND_TYPE_POINTER: u8 = 1;
+const BIND_SYMBOL_FLAGS_WEAK_IMPORT: u8 = 0x1;
+const BIND_OPCODE_DONE: u8 = 0x00;
+const BIND_OPCODE_SET_DYLIB_ORDINAL_IMM: u8 = 0x10;
+const BIND_OPCODE_SET_SYMBOL_TRAILING_FLAGS_IMM: u8 = 0x40;
+const BIND_OPCODE_SET_TYPE_IMM: u8 = 0x50;
+const BIND_OPCODE_SET_SEGMENT_AND_OFFSET_ULEB: u8 = 0x70;
+const BIND_OPCODE_DO_BIND: u8 = 0x90;
+const CSMAGIC_EMBEDDED_SIGNATURE: u32 = 0xfade_0cc0;
+const CSMAGIC_CODEDIRECTORY: u32 = 0xfade_0c02;
+const CSSLOT_CODEDIRECTORY: u32 = 0;
+/// The code directory version with executable segment fields
+const CS_SUPPORTSEXECSEG: u32 = 0x2_0400;
+const CS_ADHOC: u32 = 0x2;
+const CS_LINKER_SIGNED: u32 = 0x2_0000;
+const CS_HASHTYPE_SHA256: u8 = 2;
+const CS_EXECSEG_MAIN_BINARY: u64 = 0x1;
+/// The super blob header with its one index entry
+const SUPER_BLOB_SIZE: u64 = 20;
+const CODE_DIRECTORY_SIZE: u64 = 88;
+const HASH_SIZE: u64 = 32;
+const LIB_SYSTEM: &str = "/usr/lib/libSystem.B.dylib";
+const DYLD: &str = "/usr/lib/dyld";
+
+/// A name padded to the 16 bytes of a segment or section name.
+fn name16(name: &str) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    bytes[..name.len()].copy_from_slice(name.as_bytes());
+    bytes
+}
+
+/// The fields of `LC_SEGMENT_64` up to the section count.
+fn segment_command(name: &str, vmaddr: u64, vmsize: u64, fileoff: u64, filesize: u64, prot: u32) -> Vec<u8> {
+    let mut body = name16(name).to_vec();
+    for value in [vmaddr, vmsize, fileoff, filesize] {
+        body.extend(value.to_le_bytes());
+    }
+    // The maximum protection is the initial one
+    body.extend(prot.to_le_bytes());
+    body.extend(prot.to_le_bytes());
+    body
+}
+
+/// The body of `LC_ID_DYLIB` or `LC_LOAD_DYLIB`, at version 1.0.0.
+fn dylib_command(name: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    // The name follows the 24-byte command
+    body.extend(24u32.to_le_bytes());
+    body.extend(2u32.to_le_bytes());
+    body.extend(0x1_0000u32.to_le_bytes());
+    body.extend(0x1_0000u32.to_le_bytes());
+    body.extend(name.as_bytes());
+    body.push(0);
+    body.resize((body.len() + 8).next_multiple_of(8) - 8, 0);
+    body
+}
+
+/// The body of `LC_LOAD_DYLINKER`.
+fn dylinker_command(name: &str) -> Vec<u8> {
+    // The name follows the 12-byte command
+    let mut body = 12u32.to_le_bytes().to_vec();
+    body.extend(name.as_bytes());
+    body.push(0);
+    body.resize((body.len() + 8).next_multiple_of(8) - 8, 0);
+    body
+}
+
+fn uleb(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        match value {
+            0 => {
+                out.push(byte);
+                return;
+            }
+            _ => out.push(byte | 0x80),
+        }
+    }
+}
+
+fn uleb_size(value: u64) -> u64 {
+    let mut bytes = Vec::new();
+    uleb(&mut bytes, value);
+    bytes.len() as u64
+}
+
+fn align(out: &mut Vec<u8>, align: usize) {
+    out.resize(out.len().next_multiple_of(align), 0);
+}
+
+/// A node of the export trie: the symbol ending here, if any, as its
+/// flags and offset from the header, and edges labelled with the rest of
+/// each name.
+#[derive(Default)]
+struct TrieNode {
+    terminal: Option<(u16, u64)>,
+    edges: Vec<(String, usize)>,
+}
+
+/// Adds the node for `names`, which have had a common prefix removed,
+/// returning its index. No two edges from a node share a first byte.
+fn trie_node(nodes: &mut Vec<TrieNode>, names: &[(&str, u16, u64)]) -> usize {
+    let index = nodes.len();
+    nodes.push(TrieNode::default());
+    nodes[index].terminal = names.iter().find(|(name, _, _)| name.is_empty()).map(|(_, flags, offset)| (*flags, *offset));
+    let mut rest: Vec<&(&str, u16, u64)> = names.iter().filter(|(name, _, _)| !name.is_empty()).collect();
+    rest.sort_by_key(|(name, _, _)| *name);
+    let mut start = 0;
+    while start < rest.len() {
+        let first = rest[start].0.as_bytes()[0];
+        let end = start + rest[start..].iter().take_while(|(name, _, _)| name.as_bytes()[0] == first).count();
+        let group = &rest[start..end];
+        let mut prefix = group[0].0.len();
+        for (name, _, _) in group {
+            prefix = prefix.min(group[0].0.bytes().zip(name.bytes()).take_while(|(a, b)| a == b).count());
+        }
+        let label = group[0].0[..prefix].to_string();
+        let suffixes: Vec<(&str, u16, u64)> = group.iter().map(|(name, flags, offset)| (&name[prefix..], *flags, *offset)).collect();
+        let child = trie_node(nodes, &suffixes);
+        nodes[index].edges.push((label, child));
+        start = end;
+    }
+    index
+}
+
+/// The export trie for `exports`: names with their flags and offsets from
+/// the header.
+fn export_trie(exports: &[(&str, u16, u64)]) -> Vec<u8> {
+    if exports.is_empty() {
+        return Vec::new();
+    }
+    let mut nodes = Vec::new();
+    trie_node(&mut nodes, exports);
+
+    let terminal = |node: &TrieNode| {
+        let mut info = Vec::new();
+        if let Some((flags, offset)) = node.terminal {
+            uleb(&mut info, flags as u64);
+            uleb(&mut info, offset);
+        }
+        info
+    };
+    // Node offsets depend on the sizes of the offsets before them, so
+    // settle them by repetition
+    let mut offsets = vec![0u64; nodes.len()];
+    loop {
+        let mut at = 0;
+        let mut changed = false;
+        for (index, node) in nodes.iter().enumerate() {
+            if offsets[index] != at {
+                offsets[index] = at;
+                changed = true;
+            }
+            let info = terminal(node).len() as u64;
+            at += uleb_size(info) + info + 1;
+            at += node.edges.iter().map(|(label, child)| label.len() as u64 + 1 + uleb_size(offsets[*child])).sum::<u64>();
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut out = Vec::new();
+    for node in &nodes {
+        let info = terminal(node);
+        uleb(&mut out, info.len() as u64);
+        out.extend(info);
+        out.push(node.edges.len() as u8);
+        for (label, child) in &node.edges {
+            out.extend(label.as_bytes());
+            out.push(0);
+            uleb(&mut out, offsets[*child]);
+        }
+    }
+    out
+}
+
+impl Program<ARM64, Arm64Register> {
+    /// A macOS dynamic library loaded by `install_name`, exporting the
+    /// program's global symbols. Undefined calls and GOT loads are bound
+    /// to libSystem.
+    pub fn dylib(&self, install_name: &str) -> Result<MachO, MachOError> {
+        image(&self.ins.arch, &self.ctx, &self.symbols(), Output::Dylib(install_name))
+    }
+
+    /// A macOS executable starting at the label `entry`, which dyld calls
+    /// as `main`. Undefined calls and GOT loads are bound to libSystem.
+    pub fn macho(&self, entry: &str) -> Result<MachO, MachOError> {
+        image(&self.ins.arch, &self.ctx, &self.symbols(), Output::Execute(entry))
+    }
+}
+
+impl Module<ARM64> {
+    /// A macOS dynamic library, as [`Program::dylib`].
+    pub fn dylib(&self, install_name: &str) -> Result<MachO, MachOError> {
+        image(&self.arch, &self.ctx, self.ctx.get_symbols(), Output::Dylib(install_name))
+    }
+
+    /// A macOS executable, as [`Program::macho`].
+    pub fn macho(&self, entry: &str) -> Result<MachO, MachOError> {
+        image(&self.arch, &self.ctx, self.ctx.get_symbols(), Output::Execute(entry))
+    }
+}
+
+/// Mark the end of the constants and the page where `__DATA` starts.
+const CONST_END: &str = "L__const_end";
+const DATA_START: &str = "L__data_start";
+
+/// What an image is built as.
+enum Output<'a> {
+    /// A dylib with this install name
+    Dylib(&'a str),
+    /// An executable starting at this label
+    Execute(&'a str),
+}
+
+fn image(arch: &ARM64, ctx: &Context, symbols: &SymbolTable, output: Output) -> Result<MachO, MachOError> {
+    let mut arch = arch.clone();
+    let sections = ctx.get_sections();
+    let got = Got::new(&mut arch, sections, true);
+
+    // Pointers are rebased at load time, so read-only ones move to the
+    // writable segment
+    let pointer = |var: &Variable| matches!(var.value, Value::Pointer { .. });
+    let (relocated, readonly): (Vec<_>, Vec<_>) = sections
+        .iter()
+        .filter(|section| matches!(section.kind, SectionKind::Text | SectionKind::Rodata))
+        .flat_map(|section| section.variables())
+        .partition(|var| pointer(var));
+    let mut data = relocated;
+    data.extend(sections.iter().filter(|section| section.kind == SectionKind::Data).flat_map(|section| section.variables()));
+    let bss: Vec<_> = sections.iter().filter(|section| section.kind == SectionKind::Bss).flat_map(|section| section.variables()).collect();
+
+    let has_const = !arch.get_rodata().is_empty() || !readonly.is_empty();
+    let has_data = !got.entries.is_empty() || !data.is_empty() || !bss.is_empty();
+    for var in &readonly {
+        arch.global(&var.label, &var.value, var.align);
+    }
+    if has_data {
+        arch.global(CONST_END, &Value::Zero(0), 1);
+        arch.global(DATA_START, &Value::Zero(0), PAGE);
+        for (label, value) in &got.entries {
+            arch.global(label, value, 8);
+        }
+        for var in data.iter().chain(&bss) {
+            arch.global(&var.label, &var.value, var.align);
+        }
+    }
+
+    // Sections are placed once the header size is known, and the entry
+    // once the code is assembled
+    let mut macho = match output {
+        Output::Dylib(install_name) => MachO::new(FileType::Dylib { install_name: install_name.to_string() }),
+        Output::Execute(_) => MachO::new(FileType::Execute { entry: 0 }),
+    };
+    let text_address = match output {
+        Output::Dylib(_) => 0,
+        Output::Execute(_) => {
+            macho.segments.push(MachSegment::new("__PAGEZERO", 0, TEXT_ADDRESS, 0));
+            TEXT_ADDRESS
+        }
+    };
+    let first = macho.segments.len();
+    let mut text = MachSegment::new("__TEXT", text_address, 0, VM_PROT_READ | VM_PROT_EXECUTE);
+    text.sections.push(MachSection::new("__text", SectionType::Code, text_address, Vec::new()));
+    if has_const {
+        text.sections.push(MachSection::new("__const", SectionType::Data, text_address, Vec::new()));
+    }
+    macho.segments.push(text);
+    if has_data {
+        let mut segment = MachSegment::new("__DATA", text_address, 0, VM_PROT_READ | VM_PROT_WRITE);
+        if !got.entries.is_empty() {
+            segment.sections.push(MachSection::new("__got", SectionType::Got, text_address, Vec::new()));
+        }
+        if !data.is_empty() {
+            segment.sections.push(MachSection::new("__data", SectionType::Data, text_address, Vec::new()));
+        }
+        if !bss.is_empty() {
+            segment.sections.push(MachSection::zero_fill("__bss", text_address, 0));
+        }
+        macho.segments.push(segment);
+    }
+    let text_base = text_address + macho.header_size().next_multiple_of(16);
+    let out = assemble(&arch, text_base).map_err(MachOError::Encode)?;
+    if let Output::Execute(entry) = output {
+        let entry = *out.labels.get(entry).ok_or_else(|| MachOError::UndefinedEntry(entry.to_string()))?;
+        macho.file_type = FileType::Execute { entry };
+    }
+
+    let text = &mut macho.segments[first];
+    text.sections[0] = MachSection::new("__text", SectionType::Code, out.text_base, out.text);
+    let const_end = match has_data {
+        true => out.labels[CONST_END],
+        false => out.rodata_base + out.rodata.len() as u64,
+    };
+    let mut text_end = out.text_base + text.sections[0].size;
+    if has_const {
+        let bytes = out.rodata[..(const_end - out.rodata_base) as usize].to_vec();
+        text.sections[1] = MachSection::new("__const", SectionType::Data, out.rodata_base, bytes);
+        text_end = const_end;
+    }
+    text.vmsize = (text_end - text_address).next_multiple_of(PAGE);
+
+    if has_data {
+        let data_start = out.labels[DATA_START];
+        let end = out.rodata_base + out.rodata.len() as u64;
+        let bytes = |from: u64, to: u64| out.rodata[(from - out.rodata_base) as usize..(to - out.rodata_base) as usize].to_vec();
+        let got_end = data_start + 8 * got.entries.len() as u64;
+        let bss_start = bss.first().map_or(end, |var| out.labels[&var.label]);
+        let segment = &mut macho.segments[first + 1];
+        segment.vmaddr = data_start;
+        segment.vmsize = (end - data_start).next_multiple_of(PAGE);
+        for section in &mut segment.sections {
+            *section = match section.kind {
+                SectionType::Got => MachSection::new("__got", SectionType::Got, data_start, bytes(data_start, got_end)),
+                SectionType::ZeroFill => MachSection::zero_fill("__bss", bss_start, end - bss_start),
+                _ => {
+                    let start = out.labels[&data[0].label];
+                    MachSection::new("__data", SectionType::Data, start, bytes(start, bss_start))
+                }
+            };
+        }
+    }
+
+    let local = got.local().chain(data.iter().filter(|var| pointer(var)).map(|var| &var.label));
+    macho.rebases = local.map(|label| out.labels[label]).collect();
+    for label in got.imports {
+        let weak = symbols.get(&label).is_some_and(|symbol| symbol.binding == Binding::Weak);
+        macho.binds.push(Bind { address: out.labels[&got::entry(&label)], symbol: label, weak });
+    }
+    for symbol in symbols.iter().filter(|symbol| symbol.defined) {
+        if let Some(addr) = out.labels.get(&symbol.name) {
+            macho.symbols.push((symbol.clone(), *addr));
+        }
+    }
+    Ok(macho)
+}
@@ -1,10 +1,23 @@
 use crate::{builder::InstructionBuilder, instruction::Register};
-use crate::context::Context;
+use crate::arch::arm64::{Arm64Register, ARM64};
+use crate::context::{Context, Global, Section, SectionKind, Symbol, SymbolTable};
+use crate::platform::{macos::MacOS, Platform};
 use std::fmt;
 use std::path::Path;
 // use crate::compiler::{Compiler, CompilerOptions, CompileError};
 use crate::compiler::{CompilerOptions, CompileError};
-use crate::instruction::{GenericRegister, RegisterMapping};
+use crate::instruction::{Backend, GenericRegister, RegisterMapping};
+
+pub mod elf;
+mod got;
+pub mod image;
+pub mod link;
+pub mod macho;
+pub mod module;
+pub mod sha256;
+
+pub use link::{LinkError, Linker};
+pub use module::Module;
 
 pub struct Program<A, R: Register> {
     pub ins: InstructionBuilder<A, R>,
@@ -15,39 +28,111 @@ impl<A, R: Register> Program<A, R>
 where
     GenericRegister: RegisterMapping<R>
 {
-    pub fn new(arch: A) -> Self {
+    pub fn new(arch: A) -> Self
+    where
+        A: Backend<Register = R>
+    {
+        let mut ctx = Context::new();
+        // The entry point the listing defines
+        ctx.add_symbol(Symbol::function("_start").global());
         Self {
             ins: InstructionBuilder::<A, R>::new(arch),
-            ctx: Context::new(),
+            ctx,
         }
     }
 
+    #[track_caller]
     pub fn var(&mut self, name: &str, value: &str) -> String {
         self.ctx.add_variable(name, value)
     }
 
+    /// Adds a typed global, returning its label.
+    #[track_caller]
+    pub fn global(&mut self, name: &str, global: Global) -> String {
+        self.ctx.add_global(name, global)
+    }
+
+    /// Declares `symbol`, replacing an earlier declaration of the same name.
+    pub fn symbol(&mut self, symbol: Symbol) {
+        self.ctx.add_symbol(symbol);
+    }
+
+    /// The section `name`: one of the standard `text`, `rodata`, `data` and
+    /// `bss`, or a custom section, created as `kind` if it is new. An
+    /// existing section keeps its kind. Globals are placed in it with
+    /// [`Global::in_section`].
+    pub fn section(&mut self, name: &str, kind: SectionKind) -> &Section {
+        self.ctx.sections.get_or_insert(name, kind)
+    }
+
     // pub fn compile(&self, path: &Path) -> Result<(), CompileError> {
     //     let compiler = Compiler::new(CompilerOptions::default());
     //     compiler.compile_and_link(path)
     // }
 }
 
-impl<A, R: Register> fmt::Display for Program<A, R> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // Write data section
-        writeln!(f, ".section __DATA,__data")?;
-        for var in self.ctx.variables.values() {
-            writeln!(f, "{}:", var.label)?;
-            writeln!(f, "    .asciz \"{}\"", var.value)?;
+impl Program<ARM64, Arm64Register> {
+    /// The declared symbols, then every `bl` target the program does not
+    /// define as an external reference.
+    pub fn symbols(&self) -> SymbolTable {
+        let mut symbols = self.ctx.get_symbols().clone();
+        for call in self.ins.arch.undefined_calls() {
+            symbols.refer(&call);
         }
-        
+        symbols
+    }
+
+    /// The assembly listing with `platform`'s sections and symbol
+    /// directives, each instruction followed by its comment.
+    pub fn listing(&self, platform: &impl Platform) -> String {
+        let mut out = String::new();
+        self.write_listing(&mut out, platform, false).unwrap();
+        out
+    }
+
+    /// The listing with the generator call site of each instruction beside
+    /// its comment, as in [`ARM64::annotated_listing`].
+    pub fn annotated_listing(&self, platform: &impl Platform) -> String {
+        let mut out = String::new();
+        self.write_listing(&mut out, platform, true).unwrap();
+        out
+    }
+
+    fn write_listing(&self, f: &mut impl fmt::Write, platform: &impl Platform, call_sites: bool) -> fmt::Result {
+        // Write data sections in order, skipping empty ones
+        for section in self.ctx.get_sections().iter() {
+            if section.kind == SectionKind::Text || section.variables().is_empty() {
+                continue;
+            }
+            match section.name.as_str() {
+                "rodata" => writeln!(f, "{}", platform.rodata_section())?,
+                "data" => writeln!(f, "{}", platform.data_section())?,
+                "bss" => writeln!(f, "{}", platform.bss_section())?,
+                name => writeln!(f, "{}", platform.custom_section(name, section.kind))?,
+            }
+            for var in section.variables() {
+                if var.align > 1 {
+                    writeln!(f, "    .p2align {}", var.align.trailing_zeros())?;
+                }
+                writeln!(f, "{}:", var.label)?;
+                writeln!(f, "    {}", var.value)?;
+            }
+        }
+
         // Write text section
-        writeln!(f, ".section __TEXT,__text")?;
-        writeln!(f, ".global _start")?;
+        writeln!(f, "{}", platform.text_section())?;
+        for symbol in self.symbols().iter() {
+            for directive in platform.symbol_directives(symbol) {
+                writeln!(f, "{}", directive)?;
+            }
+        }
         writeln!(f, "_start:")?;
-        
-        // TODO: Format instructions with comments
-        
-        Ok(())
+        self.ins.arch.write_text(f, platform, call_sites)
     }
-} 
\ No newline at end of file
+}
+
+impl fmt::Display for Program<ARM64, Arm64Register> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_listing(f, &MacOS, false)
+    }
+}
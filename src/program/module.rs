@@ -0,0 +1,75 @@
+//! Finished programs.
+//!
+//! [`Program::finish`] closes a program for further building and checks it
+//! as a whole, reporting every [`BuildError`] the builders recorded or the
+//! checks found rather than stopping at the first. A module exports the
+//! global and weak symbols it defines and imports the ones it only refers
+//! to, which [`super::Linker`] resolves across modules.
+
+use super::Program;
+use crate::arch::arm64::validate::diagnose;
+use crate::arch::arm64::{Arm64Register, ARM64};
+use crate::context::{Binding, Context, Symbol};
+use crate::instruction::BuildError;
+
+/// A program that passed [`Program::finish`].
+pub struct Module<A> {
+    pub arch: A,
+    pub ctx: Context,
+}
+
+impl Program<ARM64, Arm64Register> {
+    /// Checks the program, returning the errors in its globals, then those
+    /// of its switches and then those of its instructions, ordered by
+    /// instruction. Labels of data
+    /// variables and externs, including undefined `bl` targets, count as
+    /// defined.
+    pub fn finish(self) -> Result<Module<ARM64>, Vec<BuildError>> {
+        let mut external: Vec<String> = self.ctx.get_sections().variables().map(|var| var.label.clone()).collect();
+        external.extend(self.symbols().externs().map(|symbol| symbol.name.clone()));
+
+        let symbols = self.symbols();
+        let mut errors = self.ins.build_errors().to_vec();
+        let arch = self.ins.arch;
+        errors.extend(arch.build_errors().iter().cloned());
+        // An instruction built with a placeholder operand would be reported
+        // again by the checks
+        let reported: Vec<usize> = errors.iter().filter_map(|error| error.site()).map(|site| site.index).collect();
+        errors.extend(diagnose(&arch, &external).into_iter().filter(|error| !error.site().is_some_and(|site| reported.contains(&site.index))));
+        errors.sort_by_key(|error| error.site().map(|site| site.index));
+        errors.splice(0..0, self.ctx.build_errors().iter().cloned());
+
+        // Keep the calls recorded as externs with the declared symbols
+        let mut ctx = self.ctx;
+        ctx.symbols = symbols;
+        match errors.is_empty() {
+            true => Ok(Module { arch, ctx }),
+            false => Err(errors),
+        }
+    }
+}
+
+impl Module<ARM64> {
+    /// Labels the module defines, in its text, read-only data and globals.
+    pub fn definitions(&self) -> Vec<String> {
+        let mut labels = self.arch.defined_labels();
+        labels.extend(self.ctx.get_sections().variables().map(|var| var.label.clone()));
+        labels
+    }
+
+    /// Global and weak symbols the module defines, which other modules can
+    /// refer to.
+    pub fn exports(&self) -> Vec<&Symbol> {
+        let definitions = self.definitions();
+        self.ctx
+            .get_symbols()
+            .iter()
+            .filter(|symbol| symbol.defined && symbol.binding != Binding::Local && definitions.contains(&symbol.name))
+            .collect()
+    }
+
+    /// Symbols the module refers to without defining.
+    pub fn imports(&self) -> Vec<&Symbol> {
+        self.ctx.get_symbols().externs().collect()
+    }
+}
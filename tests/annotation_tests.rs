@@ -0,0 +1,96 @@
+use asm_test::arch::arm64::{ARM64, Arm64Register, Instruction};
+use asm_test::instruction::GenericRegister::*;
+use asm_test::instruction::{AnnotationBuilder, MovBuilder};
+mod common;
+
+#[test]
+fn test_comment_attaches_to_next_instruction_only() {
+    let mut program = common::setup_test_program();
+    program.ins
+        .label("f")
+        .comment("sum the inputs")
+        .add(X0, X1, X2)
+        .sub(X0, X0, X3)
+        .comment("done")
+        .ret();
+
+    assert_eq!(
+        program.ins.arch.to_string(),
+        concat!(
+            "f:\n",
+            "    add x0, x1, x2 // sum the inputs\n",
+            "    sub x0, x0, x3\n",
+            "    ret // done\n",
+        )
+    );
+    let comments: Vec<_> = program.ins.arch.emitted().iter().map(|e| e.comment.as_deref()).collect();
+    assert_eq!(comments, vec![None, Some("sum the inputs"), None, Some("done")]);
+}
+
+#[test]
+fn test_tags_are_recorded() {
+    let mut program = common::setup_test_program();
+    program.ins
+        .tag("prologue")
+        .tag("hot")
+        .mov(X0, X1)
+        .ret();
+
+    let emitted = program.ins.arch.emitted();
+    assert_eq!(emitted[0].tags, vec!["prologue".to_string(), "hot".to_string()]);
+    assert!(emitted[1].tags.is_empty());
+}
+
+#[test]
+fn test_source_location_points_at_generator() {
+    let mut program = common::setup_test_program();
+    let line = line!() + 1;
+    program.ins.mov(X0, X1);
+
+    let mut arch = ARM64::new();
+    let direct = line!() + 1;
+    arch.mov(Arm64Register::X2, Arm64Register::X3);
+
+    let location = program.ins.arch.emitted()[0].source_location.unwrap();
+    assert!(location.file().ends_with("annotation_tests.rs"));
+    assert_eq!(location.line(), line);
+    assert_eq!(arch.emitted()[0].source_location.unwrap().line(), direct);
+}
+
+#[test]
+fn test_backend_instructions_have_no_location() {
+    let mut arch = ARM64::new();
+    arch.mov_imm(Arm64Register::X0, 0x1234_5678_9abc_def0);
+    arch.flush_literal_pool();
+
+    let emitted = arch.emitted();
+    assert!(emitted[0].source_location.is_some());
+    assert!(emitted[1..].iter().all(|e| e.source_location.is_none()));
+    assert!(emitted.iter().any(|e| matches!(e.inst, Instruction::Data(_))));
+}
+
+#[test]
+fn test_annotated_listing() {
+    let mut arch = ARM64::new();
+    arch.comment("copy");
+    arch.mov(Arm64Register::X0, Arm64Register::X1);
+
+    let listing = arch.annotated_listing();
+    let line = listing.lines().next().unwrap();
+    assert!(line.starts_with(&format!("{:<40} // ", "    mov x0, x1")));
+    assert!(line.contains("annotation_tests.rs:"));
+    assert!(line.ends_with(": copy"));
+}
+
+#[test]
+fn test_program_listing_carries_comments_and_call_sites() {
+    let mut program = common::setup_test_program();
+    program.ins.comment("exit code").mov(X0, XZR).bl("_exit");
+
+    assert!(program.to_string().ends_with("_start:\n    mov x0, xzr // exit code\n    bl _exit\n"));
+    let listing = program.annotated_listing(&asm_test::platform::linux::Linux);
+    let line = listing.lines().find(|line| line.contains("mov x0")).unwrap();
+    assert!(line.starts_with(&format!("{:<40} // ", "    mov x0, xzr")));
+    assert!(line.contains("annotation_tests.rs:"));
+    assert!(line.ends_with(": exit code"));
+}
@@ -67,4 +67,57 @@ fn test_function_prologue() {
     
     let instructions = program.ins.arch.get_instructions();
     assert_eq!(instructions.len(), 2);
-} 
\ No newline at end of file
+} 
+
+#[test]
+fn test_mov_imm_picks_shortest_sequence() {
+    let mut program = common::setup_test_program();
+
+    program.ins
+        .mov_imm(GenericRegister::X0, 0)
+        .mov_imm(GenericRegister::X1, 0x1234_0000_5678)
+        .mov_imm(GenericRegister::X2, -2i64 as u64)
+        .mov_imm(GenericRegister::X3, 0xffff_1234_ffff_ffff)
+        .mov_imm(GenericRegister::X4, 0x5555_5555_5555_5555)
+        .mov_imm(GenericRegister::X5, 0x1234_5678_9abc_def0);
+
+    assert_eq!(
+        program.ins.arch.to_string(),
+        concat!(
+            "    movz x0, #0x0\n",
+            "    movz x1, #0x5678\n",
+            "    movk x1, #0x1234, lsl #32\n",
+            "    movn x2, #0x1\n",
+            "    movn x3, #0xedcb, lsl #32\n",
+            "    orr x4, xzr, #0x5555555555555555\n",
+            "    ldr x5, Llit0\n",
+            "    .p2align 3\n",
+            "Llit0:\n",
+            "    .quad 0x123456789abcdef0\n",
+        )
+    );
+}
+
+#[test]
+fn test_literal_pool_stays_in_range() {
+    let mut arch = ARM64::new();
+    arch.mov_imm(Arm64Register::X0, 0x1234_5678_9abc_def0);
+    for _ in 0..300_000 {
+        ArithmeticBuilder::add(&mut arch, Arm64Register::X1, Arm64Register::X1, Operand::Immediate("1".to_string()));
+    }
+    arch.mov_imm(Arm64Register::X2, 0x1234_5678_9abc_def0);
+
+    // The first pool was placed before the ldr went out of range, behind a
+    // branch, and the second load started a new one
+    let instructions = arch.get_instructions();
+    let pool = instructions.iter().position(|inst| {
+        matches!(inst, arch::arm64::Instruction::Label(label) if label == "Llit0")
+    }).expect("pool flushed");
+    assert!((pool as u64) * 4 < 1 << 20);
+    assert!(matches!(
+        &instructions[pool - 2],
+        arch::arm64::Instruction::Branch(arch::arm64::BranchOp::B { .. })
+    ));
+    assert_eq!(arch.pending_literals().len(), 1);
+    assert_ne!(arch.pending_literals()[0].0, "Llit0");
+}
@@ -0,0 +1,156 @@
+use asm_test::arch::arm64::emulator::Emulator;
+use asm_test::arch::arm64::validate::validate;
+use asm_test::arch::arm64::{ARM64, Arm64Register::*};
+use asm_test::instruction::*;
+mod common;
+
+const BUFFER: u64 = 0x4000_0000;
+
+#[test]
+fn test_atomic_rendering_and_encoding() {
+    let mut arch = ARM64::new();
+    arch.ldxr(MemSize::Double, X0, X1);
+    arch.ldaxr(MemSize::Byte, X2, X3);
+    arch.stxr(MemSize::Double, X4, X0, X1);
+    arch.stlxr(MemSize::Half, W4, X5, SP);
+    arch.ldadd(MemoryOrder::Relaxed, MemSize::Double, X0, X1, X2);
+    arch.ldadd(MemoryOrder::AcquireRelease, MemSize::Byte, X0, X1, X2);
+    arch.ldadd(MemoryOrder::Release, MemSize::Word, W3, W4, X5);
+    arch.swp(MemoryOrder::Acquire, MemSize::Double, X6, X7, X8);
+    arch.swp(MemoryOrder::Relaxed, MemSize::Half, X1, X2, X3);
+    arch.cas(MemoryOrder::Relaxed, MemSize::Double, X0, X1, X2);
+    arch.casal(MemSize::Word, W3, W4, X5);
+    arch.cas(MemoryOrder::Acquire, MemSize::Byte, W1, W2, X3);
+    arch.dmb(BarrierDomain::Ish);
+    arch.dsb(BarrierDomain::Sy);
+    arch.dmb(BarrierDomain::OshLd);
+    arch.isb();
+
+    assert_eq!(
+        arch.to_string(),
+        concat!(
+            "    ldxr x0, [x1]\n",
+            "    ldaxrb w2, [x3]\n",
+            "    stxr w4, x0, [x1]\n",
+            "    stlxrh w4, w5, [sp]\n",
+            "    ldadd x0, x1, [x2]\n",
+            "    ldaddalb w0, w1, [x2]\n",
+            "    ldaddl w3, w4, [x5]\n",
+            "    swpa x6, x7, [x8]\n",
+            "    swph w1, w2, [x3]\n",
+            "    cas x0, x1, [x2]\n",
+            "    casal w3, w4, [x5]\n",
+            "    casab w1, w2, [x3]\n",
+            "    dmb ish\n",
+            "    dsb sy\n",
+            "    dmb oshld\n",
+            "    isb\n",
+        )
+    );
+    // Expected words taken from `llvm-mc -triple=aarch64 -mattr=+lse -show-encoding`
+    assert_eq!(
+        common::words(&arch),
+        vec![
+            0xc85f7c20, 0x085ffc62, 0xc8047c20, 0x4804ffe5, 0xf8200041, 0x38e00041, 0xb86300a4, 0xf8a68107,
+            0x78218062, 0xc8a07c41, 0x88e3fca4, 0x08e17c62, 0xd5033bbf, 0xd5033f9f, 0xd50331bf, 0xd5033fdf,
+        ]
+    );
+}
+
+#[test]
+fn test_atomic_widths_are_checked() {
+    let rejected: Vec<fn(&mut ARM64)> = vec![
+        |a| a.ldxr(MemSize::Double, W0, X1),
+        |a| a.stxr(MemSize::Word, W2, W0, W1),
+        |a| a.ldadd(MemoryOrder::Relaxed, MemSize::Double, X0, W1, X2),
+        |a| a.casal(MemSize::Double, W0, X1, X2),
+    ];
+    for build in rejected {
+        let mut arch = ARM64::new();
+        build(&mut arch);
+        assert!(validate(&arch).is_err(), "accepted {}", arch);
+    }
+}
+
+#[test]
+fn test_exclusive_monitor() {
+    let mut arch = ARM64::new();
+    // Spinlock around a counter increment; returns the new count
+    arch.label("locked_increment");
+    arch.mov_imm(X3, 1);
+    arch.label("acquire");
+    arch.ldaxr(MemSize::Word, W2, X0);
+    arch.cbnz(W2, "acquire");
+    arch.stxr(MemSize::Word, W4, W3, X0);
+    arch.cbnz(W4, "acquire");
+    arch.load(MemSize::Double, false, X5, MemOperand::Offset(X1, 0));
+    ArithmeticBuilder::add(&mut arch, X5, X5, Operand::Immediate("1".to_string()));
+    arch.store(MemSize::Double, X5, MemOperand::Offset(X1, 0));
+    arch.dmb(BarrierDomain::Ish);
+    arch.store(MemSize::Word, WZR, MemOperand::Offset(X0, 0));
+    arch.mov(X0, X5);
+    arch.ret();
+
+    // A plain store to the marked location makes the exclusive store fail
+    arch.label("interfered");
+    arch.ldxr(MemSize::Double, X1, X0);
+    arch.store(MemSize::Byte, WZR, MemOperand::Offset(X0, 7));
+    arch.stxr(MemSize::Double, W0, X1, X0);
+    arch.ret();
+
+    // The store exclusive must target the location the load marked
+    arch.label("mismatched");
+    arch.ldxr(MemSize::Double, X2, X0);
+    arch.stxr(MemSize::Word, W3, W2, X0);
+    arch.stxr(MemSize::Double, W4, X2, X1);
+    arch.ldxr(MemSize::Double, X2, X0);
+    arch.stxr(MemSize::Double, W5, X2, X0);
+    arch.stxr(MemSize::Double, W6, X2, X0);
+    arch.ret();
+
+    let mut emulator = Emulator::new(&arch).unwrap();
+    emulator.map(BUFFER, 0x100);
+    assert_eq!(emulator.call("locked_increment", &[BUFFER, BUFFER + 8]).unwrap(), 1);
+    assert_eq!(emulator.call("locked_increment", &[BUFFER, BUFFER + 8]).unwrap(), 2);
+    assert_eq!(emulator.read_memory(BUFFER, 4).unwrap(), vec![0; 4]);
+
+    assert_eq!(emulator.call("interfered", &[BUFFER + 16]).unwrap(), 1);
+
+    emulator.call("mismatched", &[BUFFER + 16, BUFFER + 24]).unwrap();
+    assert_eq!((emulator.reg(X3), emulator.reg(X4)), (1, 1));
+    // Success clears the mark, so a second store exclusive fails
+    assert_eq!((emulator.reg(X5), emulator.reg(X6)), (0, 1));
+}
+
+#[test]
+fn test_lse_read_modify_write() {
+    let mut arch = ARM64::new();
+    arch.label("fetch_add");
+    arch.ldadd(MemoryOrder::AcquireRelease, MemSize::Double, X1, X0, X0);
+    arch.ret();
+
+    arch.label("exchange_byte");
+    arch.swp(MemoryOrder::Relaxed, MemSize::Byte, W1, W0, X0);
+    arch.ret();
+
+    arch.label("compare_exchange");
+    arch.casal(MemSize::Word, W1, W2, X0);
+    arch.mov(X0, X1);
+    arch.ret();
+
+    let mut emulator = Emulator::new(&arch).unwrap();
+    emulator.map(BUFFER, 0x100);
+    emulator.write_memory(BUFFER, &40u64.to_le_bytes()).unwrap();
+    assert_eq!(emulator.call("fetch_add", &[BUFFER, 2]).unwrap(), 40);
+    assert_eq!(emulator.read_memory(BUFFER, 8).unwrap(), 42u64.to_le_bytes());
+
+    assert_eq!(emulator.call("exchange_byte", &[BUFFER, 0x1ff]).unwrap(), 42);
+    assert_eq!(emulator.read_memory(BUFFER, 2).unwrap(), vec![0xff, 0]);
+
+    emulator.write_memory(BUFFER + 8, &7u32.to_le_bytes()).unwrap();
+    // The expected value is compared at the access width
+    assert_eq!(emulator.call("compare_exchange", &[BUFFER + 8, 0x1_0000_0007, 9]).unwrap(), 7);
+    assert_eq!(emulator.read_memory(BUFFER + 8, 4).unwrap(), 9u32.to_le_bytes());
+    assert_eq!(emulator.call("compare_exchange", &[BUFFER + 8, 7, 11]).unwrap(), 9);
+    assert_eq!(emulator.read_memory(BUFFER + 8, 4).unwrap(), 9u32.to_le_bytes());
+}
@@ -0,0 +1,70 @@
+use asm_test::arch::arm64::{ARM64, Arm64Register::*};
+use asm_test::instruction::*;
+
+#[test]
+fn test_logical_and_shift_rendering() {
+    let mut arch = ARM64::new();
+    arch.and(X0, X1, Operand::Register(X2));
+    arch.orr(X0, X1, Operand::Shifted(X2, ShiftKind::Lsl, 3));
+    arch.eor(X0, X1, Operand::Immediate("0xff00".to_string()));
+    arch.bic(X0, X1, Operand::Shifted(X2, ShiftKind::Ror, 7));
+    arch.orn(X0, XZR, Operand::Register(X2));
+    arch.eon(X0, X1, Operand::Register(X2));
+    arch.bic(X3, X3, Operand::Immediate("1".to_string()));
+    arch.lsl(X0, X1, Operand::Immediate("4".to_string()));
+    arch.lsr(X0, X1, Operand::Register(X2));
+    arch.asr(X0, X1, Operand::Immediate("63".to_string()));
+    arch.ror(X0, X1, Operand::Register(X2));
+    ArithmeticBuilder::add(&mut arch, X0, X1, Operand::Shifted(X2, ShiftKind::Lsl, 2));
+
+    assert_eq!(
+        arch.to_string(),
+        concat!(
+            "    and x0, x1, x2\n",
+            "    orr x0, x1, x2, lsl #3\n",
+            "    eor x0, x1, #0xff00\n",
+            "    bic x0, x1, x2, ror #7\n",
+            "    orn x0, xzr, x2\n",
+            "    eon x0, x1, x2\n",
+            "    and x3, x3, #0xfffffffffffffffe\n",
+            "    lsl x0, x1, #4\n",
+            "    lsr x0, x1, x2\n",
+            "    asr x0, x1, #63\n",
+            "    ror x0, x1, x2\n",
+            "    add x0, x1, x2, lsl #2\n",
+        )
+    );
+}
+
+#[test]
+fn test_bitfield_and_bit_rendering() {
+    let mut arch = ARM64::new();
+    arch.ubfx(X0, X1, 8, 4);
+    arch.sbfx(X0, X1, 0, 32);
+    arch.bfi(X0, X1, 16, 8);
+    arch.ubfiz(X0, X1, 2, 30);
+    arch.extr(X0, X1, X2, 17);
+    arch.clz(X0, X1);
+    arch.cls(X0, X1);
+    arch.rbit(X0, X1);
+    arch.rev(X0, X1);
+    arch.rev16(X0, X1);
+    arch.rev32(X0, X1);
+
+    assert_eq!(
+        arch.to_string(),
+        concat!(
+            "    ubfx x0, x1, #8, #4\n",
+            "    sbfx x0, x1, #0, #32\n",
+            "    bfi x0, x1, #16, #8\n",
+            "    ubfiz x0, x1, #2, #30\n",
+            "    extr x0, x1, x2, #17\n",
+            "    clz x0, x1\n",
+            "    cls x0, x1\n",
+            "    rbit x0, x1\n",
+            "    rev x0, x1\n",
+            "    rev16 x0, x1\n",
+            "    rev32 x0, x1\n",
+        )
+    );
+}
@@ -0,0 +1,101 @@
+use asm_test::arch::arm64::boot::{self, ExceptionKind, ExceptionSource, VectorTable, FRAME_SIZE};
+use asm_test::arch::arm64::emulator::{Emulator, EmulatorError};
+use asm_test::arch::arm64::encoder::assemble;
+use asm_test::arch::arm64::{ARM64, Arm64Register::*, SystemRegister};
+use asm_test::instruction::*;
+use asm_test::program::image::{LinkerScript, MemoryRegion};
+
+fn kernel() -> (ARM64, VectorTable) {
+    let mut arch = ARM64::new();
+    let mut table = VectorTable::new("vectors");
+    table.handler(ExceptionSource::LowerElAarch64, ExceptionKind::Synchronous, "syscall");
+    table.handler(ExceptionSource::CurrentElSpx, ExceptionKind::Synchronous, "syscall");
+    table.emit(&mut arch);
+
+    // Adds the call number from ESR_EL1 to the caller's X0 and clobbers X1
+    arch.label("syscall");
+    arch.mrs(X9, SystemRegister::EsrEl1);
+    arch.and(X9, X9, Operand::Immediate("0xffff".to_string()));
+    arch.load(MemSize::Double, false, X10, MemOperand::Offset(X0, 0));
+    ArithmeticBuilder::add(&mut arch, X10, X10, Operand::Register(X9));
+    arch.store(MemSize::Double, X10, MemOperand::Offset(X0, 0));
+    arch.mov_imm(X1, 0xdead);
+    arch.ret();
+
+    arch.label("boot");
+    table.install(&mut arch, X9);
+    boot::enter_el1(&mut arch, "el1_main", X9);
+    arch.label("el1_main");
+    arch.svc(1);
+    boot::enter_el0(&mut arch, "el0_main", X2, X9);
+    arch.label("el0_main");
+    arch.mov_imm(X1, 7);
+    arch.svc(100);
+    ArithmeticBuilder::add(&mut arch, X0, X0, Operand::Register(X1));
+    arch.ret();
+    (arch, table)
+}
+
+#[test]
+fn test_vector_table_layout() {
+    let (arch, _) = kernel();
+    let out = assemble(&arch, 0x10000).unwrap();
+    let vectors = out.labels["vectors"];
+    assert_eq!(vectors % 2048, 0);
+    let word = |addr: u64| {
+        let at = (addr - out.text_base) as usize;
+        u32::from_le_bytes(out.text[at..at + 4].try_into().unwrap())
+    };
+    let branch_target = |addr: u64| {
+        let imm26 = (word(addr) & 0x03ff_ffff) as i64;
+        (addr as i64 + ((imm26 << 38) >> 36)) as u64
+    };
+    for index in 0..16 {
+        // Every entry opens with a branch, padded with nops to 128 bytes
+        assert_eq!(word(vectors + index * 0x80) >> 26, 0b000101);
+        assert_eq!(word(vectors + index * 0x80 + 4), 0xd503201f);
+    }
+    let lower_sync = VectorTable::offset(ExceptionSource::LowerElAarch64, ExceptionKind::Synchronous);
+    assert_eq!(lower_sync, 0x400);
+    assert_eq!(branch_target(vectors + lower_sync), out.labels["Lvectors_entry8"]);
+    let irq = VectorTable::offset(ExceptionSource::CurrentElSp0, ExceptionKind::Irq);
+    assert_eq!(branch_target(vectors + irq), out.labels["Lvectors_unhandled"]);
+
+    let listing = arch.to_string();
+    assert!(listing.starts_with("    .p2align 11\nvectors:\n    b Lvectors_unhandled\n    .p2align 7\n"));
+    assert!(listing.contains(&format!("    stp x0, x1, [sp, #-{}]!\n", FRAME_SIZE)));
+    assert!(listing.contains("    mrs x0, elr_el1\n    mrs x1, spsr_el1\n"));
+    assert!(listing.contains(&format!("    ldp x0, x1, [sp], #{}\n    eret\n", FRAME_SIZE)));
+}
+
+#[test]
+fn test_exception_levels_and_syscalls() {
+    let (arch, _) = kernel();
+    let mut emulator = Emulator::new(&arch).unwrap();
+    emulator.set_sysreg(SystemRegister::CurrentEl, 2 << 2);
+    // The EL1 call adds 1, the EL0 call adds 100, and X1 survives both
+    assert_eq!(emulator.call("boot", &[5, 0, 0x7000_0000]).unwrap(), 5 + 1 + 100 + 7);
+    assert_eq!(emulator.sysreg(SystemRegister::CurrentEl), 0);
+    assert_eq!(emulator.sysreg(SystemRegister::SpEl0), 0x7000_0000);
+    assert_eq!(emulator.sysreg(SystemRegister::HcrEl2), 1 << 31);
+    assert_eq!(emulator.sysreg(SystemRegister::EsrEl1), 0x5600_0064);
+    assert_eq!(emulator.sysreg(SystemRegister::VbarEl1), emulator.label_address("vectors").unwrap());
+
+    // Without a vector table there is nowhere to take the call
+    let mut arch = ARM64::new();
+    arch.label("f");
+    arch.svc(0);
+    arch.ret();
+    arch.label("g");
+    arch.eret();
+    let mut emulator = Emulator::new(&arch).unwrap();
+    assert!(matches!(emulator.call("f", &[]), Err(EmulatorError::Unsupported(_))));
+    assert!(matches!(emulator.call("g", &[]), Err(EmulatorError::Unsupported(_))));
+}
+
+#[test]
+fn test_linker_script() {
+    let script = LinkerScript::new("_start", MemoryRegion::new("RAM", "rwx", 0x4008_0000, 0x80_0000)).to_string();
+    assert!(script.contains("    RAM (rwx) : ORIGIN = 0x40080000, LENGTH = 0x800000\n"));
+    assert!(script.contains("        KEEP(*(.text.boot))\n"));
+}
@@ -0,0 +1,78 @@
+use asm_test::instruction::GenericRegister::*;
+use asm_test::instruction::{BuildError, FpSize};
+mod common;
+
+#[test]
+fn test_finish_accepts_valid_program() {
+    let mut program = common::setup_test_program();
+    let message = program.var("message", "hi");
+    program.ctx.add_extern("_puts");
+    program.ins
+        .label("_start")
+        .adrp_add(X0, X1, &message)
+        .bl("_puts")
+        .and(X0, X0, "0xff")
+        .ret();
+
+    let module = program.finish().ok().unwrap();
+    assert_eq!(module.arch.emitted().len(), 5);
+}
+
+#[test]
+fn test_finish_reports_every_error() {
+    let mut program = common::setup_test_program();
+    program.ins.label("f");
+    let first = line!() + 1;
+    program.ins.and(X0, X1, "mask");
+    program.ins.fmov(FpSize::Double, X0, X1);
+    program.ins.add(X0, W1, X2);
+    program.ins.lsl(X0, X1, "64");
+    program.ins.b("missing");
+    program.ins.ret();
+
+    let errors = program.finish().err().unwrap();
+    let indices: Vec<usize> = errors.iter().map(|error| error.site().unwrap().index).collect();
+    assert_eq!(indices, vec![1, 2, 3, 4, 5]);
+    assert!(matches!(&errors[0], BuildError::ImmediateOutOfRange { value, .. } if value == "mask"));
+    assert!(matches!(&errors[1], BuildError::InvalidRegisterClass { operand, .. } if operand == "x1"));
+    assert!(matches!(&errors[2], BuildError::WidthMismatch { inst, .. } if inst == "add x0, w1, x2"));
+    assert!(matches!(&errors[3], BuildError::ImmediateOutOfRange { value, .. } if value == "64"));
+    assert!(matches!(&errors[4], BuildError::UndefinedLabel { label, .. } if label == "missing"));
+
+    let location = errors[0].site().unwrap().location.unwrap();
+    assert!(location.file().ends_with("build_error_tests.rs"));
+    assert_eq!(location.line(), first);
+    assert_eq!(errors[3].site().unwrap().location.unwrap().line(), first + 3);
+}
+
+#[test]
+fn test_finish_reports_label_errors() {
+    let mut program = common::setup_test_program();
+    program.ins.label("f").b("missing").label("f").ret();
+
+    // Duplicate labels stop layout, so they are reported alone
+    let errors = program.finish().err().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(&errors[0], BuildError::DuplicateLabel { site, label } if site.index == 2 && label == "f"));
+
+    let mut program = common::setup_test_program();
+    program.ins.label("f").cbz(X0, "missing").b("f");
+    let line = line!() - 1;
+    let errors = program.finish().err().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(&errors[0], BuildError::UndefinedLabel { label, .. } if label == "missing"));
+    let message = errors[0].to_string();
+    assert!(message.starts_with(&format!("{}:{}:", file!(), line)));
+    assert!(message.ends_with(": instruction 1: undefined label missing"));
+}
+
+#[test]
+fn test_builder_errors_are_recorded_in_place() {
+    let mut program = common::setup_test_program();
+    program.ins.mov(X0, X1).orr(X0, X0, "flags").ret();
+
+    let errors = program.ins.arch.build_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].site().unwrap().index, 1);
+    assert_eq!(program.ins.arch.emitted().len(), 3);
+}
@@ -0,0 +1,92 @@
+use asm_test::arch::arm64::emulator::Emulator;
+use asm_test::arch::arm64::SystemRegister;
+use asm_test::instruction::GenericRegister::*;
+use asm_test::instruction::{BarrierDomain, Condition, FpSize, MemOperand, MemSize, MemoryOrder, ShiftKind, VectorArrangement};
+mod common;
+
+#[test]
+fn test_fluent_builder_reaches_every_family() {
+    let mut program = common::setup_test_program();
+    program.ins
+        .label("f")
+        .sub(X0, X1, X2)
+        .mul(X0, X0, X3)
+        .and(X1, X1, (X2, ShiftKind::Lsl, 4))
+        .lsr(X1, X1, "3")
+        .ubfx(X2, X1, 4, 8)
+        .clz(W3, W2)
+        .cmp(X0, X1)
+        .csel(X0, X0, X1, Condition::Gt)
+        .fadd(FpSize::Double, V0, V1, V2)
+        .scvtf(FpSize::Single, V3, W4)
+        .vadd(VectorArrangement::S4, V0, V1, V2)
+        .ld1(VectorArrangement::B16, V4, 1, MemOperand::PostIndex(X5, 16))
+        .ldadd(MemoryOrder::AcquireRelease, MemSize::Word, W1, W2, X3)
+        .dmb(BarrierDomain::Ish)
+        .load(MemSize::Half, true, X6, MemOperand::Offset(X7, 2))
+        .stp(X29, X30, MemOperand::PreIndex(SP, -16))
+        .ldr(X8, "Lvalue")
+        .str(X8, "Lvalue")
+        .adrp_add(X9, X10, "Lvalue")
+        .cbz(X0, "done")
+        .b("done")
+        .label("done")
+        .svc(0x80)
+        .mrs(X11, SystemRegister::TpidrEl0)
+        .ret();
+
+    assert_eq!(
+        program.ins.arch.to_string(),
+        concat!(
+            "f:\n",
+            "    sub x0, x1, x2\n",
+            "    mul x0, x0, x3\n",
+            "    and x1, x1, x2, lsl #4\n",
+            "    lsr x1, x1, #3\n",
+            "    ubfx x2, x1, #4, #8\n",
+            "    clz w3, w2\n",
+            "    cmp x0, x1\n",
+            "    csel x0, x0, x1, gt\n",
+            "    fadd d0, d1, d2\n",
+            "    scvtf s3, w4\n",
+            "    add v0.4s, v1.4s, v2.4s\n",
+            "    ld1 { v4.16b }, [x5], #16\n",
+            "    ldaddal w1, w2, [x3]\n",
+            "    dmb ish\n",
+            "    ldrsh x6, [x7, #2]\n",
+            "    stp x29, x30, [sp, #-16]!\n",
+            "    ldr x8, Lvalue\n",
+            "    str x8, Lvalue\n",
+            "    adrp x10, Lvalue@PAGE\n",
+            "    add x9, x10, Lvalue@PAGEOFF\n",
+            "    cbz x0, done\n",
+            "    b done\n",
+            "done:\n",
+            "    svc #128\n",
+            "    mrs x11, tpidr_el0\n",
+            "    ret\n",
+        )
+    );
+}
+
+#[test]
+fn test_builder_only_function_runs() {
+    // |x0 - x1| * 3, written without touching `program.ins.arch`
+    let mut program = common::setup_test_program();
+    program.ins
+        .label("scaled_distance")
+        .cmp(X0, X1)
+        .b_cond(Condition::Ge, "ordered")
+        .mov(X2, X0)
+        .mov(X0, X1)
+        .mov(X1, X2)
+        .label("ordered")
+        .sub(X0, X0, X1)
+        .mov_imm(X3, 3)
+        .mul(X0, X0, X3)
+        .ret();
+
+    let mut emulator = Emulator::new(&program.ins.arch).unwrap();
+    assert_eq!(emulator.call("scaled_distance", &[10, 4]).unwrap(), 18);
+    assert_eq!(emulator.call("scaled_distance", &[4, 10]).unwrap(), 18);
+}
@@ -1,7 +1,16 @@
 // Shared test utilities
+// Each test binary uses only some of them
+#![allow(dead_code)]
 use asm_test::*;
+use asm_test::arch::arm64::encoder::assemble;
 use asm_test::arch::arm64::{ARM64, Arm64Register};
 
 pub fn setup_test_program() -> Program<ARM64, Arm64Register> {
     Program::new(ARM64::new())
-} 
\ No newline at end of file
+}
+
+/// The encoded text of `arch`, assembled at 0x10000, as instruction words.
+pub fn words(arch: &ARM64) -> Vec<u32> {
+    let out = assemble(arch, 0x10000).unwrap();
+    out.text.chunks(4).map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]])).collect()
+}
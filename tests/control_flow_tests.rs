@@ -0,0 +1,305 @@
+use asm_test::*;
+use asm_test::instruction::Condition;
+use asm_test::instruction::GenericRegister::*;
+mod common;
+
+#[test]
+fn test_if_else_inverts_condition() {
+    let mut program = common::setup_test_program();
+
+    program.ins
+        .if_(Cond::lt(X0, X1), |b| {
+            b.mov(X2, X0);
+        })
+        .else_(|b| {
+            b.mov(X2, X1);
+        })
+        .bl("_use");
+
+    assert_eq!(
+        program.ins.arch.to_string(),
+        "    cmp x0, x1\n    b.ge Lelse0\n    mov x2, x0\n    b Lendif1\nLelse0:\n    mov x2, x1\nLendif1:\n    bl _use\n"
+    );
+}
+
+#[test]
+fn test_if_without_else() {
+    let mut program = common::setup_test_program();
+
+    program.ins.if_(Cond::NonZero(X3), |b| {
+        b.bl("_flush");
+    });
+
+    assert_eq!(
+        program.ins.arch.to_string(),
+        "    cbz x3, Lelse0\n    bl _flush\nLelse0:\n"
+    );
+}
+
+#[test]
+fn test_always_condition_runs_body() {
+    assert_eq!(Condition::Lt.invert(), Some(Condition::Ge));
+    assert_eq!(Condition::Al.invert(), None);
+
+    let mut program = common::setup_test_program();
+    program.ins.if_(Cond::Compare(X0, Condition::Al, 5.into()), |b| {
+        b.bl("_always");
+    });
+    program.ins.if_(Cond::Flags(Condition::Al), |b| {
+        b.bl("_again");
+    });
+
+    // Neither a compare nor a branch around the body
+    assert_eq!(program.ins.arch.to_string(), "    bl _always\nLelse0:\n    bl _again\nLelse1:\n");
+}
+
+#[test]
+fn test_large_loop_bounds() {
+    let mut program = common::setup_test_program();
+    program.ins.for_range(X1, 0, 10_000, |b| {
+        b.bl("_step");
+    });
+
+    // The bound does not fit `cmp`, so it goes through a scratch register
+    assert!(program.ins.arch.to_string().starts_with(concat!(
+        "    movz x1, #0x0\n",
+        "Lwhile0:\n",
+        "    movz x16, #0x2710\n",
+        "    cmp x1, x16\n",
+        "    b.ge Lendwhile1\n",
+    )));
+}
+
+#[test]
+fn test_w_register_conditions() {
+    let mut program = common::setup_test_program();
+    program.ins.if_(Cond::lt(W3, -5), |b| {
+        b.bl("_negative");
+    });
+    program.ins.for_range(W1, 0, 10_000, |b| {
+        b.bl("_step");
+    });
+    program.ins.while_(Cond::ne(W16, -70_000), |b| {
+        b.bl("_wait");
+    });
+
+    // Negative immediates are compared with `cmn`; other constants go
+    // through the W view of the scratch register
+    assert_eq!(
+        program.ins.arch.to_string(),
+        concat!(
+            "    cmp w3, #-5\n",
+            "    b.ge Lelse0\n",
+            "    bl _negative\n",
+            "Lelse0:\n",
+            "    movz w1, #0x0\n",
+            "Lwhile1:\n",
+            "    movz w16, #0x2710\n",
+            "    cmp w1, w16\n",
+            "    b.ge Lendwhile2\n",
+            "    bl _step\n",
+            "    add w1, w1, #1\n",
+            "    b Lwhile1\n",
+            "Lendwhile2:\n",
+            "Lwhile3:\n",
+            "    movz w17, #0xee90\n",
+            "    movk w17, #0xfffe, lsl #16\n",
+            "    cmp w16, w17\n",
+            "    b.eq Lendwhile4\n",
+            "    bl _wait\n",
+            "    b Lwhile3\n",
+            "Lendwhile4:\n",
+        )
+    );
+    assert_eq!(asm_test::arch::arm64::validate::validate(&program.ins.arch), Ok(()));
+}
+
+#[test]
+fn test_while_and_for_range() {
+    let mut program = common::setup_test_program();
+
+    program.ins.while_(Cond::ne(X0, 0), |b| {
+        b.for_range(X1, 0, X2, |b| {
+            b.add(X3, X3, X1);
+        });
+        b.add(X0, X0, "-1");
+    });
+
+    assert_eq!(
+        program.ins.arch.to_string(),
+        concat!(
+            "Lwhile0:\n",
+            "    cmp x0, #0\n",
+            "    b.eq Lendwhile1\n",
+            "    movz x1, #0x0\n",
+            "Lwhile2:\n",
+            "    cmp x1, x2\n",
+            "    b.ge Lendwhile3\n",
+            "    add x3, x3, x1\n",
+            "    add x1, x1, #1\n",
+            "    b Lwhile2\n",
+            "Lendwhile3:\n",
+            "    sub x0, x0, #1\n",
+            "    b Lwhile0\n",
+            "Lendwhile1:\n",
+        )
+    );
+}
+
+#[test]
+fn test_dense_switch_uses_jump_table() {
+    let mut program = common::setup_test_program();
+
+    program.ins.switch_(X0, |s| {
+        s.case(10, |b| { b.bl("_ten"); });
+        s.case(11, |b| { b.bl("_eleven"); });
+        s.case(13, |b| { b.bl("_thirteen"); });
+        s.case(14, |b| { b.bl("_fourteen"); });
+        s.default(|b| { b.bl("_other"); });
+    });
+
+    let asm = program.ins.arch.to_string();
+    assert!(asm.starts_with(concat!(
+        "    sub x16, x0, #10\n",
+        "    cmp x16, #4\n",
+        "    b.hi Ldefault4\n",
+        "    adrp x17, Ljumptable6@PAGE\n",
+        "    add x17, x17, Ljumptable6@PAGEOFF\n",
+        "    ldrsw x16, [x17, x16, lsl #2]\n",
+        "    add x16, x17, x16\n",
+        "    br x16\n",
+    )));
+    assert!(asm.ends_with(concat!(
+        ".section __TEXT,__const\n",
+        "    .p2align 2\n",
+        "Ljumptable6:\n",
+        "    .word Lcase0 - Ljumptable6\n",
+        "    .word Lcase1 - Ljumptable6\n",
+        "    .word Ldefault4 - Ljumptable6\n",
+        "    .word Lcase2 - Ljumptable6\n",
+        "    .word Lcase3 - Ljumptable6\n",
+    )));
+}
+
+#[test]
+fn test_sparse_switch_uses_compare_chain() {
+    let mut program = common::setup_test_program();
+
+    program.ins.switch_(X0, |s| {
+        s.case(1, |b| { b.bl("_one"); });
+        s.case(1000, |b| { b.bl("_thousand"); });
+    });
+
+    assert!(program.ins.arch.get_rodata().is_empty());
+    assert_eq!(
+        program.ins.arch.to_string(),
+        concat!(
+            "    cmp x0, #1\n",
+            "    b.eq Lcase0\n",
+            "    cmp x0, #1000\n",
+            "    b.eq Lcase1\n",
+            "    b Ldefault2\n",
+            "Lcase0:\n",
+            "    bl _one\n",
+            "    b Lendswitch3\n",
+            "Lcase1:\n",
+            "    bl _thousand\n",
+            "    b Lendswitch3\n",
+            "Ldefault2:\n",
+            "Lendswitch3:\n",
+        )
+    );
+}
+
+#[test]
+fn test_switch_on_scratch_registers() {
+    let dense = |reg, base: i64| {
+        let mut program = common::setup_test_program();
+        program.ins.switch_(reg, |s| {
+            for value in base..base + 4 {
+                s.case(value, |b| { b.bl("_case"); });
+            }
+        });
+        program.ins.arch.to_string()
+    };
+
+    // The value is copied out of X17 before the table address replaces it
+    assert!(dense(X17, 0).starts_with(concat!(
+        "    mov x16, x17\n",
+        "    cmp x16, #3\n",
+        "    b.hi Ldefault4\n",
+        "    adrp x17, Ljumptable6@PAGE\n",
+    )));
+    // A large base is loaded into the scratch register not holding the value
+    assert!(dense(X17, 0x10000).starts_with(concat!(
+        "    movz x16, #0x1, lsl #16\n",
+        "    sub x16, x17, x16\n",
+        "    cmp x16, #3\n",
+    )));
+    assert!(dense(X16, 0x10000).starts_with(concat!(
+        "    movz x17, #0x1, lsl #16\n",
+        "    sub x16, x16, x17\n",
+    )));
+
+    let mut program = common::setup_test_program();
+    program.ins.switch_(X16, |s| {
+        s.case(1, |b| { b.bl("_one"); });
+        s.case(100_000, |b| { b.bl("_many"); });
+    });
+    assert!(program.ins.arch.to_string().starts_with(concat!(
+        "    cmp x16, #1\n",
+        "    b.eq Lcase0\n",
+        "    movz x17, #0x86a0\n",
+        "    movk x17, #0x1, lsl #16\n",
+        "    cmp x16, x17\n",
+    )));
+}
+
+#[test]
+fn test_switch_on_w_register() {
+    let dense = |reg, base: i64| {
+        let mut program = common::setup_test_program();
+        program.ins.switch_(reg, |s| {
+            for value in base..base + 4 {
+                s.case(value, |b| { b.bl("_case"); });
+            }
+        });
+        assert_eq!(asm_test::arch::arm64::validate::validate(&program.ins.arch), Ok(()));
+        program.ins.arch.to_string()
+    };
+
+    // The value is rebased in W16, leaving it zero-extended in X16
+    assert!(dense(W5, 0).starts_with(concat!(
+        "    mov w16, w5\n",
+        "    cmp w16, #3\n",
+        "    b.hi Ldefault4\n",
+        "    adrp x17, Ljumptable6@PAGE\n",
+        "    add x17, x17, Ljumptable6@PAGEOFF\n",
+        "    ldrsw x16, [x17, x16, lsl #2]\n",
+    )));
+    assert!(dense(W5, 1).starts_with(concat!(
+        "    sub w16, w5, #1\n",
+        "    cmp w16, #3\n",
+    )));
+    assert!(dense(W17, -0x10000).starts_with(concat!(
+        "    movz w16, #0xffff, lsl #16\n",
+        "    sub w16, w17, w16\n",
+        "    cmp w16, #3\n",
+    )));
+}
+
+#[test]
+fn test_duplicate_switch_case_is_reported() {
+    let mut program = common::setup_test_program();
+    program.ins.switch_(X0, |s| {
+        s.case(1, |b| { b.bl("_one"); });
+        s.case(1, |b| { b.bl("_again"); });
+    });
+
+    // The second arm is dropped
+    assert!(!program.ins.arch.to_string().contains("_again"));
+    let errors = program.finish().err().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0], asm_test::instruction::BuildError::DuplicateCase { value: 1, .. }));
+    assert!(errors[0].to_string().ends_with("switch case 1 is given twice"));
+}
@@ -0,0 +1,539 @@
+use asm_test::arch::arm64::encoder::EncodeError;
+use asm_test::arch::arm64::{Arm64Register, ARM64};
+use asm_test::arch::x86_64::{X86Register, X86_64};
+use asm_test::context::{Global, Symbol, Value};
+use asm_test::instruction::GenericRegister::*;
+use asm_test::instruction::{MemOperand, MemSize};
+use asm_test::program::elf::*;
+use asm_test::program::Linker;
+use asm_test::Program;
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Each program header's type and the allocated sections lying inside it,
+/// as `readelf -l` maps sections to segments: a section is in a segment
+/// when its addresses are, and its file bytes too unless it is `NOBITS`.
+fn section_mapping(bytes: &[u8]) -> Vec<(u32, Vec<String>)> {
+    let (phoff, shoff) = (u64_at(bytes, 32) as usize, u64_at(bytes, 40) as usize);
+    let (phnum, shnum, shstrndx) = (u16_at(bytes, 56) as usize, u16_at(bytes, 60) as usize, u16_at(bytes, 62) as usize);
+    let section = |index: usize| shoff + 64 * index;
+    let names = u64_at(bytes, section(shstrndx) + 24) as usize;
+    let name = |at: usize| {
+        let start = names + u32_at(bytes, at) as usize;
+        let end = start + bytes[start..].iter().position(|byte| *byte == 0).unwrap();
+        String::from_utf8(bytes[start..end].to_vec()).unwrap()
+    };
+
+    (0..phnum)
+        .map(|index| {
+            let at = phoff + 56 * index;
+            let (offset, vaddr) = (u64_at(bytes, at + 8), u64_at(bytes, at + 16));
+            let (file_size, mem_size) = (u64_at(bytes, at + 32), u64_at(bytes, at + 40));
+            let sections = (1..shnum)
+                .map(section)
+                .filter(|at| u64_at(bytes, at + 8) & 2 != 0)
+                .filter(|at| {
+                    let (addr, sh_offset, size) = (u64_at(bytes, at + 16), u64_at(bytes, at + 24), u64_at(bytes, at + 32));
+                    let in_memory = addr >= vaddr && addr + size <= vaddr + mem_size;
+                    let nobits = u32_at(bytes, at + 4) == 8;
+                    in_memory && (nobits || (sh_offset >= offset && sh_offset + size <= offset + file_size))
+                })
+                .map(name)
+                .collect();
+            (u32_at(bytes, at), sections)
+        })
+        .collect()
+}
+
+/// Writes "Hello, ELF!\n" through a pointer in `.data` and exits with the
+/// status byte beside it.
+fn x86_64_hello() -> ElfExecutable {
+    #[rustfmt::skip]
+    let text = vec![
+        0xb8, 0x01, 0x00, 0x00, 0x00,       // mov eax, 1
+        0xbf, 0x01, 0x00, 0x00, 0x00,       // mov edi, 1
+        0x48, 0x8b, 0x35, 0, 0, 0, 0,       // mov rsi, [rip + pointer]
+        0xba, 0x0c, 0x00, 0x00, 0x00,       // mov edx, 12
+        0x0f, 0x05,                         // syscall
+        0x0f, 0xb6, 0x3d, 0, 0, 0, 0,       // movzx edi, byte [rip + status]
+        0xb8, 0x3c, 0x00, 0x00, 0x00,       // mov eax, 60
+        0x0f, 0x05,                         // syscall
+    ];
+    let mut data = vec![42, 0, 0, 0, 0, 0, 0, 0];
+    data.extend([0; 8]);
+
+    let mut elf = ElfExecutable::new(Machine::X86_64, 0x40_1000);
+    elf.segments.push(Segment::new(".text", 0x40_1000, text, PF_R | PF_X));
+    elf.segments.push(Segment::new(".rodata", 0x40_2000, b"Hello, ELF!\n".to_vec(), PF_R));
+    elf.segments.push(Segment::new(".data", 0x40_3000, data, PF_R | PF_W).zero_fill(64));
+    elf.symbols.push((Symbol::function("_start").global(), 0x40_1000));
+    elf.symbols.push((Symbol::object("message").size(12), 0x40_2000));
+    elf.symbols.push((Symbol::object("status").size(1), 0x40_3000));
+    elf.symbols.push((Symbol::object("pointer").size(8), 0x40_3008));
+    elf.symbols.push((Symbol::object("buffer").size(64), 0x40_3010));
+    let relocations = [
+        (0x40_100d, "pointer", -4, RelocationKind::Pc32),
+        (0x40_101b, "status", -4, RelocationKind::Pc32),
+        (0x40_3008, "message", 0, RelocationKind::Abs64),
+    ];
+    for (address, symbol, addend, kind) in relocations {
+        elf.relocations.push(Relocation { address, symbol: symbol.to_string(), addend, kind });
+    }
+    elf
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn test_x86_64_executable_runs() {
+    let path = std::env::temp_dir().join(format!("asm_test_elf_{}", std::process::id()));
+    x86_64_hello().write(&path).unwrap();
+    let output = std::process::Command::new(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello, ELF!\n");
+    assert_eq!(output.status.code(), Some(42));
+}
+
+#[test]
+fn test_headers_and_relocations() {
+    let bytes = x86_64_hello().to_bytes().unwrap();
+    assert_eq!(&bytes[..4], b"\x7fELF");
+    assert_eq!(u16_at(&bytes, 16), 2);
+    assert_eq!(u16_at(&bytes, 18), 62);
+    assert_eq!(u64_at(&bytes, 24), 0x40_1000);
+    // Three loadable segments and the stack
+    assert_eq!(u16_at(&bytes, 56), 4);
+
+    // Segments sit at offsets matching their addresses within a page
+    let text = u64_at(&bytes, 64 + 8) as usize;
+    assert_eq!(text % 0x1000, 0);
+    let disp = i32::from_le_bytes(bytes[text + 13..text + 17].try_into().unwrap());
+    assert_eq!(0x40_1011 + disp as i64, 0x40_3008);
+    let data = u64_at(&bytes, 64 + 2 * 56 + 8) as usize;
+    assert_eq!(u64_at(&bytes, data + 8), 0x40_2000);
+    // The zero fill takes no file space
+    assert_eq!(u64_at(&bytes, 64 + 2 * 56 + 32), 16);
+    assert_eq!(u64_at(&bytes, 64 + 2 * 56 + 40), 80);
+}
+
+#[test]
+fn test_relocation_errors() {
+    let mut elf = x86_64_hello();
+    elf.relocations[0].symbol = "missing".to_string();
+    assert_eq!(elf.to_bytes().err(), Some(ElfError::UndefinedSymbol("missing".to_string())));
+
+    let mut elf = x86_64_hello();
+    elf.symbols[1].1 = 0x1_0000_0000;
+    elf.relocations[2].kind = RelocationKind::Pc32;
+    elf.relocations[2].symbol = "message".to_string();
+    elf.relocations[0].symbol = "message".to_string();
+    assert_eq!(elf.to_bytes().err(), Some(ElfError::Relocation(0x40_100d)));
+
+    let mut elf = x86_64_hello();
+    elf.relocations[0].address = 0x50_0000;
+    assert_eq!(elf.to_bytes().err(), Some(ElfError::Relocation(0x50_0000)));
+}
+
+fn aarch64_program() -> Program<ARM64, Arm64Register> {
+    let mut program = Program::new(ARM64::new());
+    let message = program.global("message", Global::new(Value::Asciz("hi\n".to_string())));
+    let counter = program.global("counter", Global::new(Value::Quad(vec![7])).mutable());
+    let buffer = program.global("buffer", Global::new(Value::Zero(256)).mutable().align(16));
+    program.ins
+        .label("_start")
+        .mov_imm(X0, 1)
+        .adrp_add(X1, X1, &message)
+        .mov_imm(X2, 3)
+        .mov_imm(X8, 64)
+        .svc(0)
+        .adrp_add(X3, X3, &counter)
+        .load(MemSize::Double, false, X0, MemOperand::Offset(X3, 0))
+        .adrp_add(X4, X4, &buffer)
+        .store(MemSize::Double, X0, MemOperand::Offset(X4, 0))
+        .mov_imm(X8, 93)
+        .svc(0);
+    program
+}
+
+#[test]
+fn test_aarch64_executable_layout() {
+    let elf = aarch64_program().elf("_start").unwrap();
+    assert_eq!(elf.machine, Machine::Aarch64);
+    assert_eq!(elf.entry, elf.symbol("_start").unwrap());
+
+    let segments: Vec<(&str, u64, usize, u64, u32)> = elf
+        .segments
+        .iter()
+        .map(|segment| (segment.name.as_str(), segment.vaddr, segment.bytes.len(), segment.mem_size, segment.flags))
+        .collect();
+    assert_eq!(
+        segments,
+        vec![
+            (".text", 0x40_0120, 0x38, 0x38, PF_R | PF_X),
+            (".rodata", 0x40_1000, 4, 4, PF_R),
+            (".data", 0x40_2000, 16, 16 + 256, PF_R | PF_W),
+        ]
+    );
+    assert_eq!(&elf.segments[1].bytes, b"hi\n\0");
+    assert_eq!(elf.segments[2].bytes[..8], 7u64.to_le_bytes());
+    assert_eq!(elf.symbol("L0"), Some(0x40_1000));
+    assert_eq!(elf.symbol("L2"), Some(0x40_2010));
+
+    let bytes = elf.to_bytes().unwrap();
+    assert_eq!(u16_at(&bytes, 18), 183);
+    assert_eq!(u64_at(&bytes, 24), 0x40_0120);
+    // The text shares the first page with the headers
+    assert_eq!(u64_at(&bytes, 64 + 8), 0x120);
+    // Three loads and the stack, as `readelf -l` maps them
+    assert_eq!(
+        section_mapping(&bytes),
+        vec![
+            (1, vec![".text".to_string()]),
+            (1, vec![".rodata".to_string()]),
+            (1, vec![".data".to_string(), ".bss".to_string()]),
+            (0x6474e551, vec![]),
+        ]
+    );
+}
+
+#[test]
+fn test_aarch64_executable_errors() {
+    assert_eq!(aarch64_program().elf("main").err(), Some(ElfError::UndefinedEntry("main".to_string())));
+    let mut program = aarch64_program();
+    program.ins.bl("_exit");
+    assert_eq!(program.elf("_start").err(), Some(ElfError::Encode(EncodeError::UndefinedLabel("_exit".to_string()))));
+}
+
+/// Writes "hello\n", sums 1 to 10 through a counter in `.data` and exits
+/// with the sum.
+fn x86_64_program() -> Program<X86_64, X86Register> {
+    let mut program = Program::new(X86_64::new());
+    let message = program.global("message", Global::new(Value::Asciz("hello\n".to_string())));
+    let total = program.global("total", Global::new(Value::Quad(vec![0])).mutable());
+    program.ins
+        .label("_start")
+        .mov_imm(X8, 1)
+        .mov_imm(X0, 1)
+        .adr(X1, &message)
+        .mov_imm(X2, 6)
+        .svc(0)
+        .mov_imm(X9, 0)
+        .for_range(X10, 1, 11, |b| {
+            b.add(X9, X9, X10);
+        })
+        .adrp_add(X3, X3, &total)
+        .store(MemSize::Double, X9, MemOperand::Offset(X3, 0))
+        .load(MemSize::Double, false, X0, MemOperand::Offset(X3, 0))
+        .mov_imm(X8, 60)
+        .svc(0);
+    program
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn test_x86_64_program_executable_runs() {
+    let path = std::env::temp_dir().join(format!("asm_test_program_elf_{}", std::process::id()));
+    x86_64_program().elf("_start").unwrap().write(&path).unwrap();
+    let output = std::process::Command::new(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello\n");
+    assert_eq!(output.status.code(), Some(55));
+}
+
+#[test]
+fn test_x86_64_program_executable_layout() {
+    let elf = x86_64_program().elf("_start").unwrap();
+    assert_eq!(elf.machine, Machine::X86_64);
+    assert_eq!(elf.entry, 0x40_0120);
+    let segments: Vec<(&str, u64, u32)> = elf.segments.iter().map(|segment| (segment.name.as_str(), segment.vaddr, segment.flags)).collect();
+    assert_eq!(segments, vec![(".text", 0x40_0120, PF_R | PF_X), (".rodata", 0x40_1000, PF_R), (".data", 0x40_2000, PF_R | PF_W)]);
+    assert_eq!(&elf.segments[1].bytes, b"hello\n\0");
+    // `mov eax, 1`, `mov edi, 1` and `lea rsi, [rip + L0]`
+    let lea = 0x40_0120 + 10 + 7;
+    let mut start = vec![0xb8, 1, 0, 0, 0, 0xbf, 1, 0, 0, 0, 0x48, 0x8d, 0x35];
+    start.extend(((0x40_1000 - lea) as i32).to_le_bytes());
+    assert_eq!(elf.segments[0].bytes[..start.len()], start);
+    assert_eq!(u16_at(&elf.to_bytes().unwrap(), 18), 62);
+}
+
+#[test]
+fn test_x86_64_program_executable_errors() {
+    assert_eq!(x86_64_program().elf("main").err(), Some(ElfError::UndefinedEntry("main".to_string())));
+    let mut program = x86_64_program();
+    program.ins.bl("_exit");
+    assert_eq!(program.elf("_start").err(), Some(ElfError::Encode(EncodeError::UndefinedLabel("_exit".to_string()))));
+
+    // Build errors are reported before anything is assembled
+    let mut program = x86_64_program();
+    program.ins.sdiv(X0, X0, X1).mov(X20, X0);
+    let Some(ElfError::Build(errors)) = program.elf("_start").err() else { panic!("expected build errors") };
+    let messages: Vec<String> = errors.iter().map(|error| error.to_string().rsplit(": ").next().unwrap().to_string()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "`sdiv rdi, rdi, rsi` is not supported on this target",
+            "expected a register with an x86_64 counterpart, found x20",
+        ]
+    );
+}
+
+#[test]
+fn test_linked_module_executable() {
+    let mut runtime = Program::new(ARM64::new());
+    runtime.symbol(Symbol::function("exit").global());
+    runtime.ins.label("exit").mov_imm(X8, 93).svc(0);
+    let mut main = aarch64_program();
+    main.symbol(Symbol::function("_start").global());
+    main.ins.bl("exit");
+
+    let mut linker = Linker::new();
+    linker.add(runtime.finish().ok().unwrap()).add(main.finish().ok().unwrap());
+    let elf = linker.link().unwrap().elf("_start").unwrap();
+    assert_eq!(elf.symbol("exit"), Some(elf.segments[0].vaddr));
+    assert!(elf.symbol("L0.m1").is_some());
+    assert!(elf.to_bytes().is_ok());
+}
+
+/// Prints through `puts` and exits with status 7 through `exit`, both
+/// called through GOT entries the dynamic linker fills in.
+fn x86_64_dynamic() -> ElfExecutable {
+    #[rustfmt::skip]
+    let text = vec![
+        0x48, 0x8d, 0x3d, 0, 0, 0, 0,       // lea rdi, [rip + message]
+        0xff, 0x15, 0, 0, 0, 0,             // call [rip + puts@GOT]
+        0xbf, 0x07, 0x00, 0x00, 0x00,       // mov edi, 7
+        0xff, 0x15, 0, 0, 0, 0,             // call [rip + exit@GOT]
+    ];
+
+    // The text shares the first page with the headers, which the dynamic
+    // linker reads
+    let mut elf = ElfExecutable::new(Machine::X86_64, 0x40_0200);
+    elf.segments.push(Segment::new(".text", 0x40_0200, text, PF_R | PF_X));
+    elf.segments.push(Segment::new(".rodata", 0x40_1000, b"Hello from libc\0".to_vec(), PF_R));
+    elf.segments.push(Segment::new(".data", 0x40_2000, vec![0; 16], PF_R | PF_W));
+    elf.symbols.push((Symbol::function("_start").global(), 0x40_0200));
+    elf.symbols.push((Symbol::object("message").size(16), 0x40_1000));
+    elf.symbols.push((Symbol::object("puts@GOT").size(8), 0x40_2000));
+    elf.symbols.push((Symbol::object("exit@GOT").size(8), 0x40_2008));
+    for (address, symbol) in [(0x40_0203, "message"), (0x40_0209, "puts@GOT"), (0x40_0214, "exit@GOT")] {
+        elf.relocations.push(Relocation { address, symbol: symbol.to_string(), addend: -4, kind: RelocationKind::Pc32 });
+    }
+    for (address, symbol) in [(0x40_2000, "puts"), (0x40_2008, "exit")] {
+        elf.dynamic_relocations.push(DynamicRelocation { address, symbol: symbol.to_string() });
+    }
+    elf.dynamic = Some(Dynamic::libc(Machine::X86_64));
+    elf
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn test_x86_64_dynamic_executable_runs() {
+    let path = std::env::temp_dir().join(format!("asm_test_dynamic_{}", std::process::id()));
+    x86_64_dynamic().write(&path).unwrap();
+    let output = std::process::Command::new(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello from libc\n");
+    assert_eq!(output.status.code(), Some(7));
+}
+
+#[test]
+fn test_dynamic_headers() {
+    let bytes = x86_64_dynamic().to_bytes().unwrap();
+    let program_header = |index: usize| {
+        let at = 64 + index * 56;
+        (u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()), u64_at(&bytes, at + 8), u64_at(&bytes, at + 16))
+    };
+    // `PT_INTERP`, four loads, `PT_DYNAMIC` and the stack
+    assert_eq!(u16_at(&bytes, 56), 7);
+    let (kind, offset, vaddr) = program_header(0);
+    assert_eq!((kind, vaddr), (3, 0x40_3000));
+    assert_eq!(&bytes[offset as usize..offset as usize + 28], b"/lib64/ld-linux-x86-64.so.2\0");
+    // The first load maps the headers with the text
+    assert_eq!(program_header(1), (1, 0, 0x40_0000));
+    assert_eq!(program_header(4), (1, 0x3000, 0x40_3000));
+    let (kind, offset, _) = program_header(5);
+    assert_eq!(kind, 2);
+    let dynamic: Vec<(u64, u64)> =
+        (0..10).map(|index| (u64_at(&bytes, offset as usize + index * 16), u64_at(&bytes, offset as usize + index * 16 + 8))).collect();
+    // `DT_NEEDED` names libc, and the table ends with `DT_NULL`
+    assert_eq!(dynamic[0].0, 1);
+    let strtab = dynamic.iter().find(|(tag, _)| *tag == 5).unwrap().1 - 0x40_3000 + 0x3000;
+    let name = (strtab + dynamic[0].1) as usize;
+    assert_eq!(&bytes[name..name + 10], b"libc.so.6\0");
+    assert!(dynamic.contains(&(8, 48)));
+    assert_eq!(dynamic[9], (0, 0));
+
+    let mut elf = x86_64_dynamic();
+    elf.dynamic_relocations[0].address = 0x40_1000;
+    assert_eq!(elf.to_bytes().err(), Some(ElfError::Relocation(0x40_1000)));
+}
+
+#[test]
+fn test_aarch64_dynamic_executable() {
+    let mut program = Program::new(ARM64::new());
+    let message = program.global("message", Global::new(Value::Asciz("hi".to_string())));
+    program.ins
+        .label("_start")
+        .adrp_add(X0, X0, &message)
+        .bl("puts")
+        .adrp_got(X1, "environ")
+        .adrp_got(X2, &message)
+        .mov_imm(X0, 0)
+        .bl("exit");
+    let elf = program.dynamic_elf("_start", Dynamic::libc(Machine::Aarch64)).unwrap();
+    assert_eq!(elf.dynamic.as_ref().unwrap().interpreter.as_deref(), Some("/lib/ld-linux-aarch64.so.1"));
+
+    // Imports get a GOT entry each; the local message gets one too but
+    // needs no relocation
+    let relocations: Vec<(&str, u64)> =
+        elf.dynamic_relocations.iter().map(|relocation| (relocation.symbol.as_str(), relocation.address)).collect();
+    assert_eq!(relocations, vec![("environ", 0x40_2000), ("puts", 0x40_2010), ("exit", 0x40_2018)]);
+    assert_eq!(elf.segments[2].bytes[8..16], 0x40_1000u64.to_le_bytes());
+
+    // Each call goes through a stub loading its GOT entry into x16
+    let text = &elf.segments[0];
+    let word = |addr: u64| {
+        let at = (addr - text.vaddr) as usize;
+        u32::from_le_bytes(text.bytes[at..at + 4].try_into().unwrap())
+    };
+    let call = text.vaddr + 8;
+    let stub = call.wrapping_add(((word(call) << 6) as i32 >> 4) as u64);
+    // adrp x16, :got:puts; ldr x16, [x16, :got_lo12:puts]; br x16
+    assert_eq!([word(stub) & 0x9f00001f, word(stub + 4), word(stub + 8)], [0x90000010, 0xf9400a10, 0xd61f0200]);
+
+    // The interpreter and dynamic tables share the last load
+    let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        section_mapping(&elf.to_bytes().unwrap()),
+        vec![
+            (3, names(&[".interp"])),
+            (1, names(&[".text"])),
+            (1, names(&[".rodata"])),
+            (1, names(&[".data"])),
+            (1, names(&[".interp", ".dynsym", ".dynstr", ".hash", ".rela.dyn", ".dynamic"])),
+            (2, names(&[".dynamic"])),
+            (0x6474e551, vec![]),
+        ]
+    );
+
+    // Linked statically, the imports are undefined
+    assert_eq!(program.elf("_start").err(), Some(ElfError::Encode(EncodeError::UndefinedLabel("puts".to_string()))));
+}
+
+/// Exports `greet`, which prints through a pointer the dynamic linker
+/// relocates and tail-calls `puts`.
+fn x86_64_shared() -> ElfExecutable {
+    #[rustfmt::skip]
+    let text = vec![
+        0x48, 0x8b, 0x3d, 0, 0, 0, 0,       // mov rdi, [rip + pointer]
+        0xff, 0x25, 0, 0, 0, 0,             // jmp [rip + puts@GOT]
+    ];
+
+    let mut elf = ElfExecutable::new(Machine::X86_64, 0);
+    elf.shared = true;
+    elf.segments.push(Segment::new(".text", 0x200, text, PF_R | PF_X));
+    elf.segments.push(Segment::new(".rodata", 0x1000, b"Hello from a shared object\0".to_vec(), PF_R));
+    elf.segments.push(Segment::new(".data", 0x2000, vec![0; 16], PF_R | PF_W));
+    elf.symbols.push((Symbol::function("greet").global().size(13), 0x200));
+    elf.symbols.push((Symbol::object("message").size(27), 0x1000));
+    elf.symbols.push((Symbol::object("pointer").size(8), 0x2000));
+    elf.symbols.push((Symbol::object("puts@GOT").size(8), 0x2008));
+    elf.relocations.push(Relocation { address: 0x203, symbol: "pointer".to_string(), addend: -4, kind: RelocationKind::Pc32 });
+    elf.relocations.push(Relocation { address: 0x209, symbol: "puts@GOT".to_string(), addend: -4, kind: RelocationKind::Pc32 });
+    elf.relocations.push(Relocation { address: 0x2000, symbol: "message".to_string(), addend: 0, kind: RelocationKind::Abs64 });
+    elf.relative_relocations.push(0x2000);
+    elf.dynamic_relocations.push(DynamicRelocation { address: 0x2008, symbol: "puts".to_string() });
+    elf.dynamic = Some(Dynamic::shared("libgreet.so"));
+    elf
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn test_x86_64_shared_object_runs() {
+    #[rustfmt::skip]
+    let text = vec![
+        0xff, 0x15, 0, 0, 0, 0,             // call [rip + greet@GOT]
+        0xbf, 0x05, 0x00, 0x00, 0x00,       // mov edi, 5
+        0xff, 0x15, 0, 0, 0, 0,             // call [rip + exit@GOT]
+    ];
+    let mut elf = ElfExecutable::new(Machine::X86_64, 0x40_0200);
+    elf.segments.push(Segment::new(".text", 0x40_0200, text, PF_R | PF_X));
+    elf.segments.push(Segment::new(".data", 0x40_1000, vec![0; 16], PF_R | PF_W));
+    elf.symbols.push((Symbol::function("_start").global(), 0x40_0200));
+    elf.symbols.push((Symbol::object("greet@GOT").size(8), 0x40_1000));
+    elf.symbols.push((Symbol::object("exit@GOT").size(8), 0x40_1008));
+    for (address, symbol) in [(0x40_0202, "greet@GOT"), (0x40_020d, "exit@GOT")] {
+        elf.relocations.push(Relocation { address, symbol: symbol.to_string(), addend: -4, kind: RelocationKind::Pc32 });
+    }
+    for (address, symbol) in [(0x40_1000, "greet"), (0x40_1008, "exit")] {
+        elf.dynamic_relocations.push(DynamicRelocation { address, symbol: symbol.to_string() });
+    }
+    let mut dynamic = Dynamic::libc(Machine::X86_64);
+    dynamic.needed.insert(0, "libgreet.so".to_string());
+    elf.dynamic = Some(dynamic);
+
+    let dir = std::env::temp_dir().join(format!("asm_test_shared_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    x86_64_shared().write(&dir.join("libgreet.so")).unwrap();
+    elf.write(&dir.join("main")).unwrap();
+    let output = std::process::Command::new(dir.join("main")).env("LD_LIBRARY_PATH", &dir).output().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello from a shared object\n");
+    assert_eq!(output.status.code(), Some(5));
+}
+
+#[test]
+fn test_shared_object_headers() {
+    let bytes = x86_64_shared().to_bytes().unwrap();
+    // `ET_DYN` with no entry point, and no `PT_INTERP`: three loads, the
+    // dynamic linking segment, `PT_DYNAMIC` and the stack
+    assert_eq!(u16_at(&bytes, 16), 3);
+    assert_eq!(u64_at(&bytes, 24), 0);
+    assert_eq!(u16_at(&bytes, 56), 6);
+    assert_eq!(u32::from_le_bytes(bytes[64..68].try_into().unwrap()), 1);
+
+    let mut elf = x86_64_shared();
+    elf.relative_relocations.push(0x1000);
+    assert_eq!(elf.to_bytes().err(), Some(ElfError::Relocation(0x1000)));
+}
+
+#[test]
+fn test_aarch64_shared_object() {
+    let mut program = Program::new(ARM64::new());
+    let message = program.global("message", Global::new(Value::Asciz("hi".to_string())));
+    let table = program.global("table", Global::new(Value::Pointer { symbol: message.clone(), offset: 0 }));
+    program.symbol(Symbol::function("greet").global());
+    program.symbol(Symbol::function("helper").global().hidden());
+    program.ins
+        .label("greet")
+        .adrp_got(X0, &table)
+        .load(MemSize::Double, false, X0, MemOperand::Offset(X0, 0))
+        .bl("helper")
+        .bl("puts")
+        .label("helper")
+        .ret();
+    let elf = program.shared_elf(Dynamic::shared("libgreet.so")).unwrap();
+    assert!(elf.shared);
+    assert_eq!(elf.entry, 0);
+    assert_eq!(elf.segments[0].vaddr, 0x190);
+
+    // The pointer moves from read-only data to the writable page, and it
+    // and the GOT entry of the local table are relocated
+    let table = elf.symbol(&table).unwrap();
+    assert_eq!(table, 0x2010);
+    assert_eq!(elf.relative_relocations, vec![0x2000, table]);
+    let data = &elf.segments[2].bytes;
+    assert_eq!(data[..8], table.to_le_bytes());
+    assert_eq!(data[0x10..0x18], elf.symbol(&message).unwrap().to_le_bytes());
+    assert_eq!(elf.dynamic_relocations[0].symbol, "puts");
+    assert!(elf.to_bytes().is_ok());
+}
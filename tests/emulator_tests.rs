@@ -0,0 +1,168 @@
+use asm_test::arch::arm64::emulator::{Emulator, EmulatorError};
+use asm_test::arch::arm64::{ARM64, Arm64Register::*};
+use asm_test::context::Value;
+use asm_test::instruction::*;
+use asm_test::ir::{lower_function, CmpPred, FunctionBuilder, Type};
+use asm_test::{GenericRegister, InstructionBuilder};
+
+#[test]
+fn test_run_lowered_loop() {
+    let mut f = FunctionBuilder::new("sum", &[Type::I64], Some(Type::I64));
+    let n = f.param(0);
+    let entry = f.current_block();
+    let header = f.create_block();
+    let body = f.create_block();
+    let exit = f.create_block();
+    let zero = f.iconst(Type::I64, 0);
+    f.br(header);
+    f.switch_to_block(header);
+    let i = f.phi(Type::I64);
+    let acc = f.phi(Type::I64);
+    let done = f.cmp(CmpPred::Sge, i, n);
+    f.cond_br(done, exit, body);
+    f.switch_to_block(body);
+    let one = f.iconst(Type::I64, 1);
+    let next_acc = f.add(acc, i);
+    let next_i = f.add(i, one);
+    f.br(header);
+    f.add_incoming(i, entry, zero);
+    f.add_incoming(i, body, next_i);
+    f.add_incoming(acc, entry, zero);
+    f.add_incoming(acc, body, next_acc);
+    f.switch_to_block(exit);
+    f.ret(Some(acc));
+    let func = f.finish().unwrap();
+
+    let mut arch = ARM64::new();
+    lower_function(&func, &mut arch).unwrap();
+    let mut emulator = Emulator::new(&arch).unwrap();
+    assert_eq!(emulator.call("sum", &[10]).unwrap(), 45);
+    assert_eq!(emulator.call("sum", &[0]).unwrap(), 0);
+}
+
+#[test]
+fn test_multiply_and_divide() {
+    let mut arch = ARM64::new();
+    arch.label("kernel");
+    arch.sdiv(X2, X0, X1);
+    arch.udiv(X3, X0, X1);
+    arch.madd(X4, X0, X1, X2);
+    arch.msub(X5, X0, X1, X2);
+    arch.mneg(X6, X0, X1);
+    arch.smull(X7, X0, X1);
+    arch.umull(X8, X0, X1);
+    arch.smulh(X9, X0, X1);
+    arch.umulh(X10, X0, X1);
+    arch.smaddl(X11, X0, X1, X2);
+    arch.umaddl(X12, X0, X1, X2);
+    arch.ret();
+
+    assert_eq!(
+        arch.to_string(),
+        concat!(
+            "kernel:\n",
+            "    sdiv x2, x0, x1\n",
+            "    udiv x3, x0, x1\n",
+            "    madd x4, x0, x1, x2\n",
+            "    msub x5, x0, x1, x2\n",
+            "    mneg x6, x0, x1\n",
+            "    smull x7, w0, w1\n",
+            "    umull x8, w0, w1\n",
+            "    smulh x9, x0, x1\n",
+            "    umulh x10, x0, x1\n",
+            "    smaddl x11, w0, w1, x2\n",
+            "    umaddl x12, w0, w1, x2\n",
+            "    ret\n",
+        )
+    );
+
+    let mut emulator = Emulator::new(&arch).unwrap();
+    emulator.call("kernel", &[-7i64 as u64, 2]).unwrap();
+    assert_eq!(emulator.reg(X2) as i64, -3);
+    assert_eq!(emulator.reg(X3), (-7i64 as u64) / 2);
+    assert_eq!(emulator.reg(X4) as i64, -17);
+    assert_eq!(emulator.reg(X5) as i64, 11);
+    assert_eq!(emulator.reg(X6) as i64, 14);
+    assert_eq!(emulator.reg(X7) as i64, -14);
+    assert_eq!(emulator.reg(X8), 0xffff_fff9 * 2);
+    assert_eq!(emulator.reg(X9), u64::MAX);
+    assert_eq!(emulator.reg(X10), 1);
+    assert_eq!(emulator.reg(X11) as i64, -17);
+    assert_eq!(emulator.reg(X12), 0xffff_fff9 * 2 - 3);
+
+    // Division by zero yields zero, and the one overflowing quotient wraps
+    emulator.call("kernel", &[42, 0]).unwrap();
+    assert_eq!(emulator.reg(X2), 0);
+    assert_eq!(emulator.reg(X3), 0);
+    emulator.call("kernel", &[i64::MIN as u64, -1i64 as u64]).unwrap();
+    assert_eq!(emulator.reg(X2), i64::MIN as u64);
+}
+
+#[test]
+fn test_fixed_point_multiply_via_builder() {
+    // Q32.32 multiply: the middle 64 bits of the 128-bit product
+    let mut builder: InstructionBuilder<ARM64, _> = InstructionBuilder::new(ARM64::new());
+    builder
+        .label("qmul")
+        .umulh(GenericRegister::X2, GenericRegister::X0, GenericRegister::X1)
+        .madd(GenericRegister::X3, GenericRegister::X0, GenericRegister::X1, GenericRegister::XZR);
+    builder.arch.extr(X0, X2, X3, 32);
+    builder.arch.ret();
+
+    let mut emulator = Emulator::new(&builder.arch).unwrap();
+    let (a, b) = (3u64 << 31, 5u64 << 32);
+    assert_eq!(emulator.call("qmul", &[a, b]).unwrap(), 15u64 << 31);
+}
+
+#[test]
+fn test_switch_jump_table_executes() {
+    let mut builder: InstructionBuilder<ARM64, _> = InstructionBuilder::new(ARM64::new());
+    builder.label("classify");
+    builder.switch_(GenericRegister::X0, |s| {
+        s.case(10, |b| { b.mov_imm(GenericRegister::X1, 100); });
+        s.case(11, |b| { b.mov_imm(GenericRegister::X1, 110); });
+        s.case(13, |b| { b.mov_imm(GenericRegister::X1, 130); });
+        s.case(14, |b| { b.mov_imm(GenericRegister::X1, 140); });
+        s.default(|b| { b.mov_imm(GenericRegister::X1, 0); });
+    });
+    builder.arch.mov(X0, X1);
+    builder.arch.ret();
+
+    let mut emulator = Emulator::new(&builder.arch).unwrap();
+    for (input, expected) in [(10, 100), (11, 110), (12, 0), (13, 130), (14, 140), (9, 0), (15, 0)] {
+        assert_eq!(emulator.call("classify", &[input]).unwrap(), expected);
+    }
+}
+
+#[test]
+fn test_emulator_errors() {
+    let mut arch = ARM64::new();
+    arch.label("spin");
+    arch.b("spin");
+    arch.label("missing");
+    arch.bl("nowhere");
+
+    let mut emulator = Emulator::new(&arch).unwrap();
+    emulator.set_step_limit(100);
+    assert_eq!(emulator.call("spin", &[]), Err(EmulatorError::StepLimit(100)));
+    assert_eq!(
+        emulator.call("missing", &[]),
+        Err(EmulatorError::UndefinedLabel("nowhere".to_string()))
+    );
+}
+
+#[test]
+fn test_run_got_load() {
+    let mut arch = ARM64::new();
+    arch.label("f");
+    arch.adrp_got(X0, "value");
+    arch.load(MemSize::Double, false, X0, MemOperand::Offset(X0, 0));
+    arch.ret();
+    arch.global("value", &Value::Quad(vec![7]), 8);
+    // Without an entry the label's own address is used
+    assert_eq!(Emulator::new(&arch).unwrap().call("f", &[]), Ok(7));
+
+    arch.global("other", &Value::Quad(vec![9]), 8);
+    arch.global("value@GOT", &Value::Pointer { symbol: "other".to_string(), offset: 0 }, 8);
+    assert_eq!(Emulator::new(&arch).unwrap().call("f", &[]), Ok(9));
+}
@@ -0,0 +1,182 @@
+use asm_test::arch::arm64::encoder::{assemble, encode, EncodeError};
+use asm_test::arch::arm64::{ARM64, Arm64Register::*};
+use asm_test::context::Value;
+use asm_test::instruction::*;
+use asm_test::platform::{linux::Linux, macos::MacOS};
+use asm_test::{GenericRegister, InstructionBuilder};
+use std::io::Write;
+mod common;
+
+#[test]
+fn test_encode_single_instructions() {
+    let mut arch = ARM64::new();
+    ArithmeticBuilder::add(&mut arch, X0, X1, Operand::Register(X2));
+    arch.fadd(FpSize::Double, V0, V1, V2);
+    arch.load(MemSize::Double, false, X1, MemOperand::Offset(X2, 8));
+    arch.stp(X29, X30, MemOperand::PreIndex(SP, -16));
+    arch.vadd(VectorArrangement::S4, V0, V1, V2);
+    arch.ld1(VectorArrangement::B16, V0, 2, MemOperand::PostIndex(X0, 32));
+    arch.addv(VectorArrangement::S4, V0, V1);
+    arch.umov(MemSize::Word, X3, V4, 1);
+    arch.ret();
+
+    // Expected words taken from `llvm-mc -triple=aarch64 -show-encoding`
+    assert_eq!(
+        common::words(&arch),
+        vec![
+            0x8b020020, 0x1e622820, 0xf9400441, 0xa9bf7bfd, 0x4ea28420, 0x4cdfa000, 0x4eb1b820, 0x0e0c3c83,
+            0xd65f03c0,
+        ]
+    );
+}
+
+#[test]
+fn test_assemble_resolves_labels() {
+    let mut builder: InstructionBuilder<ARM64, _> = InstructionBuilder::new(ARM64::new());
+    builder.label("start");
+    builder.switch_(GenericRegister::X0, |s| {
+        s.case(10, |b| { b.mov_imm(GenericRegister::X1, 100); });
+        s.case(11, |b| { b.mov_imm(GenericRegister::X1, 110); });
+        s.case(13, |b| { b.mov_imm(GenericRegister::X1, 130); });
+        s.case(14, |b| { b.mov_imm(GenericRegister::X1, 140); });
+        s.default(|b| { b.mov_imm(GenericRegister::X1, 0); });
+    });
+    let arch = &mut builder.arch;
+    arch.cbz(X0, "start");
+    arch.cbnz(X1, "end");
+    arch.b_cond(Condition::Le, "start");
+    arch.bl("start");
+    arch.adr(X0, "start");
+    arch.adrp_add(X2, X3, "Ljumptable6");
+    arch.mov_imm(X5, 0x1234_5678_9abc_def0);
+    arch.label("end");
+    arch.b("start");
+
+    let out = assemble(arch, 0x10000).unwrap();
+    assert_eq!(out.text_base, 0x10000);
+    assert_eq!(out.rodata_base, 0x11000);
+    assert_eq!(out.labels["Ldefault4"], 0x10040);
+    assert_eq!(out.labels["end"], 0x10064);
+
+    let text = common::words(arch);
+    // b.hi Ldefault4; adrp x17, Ljumptable6@PAGE; add x17, x17, Ljumptable6@PAGEOFF
+    assert_eq!(text[2..5], [0x540001c8, 0xb0000011, 0x91000231]);
+    // cbz, cbnz, b.le, bl, adr back to `start` and forward to `end`
+    assert_eq!(text[17..22], [0xb4fffde0, 0xb50000e1, 0x54fffdad, 0x97ffffec, 0x10fffd60]);
+    // adrp x3, Ljumptable6@PAGE; add x2, x3, Ljumptable6@PAGEOFF; ldr x5, Llit0
+    assert_eq!(text[22..25], [0xb0000003, 0x91000062, 0x58000045]);
+    // b start, then the literal pool, already 8-byte aligned
+    assert_eq!(text[25..], [0x17ffffe7, 0x9abcdef0, 0x12345678]);
+
+    // Jump table entries are offsets of each case from the table itself
+    let entries: Vec<i32> = out.rodata.chunks(4).map(|w| i32::from_le_bytes([w[0], w[1], w[2], w[3]])).collect();
+    let table = out.labels["Ljumptable6"] as i64;
+    for (entry, case) in entries.iter().zip(["Lcase0", "Lcase1", "Ldefault4", "Lcase2", "Lcase3"]) {
+        assert_eq!(table + *entry as i64, out.labels[case] as i64);
+    }
+}
+
+#[test]
+fn test_encode_errors() {
+    let resolve = |name: &str| if name == "far" { Some(0x1000_0000) } else { None };
+
+    let mut arch = ARM64::new();
+    arch.b("nowhere");
+    arch.cbz(X0, "far");
+    ArithmeticBuilder::add(&mut arch, X0, X1, Operand::Immediate("0x12345".to_string()));
+    let insts = arch.get_instructions();
+    assert_eq!(encode(&insts[0], 0, &resolve), Err(EncodeError::UndefinedLabel("nowhere".to_string())));
+    assert_eq!(
+        encode(&insts[1], 0, &resolve),
+        Err(EncodeError::OutOfRange { inst: "cbz x0, far".to_string(), value: 0x1000_0000 })
+    );
+    assert!(matches!(encode(&insts[2], 0, &resolve), Err(EncodeError::OutOfRange { .. })));
+
+    let mut arch = ARM64::new();
+    arch.label("twice");
+    arch.label("twice");
+    assert_eq!(assemble(&arch, 0).err(), Some(EncodeError::DuplicateLabel("twice".to_string())));
+}
+
+#[test]
+fn test_encode_got_load() {
+    let mut arch = ARM64::new();
+    arch.adrp_got(X0, "value");
+    arch.ret();
+    arch.global("value", &Value::Quad(vec![7]), 8);
+    assert_eq!(arch.to_string().lines().next(), Some("    adrp x0, value@GOTPAGE"));
+    assert_eq!(assemble(&arch, 0x10000).err(), Some(EncodeError::UndefinedLabel("value@GOT".to_string())));
+
+    arch.global("value@GOT", &Value::Pointer { symbol: "value".to_string(), offset: 0 }, 8);
+    // adrp x0, value@GOTPAGE; ldr x0, [x0, value@GOTPAGEOFF]
+    assert_eq!(common::words(&arch)[..2], [0xb0000000, 0xf9400400]);
+}
+
+fn address_listing() -> ARM64 {
+    let mut arch = ARM64::new();
+    arch.adrp_add(X1, X2, "value");
+    arch.adrp_got(X0, "value");
+    arch.adrp_got(X3, "_environ");
+    arch.ret();
+    arch.global("value", &Value::Quad(vec![7]), 8);
+    arch
+}
+
+#[test]
+fn test_address_listing_syntax() {
+    let arch = address_listing();
+    let linux: Vec<String> = arch.listing(&Linux).lines().take(6).map(str::to_string).collect();
+    assert_eq!(
+        linux,
+        [
+            "    adrp x2, value",
+            "    add x1, x2, :lo12:value",
+            "    adrp x0, :got:value",
+            "    ldr x0, [x0, :got_lo12:value]",
+            "    adrp x3, :got:_environ",
+            "    ldr x3, [x3, :got_lo12:_environ]",
+        ]
+    );
+    let macos: Vec<String> = arch.listing(&MacOS).lines().take(6).map(str::to_string).collect();
+    assert_eq!(
+        macos,
+        [
+            "    adrp x2, value@PAGE",
+            "    add x1, x2, value@PAGEOFF",
+            "    adrp x0, value@GOTPAGE",
+            "    ldr x0, [x0, value@GOTPAGEOFF]",
+            "    adrp x3, _environ@GOTPAGE",
+            "    ldr x3, [x3, _environ@GOTPAGEOFF]",
+        ]
+    );
+    assert_eq!(arch.to_string(), arch.listing(&MacOS));
+}
+
+#[test]
+#[ignore = "needs llvm-mc"]
+fn test_address_listing_assembles() {
+    let arch = address_listing();
+    for (triple, listing) in [
+        ("aarch64-linux-gnu", arch.listing(&Linux)),
+        ("arm64-apple-macos", arch.listing(&MacOS)),
+    ] {
+        let mut child = std::process::Command::new("llvm-mc")
+            .args([&format!("-triple={}", triple), "-filetype=obj", "-o", "/dev/null"])
+            .stdin(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .expect("llvm-mc is not installed");
+        child.stdin.take().unwrap().write_all(listing.as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{}: {}", triple, String::from_utf8_lossy(&output.stderr));
+    }
+}
+
+#[test]
+fn test_encode_cset() {
+    let mut arch = ARM64::new();
+    arch.cset(X0, Condition::Lt);
+    arch.cset(W1, Condition::Al);
+    // cset x0, lt; then `al` always holds, so mov w1, #1
+    assert_eq!(common::words(&arch), [0x9a9fa7e0, 0x52800021]);
+}
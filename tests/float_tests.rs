@@ -0,0 +1,123 @@
+use asm_test::arch::arm64::emulator::Emulator;
+use asm_test::arch::arm64::imm::{encode_fp_imm, f16_to_f64, f64_to_f16};
+use asm_test::arch::arm64::{ARM64, Arm64Register::*};
+use asm_test::instruction::*;
+
+#[test]
+fn test_float_rendering() {
+    let mut arch = ARM64::new();
+    arch.fadd(FpSize::Double, V0, V1, V2);
+    arch.fsub(FpSize::Single, V0, V1, V2);
+    arch.fmul(FpSize::Half, V3, V4, V5);
+    arch.fdiv(FpSize::Double, V31, V30, V29);
+    arch.fsqrt(FpSize::Single, V0, V1);
+    arch.fneg(FpSize::Double, V0, V1);
+    arch.fabs(FpSize::Half, V0, V1);
+    arch.fmadd(FpSize::Double, V0, V1, V2, V3);
+    arch.fcmp(FpSize::Single, V8, V9);
+    arch.fcsel(FpSize::Double, V0, V1, V2, Condition::Mi);
+    arch.fmov(FpSize::Double, V0, V1);
+    arch.fmov(FpSize::Double, V0, X1);
+    arch.fmov(FpSize::Single, X2, V3);
+    arch.fmov_imm(FpSize::Double, V0, -1.5);
+    arch.fmov_imm(FpSize::Single, V1, 0.0);
+    arch.fmov_imm(FpSize::Double, V2, 0.1);
+    arch.scvtf(FpSize::Double, V0, X1);
+    arch.ucvtf(FpSize::Single, V0, X1);
+    arch.fcvtzs(FpSize::Double, X0, V1);
+    arch.fcvtzu(FpSize::Half, X0, V1);
+    arch.fcvt(FpSize::Single, V0, FpSize::Double, V1);
+
+    assert_eq!(
+        arch.to_string(),
+        concat!(
+            "    fadd d0, d1, d2\n",
+            "    fsub s0, s1, s2\n",
+            "    fmul h3, h4, h5\n",
+            "    fdiv d31, d30, d29\n",
+            "    fsqrt s0, s1\n",
+            "    fneg d0, d1\n",
+            "    fabs h0, h1\n",
+            "    fmadd d0, d1, d2, d3\n",
+            "    fcmp s8, s9\n",
+            "    fcsel d0, d1, d2, mi\n",
+            "    fmov d0, d1\n",
+            "    fmov d0, x1\n",
+            "    fmov w2, s3\n",
+            "    fmov d0, #-1.5\n",
+            "    fmov s1, wzr\n",
+            "    ldr x16, Llit0\n",
+            "    fmov d2, x16\n",
+            "    scvtf d0, x1\n",
+            "    ucvtf s0, x1\n",
+            "    fcvtzs x0, d1\n",
+            "    fcvtzu x0, h1\n",
+            "    fcvt s0, d1\n",
+            "    .p2align 3\n",
+            "Llit0:\n",
+            "    .quad 0x3fb999999999999a\n",
+        )
+    );
+}
+
+#[test]
+fn test_fp_immediates() {
+    assert_eq!(encode_fp_imm(2.0), Some(0x00));
+    assert_eq!(encode_fp_imm(1.0), Some(0x70));
+    assert_eq!(encode_fp_imm(-0.125), Some(0xc0));
+    assert_eq!(encode_fp_imm(31.0), Some(0x3f));
+    assert_eq!(encode_fp_imm(0.0), None);
+    assert_eq!(encode_fp_imm(0.1), None);
+    assert_eq!(encode_fp_imm(64.0), None);
+
+    assert_eq!(f64_to_f16(1.0), 0x3c00);
+    assert_eq!(f64_to_f16(-2.5), 0xc100);
+    assert_eq!(f64_to_f16(65504.0), 0x7bff);
+    assert_eq!(f64_to_f16(65520.0), 0x7c00);
+    assert_eq!(f64_to_f16(2f64.powi(-24)), 0x0001);
+    assert_eq!(f64_to_f16(1.0 + 2f64.powi(-11)), 0x3c00);
+    assert_eq!(f16_to_f64(0x3555), 0.333251953125);
+    assert!(f16_to_f64(0x7e00).is_nan());
+}
+
+#[test]
+fn test_float_emulation() {
+    let mut arch = ARM64::new();
+    arch.label("hypot");
+    arch.scvtf(FpSize::Double, V0, X0);
+    arch.scvtf(FpSize::Double, V1, X1);
+    arch.fmul(FpSize::Double, V1, V1, V1);
+    arch.fmadd(FpSize::Double, V0, V0, V0, V1);
+    arch.fsqrt(FpSize::Double, V0, V0);
+    arch.fcvtzs(FpSize::Double, X0, V0);
+    arch.ret();
+
+    arch.label("clamp_nan");
+    arch.fmov(FpSize::Single, V0, X0);
+    arch.fmov_imm(FpSize::Single, V1, 0.0);
+    // Unordered compares set C and V, so `ge` picks the fallback for NaN
+    arch.fcmp(FpSize::Single, V0, V0);
+    arch.fcsel(FpSize::Single, V0, V0, V1, Condition::Ge);
+    arch.fneg(FpSize::Single, V0, V0);
+    arch.fmov(FpSize::Single, X0, V0);
+    arch.ret();
+
+    arch.label("to_half");
+    arch.fmov(FpSize::Double, V0, X0);
+    arch.fcvt(FpSize::Half, V0, FpSize::Double, V0);
+    arch.fmov(FpSize::Half, X0, V0);
+    arch.ret();
+
+    arch.label("saturate");
+    arch.fmov_imm(FpSize::Double, V0, 1e300);
+    arch.fcvtzu(FpSize::Double, X0, V0);
+    arch.ret();
+
+    let mut emulator = Emulator::new(&arch).unwrap();
+    assert_eq!(emulator.call("hypot", &[3, 4]).unwrap(), 5);
+    assert_eq!(emulator.call("hypot", &[-5i64 as u64, 12]).unwrap(), 13);
+    assert_eq!(emulator.call("clamp_nan", &[2.5f32.to_bits() as u64]).unwrap(), (-2.5f32).to_bits() as u64);
+    assert_eq!(emulator.call("clamp_nan", &[f32::NAN.to_bits() as u64]).unwrap(), (-0.0f32).to_bits() as u64);
+    assert_eq!(emulator.call("to_half", &[1.5f64.to_bits()]).unwrap(), 0x3e00);
+    assert_eq!(emulator.call("saturate", &[]).unwrap(), u64::MAX);
+}
@@ -0,0 +1,108 @@
+use asm_test::arch::arm64::{ARM64, Arm64Register, Arm64Register::*};
+use asm_test::context::{quote, Global, Value};
+use asm_test::instruction::*;
+use asm_test::Program;
+
+#[test]
+fn test_string_quoting() {
+    assert_eq!(quote("Hello, World!\n"), r#""Hello, World!\n""#);
+    assert_eq!(quote("say \"hi\"\t\\"), r#""say \"hi\"\t\\""#);
+    // Control characters and UTF-8 bytes are written in octal
+    assert_eq!(quote("\u{1}\r\u{7f}é"), r#""\001\r\177\303\251""#);
+}
+
+#[test]
+fn test_value_directives() {
+    let cases = [
+        (Value::Asciz("a\"b".to_string()), r#".asciz "a\"b""#, 4, 1),
+        (Value::Byte(vec![1, 0xff]), ".byte 0x1, 0xff", 2, 1),
+        (Value::Hword(vec![0x1234]), ".hword 0x1234", 2, 2),
+        (Value::Word(vec![1, 2, 3]), ".word 0x1, 0x2, 0x3", 12, 4),
+        (Value::Quad(vec![u64::MAX]), ".quad 0xffffffffffffffff", 8, 8),
+        (Value::Float(vec![1.5, f32::NAN]), ".word 0x3fc00000, 0x7fc00000", 8, 4),
+        (Value::Double(vec![-2.0]), ".quad 0xc000000000000000", 8, 8),
+        (Value::Zero(64), ".zero 64", 64, 1),
+        (Value::Pointer { symbol: "table".to_string(), offset: 0 }, ".quad table", 8, 8),
+        (Value::Pointer { symbol: "table".to_string(), offset: 16 }, ".quad table+16", 8, 8),
+        (Value::Pointer { symbol: "table".to_string(), offset: -8 }, ".quad table-8", 8, 8),
+    ];
+    for (value, directive, size, align) in cases {
+        assert_eq!(value.to_string(), directive);
+        assert_eq!(value.size(), size, "{}", directive);
+        assert_eq!(value.natural_align(), align, "{}", directive);
+    }
+}
+
+#[test]
+fn test_globals_are_placed_by_mutability() {
+    let mut program = Program::new(ARM64::new());
+    let counter = program.global("counter", Global::new(Value::Quad(vec![0])).mutable());
+    let table = program.global("table", Global::new(Value::Word(vec![1, 2])).align(16));
+    assert_eq!((counter.as_str(), table.as_str()), ("L0", "L1"));
+
+    let var = program.ctx.get_variable("table").unwrap();
+    assert_eq!((var.align, var.mutable), (16, false));
+    assert_eq!(
+        program.to_string(),
+        concat!(
+            ".section __TEXT,__const\n",
+            "    .p2align 4\n",
+            "L1:\n",
+            "    .word 0x1, 0x2\n",
+            ".section __DATA,__data\n",
+            "    .p2align 3\n",
+            "L0:\n",
+            "    .quad 0x0\n",
+            ".section __TEXT,__text\n",
+            ".globl _start\n",
+            "_start:\n",
+        )
+    );
+}
+
+#[test]
+fn test_globals_in_image() {
+    const LOAD: u64 = 0x4000_0000;
+    let mut program: Program<ARM64, Arm64Register> = Program::new(ARM64::new());
+    let blob = program.global("blob", Global::new(Value::Byte(vec![1, 2, 3])));
+    let pointer = program.global("pointer", Global::new(Value::Pointer { symbol: blob.clone(), offset: 1 }));
+    let half = program.global("half", Global::new(Value::Float(vec![0.5])).mutable());
+    let arch = &mut program.ins.arch;
+    arch.label("_start");
+    arch.adrp_add(X0, X1, &pointer);
+    arch.load(MemSize::Double, false, X0, MemOperand::Offset(X0, 0));
+    arch.ret();
+
+    let image = program.image(LOAD, "_start").unwrap();
+    let rodata = LOAD + 0x1000;
+    assert_eq!(image.labels[&blob], rodata);
+    // The pointer is aligned past the three byte blob
+    assert_eq!(image.labels[&pointer], rodata + 8);
+    assert_eq!(image.labels[&half], rodata + 16);
+
+    let bytes = &image.to_bin()[0x1000..];
+    assert_eq!(&bytes[..3], &[1, 2, 3]);
+    assert_eq!(&bytes[8..16], &(rodata + 1).to_le_bytes());
+    assert_eq!(&bytes[16..20], &0.5f32.to_bits().to_le_bytes());
+}
+
+#[test]
+fn test_global_errors_are_reported() {
+    let mut program = Program::new(ARM64::new());
+    let line = line!() + 1;
+    let odd = program.global("odd", Global::new(Value::Quad(vec![1])).align(12));
+    program.global("twice", Global::new(Value::Byte(vec![1])));
+    program.global("twice", Global::new(Value::Byte(vec![2])));
+    program.ins.label("_start").ret();
+
+    // The global keeps its natural alignment so layout still works
+    assert_eq!(program.ctx.get_sections().variables().find(|var| var.label == odd).unwrap().align, 8);
+    let errors = program.finish().err().unwrap();
+    assert_eq!(errors.len(), 2);
+    assert!(matches!(&errors[0], BuildError::InvalidAlignment { global, align: 12, .. } if global == "odd"));
+    assert!(matches!(&errors[1], BuildError::DuplicateGlobal { global, .. } if global == "twice"));
+    assert_eq!(errors[0].site(), None);
+    let message = errors[0].to_string();
+    assert!(message.starts_with(&format!("{}:{}:", file!(), line)));
+    assert!(message.ends_with(": alignment 12 of global odd is not a power of two"));
+}
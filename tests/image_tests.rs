@@ -0,0 +1,118 @@
+use asm_test::arch::arm64::{ARM64, Arm64Register, Arm64Register::*};
+use asm_test::instruction::*;
+use asm_test::program::image::{ImageError, LinkerScript, MemoryRegion};
+use asm_test::Program;
+
+const LOAD: u64 = 0x4000_0000;
+
+fn program() -> Program<ARM64, Arm64Register> {
+    let mut program = Program::new(ARM64::new());
+    let greeting = program.var("greeting", "hi\n");
+    let arch = &mut program.ins.arch;
+    arch.label("_start");
+    arch.adrp_add(X0, X1, &greeting);
+    arch.bl("done");
+    arch.label("done");
+    arch.ret();
+    program
+}
+
+fn hex_bytes(text: &str) -> Vec<u8> {
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+}
+
+#[test]
+fn test_flat_binary() {
+    let image = program().image(LOAD, "_start").unwrap();
+    assert_eq!(image.entry, LOAD);
+    assert_eq!(image.labels["L0"], LOAD + 0x1000);
+
+    let bin = image.to_bin();
+    assert_eq!(bin.len(), 0x1000 + 4);
+    let word = |at: usize| u32::from_le_bytes(bin[at..at + 4].try_into().unwrap());
+    // adrp x1, L0@PAGE; add x0, x1, L0@PAGEOFF; bl done; ret
+    assert_eq!([word(0), word(4), word(8), word(12)], [0xb0000001, 0x91000020, 0x94000001, 0xd65f03c0]);
+    assert!(bin[16..0x1000].iter().all(|byte| *byte == 0));
+    assert_eq!(&bin[0x1000..], b"hi\n\0");
+}
+
+#[test]
+fn test_hex_and_srec_images() {
+    let image = program().image(LOAD, "done").unwrap();
+    let ihex = image.to_ihex().unwrap();
+    let lines: Vec<&str> = ihex.lines().collect();
+    assert_eq!(lines[0], ":020000044000BA");
+    assert_eq!(lines[1], ":10000000010000B02000009101000094C0035FD601");
+    assert_eq!(lines[lines.len() - 2], ":040000054000000CAB");
+    assert_eq!(lines[lines.len() - 1], ":00000001FF");
+
+    // Every record checksums to zero and the data reassembles the image
+    let mut bytes = vec![0; image.bytes.len()];
+    let mut upper = 0;
+    for line in &lines {
+        let record = hex_bytes(&line[1..]);
+        assert_eq!(record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)), 0, "{}", line);
+        let addr = u16::from_be_bytes([record[1], record[2]]) as u64;
+        match record[3] {
+            0x00 => {
+                let start = ((upper << 16) + addr - LOAD) as usize;
+                bytes[start..start + record[0] as usize].copy_from_slice(&record[4..4 + record[0] as usize]);
+            }
+            0x04 => upper = u16::from_be_bytes([record[4], record[5]]) as u64,
+            _ => {}
+        }
+    }
+    assert_eq!(bytes, image.bytes);
+
+    let srec = image.to_srec().unwrap();
+    let lines: Vec<&str> = srec.lines().collect();
+    assert_eq!(lines[0], "S0030000FC");
+    // The S5 record counts the data records between the header and itself
+    assert!(lines[lines.len() - 2].starts_with(&format!("S503{:04X}", lines.len() - 3)));
+    assert_eq!(lines[lines.len() - 1], "S7054000000CAE");
+    let mut bytes: Vec<u8> = Vec::new();
+    for line in &lines[1..lines.len() - 2] {
+        let record = hex_bytes(&line[2..]);
+        assert_eq!(record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)), 0xff, "{}", line);
+        assert_eq!(u32::from_be_bytes(record[1..5].try_into().unwrap()) as u64, LOAD + bytes.len() as u64);
+        bytes.extend(&record[5..record.len() - 1]);
+    }
+    assert_eq!(bytes, image.bytes);
+}
+
+#[test]
+fn test_image_errors() {
+    assert_eq!(program().image(LOAD, "main").err(), Some(ImageError::UndefinedEntry("main".to_string())));
+    let image = program().image(0x1_0000_0000, "_start").unwrap();
+    assert_eq!(image.to_ihex().err(), Some(ImageError::AddressRange(0x1_0000_1004)));
+    assert!(matches!(image.to_srec(), Err(ImageError::AddressRange(_))));
+
+    // An image may end at 4GB, but its entry may not lie there
+    let mut image = program().image(LOAD, "_start").unwrap();
+    image.load_address = (1 << 32) - image.bytes.len() as u64;
+    assert!(image.to_ihex().is_ok());
+    image.entry = 1 << 32;
+    assert_eq!(image.to_srec().err(), Some(ImageError::AddressRange(1 << 32)));
+}
+
+#[test]
+fn test_srec_large_count() {
+    let mut image = program().image(LOAD, "_start").unwrap();
+    image.bytes.resize(0x10_0010, 0);
+    let srec = image.to_srec().unwrap();
+    let lines: Vec<&str> = srec.lines().collect();
+    // 0x10001 data records need the 24-bit S6 count
+    assert_eq!(lines[lines.len() - 2], "S604010001F9");
+}
+
+#[test]
+fn test_linker_script() {
+    let script = LinkerScript::new("_start", MemoryRegion::new("ROM", "rwx", LOAD, 0x10_0000));
+    let text = script.to_string();
+    assert!(text.starts_with("ENTRY(_start)\n\nMEMORY\n{\n    ROM (rwx) : ORIGIN = 0x40000000, LENGTH = 0x100000\n}\n"));
+    // Data and bss follow rodata in ROM as in the image, with no copy-down
+    assert!(text.contains(
+        "    .rodata : ALIGN(4096) {\n        *(.rodata*)\n        *(.data*)\n        *(.bss*)\n        *(COMMON)\n    } > ROM\n"
+    ));
+    assert!(!text.contains("AT >"));
+}
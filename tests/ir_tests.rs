@@ -0,0 +1,171 @@
+use asm_test::arch::arm64::{ARM64, Instruction, BranchOp};
+use asm_test::ir::{lower_function, CmpPred, FunctionBuilder, IrError, Type};
+
+/// `sum(n) = 0 + 1 + .. + (n - 1)` as a counted loop with phis.
+fn build_sum() -> asm_test::ir::Function {
+    let mut f = FunctionBuilder::new("sum", &[Type::I64], Some(Type::I64));
+    let n = f.param(0);
+    let entry = f.current_block();
+    let header = f.create_block();
+    let body = f.create_block();
+    let exit = f.create_block();
+
+    let zero = f.iconst(Type::I64, 0);
+    f.br(header);
+
+    f.switch_to_block(header);
+    let i = f.phi(Type::I64);
+    let acc = f.phi(Type::I64);
+    let done = f.cmp(CmpPred::Sge, i, n);
+    f.cond_br(done, exit, body);
+
+    f.switch_to_block(body);
+    let one = f.iconst(Type::I64, 1);
+    let next_acc = f.add(acc, i);
+    let next_i = f.add(i, one);
+    f.br(header);
+
+    f.add_incoming(i, entry, zero);
+    f.add_incoming(i, body, next_i);
+    f.add_incoming(acc, entry, zero);
+    f.add_incoming(acc, body, next_acc);
+
+    f.switch_to_block(exit);
+    f.ret(Some(acc));
+    f.finish().expect("valid IR")
+}
+
+#[test]
+fn test_lower_loop_with_phis() {
+    let func = build_sum();
+    let mut arch = ARM64::new();
+    lower_function(&func, &mut arch).unwrap();
+
+    let asm = arch.to_string();
+    assert!(asm.starts_with("sum:\n    stp x29, x30, [sp, #-16]!\n    add x29, sp, #0\n"));
+    assert!(asm.contains("    cmp x9, x10\n    cset x9, ge\n"));
+    assert!(asm.contains("    cbnz x9, Lsum_bb3\n    b Lsum_bb2\n"));
+    assert!(asm.ends_with("    ldp x29, x30, [sp], #16\n    ret\n"));
+
+    let back_edges = arch.get_instructions().iter().filter(|inst| {
+        matches!(inst, Instruction::Branch(BranchOp::B { label }) if label == "Lsum_bb1")
+    });
+    assert_eq!(back_edges.count(), 2);
+}
+
+#[test]
+fn test_lower_narrow_memory_access() {
+    let mut f = FunctionBuilder::new("bump", &[Type::Ptr], None);
+    let ptr = f.param(0);
+    let value = f.load(Type::I8, ptr);
+    let one = f.iconst(Type::I8, 1);
+    let sum = f.add(value, one);
+    f.store(sum, ptr);
+    f.ret(None);
+    let func = f.finish().unwrap();
+
+    let mut arch = ARM64::new();
+    lower_function(&func, &mut arch).unwrap();
+    let asm = arch.to_string();
+    assert!(asm.contains("    ldrsb x9, [x10]\n"));
+    assert!(asm.contains("    strb w9, [x10]\n"));
+}
+
+#[test]
+fn test_verifier_rejects_mismatched_types() {
+    let mut f = FunctionBuilder::new("bad", &[Type::I32, Type::I64], Some(Type::I64));
+    let a = f.param(0);
+    let b = f.param(1);
+    let sum = f.add(a, b);
+    f.ret(Some(sum));
+    assert!(matches!(f.finish(), Err(IrError::TypeMismatch(_))));
+}
+
+#[test]
+fn test_verifier_rejects_foreign_values() {
+    let mut other = FunctionBuilder::new("other", &[], Some(Type::I64));
+    for value in 0..4 {
+        other.iconst(Type::I64, value);
+    }
+    let foreign = other.iconst(Type::I64, 4);
+
+    let mut f = FunctionBuilder::new("bad", &[Type::I64], Some(Type::I64));
+    let a = f.param(0);
+    let sum = f.add(a, foreign);
+    f.ret(Some(sum));
+    assert_eq!(f.finish().err(), Some(IrError::ForeignValue(foreign)));
+
+    let mut f = FunctionBuilder::new("bad", &[], Some(Type::I64));
+    f.ret(Some(foreign));
+    assert_eq!(f.finish().err(), Some(IrError::ForeignValue(foreign)));
+}
+
+#[test]
+fn test_verifier_rejects_undominated_uses() {
+    // `then` defines `x`, which neither `merge` nor the edge from `else`
+    // may see
+    let diamond = |use_in_merge: bool| {
+        let mut f = FunctionBuilder::new("bad", &[Type::I8], Some(Type::I64));
+        let cond = f.param(0);
+        let (then_block, else_block, merge) = (f.create_block(), f.create_block(), f.create_block());
+        f.cond_br(cond, then_block, else_block);
+        f.switch_to_block(then_block);
+        let x = f.iconst(Type::I64, 1);
+        f.br(merge);
+        f.switch_to_block(else_block);
+        f.br(merge);
+        f.switch_to_block(merge);
+        let result = match use_in_merge {
+            true => f.add(x, x),
+            false => {
+                let phi = f.phi(Type::I64);
+                f.add_incoming(phi, then_block, x);
+                f.add_incoming(phi, else_block, x);
+                phi
+            }
+        };
+        f.ret(Some(result));
+        (f.finish().err(), x, else_block, merge)
+    };
+    let (error, x, _, merge) = diamond(true);
+    assert_eq!(error, Some(IrError::UndominatedUse(x, merge)));
+    let (error, x, else_block, _) = diamond(false);
+    assert_eq!(error, Some(IrError::UndominatedUse(x, else_block)));
+
+    // Values from dominating blocks and loop back edges are fine
+    build_sum();
+}
+
+#[test]
+fn test_lower_float_arithmetic() {
+    // `mix(a, n, b) = if a < widen(b) { a * widen(b) } else { widen(b) * 0.5 }`.
+    // Integer and floating-point arguments are numbered separately, so `n`
+    // arrives in x0 and `b` in s1.
+    let mut f = FunctionBuilder::new("mix", &[Type::F64, Type::I64, Type::F32], Some(Type::F64));
+    let a = f.param(0);
+    let b = f.param(2);
+    let wide_b = f.call("widen", &[b], Some(Type::F64));
+    let product = f.mul(a, wide_b);
+    let less = f.cmp(CmpPred::Slt, a, wide_b);
+    let then_block = f.create_block();
+    let else_block = f.create_block();
+    f.cond_br(less, then_block, else_block);
+    f.switch_to_block(then_block);
+    f.ret(Some(product));
+    f.switch_to_block(else_block);
+    let half = f.fconst(Type::F64, 0.5);
+    let scaled = f.mul(wide_b, half);
+    f.ret(Some(scaled));
+    let func = f.finish().unwrap();
+
+    let mut arch = ARM64::new();
+    lower_function(&func, &mut arch).unwrap();
+    let asm = arch.to_string();
+    assert!(asm.contains("    fmov x9, d0\n    str x9, [sp]\n"));
+    assert!(asm.contains("    str x0, [sp, #8]\n"));
+    assert!(asm.contains("    fmov w9, s1\n    str w9, [sp, #16]\n"));
+    assert!(asm.contains("    ldr w9, [sp, #16]\n    fmov s0, w9\n    bl widen\n    fmov x9, d0\n"));
+    assert!(asm.contains("    fmov d16, x9\n    fmov d17, x10\n    fmul d16, d16, d17\n"));
+    assert!(asm.contains("    fcmp d16, d17\n    cset x9, mi\n"));
+    assert!(asm.contains("    fmov d0, x9\n    add sp, sp, #"));
+}
@@ -0,0 +1,168 @@
+use asm_test::arch::arm64::emulator::Emulator;
+use asm_test::arch::arm64::{Arm64Register, ARM64};
+use asm_test::context::{Binding, Global, Symbol, Value};
+use asm_test::instruction::GenericRegister::*;
+use asm_test::instruction::{MemOperand, MemSize};
+use asm_test::program::{LinkError, Linker, Module};
+use asm_test::Program;
+
+fn module(build: impl FnOnce(&mut Program<ARM64, Arm64Register>)) -> Module<ARM64> {
+    let mut program = Program::new(ARM64::new());
+    build(&mut program);
+    program.finish().ok().unwrap()
+}
+
+/// A function that returns `value`.
+fn returning(name: &str, value: u64, symbol: Symbol) -> Module<ARM64> {
+    module(|program| {
+        program.symbol(symbol);
+        program.ins.label(name).mov_imm(X0, value).ret();
+    })
+}
+
+/// Runs `label` in the linked module with its globals placed in read-only
+/// data.
+fn call(module: &Module<ARM64>, label: &str, args: &[u64]) -> u64 {
+    let mut arch = module.arch.clone();
+    for var in module.ctx.get_sections().variables() {
+        arch.global(&var.label, &var.value, var.align);
+    }
+    Emulator::new(&arch).unwrap().call(label, args).unwrap()
+}
+
+#[test]
+fn test_exports_and_imports() {
+    let module = module(|program| {
+        program.symbol(Symbol::function("main").global());
+        program.symbol(Symbol::function("_unused").global());
+        program.ins.label("main").bl("helper").bl("_puts").ret();
+        program.ins.label("helper").ret();
+    });
+    // `_start` and `_unused` are declared but never defined
+    let exports: Vec<&str> = module.exports().iter().map(|symbol| symbol.name.as_str()).collect();
+    assert_eq!(exports, vec!["main"]);
+    let imports: Vec<&str> = module.imports().iter().map(|symbol| symbol.name.as_str()).collect();
+    assert_eq!(imports, vec!["_puts"]);
+}
+
+#[test]
+fn test_link_runtime_with_program() {
+    let runtime = module(|program| {
+        let table = program.global("table", Global::new(Value::Quad(vec![5])));
+        program.symbol(Symbol::function("rt_load").global());
+        program.ins
+            .label("rt_load")
+            .adrp_add(X1, X1, &table)
+            .load(MemSize::Double, false, X1, MemOperand::Offset(X1, 0))
+            .b("done")
+            .label("done")
+            .add(X0, X0, X1)
+            .ret();
+    });
+    let main = module(|program| {
+        let bias = program.global("bias", Global::new(Value::Quad(vec![10])));
+        program.symbol(Symbol::function("main").global());
+        program.ins
+            .label("main")
+            .stp(X29, X30, MemOperand::PreIndex(SP, -16))
+            .bl("rt_load")
+            .adrp_add(X1, X1, &bias)
+            .load(MemSize::Double, false, X1, MemOperand::Offset(X1, 0))
+            .add(X0, X0, X1)
+            .ldp(X29, X30, MemOperand::PostIndex(SP, 16))
+            .b("done")
+            .label("done")
+            .ret();
+    });
+    assert_eq!(runtime.definitions(), vec!["rt_load", "done", "L0"]);
+    assert_eq!(main.definitions(), vec!["main", "done", "L0"]);
+
+    let mut linker = Linker::new();
+    linker.add(runtime).add(main);
+    let linked = linker.link().unwrap();
+    assert_eq!(linked.definitions(), vec!["rt_load", "done.m0", "main", "done.m1", "L0.m0", "L0.m1"]);
+    assert!(linked.imports().is_empty());
+    let symbols = linked.ctx.get_symbols();
+    assert_eq!(symbols.get("L0.m1").unwrap().binding, Binding::Local);
+    assert_eq!(symbols.get("rt_load").unwrap().binding, Binding::Global);
+    assert_eq!(call(&linked, "main", &[1]), 16);
+}
+
+#[test]
+fn test_link_reports_duplicate_and_undefined_symbols() {
+    let mut linker = Linker::new();
+    linker.add(returning("f", 1, Symbol::function("f").global()));
+    linker.add(returning("f", 2, Symbol::function("f").global()));
+    linker.add(module(|program| {
+        program.ins.label("g").bl("missing").ret();
+    }));
+    assert_eq!(
+        linker.link().err().unwrap(),
+        vec![
+            LinkError::DuplicateSymbol { name: "f".to_string(), first: 0, second: 1 },
+            LinkError::UndefinedSymbol { name: "missing".to_string(), module: 2 },
+        ]
+    );
+    assert_eq!(
+        LinkError::UndefinedSymbol { name: "missing".to_string(), module: 2 }.to_string(),
+        "undefined symbol missing in module 2"
+    );
+
+    // Labels that are not exported never clash
+    let mut linker = Linker::new();
+    linker.add(returning("f", 1, Symbol::function("f")));
+    linker.add(returning("f", 2, Symbol::function("f")));
+    assert!(linker.link().is_ok());
+}
+
+#[test]
+fn test_dynamic_imports_and_weak_references_stay_undefined() {
+    let mut linker = Linker::new();
+    linker.import("_printf");
+    linker.add(module(|program| {
+        program.symbol(Symbol::external("_hook").weak());
+        program.ins.label("main").bl("_printf").bl("_hook").ret();
+    }));
+    let linked = linker.link().unwrap();
+    let imports: Vec<(&str, Binding)> = linked.imports().iter().map(|symbol| (symbol.name.as_str(), symbol.binding)).collect();
+    assert_eq!(imports, vec![("_hook", Binding::Weak), ("_printf", Binding::Global)]);
+}
+
+#[test]
+fn test_weak_definition_yields_to_global() {
+    let caller = module(|program| {
+        program.symbol(Symbol::function("main").global());
+        program.symbol(Symbol::function("hook").weak());
+        program.ins.label("main").b("hook").label("hook").mov_imm(X0, 1).ret();
+    });
+    let mut linker = Linker::new();
+    linker.add(caller).add(returning("hook", 2, Symbol::function("hook").global()));
+    let linked = linker.link().unwrap();
+
+    assert_eq!(call(&linked, "main", &[]), 2);
+    assert_eq!(call(&linked, "hook.m0", &[]), 1);
+    let symbols = linked.ctx.get_symbols();
+    assert_eq!(symbols.get("hook").unwrap().binding, Binding::Global);
+    assert_eq!(symbols.get("hook.m0").unwrap().binding, Binding::Local);
+}
+
+#[test]
+fn test_local_globals_keep_their_names_apart() {
+    let greeting = |function: &str, text: &str| {
+        let (function, text) = (function.to_string(), text.to_string());
+        module(move |program| {
+            let msg = program.var("hello_msg", &text);
+            program.symbol(Symbol::function(&function).global());
+            program.ins.label(&function).adrp_add(X0, X0, &msg).ret();
+        })
+    };
+    let mut linker = Linker::new();
+    linker.add(greeting("hello", "hi")).add(greeting("bye", "bye"));
+    let linked = linker.link().unwrap();
+
+    assert!(linked.ctx.get_variable("hello_msg").is_none());
+    let hello = linked.ctx.get_variable("hello_msg.m0").unwrap();
+    assert_eq!((hello.label.as_str(), &hello.value), ("L0.m0", &Value::Asciz("hi".to_string())));
+    let bye = linked.ctx.get_variable("hello_msg.m1").unwrap();
+    assert_eq!((bye.label.as_str(), &bye.value), ("L0.m1", &Value::Asciz("bye".to_string())));
+}
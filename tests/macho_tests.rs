@@ -0,0 +1,352 @@
+use asm_test::arch::arm64::{Arm64Register, ARM64};
+use asm_test::context::{Global, Symbol, Value};
+use asm_test::instruction::GenericRegister::*;
+use asm_test::program::macho::*;
+use asm_test::program::sha256::sha256;
+use asm_test::Program;
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Each load command's type and offset in the file.
+fn commands(bytes: &[u8]) -> Vec<(u32, usize)> {
+    let mut at = 32;
+    let mut commands = Vec::new();
+    for _ in 0..u32_at(bytes, 16) {
+        commands.push((u32_at(bytes, at), at));
+        at += u32_at(bytes, at + 4) as usize;
+    }
+    commands
+}
+
+/// The offset and size of the code signature.
+fn signature(bytes: &[u8]) -> (usize, usize) {
+    let at = commands(bytes).iter().find(|(kind, _)| *kind == 0x1d).unwrap().1;
+    (u32_at(bytes, at + 8) as usize, u32_at(bytes, at + 12) as usize)
+}
+
+fn uleb(bytes: &[u8], at: &mut usize) -> u64 {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*at];
+        *at += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+    }
+}
+
+/// Looks `name` up in an export trie, as dyld does, giving its flags and
+/// offset.
+fn lookup(trie: &[u8], name: &str) -> Option<(u64, u64)> {
+    let mut node = 0;
+    let mut rest = name.as_bytes();
+    loop {
+        let mut at = node;
+        let size = uleb(trie, &mut at) as usize;
+        if rest.is_empty() {
+            return match size {
+                0 => None,
+                _ => {
+                    let flags = uleb(trie, &mut at);
+                    Some((flags, uleb(trie, &mut at)))
+                }
+            };
+        }
+        at += size;
+        let children = trie[at];
+        at += 1;
+        let mut next = None;
+        for _ in 0..children {
+            let end = at + trie[at..].iter().position(|&byte| byte == 0).unwrap();
+            let edge = &trie[at..end];
+            at = end + 1;
+            let child = uleb(trie, &mut at) as usize;
+            if rest.starts_with(edge) {
+                next = Some((child, edge.len()));
+            }
+        }
+        let (child, len) = next?;
+        node = child;
+        rest = &rest[len..];
+    }
+}
+
+fn u32_be(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn library() -> Program<ARM64, Arm64Register> {
+    let mut program = Program::new(ARM64::new());
+    let message = program.global("message", Global::new(Value::Asciz("hi".to_string())));
+    let table = program.global("table", Global::new(Value::Pointer { symbol: message.clone(), offset: 0 }));
+    program.global("buffer", Global::new(Value::Zero(64)).mutable());
+    program.symbol(Symbol::function("_greet").global());
+    program.symbol(Symbol::function("_greeting").global());
+    program.symbol(Symbol::function("_helper").global().hidden());
+    program.symbol(Symbol::external("_hook").weak());
+    program.ins
+        .label("_greet")
+        .adrp_got(X0, &table)
+        .bl("_helper")
+        .bl("_puts")
+        .bl("_hook")
+        .label("_greeting")
+        .ret()
+        .label("_helper")
+        .ret();
+    program
+}
+
+#[test]
+fn test_dylib_layout() {
+    let macho = library().dylib("@rpath/libgreet.dylib").unwrap();
+    let segments: Vec<(&str, u64, u64, u32)> =
+        macho.segments.iter().map(|segment| (segment.name.as_str(), segment.vmaddr, segment.vmsize, segment.prot)).collect();
+    assert_eq!(
+        segments,
+        vec![("__TEXT", 0, PAGE, VM_PROT_READ | VM_PROT_EXECUTE), ("__DATA", PAGE, PAGE, VM_PROT_READ | VM_PROT_WRITE)]
+    );
+    let sections: Vec<(&str, SectionType)> =
+        macho.segments.iter().flat_map(|segment| &segment.sections).map(|section| (section.name.as_str(), section.kind)).collect();
+    assert_eq!(
+        sections,
+        vec![
+            ("__text", SectionType::Code),
+            ("__const", SectionType::Data),
+            ("__got", SectionType::Got),
+            ("__data", SectionType::Data),
+            ("__bss", SectionType::ZeroFill),
+        ]
+    );
+    // The code follows the header and load commands
+    assert_eq!(macho.symbol("_greet"), Some(macho.header_size().next_multiple_of(16)));
+
+    // The table's GOT entry and the pointer in the table are rebased; the
+    // imports are bound
+    let got = macho.segments[1].sections[0].addr;
+    let table = macho.symbol("L1").unwrap();
+    assert_eq!(macho.rebases, vec![got, table]);
+    let binds: Vec<(u64, &str, bool)> = macho.binds.iter().map(|bind| (bind.address, bind.symbol.as_str(), bind.weak)).collect();
+    assert_eq!(binds, vec![(got + 8, "_puts", false), (got + 16, "_hook", true)]);
+}
+
+#[test]
+fn test_dylib_load_commands() {
+    let macho = library().dylib("@rpath/libgreet.dylib").unwrap();
+    let bytes = macho.to_bytes().unwrap();
+    assert_eq!(u32_at(&bytes, 0), 0xfeed_facf);
+    assert_eq!(u32_at(&bytes, 4), 0x0100_000c);
+    assert_eq!(u32_at(&bytes, 12), 6);
+
+    let commands = commands(&bytes);
+    let kinds: Vec<u32> = commands.iter().map(|(kind, _)| *kind).collect();
+    // Three segments, dyld info, the symbol tables, the dylib's own name,
+    // the build version, libSystem and the code signature
+    assert_eq!(kinds, vec![0x19, 0x19, 0x19, 0x8000_0022, 0x2, 0xb, 0xd, 0x32, 0xc, 0x1d]);
+    let name = |at: usize| {
+        let start = at + u32_at(&bytes, at + 8) as usize;
+        let end = start + bytes[start..].iter().position(|&byte| byte == 0).unwrap();
+        String::from_utf8_lossy(&bytes[start..end]).to_string()
+    };
+    assert_eq!(name(commands[6].1), "@rpath/libgreet.dylib");
+    assert_eq!(name(commands[8].1), "/usr/lib/libSystem.B.dylib");
+    assert_eq!(&bytes[commands[2].1 + 8..commands[2].1 + 18], b"__LINKEDIT");
+
+    // Exports share prefixes in the trie; hidden symbols are not exported
+    let info = commands[3].1;
+    let (offset, size) = (u32_at(&bytes, info + 40) as usize, u32_at(&bytes, info + 44) as usize);
+    let trie = &bytes[offset..offset + size];
+    assert_eq!(lookup(trie, "_greet"), Some((0, macho.symbol("_greet").unwrap())));
+    assert_eq!(lookup(trie, "_greeting"), Some((0, macho.symbol("_greeting").unwrap())));
+    assert_eq!(lookup(trie, "_helper"), None);
+    assert_eq!(lookup(trie, "_gree"), None);
+
+    // The three variables and hidden _helper are local, then two exports and
+    // the imports
+    let dysymtab = commands[5].1;
+    let counts: Vec<u32> = (0..6).map(|index| u32_at(&bytes, dysymtab + 8 + 4 * index)).collect();
+    assert_eq!(counts, vec![0, 4, 4, 2, 6, 2]);
+    // No weak definitions
+    assert_eq!(u32_at(&bytes, 24) & 0x8000, 0);
+}
+
+#[test]
+fn test_dylib_weak_exports() {
+    let mut program = library();
+    program.symbol(Symbol::function("_w").weak());
+    program.ins.label("_w").ret();
+    let macho = program.dylib("libgreet.dylib").unwrap();
+    let bytes = macho.to_bytes().unwrap();
+    assert_eq!(u32_at(&bytes, 24) & 0x8000, 0x8000);
+
+    // The trie marks weak definitions; the symbol table has N_WEAK_DEF
+    let commands = commands(&bytes);
+    let info = commands[3].1;
+    let (offset, size) = (u32_at(&bytes, info + 40) as usize, u32_at(&bytes, info + 44) as usize);
+    let trie = &bytes[offset..offset + size];
+    assert_eq!(lookup(trie, "_w"), Some((0x04, macho.symbol("_w").unwrap())));
+    assert_eq!(lookup(trie, "_greet"), Some((0, macho.symbol("_greet").unwrap())));
+
+    let symtab = commands[4].1;
+    let (symoff, nsyms, stroff) = (u32_at(&bytes, symtab + 8) as usize, u32_at(&bytes, symtab + 12) as usize, u32_at(&bytes, symtab + 16) as usize);
+    let desc = (0..nsyms)
+        .map(|index| symoff + 16 * index)
+        .find(|&entry| bytes[stroff + u32_at(&bytes, entry) as usize..].starts_with(b"_w\0"))
+        .map(|entry| u16::from_le_bytes([bytes[entry + 6], bytes[entry + 7]]));
+    assert_eq!(desc, Some(0x80));
+}
+
+#[test]
+fn test_dylib_errors() {
+    let mut macho = library().dylib("libgreet.dylib").unwrap();
+    let code = macho.symbol("_greet").unwrap();
+    macho.rebases.push(code);
+    assert_eq!(macho.to_bytes().err(), Some(MachOError::Fixup(code)));
+
+    let mut macho = library().dylib("libgreet.dylib").unwrap();
+    macho.dylibs.push("/usr/lib/libc++.1.dylib".to_string());
+    let header = macho.header_size();
+    assert!(header > code);
+    assert_eq!(macho.to_bytes().err(), Some(MachOError::HeaderSpace(header)));
+}
+
+fn hello() -> Program<ARM64, Arm64Register> {
+    let mut program = Program::new(ARM64::new());
+    let message = program.global("message", Global::new(Value::Asciz("Hello from Mach-O".to_string())));
+    program.symbol(Symbol::function("_main").global());
+    program.ins.label("_main").adrp_got(X0, &message).bl("_puts").mov_imm(X0, 0).ret();
+    program
+}
+
+#[test]
+fn test_executable_layout() {
+    let macho = hello().macho("_main").unwrap();
+    let segments: Vec<(&str, u64, u64, u32)> =
+        macho.segments.iter().map(|segment| (segment.name.as_str(), segment.vmaddr, segment.vmsize, segment.prot)).collect();
+    assert_eq!(
+        segments,
+        vec![
+            ("__PAGEZERO", 0, TEXT_ADDRESS, 0),
+            ("__TEXT", TEXT_ADDRESS, PAGE, VM_PROT_READ | VM_PROT_EXECUTE),
+            ("__DATA", TEXT_ADDRESS + PAGE, PAGE, VM_PROT_READ | VM_PROT_WRITE),
+        ]
+    );
+    let main = macho.symbol("_main").unwrap();
+    assert_eq!(main, TEXT_ADDRESS + macho.header_size().next_multiple_of(16));
+    assert_eq!(macho.file_type, FileType::Execute { entry: main });
+    assert_eq!(macho.identifier, "a.out");
+
+    // The message's GOT entry slides with the image; puts comes from
+    // libSystem
+    let got = macho.segments[2].sections[0].addr;
+    assert_eq!(macho.rebases, vec![got]);
+    assert_eq!(macho.binds.iter().map(|bind| (bind.address, bind.symbol.as_str())).collect::<Vec<_>>(), vec![(got + 8, "_puts")]);
+
+    assert_eq!(hello().macho("main").err(), Some(MachOError::UndefinedEntry("main".to_string())));
+}
+
+#[test]
+fn test_executable_load_commands() {
+    let macho = hello().macho("_main").unwrap();
+    let bytes = macho.to_bytes().unwrap();
+    // An executable, position independent
+    assert_eq!(u32_at(&bytes, 12), 2);
+    assert_eq!(u32_at(&bytes, 24) & 0x20_0000, 0x20_0000);
+
+    let commands = commands(&bytes);
+    let kinds: Vec<u32> = commands.iter().map(|(kind, _)| *kind).collect();
+    // __PAGEZERO, __TEXT, __DATA and __LINKEDIT, then dyld, the entry,
+    // libSystem and the code signature
+    assert_eq!(kinds, vec![0x19, 0x19, 0x19, 0x19, 0x8000_0022, 0x2, 0xb, 0xe, 0x32, 0x8000_0028, 0xc, 0x1d]);
+    assert_eq!(&bytes[commands[0].1 + 8..commands[0].1 + 18], b"__PAGEZERO");
+    // __PAGEZERO takes no file space
+    assert_eq!(bytes[commands[0].1 + 40..commands[0].1 + 56], [0; 16]);
+    let dylinker = commands[7].1;
+    assert_eq!(u32_at(&bytes, dylinker + 8), 12);
+    assert_eq!(&bytes[dylinker + 12..dylinker + 26], b"/usr/lib/dyld\0");
+
+    // The entry is a file offset, on the default stack size
+    let main = commands[9].1;
+    assert_eq!(u64::from_le_bytes(bytes[main + 8..main + 16].try_into().unwrap()), macho.symbol("_main").unwrap() - TEXT_ADDRESS);
+    assert_eq!(bytes[main + 16..main + 24], [0; 8]);
+}
+
+#[test]
+fn test_code_signature() {
+    let mut macho = hello().macho("_main").unwrap();
+    macho.identifier = "hello".to_string();
+    let bytes = macho.to_bytes().unwrap();
+    let (offset, size) = signature(&bytes);
+    // The signature ends the file and __LINKEDIT
+    assert_eq!(offset % 16, 0);
+    assert_eq!(offset + size, bytes.len());
+    let linkedit = commands(&bytes)[3].1;
+    let end = u64::from_le_bytes(bytes[linkedit + 40..linkedit + 48].try_into().unwrap())
+        + u64::from_le_bytes(bytes[linkedit + 48..linkedit + 56].try_into().unwrap());
+    assert_eq!(end as usize, bytes.len());
+
+    // A super blob holding only the code directory
+    let blob = &bytes[offset..];
+    assert_eq!([u32_be(blob, 0), u32_be(blob, 4), u32_be(blob, 8)], [0xfade_0cc0, size as u32, 1]);
+    assert_eq!([u32_be(blob, 12), u32_be(blob, 16)], [0, 20]);
+    let directory = &blob[20..];
+    let pages = offset.div_ceil(4096);
+    assert_eq!(u32_be(directory, 0), 0xfade_0c02);
+    assert_eq!(u32_be(directory, 4) as usize, size - 20);
+    // Ad-hoc and linker-signed, with no special slots
+    assert_eq!(u32_be(directory, 12), 0x2_0002);
+    assert_eq!(u32_be(directory, 24), 0);
+    assert_eq!(u32_be(directory, 28) as usize, pages);
+    assert_eq!(u32_be(directory, 32) as usize, offset);
+    // SHA-256 hashes of 4 KiB pages
+    assert_eq!(directory[36..40], [32, 2, 0, 12]);
+    // __TEXT is the main binary's executable segment
+    let exec_segment: Vec<u64> = (0..3).map(|index| u64::from_be_bytes(directory[64 + 8 * index..72 + 8 * index].try_into().unwrap())).collect();
+    assert_eq!(exec_segment, vec![0, PAGE, 1]);
+    let ident = u32_be(directory, 20) as usize;
+    assert_eq!(&directory[ident..ident + 6], b"hello\0");
+    assert_eq!(u32_be(directory, 16) as usize, ident + 6);
+
+    // Dylibs are signed too, named by their install name
+    let dylib = library().dylib("@rpath/libgreet.dylib").unwrap();
+    assert_eq!(dylib.identifier, "libgreet.dylib");
+    let bytes = dylib.to_bytes().unwrap();
+    let (offset, _) = signature(&bytes);
+    assert_eq!(u32_be(&bytes, offset), 0xfade_0cc0);
+    assert_eq!(u64::from_be_bytes(bytes[offset + 20 + 80..offset + 20 + 88].try_into().unwrap()), 0);
+}
+
+#[test]
+fn test_sha256_vectors() {
+    let hex = |bytes: [u8; 32]| bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    // FIPS 180-4 examples, then the lengths either side of the padding
+    // spilling into a second block
+    assert_eq!(hex(sha256(b"")), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    assert_eq!(hex(sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    assert_eq!(
+        hex(sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+    assert_eq!(hex(sha256(&[b'a'; 55])), "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318");
+    assert_eq!(hex(sha256(&[b'a'; 56])), "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a");
+    assert_eq!(hex(sha256(&[b'a'; 64])), "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb");
+}
+
+#[test]
+fn test_code_signature_hashes() {
+    let bytes = hello().macho("_main").unwrap().to_bytes().unwrap();
+    let (offset, _) = signature(&bytes);
+    let directory = &bytes[offset + 20..];
+    let hashes = u32_be(directory, 16) as usize;
+    let pages: Vec<&[u8]> = bytes[..offset].chunks(4096).collect();
+    assert_eq!(u32_be(directory, 28) as usize, pages.len());
+
+    for (index, page) in pages.iter().enumerate() {
+        assert_eq!(directory[hashes + 32 * index..hashes + 32 * (index + 1)], sha256(page), "page {}", index);
+    }
+}
@@ -0,0 +1,89 @@
+use asm_test::arch::arm64::encoder::{assemble, EncodeError};
+use asm_test::arch::arm64::validate::{misclassified, validate, OperandKind, ValidationError};
+use asm_test::arch::arm64::{ArithmeticOp, FloatOp, Instruction, ARM64, Arm64Register::*};
+use asm_test::instruction::*;
+
+#[test]
+fn test_register_predicates() {
+    for reg in [X0, X15, X16, X30, LR, W0, W30] {
+        assert!(reg.is_general_purpose(), "{}", reg);
+        assert!(!reg.is_floating_point(), "{}", reg);
+    }
+    for reg in [V0, V7, V8, V31] {
+        assert!(reg.is_floating_point(), "{}", reg);
+        assert!(!reg.is_general_purpose(), "{}", reg);
+    }
+    for reg in [SP, WSP, XZR, WZR] {
+        assert!(!reg.is_general_purpose(), "{}", reg);
+        assert!(reg.is_special(), "{}", reg);
+    }
+}
+
+#[test]
+fn test_wrong_register_classes_are_rejected() {
+    let mut arch = ARM64::new();
+    arch.label("f");
+    arch.fadd(FpSize::Double, X0, X1, X2);
+    // Scalar FP operands are rendered by register number, which hides the mistake
+    assert_eq!(
+        validate(&arch),
+        Err(ValidationError::RegisterClass { index: 1, inst: "fadd d0, d1, d2".to_string(), expected: OperandKind::Fpr })
+    );
+    assert!(matches!(assemble(&arch, 0), Err(EncodeError::Invalid(_))));
+    assert!(matches!(
+        &arch.build_errors()[0],
+        BuildError::InvalidRegisterClass { site, operand, .. } if site.index == 1 && operand == "x0"
+    ));
+
+    let rejected: Vec<fn(&mut ARM64)> = vec![
+        |a| ArithmeticBuilder::add(a, V0, V1, Operand::Register(V2)),
+        |a| a.vadd(VectorArrangement::S4, V0, X1, V2),
+        |a| a.scvtf(FpSize::Single, V0, V1),
+        |a| a.fcvtzs(FpSize::Double, X0, X1),
+        |a| a.and(X0, X1, Operand::Register(SP)),
+        |a| a.load(MemSize::Double, false, X0, MemOperand::Offset(XZR, 0)),
+        |a| a.load(MemSize::Double, false, V0, MemOperand::Offset(X1, 0)),
+        |a| a.ldadd(MemoryOrder::Relaxed, MemSize::Double, X0, X1, XZR),
+        |a| a.br(XZR),
+        |a| a.adrp_add(X0, XZR, "f"),
+        |a| a.mov(SP, XZR),
+    ];
+    for build in rejected {
+        let mut arch = ARM64::new();
+        arch.label("f");
+        build(&mut arch);
+        assert!(matches!(validate(&arch), Err(ValidationError::RegisterClass { .. })), "{}", arch);
+        assert_eq!(arch.build_errors().len(), 1, "{}", arch);
+    }
+}
+
+#[test]
+fn test_sp_and_zero_register_positions_are_accepted() {
+    let mut arch = ARM64::new();
+    arch.mov(X29, SP);
+    arch.mov(SP, X29);
+    arch.mov(X0, XZR);
+    ArithmeticBuilder::add(&mut arch, SP, SP, Operand::Immediate("16".to_string()));
+    arch.cmp(SP, Operand::Register(X1));
+    arch.cmp(XZR, Operand::Register(X1));
+    arch.orr(SP, XZR, Operand::Immediate("0xff".to_string()));
+    arch.stp(X29, X30, MemOperand::PreIndex(SP, -16));
+    arch.store(MemSize::Double, XZR, MemOperand::Offset(SP, 8));
+    arch.fmov(FpSize::Double, V16, X17);
+    arch.vadd(VectorArrangement::D2, V31, V30, V29);
+    arch.ret();
+
+    assert_eq!(validate(&arch), Ok(()));
+    assert!(arch.build_errors().is_empty());
+    assert!(assemble(&arch, 0).is_ok());
+}
+
+#[test]
+fn test_operand_table() {
+    let inst = ArithmeticOp::Add { dst: SP, src1: X0, src2: X1 };
+    assert_eq!(misclassified(&Instruction::Arithmetic(inst)), None);
+    let inst = ArithmeticOp::Add { dst: SP, src1: XZR, src2: X1 };
+    assert_eq!(misclassified(&Instruction::Arithmetic(inst)), Some((XZR, OperandKind::GprOrSp)));
+    let inst = FloatOp::FmovFromFp { size: FpSize::Single, dst: V0, src: V1 };
+    assert_eq!(misclassified(&Instruction::Float(inst)), Some((V0, OperandKind::GprOrZr)));
+}
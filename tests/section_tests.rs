@@ -0,0 +1,154 @@
+use asm_test::arch::arm64::ARM64;
+use asm_test::context::{Global, SectionKind, Value};
+use asm_test::platform::linux::Linux;
+use asm_test::platform::macos::MacOS;
+use asm_test::platform::Platform;
+use asm_test::Program;
+
+#[test]
+fn test_standard_sections_come_first() {
+    let mut program = Program::new(ARM64::new());
+    program.global("vectors", Global::new(Value::Quad(vec![0])).in_section("__DATA,__vectors"));
+    program.global("greeting", Global::new(Value::Asciz("hi".to_string())));
+
+    let names: Vec<&str> = program.ctx.get_sections().iter().map(|section| section.name.as_str()).collect();
+    assert_eq!(names, vec!["text", "rodata", "data", "bss", "__DATA,__vectors"]);
+    let custom = program.ctx.get_sections().get("__DATA,__vectors").unwrap();
+    assert_eq!(custom.kind, SectionKind::Rodata);
+    assert_eq!(custom.variables()[0].name, "vectors");
+}
+
+#[test]
+fn test_globals_are_placed_by_kind() {
+    let mut program = Program::new(ARM64::new());
+    program.global("buffer", Global::new(Value::Zero(64)).mutable());
+    program.global("table", Global::new(Value::Zero(64)));
+    program.global("count", Global::new(Value::Word(vec![0])).mutable());
+
+    let sections = program.ctx.get_sections();
+    let names = |name| -> Vec<String> {
+        sections.get(name).unwrap().variables().iter().map(|var| var.name.clone()).collect()
+    };
+    // Only writable zeros are left out of the file
+    assert_eq!(names("bss"), vec!["buffer"]);
+    assert_eq!(names("rodata"), vec!["table"]);
+    assert_eq!(names("data"), vec!["count"]);
+    assert!(names("text").is_empty());
+    let order: Vec<&str> = sections.variables().map(|var| var.name.as_str()).collect();
+    assert_eq!(order, vec!["table", "count", "buffer"]);
+}
+
+#[test]
+fn test_section_layout() {
+    let mut program = Program::new(ARM64::new());
+    program.global("flag", Global::new(Value::Byte(vec![1])));
+    program.global("pair", Global::new(Value::Quad(vec![1, 2])));
+    program.global("half", Global::new(Value::Hword(vec![3])));
+    program.global("block", Global::new(Value::Byte(vec![4])).align(16));
+
+    let rodata = program.ctx.get_sections().get("rodata").unwrap();
+    assert_eq!(rodata.offsets(), vec![0, 8, 24, 32]);
+    assert_eq!(rodata.size(), 33);
+    assert_eq!(rodata.align(), 16);
+
+    let bss = program.ctx.get_sections().get("bss").unwrap();
+    assert_eq!((bss.offsets(), bss.size(), bss.align()), (vec![], 0, 1));
+}
+
+#[test]
+fn test_program_section() {
+    let mut program = Program::new(ARM64::new());
+    assert_eq!(program.section("data", SectionKind::Data).kind, SectionKind::Data);
+    // Standard sections keep their kind
+    assert_eq!(program.section("text", SectionKind::Data).kind, SectionKind::Text);
+    assert_eq!(program.section("__DATA,__extra", SectionKind::Data).kind, SectionKind::Data);
+    assert_eq!(program.section("__TEXT,__tables", SectionKind::Rodata).kind, SectionKind::Rodata);
+
+    // A global placed in an existing section keeps its kind
+    program.global("cells", Global::new(Value::Word(vec![1])).mutable().in_section("__TEXT,__tables"));
+    let sections = program.ctx.get_sections();
+    assert_eq!(sections.iter().count(), 6);
+    assert_eq!(sections.get("__TEXT,__tables").unwrap().kind, SectionKind::Rodata);
+    assert_eq!(sections.get("__TEXT,__tables").unwrap().variables().len(), 1);
+}
+
+#[test]
+fn test_sections_render_in_order() {
+    let mut program = Program::new(ARM64::new());
+    program.global("buffer", Global::new(Value::Zero(32)).mutable().align(8));
+    program.global("vectors", Global::new(Value::Quad(vec![0])).mutable().in_section("__DATA,__vectors"));
+    program.var("message", "hi");
+    program.global("one", Global::new(Value::Byte(vec![1])));
+    program.global("two", Global::new(Value::Byte(vec![2])));
+
+    let expected = concat!(
+        ".section __TEXT,__const\n",
+        "L3:\n",
+        "    .byte 0x1\n",
+        "L4:\n",
+        "    .byte 0x2\n",
+        ".section __DATA,__data\n",
+        "L2:\n",
+        "    .asciz \"hi\"\n",
+        ".section __DATA,__bss\n",
+        "    .p2align 3\n",
+        "L0:\n",
+        "    .zero 32\n",
+        ".section __DATA,__vectors\n",
+        "    .p2align 3\n",
+        "L1:\n",
+        "    .quad 0x0\n",
+        ".section __TEXT,__text\n",
+        ".globl _start\n",
+        "_start:\n",
+    );
+    assert_eq!(program.to_string(), expected);
+    assert_eq!(program.to_string(), expected);
+}
+
+#[test]
+fn test_platform_section_directives() {
+    let platform = MacOS;
+    assert_eq!(platform.rodata_section(), ".section __TEXT,__const");
+    assert_eq!(platform.bss_section(), ".section __DATA,__bss");
+}
+
+#[test]
+fn test_custom_section_directives() {
+    let kinds = [SectionKind::Text, SectionKind::Rodata, SectionKind::Data, SectionKind::Bss];
+    let macos: Vec<String> = kinds.iter().map(|kind| MacOS.custom_section("__extra", *kind)).collect();
+    assert_eq!(
+        macos,
+        vec![
+            ".section __TEXT,__extra,regular,pure_instructions",
+            ".section __TEXT,__extra",
+            ".section __DATA,__extra",
+            ".section __DATA,__extra",
+        ]
+    );
+    assert_eq!(MacOS.custom_section("__DATA,__vectors", SectionKind::Rodata), ".section __DATA,__vectors");
+
+    // ELF sections are allocated, so they are loaded
+    let linux: Vec<String> = kinds.iter().map(|kind| Linux.custom_section(".extra", *kind)).collect();
+    assert_eq!(
+        linux,
+        vec![
+            ".section .extra,\"ax\",%progbits",
+            ".section .extra,\"a\"",
+            ".section .extra,\"aw\"",
+            ".section .extra,\"aw\",%nobits",
+        ]
+    );
+}
+
+#[test]
+fn test_custom_sections_render_per_platform() {
+    let mut program = Program::new(ARM64::new());
+    program.global("cells", Global::new(Value::Word(vec![1])).mutable().in_section("__cells"));
+    program.global("scratch", Global::new(Value::Zero(16)).mutable().in_section(".scratch"));
+
+    let macos = program.listing(&MacOS);
+    assert!(macos.contains(".section __DATA,__cells\n    .p2align 2\nL0:\n    .word 0x1\n"), "{}", macos);
+    let linux = program.listing(&Linux);
+    assert!(linux.contains(".section .scratch,\"aw\",%nobits\nL1:\n    .zero 16\n"), "{}", linux);
+}
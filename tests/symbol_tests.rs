@@ -0,0 +1,124 @@
+use asm_test::arch::arm64::ARM64;
+use asm_test::context::{Binding, Global, Symbol, SymbolKind, SymbolTable, Value, Visibility};
+use asm_test::platform::linux::Linux;
+use asm_test::platform::macos::MacOS;
+use asm_test::platform::Platform;
+use asm_test::Program;
+mod common;
+
+#[test]
+fn test_symbol_builders() {
+    let symbol = Symbol::function("memcpy").weak().hidden().size(64);
+    assert_eq!(symbol.binding, Binding::Weak);
+    assert_eq!(symbol.visibility, Visibility::Hidden);
+    assert_eq!((symbol.kind, symbol.size, symbol.defined), (Some(SymbolKind::Func), Some(64), true));
+
+    let symbol = Symbol::external("puts");
+    assert_eq!((symbol.binding, symbol.kind, symbol.defined), (Binding::Global, None, false));
+    assert_eq!(Symbol::object("table").binding, Binding::Local);
+}
+
+#[test]
+fn test_declarations_replace_references() {
+    let mut table = SymbolTable::new();
+    table.refer("helper");
+    table.refer("puts");
+    table.declare(Symbol::function("helper").global());
+    table.refer("helper");
+    table.declare(Symbol::external("helper"));
+
+    let names: Vec<&str> = table.iter().map(|symbol| symbol.name.as_str()).collect();
+    assert_eq!(names, vec!["helper", "puts"]);
+    assert!(table.get("helper").unwrap().defined);
+    let externs: Vec<&str> = table.externs().map(|symbol| symbol.name.as_str()).collect();
+    assert_eq!(externs, vec!["puts"]);
+}
+
+#[test]
+fn test_calls_are_recorded_as_externs() {
+    let mut program = common::setup_test_program();
+    let message = program.var("message", "hi");
+    program.ins.label("main").bl("_puts").bl("helper").bl("_puts").bl("_exit");
+    program.ins.label("helper").ret();
+
+    let symbols = program.symbols();
+    let externs: Vec<&str> = symbols.externs().map(|symbol| symbol.name.as_str()).collect();
+    assert_eq!(externs, vec!["_puts", "_exit"]);
+    let object = symbols.get(&message).unwrap();
+    assert_eq!((object.kind, object.size, object.binding), (Some(SymbolKind::Object), Some(3), Binding::Local));
+    assert_eq!(program.ctx.get_externs(), Vec::<String>::new());
+    assert!(program.finish().is_ok());
+}
+
+#[test]
+fn test_macos_directives() {
+    let mut program = Program::new(ARM64::new());
+    program.global("table", Global::new(Value::Quad(vec![1])));
+    program.symbol(Symbol::function("_helper").global().hidden());
+    program.symbol(Symbol::function("_fallback").weak());
+    program.symbol(Symbol::external("_optional").weak());
+    program.ins.bl("_helper").bl("_printf");
+
+    assert_eq!(
+        program.to_string(),
+        concat!(
+            ".section __TEXT,__const\n",
+            "    .p2align 3\n",
+            "L0:\n",
+            "    .quad 0x1\n",
+            ".section __TEXT,__text\n",
+            ".globl _start\n",
+            ".globl _helper\n",
+            ".private_extern _helper\n",
+            ".globl _fallback\n",
+            ".weak_definition _fallback\n",
+            ".weak_reference _optional\n",
+            ".extern _printf\n",
+            "_start:\n",
+            "    bl _helper\n",
+            "    bl _printf\n",
+        )
+    );
+}
+
+#[test]
+fn test_elf_directives() {
+    let platform = Linux;
+    let symbol = Symbol::function("handler").global().protected().size(12);
+    assert_eq!(
+        platform.symbol_directives(&symbol),
+        vec![".globl handler", ".protected handler", ".type handler, %function", ".size handler, 12"]
+    );
+    let symbol = Symbol::object("counter").weak().hidden();
+    assert_eq!(platform.symbol_directives(&symbol), vec![".weak counter", ".hidden counter", ".type counter, %object"]);
+    assert_eq!(platform.symbol_directives(&Symbol::external("puts")), vec![".extern puts"]);
+    assert_eq!(platform.symbol_directives(&Symbol::external("puts").weak()), vec![".weak puts"]);
+
+    let mut program = Program::new(ARM64::new());
+    program.var("message", "hi");
+    program.ins.bl("puts");
+    assert_eq!(
+        program.listing(&Linux),
+        concat!(
+            ".data\n",
+            "L0:\n",
+            "    .asciz \"hi\"\n",
+            ".text\n",
+            ".globl _start\n",
+            ".type _start, %function\n",
+            ".type L0, %object\n",
+            ".size L0, 3\n",
+            ".extern puts\n",
+            "_start:\n",
+            "    bl puts\n",
+        )
+    );
+}
+
+#[test]
+fn test_macos_leaves_out_types_and_sizes() {
+    let symbol = Symbol::function("_f").protected().size(8);
+    assert!(MacOS.symbol_directives(&symbol).is_empty());
+    let symbol = Symbol::object("_g").global().protected().size(8);
+    assert_eq!(MacOS.symbol_directives(&symbol), vec![".globl _g"]);
+}
@@ -0,0 +1,122 @@
+use asm_test::arch::arm64::emulator::{Emulator, EmulatorError};
+use asm_test::arch::arm64::encoder::{assemble, EncodeError};
+use asm_test::arch::arm64::validate::{validate, ValidationError};
+use asm_test::arch::arm64::{ARM64, Arm64Register::*, SystemRegister};
+use asm_test::instruction::*;
+mod common;
+
+#[test]
+fn test_system_register_rendering_and_encoding() {
+    let mut arch = ARM64::new();
+    arch.msr(SystemRegister::TpidrEl0, X0);
+    arch.mrs(X1, SystemRegister::CntvctEl0);
+    arch.msr(SystemRegister::SctlrEl1, X2);
+    arch.msr(SystemRegister::VbarEl1, X3);
+    arch.mrs(X4, SystemRegister::Daif);
+    arch.msr(SystemRegister::Nzcv, X5);
+    arch.mrs(X6, SystemRegister::Fpcr);
+    arch.msr(SystemRegister::Fpsr, X7);
+    arch.mrs(X8, SystemRegister::CurrentEl);
+    arch.mrs(X0, SystemRegister::Raw { op0: 3, op1: 0, crn: 15, crm: 2, op2: 7 });
+    arch.sys(0, 7, 5, 0, X0);
+    arch.sysl(X1, 3, 7, 11, 1);
+
+    assert_eq!(
+        arch.to_string(),
+        concat!(
+            "    msr tpidr_el0, x0\n",
+            "    mrs x1, cntvct_el0\n",
+            "    msr sctlr_el1, x2\n",
+            "    msr vbar_el1, x3\n",
+            "    mrs x4, daif\n",
+            "    msr nzcv, x5\n",
+            "    mrs x6, fpcr\n",
+            "    msr fpsr, x7\n",
+            "    mrs x8, currentel\n",
+            "    mrs x0, s3_0_c15_c2_7\n",
+            "    sys #0, c7, c5, #0, x0\n",
+            "    sysl x1, #3, c7, c11, #1\n",
+        )
+    );
+    // Expected words taken from `llvm-mc -triple=aarch64 -show-encoding`
+    assert_eq!(
+        common::words(&arch),
+        vec![
+            0xd51bd040, 0xd53be041, 0xd5181002, 0xd518c003, 0xd53b4224, 0xd51b4205, 0xd53b4406, 0xd51b4427,
+            0xd5384248, 0xd538f2e0, 0xd5087500, 0xd52b7b21,
+        ]
+    );
+}
+
+#[test]
+fn test_system_register_operands_are_checked() {
+    let mut arch = ARM64::new();
+    arch.msr(SystemRegister::TpidrEl0, W0);
+    assert!(validate(&arch).is_err());
+
+    // Reading is fine, writing is not
+    let mut arch = ARM64::new();
+    arch.mrs(X0, SystemRegister::CurrentEl);
+    arch.msr(SystemRegister::CntvctEl0, X1);
+    arch.msr(SystemRegister::CurrentEl, X2);
+    assert_eq!(
+        validate(&arch),
+        Err(ValidationError::ReadOnlyRegister { index: 1, inst: "msr cntvct_el0, x1".to_string() })
+    );
+    let registers: Vec<(usize, &str)> = arch
+        .build_errors()
+        .iter()
+        .map(|error| match error {
+            BuildError::ReadOnlyRegister { site, register } => (site.index, register.as_str()),
+            other => panic!("{}", other),
+        })
+        .collect();
+    assert_eq!(registers, vec![(1, "cntvct_el0"), (2, "currentel")]);
+
+    let mut arch = ARM64::new();
+    arch.mrs(X0, SystemRegister::Raw { op0: 1, op1: 0, crn: 7, crm: 5, op2: 0 });
+    assert!(matches!(assemble(&arch, 0), Err(EncodeError::OutOfRange { .. })));
+
+    let mut arch = ARM64::new();
+    arch.sys(8, 7, 5, 0, XZR);
+    assert!(matches!(assemble(&arch, 0), Err(EncodeError::OutOfRange { .. })));
+}
+
+#[test]
+fn test_system_register_semantics() {
+    let mut arch = ARM64::new();
+    arch.label("swap_thread_pointer");
+    arch.mrs(X1, SystemRegister::TpidrEl0);
+    arch.msr(SystemRegister::TpidrEl0, X0);
+    arch.mov(X0, X1);
+    arch.ret();
+
+    // Flags written through NZCV drive conditional instructions
+    arch.label("carry_from_nzcv");
+    arch.msr(SystemRegister::Nzcv, X0);
+    arch.cset(X0, Condition::Hs);
+    arch.ret();
+
+    arch.label("elapsed");
+    arch.mrs(X1, SystemRegister::CntvctEl0);
+    arch.isb();
+    arch.mrs(X2, SystemRegister::CntvctEl0);
+    arch.sub(X0, X2, X1);
+    arch.ret();
+
+    arch.label("flush");
+    arch.sys(0, 7, 5, 0, XZR);
+    arch.ret();
+
+    let mut emulator = Emulator::new(&arch).unwrap();
+    assert_eq!(emulator.call("swap_thread_pointer", &[0x1234]).unwrap(), 0);
+    assert_eq!(emulator.call("swap_thread_pointer", &[0x5678]).unwrap(), 0x1234);
+    assert_eq!(emulator.sysreg(SystemRegister::TpidrEl0), 0x5678);
+
+    assert_eq!(emulator.call("carry_from_nzcv", &[1 << 29]).unwrap(), 1);
+    assert_eq!(emulator.call("carry_from_nzcv", &[1 << 30]).unwrap(), 0);
+    assert_eq!(emulator.sysreg(SystemRegister::Nzcv), 1 << 30);
+
+    assert_eq!(emulator.call("elapsed", &[]).unwrap(), 2);
+    assert!(matches!(emulator.call("flush", &[]), Err(EmulatorError::Unsupported(_))));
+}
@@ -0,0 +1,97 @@
+use asm_test::arch::arm64::emulator::Emulator;
+use asm_test::arch::arm64::{ARM64, Arm64Register::*};
+use asm_test::instruction::*;
+
+const BUFFER: u64 = 0x4000_0000;
+
+#[test]
+fn test_vector_rendering() {
+    let mut arch = ARM64::new();
+    arch.vadd(VectorArrangement::B16, V0, V1, V2);
+    arch.vsub(VectorArrangement::H4, V0, V1, V2);
+    arch.vmul(VectorArrangement::S2, V3, V4, V5);
+    arch.vfadd(VectorArrangement::D2, V0, V1, V2);
+    arch.vfmul(VectorArrangement::S4, V31, V30, V29);
+    arch.ld1(VectorArrangement::S4, V0, 2, MemOperand::Offset(X0, 0));
+    arch.st1(VectorArrangement::B8, V31, 2, MemOperand::PostIndex(X1, 16));
+    arch.dup(VectorArrangement::S4, V0, X1);
+    arch.dup(VectorArrangement::D2, V0, X1);
+    arch.dup_lane(VectorArrangement::S4, V2, V3, 1);
+    arch.ins(MemSize::Word, V0, 1, X2);
+    arch.umov(MemSize::Word, X3, V4, 1);
+    arch.umov(MemSize::Double, X3, V4, 0);
+    arch.addv(VectorArrangement::H8, V0, V1);
+
+    assert_eq!(
+        arch.to_string(),
+        concat!(
+            "    add v0.16b, v1.16b, v2.16b\n",
+            "    sub v0.4h, v1.4h, v2.4h\n",
+            "    mul v3.2s, v4.2s, v5.2s\n",
+            "    fadd v0.2d, v1.2d, v2.2d\n",
+            "    fmul v31.4s, v30.4s, v29.4s\n",
+            "    ld1 { v0.4s, v1.4s }, [x0]\n",
+            "    st1 { v31.8b, v0.8b }, [x1], #16\n",
+            "    dup v0.4s, w1\n",
+            "    dup v0.2d, x1\n",
+            "    dup v2.4s, v3.s[1]\n",
+            "    ins v0.s[1], w2\n",
+            "    umov w3, v4.s[1]\n",
+            "    umov x3, v4.d[0]\n",
+            "    addv h0, v1.8h\n",
+        )
+    );
+}
+
+#[test]
+fn test_vector_checksum_kernel() {
+    // Sums `x1` blocks of eight 32-bit words starting at `x0`
+    let mut arch = ARM64::new();
+    arch.label("checksum");
+    arch.dup(VectorArrangement::S4, V0, XZR);
+    arch.label("checksum_loop");
+    arch.ld1(VectorArrangement::S4, V1, 2, MemOperand::PostIndex(X0, 32));
+    arch.vadd(VectorArrangement::S4, V0, V0, V1);
+    arch.vadd(VectorArrangement::S4, V0, V0, V2);
+    ArithmeticBuilder::add(&mut arch, X1, X1, Operand::Immediate("-1".to_string()));
+    arch.cbnz(X1, "checksum_loop");
+    arch.addv(VectorArrangement::S4, V0, V0);
+    arch.umov(MemSize::Word, X0, V0, 0);
+    arch.ret();
+
+    let words: Vec<u32> = (1..=64).map(|i| i * 0x0101_0101).collect();
+    let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+    let mut emulator = Emulator::new(&arch).unwrap();
+    emulator.map(BUFFER, 0x1000);
+    emulator.write_memory(BUFFER, &bytes).unwrap();
+
+    let expected = words.iter().fold(0u32, |acc, w| acc.wrapping_add(*w));
+    assert_eq!(emulator.call("checksum", &[BUFFER, 8]).unwrap(), expected as u64);
+    assert_eq!(emulator.reg(X0), expected as u64);
+    assert_eq!(emulator.call("checksum", &[BUFFER, 1]).unwrap(), 36 * 0x0101_0101);
+}
+
+#[test]
+fn test_lane_moves() {
+    let mut arch = ARM64::new();
+    arch.label("lanes");
+    arch.dup(VectorArrangement::H8, V0, X0);
+    arch.ins(MemSize::Word, V0, 3, X1);
+    arch.dup_lane(VectorArrangement::D2, V1, V0, 1);
+    arch.vmul(VectorArrangement::H8, V2, V0, V0);
+    arch.st1(VectorArrangement::B16, V0, 3, MemOperand::Offset(X2, 0));
+    arch.umov(MemSize::Byte, X0, V2, 0);
+    arch.ret();
+
+    let mut emulator = Emulator::new(&arch).unwrap();
+    emulator.map(BUFFER, 0x1000);
+    assert_eq!(emulator.call("lanes", &[0x1_0203, 0xdead_beef, BUFFER]).unwrap(), 0x09);
+    assert_eq!(emulator.vreg(V0), 0xdead_beef_0203_0203_0203_0203_0203_0203);
+    assert_eq!(emulator.vreg(V1), 0xdead_beef_0203_0203_dead_beef_0203_0203);
+    assert_eq!(emulator.vreg(V2) as u16, 0x0203u16.wrapping_mul(0x0203));
+
+    let stored = emulator.read_memory(BUFFER, 48).unwrap();
+    assert_eq!(stored[..16], emulator.vreg(V0).to_le_bytes());
+    assert_eq!(stored[16..32], emulator.vreg(V1).to_le_bytes());
+    assert_eq!(stored[32..], emulator.vreg(V2).to_le_bytes());
+}
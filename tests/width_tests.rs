@@ -0,0 +1,183 @@
+use asm_test::arch::arm64::emulator::{Emulator, EmulatorError};
+use asm_test::arch::arm64::encoder::{assemble, EncodeError};
+use asm_test::arch::arm64::validate::{validate, ValidationError};
+use asm_test::arch::arm64::{ARM64, Arm64Register::*};
+use asm_test::instruction::*;
+use asm_test::{GenericRegister, InstructionBuilder};
+mod common;
+
+const BUFFER: u64 = 0x4000_0000;
+
+#[test]
+fn test_w_register_rendering_and_encoding() {
+    let mut builder: InstructionBuilder<ARM64, _> = InstructionBuilder::new(ARM64::new());
+    builder
+        .add(GenericRegister::W0, GenericRegister::W1, GenericRegister::W2)
+        .mov(GenericRegister::W3, GenericRegister::WZR)
+        .mov_imm(GenericRegister::W4, u64::MAX)
+        .mov_imm(GenericRegister::W5, 0x00ff_00ff);
+    let arch = &mut builder.arch;
+    arch.mov(WSP, W1);
+    arch.cmp(W0, Operand::Immediate("-5".to_string()));
+    arch.lsr(W0, W1, Operand::Immediate("31".to_string()));
+    arch.rev(W0, W1);
+    arch.load(MemSize::Byte, true, W0, MemOperand::Offset(X1, 1));
+    arch.ldp(W0, W1, MemOperand::Offset(X2, 8));
+    arch.cbz(W0, "done");
+    arch.label("done");
+
+    assert_eq!(
+        arch.to_string(),
+        concat!(
+            "    add w0, w1, w2\n",
+            "    mov w3, wzr\n",
+            "    movn w4, #0x0\n",
+            "    orr w5, wzr, #0xff00ff\n",
+            "    mov wsp, w1\n",
+            "    cmp w0, #-5\n",
+            "    lsr w0, w1, #31\n",
+            "    rev w0, w1\n",
+            "    ldrsb w0, [x1, #1]\n",
+            "    ldp w0, w1, [x2, #8]\n",
+            "    cbz w0, done\n",
+            "done:\n",
+        )
+    );
+    // Expected words taken from `llvm-mc -triple=aarch64 -show-encoding`
+    assert_eq!(
+        common::words(arch),
+        vec![
+            0x0b020020, 0x2a1f03e3, 0x12800004, 0x32009fe5, 0x1100003f, 0x3100141f, 0x531f7c20, 0x5ac00820,
+            0x39c00420, 0x29410440, 0x34000020,
+        ]
+    );
+}
+
+#[test]
+fn test_w_register_inverted_immediates() {
+    let mut arch = ARM64::new();
+    arch.bic(W0, W1, Operand::Immediate("0xff".to_string()));
+    arch.orn(W2, W3, Operand::Immediate("0xf".to_string()));
+    arch.eon(W4, W5, Operand::Immediate("0x80000000".to_string()));
+
+    // The complement stays within 32 bits
+    assert_eq!(
+        arch.to_string(),
+        concat!(
+            "    and w0, w1, #0xffffff00\n",
+            "    orr w2, w3, #0xfffffff0\n",
+            "    eor w4, w5, #0x7fffffff\n",
+        )
+    );
+    assert_eq!(common::words(&arch), vec![0x12185c20, 0x321c6c62, 0x520078a4]);
+}
+
+#[test]
+fn test_mixed_widths_are_rejected() {
+    let mut arch = ARM64::new();
+    arch.label("f");
+    ArithmeticBuilder::add(&mut arch, X0, W1, Operand::Register(X2));
+    assert_eq!(
+        validate(&arch),
+        Err(ValidationError::WidthMismatch { index: 1, inst: "add x0, w1, x2".to_string() })
+    );
+    assert!(matches!(assemble(&arch, 0), Err(EncodeError::Invalid(_))));
+    assert!(matches!(Emulator::new(&arch), Err(EmulatorError::Invalid(_))));
+
+    let rejected: Vec<fn(&mut ARM64)> = vec![
+        |a| a.load(MemSize::Double, false, W0, MemOperand::Offset(X1, 0)),
+        |a| a.load(MemSize::Word, true, W0, MemOperand::Offset(X1, 0)),
+        |a| a.store(MemSize::Byte, W0, MemOperand::Offset(W1, 0)),
+        |a| a.stp(W0, X1, MemOperand::Offset(SP, 0)),
+        |a| a.smulh(W0, W1, W2),
+        |a| a.rev32(W0, W1),
+        |a| a.cmp(X0, Operand::Register(W1)),
+        |a| a.br(W16),
+        |a| a.adr(W0, "f"),
+        |a| a.fmov(FpSize::Double, V0, W1),
+        |a| a.umov(MemSize::Double, W0, V1, 0),
+    ];
+    for build in rejected {
+        let mut arch = ARM64::new();
+        build(&mut arch);
+        assert!(validate(&arch).is_err(), "accepted {}", arch);
+    }
+
+    let mut arch = ARM64::new();
+    arch.smull(X0, W1, W2);
+    arch.umaddl(X0, X1, X2, X3);
+    arch.load(MemSize::Word, true, X0, MemOperand::Indexed(X1, X2, 2));
+    arch.scvtf(FpSize::Double, V0, W1);
+    arch.fmov(FpSize::Single, W0, V1);
+    arch.umov(MemSize::Word, X0, V1, 0);
+    assert_eq!(validate(&arch), Ok(()));
+}
+
+#[test]
+fn test_w_register_semantics() {
+    let mut arch = ARM64::new();
+    // Writes to W registers zero the upper half
+    arch.label("add32");
+    ArithmeticBuilder::add(&mut arch, W0, W0, Operand::Register(W1));
+    arch.ret();
+
+    arch.label("sdiv32");
+    arch.sdiv(W0, W0, W1);
+    arch.ret();
+
+    // Flags follow the 32-bit result: 0x8000_0000 is negative as a W value
+    arch.label("is_negative32");
+    arch.cmp(W0, Operand::Immediate("0".to_string()));
+    arch.cset(X0, Condition::Lt);
+    arch.ret();
+
+    arch.label("shifts32");
+    arch.asr(W1, W0, Operand::Immediate("4".to_string()));
+    arch.ror(W2, W0, Operand::Immediate("8".to_string()));
+    arch.clz(W3, W0);
+    arch.rev(W4, W0);
+    arch.lsl(W5, W0, Operand::Register(W6));
+    arch.ret();
+
+    arch.label("memory32");
+    arch.load(MemSize::Byte, true, W1, MemOperand::Offset(X0, 0));
+    arch.ldp(W2, W3, MemOperand::Offset(X0, 4));
+    arch.stp(W3, W2, MemOperand::Offset(X0, 12));
+    arch.ret();
+
+    arch.label("convert32");
+    arch.scvtf(FpSize::Double, V0, W0);
+    arch.fcvtzs(FpSize::Double, W1, V0);
+    arch.fmov_imm(FpSize::Double, V1, 1e12);
+    arch.fcvtzs(FpSize::Double, W2, V1);
+    arch.fcvtzs(FpSize::Double, X3, V1);
+    arch.ret();
+
+    let mut emulator = Emulator::new(&arch).unwrap();
+    assert_eq!(emulator.call("add32", &[0xffff_ffff_ffff_fff0, 0x20]).unwrap(), 0x10);
+    assert_eq!(emulator.call("sdiv32", &[-12i32 as u32 as u64, 4]).unwrap(), -3i32 as u32 as u64);
+    assert_eq!(emulator.call("is_negative32", &[0x8000_0000]).unwrap(), 1);
+    assert_eq!(emulator.call("is_negative32", &[0x1_0000_0000]).unwrap(), 0);
+
+    emulator.set_reg(X6, 33);
+    emulator.call("shifts32", &[0x8000_1234]).unwrap();
+    assert_eq!(emulator.reg(X1), 0xf800_0123);
+    assert_eq!(emulator.reg(X2), 0x3480_0012);
+    assert_eq!(emulator.reg(X3), 0);
+    assert_eq!(emulator.reg(X4), 0x3412_0080);
+    // Register shift amounts are taken modulo 32
+    assert_eq!(emulator.reg(X5), 0x0000_2468);
+
+    emulator.map(BUFFER, 0x100);
+    emulator.write_memory(BUFFER, &[0x80, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0]).unwrap();
+    emulator.call("memory32", &[BUFFER]).unwrap();
+    assert_eq!(emulator.reg(X1), 0xffff_ff80);
+    assert_eq!((emulator.reg(X2), emulator.reg(X3)), (1, 2));
+    assert_eq!(emulator.read_memory(BUFFER + 12, 8).unwrap(), vec![2, 0, 0, 0, 1, 0, 0, 0]);
+
+    emulator.call("convert32", &[-7i32 as u32 as u64]).unwrap();
+    assert_eq!(emulator.reg(X1), -7i32 as u32 as u64);
+    // Out of range conversions saturate at the destination width
+    assert_eq!(emulator.reg(X2), i32::MAX as u64);
+    assert_eq!(emulator.reg(X3), 1_000_000_000_000);
+}
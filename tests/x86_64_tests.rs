@@ -0,0 +1,160 @@
+use asm_test::arch::x86_64::encoder::assemble;
+use asm_test::arch::x86_64::X86_64;
+use asm_test::instruction::GenericRegister::*;
+use asm_test::instruction::*;
+use asm_test::{Cond, InstructionBuilder};
+use std::io::Write;
+
+/// Straight-line code covering each kind of operand, with no labels, so
+/// every instruction has a single encoding.
+fn straight_line() -> X86_64 {
+    let mut builder = InstructionBuilder::new(X86_64::new());
+    builder
+        .mov_imm(X8, 60)
+        .mov_imm(X16, -5i64 as u64)
+        .mov_imm(X9, 0x1234_5678_9abc_def0)
+        .mov(X0, X9)
+        .sub(X0, X1, X0)
+        .mul(X10, X10, X11)
+        .add(X12, X13, "-8")
+        .add(X3, X4, (X5, ShiftKind::Lsl, 3))
+        .cmp(W2, "1000")
+        .csel(X0, X1, X2, Condition::Gt)
+        .cset(X1, Condition::Lo)
+        .load(MemSize::Word, true, X2, MemOperand::Offset(SP, 16))
+        .load(MemSize::Byte, false, W3, MemOperand::Offset(X29, 0))
+        .store(MemSize::Half, W1, MemOperand::Indexed(X10, X11, 1))
+        .stp(X0, X1, MemOperand::PreIndex(SP, -16))
+        .br(X16)
+        .svc(0);
+    builder.arch
+}
+
+#[test]
+fn test_generic_code_lowers_to_x86_64() {
+    let mut builder = InstructionBuilder::new(X86_64::new());
+    builder
+        .comment("x0 = x1 - x0")
+        .sub(X0, X1, X0)
+        .csel(X0, X1, X2, Condition::Gt)
+        .ldp(X0, X1, MemOperand::PostIndex(SP, 16))
+        .if_(Cond::lt(X0, X1), |b| {
+            b.bl("_step");
+        });
+    builder.while_(Cond::NonZero(X2), |b| {
+        b.add(X2, X2, "-1");
+    });
+
+    assert_eq!(
+        builder.arch.to_string(),
+        concat!(
+            "    neg rdi # x0 = x1 - x0\n",
+            "    add rdi, rsi\n",
+            "    mov rdi, rdx\n",
+            "    cmovg rdi, rsi\n",
+            "    mov rdi, qword ptr [rsp]\n",
+            "    mov rsi, qword ptr [rsp + 8]\n",
+            "    lea rsp, [rsp + 16]\n",
+            "    cmp rdi, rsi\n",
+            "    jge Lelse0\n",
+            "    call _step\n",
+            "Lelse0:\n",
+            "Lwhile1:\n",
+            "    test rdx, rdx\n",
+            "    je Lendwhile2\n",
+            "    lea rdx, [rdx - 1]\n",
+            "    jmp Lwhile1\n",
+            "Lendwhile2:\n",
+        )
+    );
+    assert!(builder.arch.build_errors().is_empty());
+}
+
+#[test]
+fn test_encode_straight_line() {
+    let out = assemble(&straight_line(), 0x40_0000).unwrap();
+    #[rustfmt::skip]
+    assert_eq!(out.text[..31], [
+        0xb8, 0x3c, 0x00, 0x00, 0x00,                               // mov eax, 0x3c
+        0x49, 0xc7, 0xc3, 0xfb, 0xff, 0xff, 0xff,                   // mov r11, -5
+        0x48, 0xbb, 0xf0, 0xde, 0xbc, 0x9a, 0x78, 0x56, 0x34, 0x12, // movabs rbx, 0x123456789abcdef0
+        0x48, 0x89, 0xdf,                                           // mov rdi, rbx
+        0x48, 0xf7, 0xdf,                                           // neg rdi
+        0x48, 0x01, 0xf7,                                           // add rdi, rsi
+    ]);
+    // `svc` ends the text as `syscall`
+    assert_eq!(out.text[out.text.len() - 2..], [0x0f, 0x05]);
+}
+
+#[test]
+fn test_labels_are_rip_relative() {
+    let mut builder = InstructionBuilder::new(X86_64::new());
+    builder.label("_start").adr(X1, "message").b("_start");
+    let mut arch = builder.arch;
+    arch.global("message", &asm_test::context::Value::Asciz("hi".to_string()), 1);
+
+    let out = assemble(&arch, 0x40_1000).unwrap();
+    assert_eq!(out.rodata_base, 0x40_2000);
+    // lea rsi, [rip + message]; jmp _start
+    let mut text = vec![0x48, 0x8d, 0x35];
+    text.extend((0x40_2000i32 - 0x40_1007).to_le_bytes());
+    text.push(0xe9);
+    text.extend((-12i32).to_le_bytes());
+    assert_eq!(out.text, text);
+    assert_eq!(out.rodata, b"hi\0");
+}
+
+#[test]
+fn test_unsupported_operations_are_reported() {
+    let mut builder = InstructionBuilder::new(X86_64::new());
+    builder
+        .mov(X0, X1)
+        .udiv(X0, X0, X1)
+        .mov(X0, X20)
+        .mov(W0, X1)
+        .svc(0x80)
+        .add(X0, X0, "0x100000000");
+
+    let errors: Vec<(usize, String)> = builder
+        .arch
+        .build_errors()
+        .iter()
+        .map(|error| (error.site().unwrap().index, error.to_string().rsplit(": ").next().unwrap().to_string()))
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            (1, "`udiv rdi, rdi, rsi` is not supported on this target".to_string()),
+            (1, "expected a register with an x86_64 counterpart, found x20".to_string()),
+            (2, "operand widths do not agree in `mov edi, rsi`".to_string()),
+            (3, "`svc #0x80` is not supported on this target".to_string()),
+            (3, "immediate 0x100000000 is out of range".to_string()),
+        ]
+    );
+}
+
+#[test]
+#[ignore = "needs llvm-mc"]
+fn test_straight_line_matches_llvm_mc() {
+    let arch = straight_line();
+    let listing = format!(".intel_syntax noprefix\n{}", arch);
+    let mut child = std::process::Command::new("llvm-mc")
+        .args(["-triple=x86_64", "-x86-asm-syntax=intel", "-show-encoding"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("llvm-mc is not installed");
+    child.stdin.take().unwrap().write_all(listing.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // Each line reads `encoding: [0x48,0x89,0xdf]`
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let expected: Vec<u8> = stdout
+        .lines()
+        .filter_map(|line| line.split("encoding: [").nth(1))
+        .flat_map(|bytes| bytes.trim_end_matches(']').split(',').map(|byte| u8::from_str_radix(&byte[2..], 16).unwrap()).collect::<Vec<_>>())
+        .collect();
+    assert_eq!(assemble(&arch, 0).unwrap().text, expected);
+}